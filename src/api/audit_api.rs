@@ -0,0 +1,163 @@
+use actix_web::{get, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::web::{Data, Query, ServiceConfig};
+use firebase_auth::FirebaseUser;
+use mongodb::bson::{doc, Document};
+
+use crate::api::util::{is_admin, Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::{AuditAction, AuditEntry, AuditQuery};
+use crate::repository::mongo_repo::MongoRepo;
+use crate::settings::Settings;
+use crate::telemetry::request::RequestId;
+
+/// (request id, client ip) of a request, for audit entries written after the request (background jobs)
+pub fn request_context(req: &HttpRequest) -> (Option<String>, Option<String>) {
+    let request_id = req.extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone()); // X-Request-Id of the client or generated, see telemetry/request.rs
+
+    let client_ip = req.connection_info()
+        .realip_remote_addr()
+        .map(|ip| ip.to_string());
+
+    (request_id, client_ip)
+}
+
+/// Writes an entry to the audit log for a successful mutation.
+/// A failing audit write is logged but never fails the request that triggered it
+pub async fn record_audit(db: &MongoRepo, req: &HttpRequest, user: &FirebaseUser, action: AuditAction, target_id: &str, diff: Document) {
+    let (request_id, client_ip) = request_context(req);
+
+    let entry = AuditEntry {
+        id: None,
+        actor_uid: user.user_id.clone(),
+        action,
+        target_id: target_id.to_string(),
+        timestamp: mongodb::bson::DateTime::now(),
+        request_id,
+        client_ip,
+        diff,
+    };
+
+    if let Err(err) = db.insert_audit_entry(entry).await {
+        log::error!("Failed to write audit entry for {:?} on {}: {}", action, target_id, err);
+    }
+}
+
+/// Filter of the query on target, actor and a time range, a `from` or `to` that isn't RFC 3339 is a 400
+fn audit_filter(params: &AuditQuery) -> Result<Document, HttpResponse> {
+    let mut filter = doc! {};
+
+    if let Some(target_id) = &params.target_id {
+        filter.insert("target_id", target_id);
+    }
+
+    if let Some(actor_uid) = &params.actor_uid {
+        filter.insert("actor_uid", actor_uid);
+    }
+
+    let mut time_range = doc! {};
+
+    for (operator, value) in [("$gte", &params.from), ("$lte", &params.to)] {
+        if let Some(value) = value {
+            match mongodb::bson::DateTime::parse_rfc3339_str(value) {
+                Ok(date) => { time_range.insert(operator, date); }
+                Err(_) => return Err(HttpResponse::BadRequest().json(Response { message: format!("Invalid RFC 3339 timestamp: {}", value) })),
+            }
+        }
+    }
+
+    if !time_range.is_empty() {
+        filter.insert("timestamp", time_range);
+    }
+
+    Ok(filter)
+}
+
+// (page, per_page), per_page=0 would be no limit at all
+fn audit_page(params: &AuditQuery) -> (u32, u32) {
+    (params.page.unwrap_or(1).max(1), params.per_page.unwrap_or(50).clamp(1, 100))
+}
+
+// Admin only, ex ../audit?target_id=65f..&from=2024-03-01T00:00:00Z&to=2024-04-01T00:00:00Z
+#[get("/audit")]
+pub async fn get_audit_entries(db: Data<MongoRepo>, settings: Data<Settings>, params: Query<AuditQuery>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    if !is_admin(&settings, &user) {
+        return HttpResponse::Forbidden().json(Response { message: "Admin privileges required".to_string() });
+    }
+
+    let params = params.into_inner();
+
+    let filter = match audit_filter(&params) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+    let (page, per_page) = audit_page(&params);
+
+    match db.find_audit_entries(filter, page, per_page).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_audit_entries);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use mongodb::bson::doc;
+
+    use crate::models::audit_model::AuditQuery;
+
+    use super::{audit_filter, audit_page};
+
+    fn query(target_id: Option<&str>, from: Option<&str>, to: Option<&str>) -> AuditQuery {
+        AuditQuery {
+            target_id: target_id.map(str::to_string),
+            actor_uid: None,
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            page: None,
+            per_page: None,
+        }
+    }
+
+    #[test]
+    fn filter_has_the_given_parts() {
+        assert_eq!(audit_filter(&query(None, None, None)).ok().unwrap(), doc! {});
+
+        let from = mongodb::bson::DateTime::parse_rfc3339_str("2024-03-01T00:00:00Z").unwrap();
+        let filter = audit_filter(&query(Some("65f0"), Some("2024-03-01T00:00:00Z"), None)).ok().unwrap();
+        assert_eq!(filter, doc! {"target_id": "65f0", "timestamp": {"$gte": from}});
+
+        let mut by_actor = query(None, Some("2024-03-01T00:00:00Z"), Some("2024-04-01T00:00:00+02:00"));
+        by_actor.actor_uid = Some("uid".to_string());
+        let to = mongodb::bson::DateTime::parse_rfc3339_str("2024-03-31T22:00:00Z").unwrap();
+        assert_eq!(audit_filter(&by_actor).ok().unwrap(), doc! {"actor_uid": "uid", "timestamp": {"$gte": from, "$lte": to}});
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        let response = audit_filter(&query(None, None, Some("2024-04-01"))).err().unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn pages_are_clamped() {
+        assert_eq!(audit_page(&query(None, None, None)), (1, 50));
+
+        let mut params = query(None, None, None);
+        (params.page, params.per_page) = (Some(0), Some(0));
+        assert_eq!(audit_page(&params), (1, 1));
+        (params.page, params.per_page) = (Some(3), Some(500));
+        assert_eq!(audit_page(&params), (3, 100));
+    }
+}
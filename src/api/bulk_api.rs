@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use actix_web::{HttpRequest, HttpResponse, post};
+use actix_web::web::{Data, ServiceConfig};
+use firebase_auth::FirebaseUser;
+use futures::future::join_all;
+use mongodb::bson::oid::ObjectId;
+
+use crate::api::audit_api::record_audit;
+use crate::api::revision_api::{track_update, update_read_version, UpdateError};
+use crate::api::util::{field_diff, map_input_dto, RecipeStatus, unauthorized_response};
+use crate::api::version::{ApiVersion, BulkBody};
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::AuditAction;
+use crate::models::bulk_model::{BulkItemResult, BulkItemStatus, BulkOperation, BulkResponse};
+use crate::repository::mongo_repo::MongoRepo;
+
+/*
+    POST /recipes/bulk, for migration scripts and the offline sync of the mobile app.
+
+    The 2.x MongoDB driver has no bulk_write and our repository methods don't take sessions,
+    so every operation runs through the same repository calls as the single item endpoints
+    (which keeps the audit log, revisions and soft delete behaviour identical) and gets its own result.
+    Ordered mode runs them one after another and skips the rest after the first failure,
+    unordered mode runs the operations of different recipes concurrently and those of one recipe
+    one after another, in the order of the request, so they and the revisions they write can't race.
+    The size of the request (bulk.max_operations) is checked by BulkBody, see api/version.rs.
+ */
+
+fn item_result(index: usize, status: BulkItemStatus, code: u16, id: Option<String>, error: Option<String>) -> BulkItemResult {
+    BulkItemResult { index, status, code, id, error, recipe: None }
+}
+
+// The result an update that didn't go through gets, with the code PUT /recipes/{id} would have answered with
+fn update_failure(index: usize, id: String, err: UpdateError) -> BulkItemResult {
+    match err {
+        UpdateError::PreconditionFailed => item_result(index, BulkItemStatus::Failed, 412, Some(id), Some("Version conflict".to_string())),
+        UpdateError::Conflict | UpdateError::Rejected(_) => item_result(index, BulkItemStatus::Failed, 409, Some(id), Some("Recipe kept changing".to_string())),
+        UpdateError::NotFound => item_result(index, BulkItemStatus::Failed, 400, Some(id), Some("No ID Match".to_string())),
+    }
+}
+
+async fn run_operation(db: &MongoRepo, req: &HttpRequest, user: &FirebaseUser, api_version: ApiVersion, index: usize, operation: BulkOperation) -> BulkItemResult {
+    match operation {
+        BulkOperation::Create { recipe } => {
+            let object_id = ObjectId::new();
+            let recipe_entity = map_input_dto(recipe, Some(object_id), RecipeStatus::Created);
+            let diff = field_diff(None, Some(&recipe_entity));
+            let id = object_id.to_hex();
+
+            match db.insert_recipe(recipe_entity).await {
+                Ok(_) => {
+                    record_audit(db, req, user, AuditAction::Create, &id, diff).await;
+                    item_result(index, BulkItemStatus::Created, 201, Some(id), None)
+                }
+                Err(err) => item_result(index, BulkItemStatus::Failed, 500, None, Some(err.to_string())),
+            }
+        }
+        BulkOperation::Update { id, recipe, version } => {
+            let recipe_entity = map_input_dto(recipe, ObjectId::parse_str(&id).ok(), RecipeStatus::Updated);
+            let update = update_read_version(version.into(), || db.get_recipe_by_id(id.as_str()), |before| {
+                let (id, recipe_entity) = (id.as_str(), recipe_entity.clone());
+                async move { Ok(db.update_recipe_by_id(id, recipe_entity, Some(before.version)).await) }
+            }).await;
+
+            match update {
+                Ok((before, recipe)) => {
+                    track_update(db, req, user, AuditAction::Update, &id, before, &recipe).await;
+                    BulkItemResult { recipe: Some(api_version.recipe(recipe)), ..item_result(index, BulkItemStatus::Updated, 200, Some(id), None) }
+                }
+                Err(err) => update_failure(index, id, err),
+            }
+        }
+        BulkOperation::Delete { id, version } => {
+            let before = db.get_recipe_by_id(id.as_str()).await;
+
+            match db.delete_recipe_by_id(id.as_str(), version).await {
+                Some(deleted) => {
+                    record_audit(db, req, user, AuditAction::Delete, &id, field_diff(before.as_ref(), Some(&deleted))).await;
+                    item_result(index, BulkItemStatus::Deleted, 200, Some(id), None)
+                }
+                None if version.is_some() && before.is_some() => item_result(index, BulkItemStatus::Failed, 412, Some(id), Some("Version conflict".to_string())),
+                None => item_result(index, BulkItemStatus::Failed, 400, Some(id), Some("No ID Match".to_string())),
+            }
+        }
+    }
+}
+
+// The recipe an operation writes to, creates get a new one each
+fn target(operation: &BulkOperation) -> Option<&str> {
+    match operation {
+        BulkOperation::Create { .. } => None,
+        BulkOperation::Update { id, .. } | BulkOperation::Delete { id, .. } => Some(id.as_str()),
+    }
+}
+
+/// Runs every operation with `run` and returns the results in the order of the operations
+async fn run_all<F, Fut>(operations: Vec<BulkOperation>, ordered: bool, run: F) -> Vec<BulkItemResult>
+    where
+        F: Fn(usize, BulkOperation) -> Fut,
+        Fut: Future<Output = BulkItemResult>,
+{
+    if ordered {
+        let mut results = Vec::with_capacity(operations.len());
+        let mut failed = false;
+
+        for (index, operation) in operations.into_iter().enumerate() {
+            if failed {
+                results.push(item_result(index, BulkItemStatus::Skipped, 424, None, None));
+                continue;
+            }
+
+            let result = run(index, operation).await;
+            failed = result.status == BulkItemStatus::Failed;
+            results.push(result);
+        }
+
+        return results;
+    }
+
+    // One chain per recipe, the chains run concurrently and the operations of a chain in order
+    let mut chains: Vec<Vec<(usize, BulkOperation)>> = Vec::new();
+    let mut chain_of: HashMap<String, usize> = HashMap::new();
+
+    for (index, operation) in operations.into_iter().enumerate() {
+        match target(&operation).map(str::to_string) {
+            Some(id) => match chain_of.get(&id) {
+                Some(&chain) => chains[chain].push((index, operation)),
+                None => {
+                    chain_of.insert(id, chains.len());
+                    chains.push(vec![(index, operation)]);
+                }
+            },
+            None => chains.push(vec![(index, operation)]),
+        }
+    }
+
+    let mut results: Vec<BulkItemResult> = join_all(chains
+        .into_iter()
+        .map(|chain| {
+            let run = &run;
+            async move {
+                let mut results = Vec::with_capacity(chain.len());
+                for (index, operation) in chain {
+                    results.push(run(index, operation).await);
+                }
+                results
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+
+    results.sort_by_key(|result| result.index);
+    results
+}
+
+fn bulk_response(ordered: bool, results: Vec<BulkItemResult>) -> BulkResponse {
+    let failed = results.iter().filter(|result| result.status == BulkItemStatus::Failed).count();
+    let succeeded = results.iter().filter(|result| matches!(result.status, BulkItemStatus::Created | BulkItemStatus::Updated | BulkItemStatus::Deleted)).count();
+
+    BulkResponse { ordered, succeeded, failed, results }
+}
+
+#[post("/recipes/bulk")]
+pub async fn bulk_recipes(req: HttpRequest, db: Data<MongoRepo>, bulk: BulkBody, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let bulk = bulk.into_inner();
+    let results = run_all(bulk.operations, bulk.ordered, |index, operation| run_operation(&db, &req, &user, version, index, operation)).await;
+
+    HttpResponse::Ok().json(bulk_response(bulk.ordered, results))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(bulk_recipes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use serde_json::json;
+
+    use crate::models::bulk_model::{BulkItemStatus, BulkOperation, BulkRequest};
+
+    use super::{bulk_response, item_result, run_all, update_failure, UpdateError};
+
+    fn operations(request: serde_json::Value) -> Vec<BulkOperation> {
+        serde_json::from_value::<BulkRequest>(request).unwrap().operations
+    }
+
+    fn recipe() -> serde_json::Value {
+        json!({ "title": "Pancakes", "description": "", "steps": [], "photo_url": "", "ingredients": [], "email": "cook@example.com", "tags": [] })
+    }
+
+    #[actix_web::test]
+    async fn ordered_skips_after_the_first_failure() {
+        let operations = operations(json!({ "operations": [
+            { "op": "create", "recipe": recipe() },
+            { "op": "delete", "id": "a" },
+            { "op": "create", "recipe": recipe() },
+            { "op": "delete", "id": "b" },
+        ]}));
+        let ran = RefCell::new(Vec::new());
+
+        let results = run_all(operations, true, |index, _| {
+            ran.borrow_mut().push(index);
+            async move {
+                match index {
+                    1 => item_result(index, BulkItemStatus::Failed, 400, Some("a".to_string()), Some("No ID Match".to_string())),
+                    _ => item_result(index, BulkItemStatus::Created, 201, None, None),
+                }
+            }
+        }).await;
+
+        assert_eq!(*ran.borrow(), vec![0, 1]);
+        let codes: Vec<(usize, u16)> = results.iter().map(|result| (result.index, result.code)).collect();
+        assert_eq!(codes, vec![(0, 201), (1, 400), (2, 424), (3, 424)]);
+        assert_eq!(results[2].status, BulkItemStatus::Skipped);
+
+        let response = bulk_response(true, results);
+        assert_eq!((response.succeeded, response.failed), (1, 1));
+    }
+
+    #[actix_web::test]
+    async fn unordered_runs_one_recipe_at_a_time() {
+        let operations = operations(json!({ "ordered": false, "operations": [
+            { "op": "update", "id": "a", "recipe": recipe() },
+            { "op": "update", "id": "b", "recipe": recipe() },
+            { "op": "delete", "id": "a" },
+            { "op": "create", "recipe": recipe() },
+        ]}));
+        let events = RefCell::new(Vec::new());
+
+        let results = run_all(operations, false, |index, operation| {
+            let events = &events;
+            async move {
+                events.borrow_mut().push(format!("start {}", index));
+                actix_web::rt::task::yield_now().await; // Lets the other operations run in between
+                events.borrow_mut().push(format!("end {}", index));
+                let status = match operation {
+                    BulkOperation::Delete { .. } => BulkItemStatus::Failed,
+                    _ => BulkItemStatus::Updated,
+                };
+                item_result(index, status, 200, None, None)
+            }
+        }).await;
+
+        let events = events.into_inner();
+        let position = |event: &str| events.iter().position(|logged| logged == event).unwrap();
+        assert!(position("end 0") < position("start 2"), "{:?}", events);
+        assert!(position("start 1") < position("end 0"), "{:?}", events); // Other recipes don't wait
+        assert!(position("start 3") < position("end 0"), "{:?}", events);
+
+        // A failure doesn't skip anything in unordered mode
+        assert_eq!(results.iter().map(|result| result.index).collect::<Vec<usize>>(), vec![0, 1, 2, 3]);
+        let response = bulk_response(false, results);
+        assert_eq!((response.succeeded, response.failed), (3, 1));
+    }
+
+    #[test]
+    fn failed_updates_get_the_code_of_the_single_endpoint() {
+        let code = |err| update_failure(4, "a".to_string(), err).code;
+
+        assert_eq!(code(UpdateError::PreconditionFailed), 412);
+        assert_eq!(code(UpdateError::Conflict), 409);
+        assert_eq!(code(UpdateError::NotFound), 400);
+
+        let result = update_failure(4, "a".to_string(), UpdateError::NotFound);
+        assert_eq!((result.index, result.status, result.id.as_deref(), result.error.as_deref()), (4, BulkItemStatus::Failed, Some("a"), Some("No ID Match")));
+    }
+}
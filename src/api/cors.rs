@@ -0,0 +1,174 @@
+use actix_cors::Cors;
+
+use crate::settings::cors::{CorsSettings, origin_matches};
+
+/// CORS middleware from the settings of the running profile, see settings/cors.rs
+pub fn cors(settings: &CorsSettings) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(settings.allowed_methods.iter().map(String::as_str)) // Specify the allowed methods
+        .allowed_headers(settings.allowed_headers.iter().map(String::as_str))
+        .expose_headers(settings.exposed_headers.iter().map(String::as_str))
+        .max_age(settings.max_age_secs); // Set the max age for the preflight cache
+
+    if settings.origins().iter().any(|origin| origin == "*") {
+        cors = cors.allow_any_origin();
+    } else {
+        let patterns = settings.origins().to_vec();
+
+        cors = cors.allowed_origin_fn(move |origin, _| origin
+            .to_str()
+            .is_ok_and(|origin| patterns.iter().any(|pattern| origin_matches(pattern, origin))));
+    }
+
+    if settings.supports_credentials {
+        cors = cors.supports_credentials(); // If you need to support credentials (cookies, session, etc.)
+    }
+
+    cors
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpResponse, test, web};
+    use actix_web::dev::Service;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::http::header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ETAG, HeaderMap, ORIGIN,
+    };
+
+    use crate::settings::cors::{CorsSettings, default_origins, Profile};
+
+    use super::cors;
+
+    fn settings(origins: &[&str]) -> CorsSettings {
+        CorsSettings {
+            allowed_origins: Some(origins.iter().map(|origin| origin.to_string()).collect()),
+            ..CorsSettings::default()
+        }
+    }
+
+    async fn preflight(settings: &CorsSettings, origin: &str, method: &str, headers: &str) -> (StatusCode, HeaderMap) {
+        let app = test::init_service(App::new()
+            .wrap(cors(settings))
+            .route("/recipes", web::get().to(HttpResponse::Ok)))
+            .await;
+
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/recipes")
+            .insert_header((ORIGIN, origin))
+            .insert_header((ACCESS_CONTROL_REQUEST_METHOD, method))
+            .insert_header((ACCESS_CONTROL_REQUEST_HEADERS, headers))
+            .to_request();
+
+        // The middleware answers a rejected preflight with an error instead of a response
+        match app.call(req).await {
+            Ok(response) => (response.status(), response.headers().clone()),
+            Err(err) => panic!("Preflight failed: {}", err),
+        }
+    }
+
+    async fn rejected(settings: &CorsSettings, origin: &str, method: &str) -> bool {
+        let app = test::init_service(App::new()
+            .wrap(cors(settings))
+            .route("/recipes", web::get().to(HttpResponse::Ok)))
+            .await;
+
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/recipes")
+            .insert_header((ORIGIN, origin))
+            .insert_header((ACCESS_CONTROL_REQUEST_METHOD, method))
+            .to_request();
+
+        match app.call(req).await {
+            Ok(response) => response.status().is_client_error() && !response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN),
+            Err(err) => err.as_response_error().status_code() == StatusCode::BAD_REQUEST,
+        }
+    }
+
+    #[actix_web::test]
+    async fn preflight_from_allowed_origin() {
+        let settings = settings(&["https://recipes.example.com"]);
+        let (status, headers) = preflight(&settings, "https://recipes.example.com", "PUT", "authorization, if-match").await;
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default().to_lowercase();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header(ACCESS_CONTROL_ALLOW_ORIGIN), "https://recipes.example.com");
+        assert_eq!(header(ACCESS_CONTROL_ALLOW_CREDENTIALS), "true");
+        assert_eq!(header(ACCESS_CONTROL_MAX_AGE), "3600");
+        assert!(header(ACCESS_CONTROL_ALLOW_METHODS).contains("put"));
+        assert!(header(ACCESS_CONTROL_ALLOW_HEADERS).contains("if-match"));
+    }
+
+    #[actix_web::test]
+    async fn preflight_from_other_origin_is_rejected() {
+        let settings = settings(&["https://recipes.example.com"]);
+
+        assert!(rejected(&settings, "https://evil.example.org", "GET").await);
+        assert!(rejected(&settings, "http://recipes.example.com", "GET").await);
+    }
+
+    #[actix_web::test]
+    async fn preflight_with_method_not_allowed_is_rejected() {
+        let settings = CorsSettings { allowed_methods: vec!["GET".to_string()], ..settings(&["https://recipes.example.com"]) };
+
+        assert!(rejected(&settings, "https://recipes.example.com", "DELETE").await);
+    }
+
+    #[actix_web::test]
+    async fn wildcard_subdomains() {
+        let settings = settings(&["https://*.example.com"]);
+        let (_, headers) = preflight(&settings, "https://app.staging.example.com", "GET", "authorization").await;
+
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://app.staging.example.com");
+        assert!(rejected(&settings, "https://example.com", "GET").await);
+        assert!(rejected(&settings, "https://example.com.evil.io", "GET").await);
+        assert!(rejected(&settings, "https://evil.io/.example.com", "GET").await);
+    }
+
+    #[actix_web::test]
+    async fn dev_profile_allows_local_dev_servers_only() {
+        let settings = CorsSettings { allowed_origins: Some(default_origins(Profile::Dev)), ..CorsSettings::default() };
+        let (_, headers) = preflight(&settings, "http://localhost:5173", "POST", "content-type").await;
+
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "http://localhost:5173");
+        assert!(rejected(&settings, "https://recipes.example.com", "POST").await);
+        assert!(rejected(&settings, "http://localhost.evil.io:80", "POST").await);
+    }
+
+    #[actix_web::test]
+    async fn prod_profile_allows_nothing_by_default() {
+        let settings = CorsSettings { allowed_origins: Some(default_origins(Profile::Prod)), ..CorsSettings::default() };
+
+        assert!(rejected(&settings, "http://localhost:5173", "GET").await);
+    }
+
+    #[actix_web::test]
+    async fn actual_request_exposes_etag() {
+        let settings = settings(&["https://recipes.example.com"]);
+        let app = test::init_service(App::new()
+            .wrap(cors(&settings))
+            .route("/recipes", web::get().to(|| async { HttpResponse::Ok().insert_header((ETAG, "\"3\"")).finish() })))
+            .await;
+
+        let req = test::TestRequest::get().uri("/recipes").insert_header((ORIGIN, "https://recipes.example.com")).to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://recipes.example.com");
+        assert!(response.headers().get(ACCESS_CONTROL_EXPOSE_HEADERS).unwrap().to_str().unwrap().to_lowercase().contains("etag"));
+    }
+
+    #[actix_web::test]
+    async fn insecure_settings_warn() {
+        let any_origin = settings(&["*"]);
+        assert!(!any_origin.warnings(Profile::Dev).is_empty());
+
+        let plain_http = settings(&["http://recipes.example.com", "http://localhost:3000"]);
+        assert_eq!(plain_http.warnings(Profile::Prod).len(), 2);
+        assert!(plain_http.warnings(Profile::Dev).is_empty());
+
+        assert!(settings(&["https://recipes.example.com"]).warnings(Profile::Prod).is_empty());
+    }
+}
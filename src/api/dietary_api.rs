@@ -0,0 +1,110 @@
+use actix_web::{delete, HttpRequest, HttpResponse, put};
+use actix_web::web::{Data, Path, ServiceConfig};
+use firebase_auth::FirebaseUser;
+use mongodb::bson::Document;
+
+use crate::api::json::JsonBody;
+use crate::api::revision_api::{conflict_response, track_update, update_read_version, UpdateError};
+use crate::api::util::{if_match_version, precondition_failed_response, recipe_response, Response, unauthorized_response};
+use crate::api::version::ApiVersion;
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::AuditAction;
+use crate::models::dietary_model::{Allergen, DietaryLabel, DietaryOverride, DietaryOverrideRequest, DietaryQuery};
+use crate::repository::mongo_repo::MongoRepo;
+
+// "vegan, gluten_free" -> [Vegan, GlutenFree], an unknown name is a 400 naming it
+fn parse_list<T: Copy>(text: Option<&str>, all: &[T], as_str: fn(&T) -> &'static str, kind: &str) -> Result<Vec<T>, HttpResponse> {
+    text.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| all
+            .iter()
+            .find(|value| as_str(value).eq_ignore_ascii_case(name))
+            .copied()
+            .ok_or_else(|| HttpResponse::BadRequest().json(Response {
+                message: format!("Unknown {} {}, expected one of {}", kind, name, all.iter().map(as_str).collect::<Vec<&str>>().join(", ")),
+            })))
+        .collect()
+}
+
+/// The ?diet=&free_from= filter of the list endpoints
+pub fn dietary_query_filter(query: &DietaryQuery) -> Result<Document, HttpResponse> {
+    let labels = parse_list(query.diet.as_deref(), &DietaryLabel::ALL, DietaryLabel::as_str, "diet")?;
+    let allergens = parse_list(query.free_from.as_deref(), &Allergen::ALL, Allergen::as_str, "allergen")?;
+
+    Ok(MongoRepo::dietary_filter(&labels, &allergens))
+}
+
+async fn write_override(req: HttpRequest, db: Data<MongoRepo>, id: String, owner_override: Option<DietaryOverride>, user: FirebaseUser, version: ApiVersion) -> HttpResponse {
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let (db, id, owner_override) = (&db, id.as_str(), owner_override.clone());
+        let owner = user.email.as_deref() == Some(before.email.as_str());
+
+        async move {
+            if !owner {
+                return Err(HttpResponse::Forbidden().json(Response { message: "Only the owner of the recipe can change its dietary labels".to_string() }));
+            }
+            Ok(db.set_dietary_override(id, owner_override, Some(before.version)).await)
+        }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::DietaryOverride, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) }),
+    }
+}
+
+// Owner only, { "labels": ["vegan", "gluten_free"], "allergens": ["soybeans"], "reason": "Gluten free flour" },
+// a field that is left out keeps what was derived from the ingredients
+#[put("/recipes/{id}/dietary")]
+pub async fn override_dietary(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, request: JsonBody<DietaryOverrideRequest>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let request = request.into_inner();
+
+    if request.labels.is_none() && request.allergens.is_none() {
+        return HttpResponse::BadRequest().json(Response { message: "Send labels, allergens or both".to_string() });
+    }
+
+    let owner_override = DietaryOverride {
+        labels: request.labels.map(|mut labels| { labels.sort(); labels.dedup(); labels }),
+        allergens: request.allergens.map(|mut allergens| { allergens.sort(); allergens.dedup(); allergens }),
+        reason: request.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty()),
+        overridden_by: user.user_id.clone(),
+        overridden_at: mongodb::bson::DateTime::now(),
+    };
+
+    write_override(req, db, id.into_inner(), Some(owner_override), user, version).await
+}
+
+// Back to the labels derived from the ingredients
+#[delete("/recipes/{id}/dietary")]
+pub async fn remove_dietary_override(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    write_override(req, db, id.into_inner(), None, user, version).await
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(override_dietary)
+        .service(remove_dietary_override);
+}
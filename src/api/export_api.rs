@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use actix_web::{get, HttpResponse};
+use actix_web::web::Bytes;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::{self, Data, Path, Query, ServiceConfig};
+use futures::Stream;
+use mongodb::bson::Document;
+
+use crate::api::util::{Response, unauthorized_response};
+use crate::api::version::ApiVersion;
+use crate::auth::VerifiedUser;
+use crate::exporters::archive::build_archive;
+use crate::exporters::html::to_html;
+use crate::exporters::markdown::to_markdown;
+use crate::exporters::pdf::to_pdf;
+use crate::exporters::schema_org::to_json_ld;
+use crate::exporters::slugify;
+use crate::models::export_model::{ExportFormat, ExportParams};
+use crate::repository::mongo_repo::MongoRepo;
+
+fn attachment(file_name: String) -> ContentDisposition {
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(file_name)],
+    }
+}
+
+#[get("/recipes/{id}/export")]
+pub async fn export_recipe(db: Data<MongoRepo>, id: Path<String>, params: Query<ExportParams>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    let id = id.into_inner();
+
+    let recipe = match db.get_recipe_by_id(id.as_str()).await {
+        Some(recipe) => recipe,
+        None => return HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) }),
+    };
+
+    let slug = slugify(&recipe.title);
+
+    match params.format {
+        ExportFormat::Jsonld => HttpResponse::Ok()
+            .content_type("application/ld+json")
+            .json(to_json_ld(&recipe)),
+        ExportFormat::Markdown => HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .insert_header(attachment(format!("{}.md", slug)))
+            .body(to_markdown(&recipe)),
+        ExportFormat::Html => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(to_html(&recipe)),
+        ExportFormat::Pdf => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(attachment(format!("{}.pdf", slug)))
+            .body(to_pdf(&recipe)),
+    }
+}
+
+const CHUNK_BYTES: usize = 64 * 1024;
+
+// The archive file as a body, read in chunks on the blocking pool
+fn file_stream(file: File) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    futures::stream::try_unfold(Some(file), |file| async move {
+        let Some(mut file) = file else { return Ok(None) };
+
+        let (file, chunk) = web::block(move || {
+            let mut chunk = vec![0; CHUNK_BYTES];
+            let read = file.read(&mut chunk)?;
+            chunk.truncate(read);
+            Ok::<_, std::io::Error>((file, chunk))
+        }).await??;
+
+        Ok(if chunk.is_empty() { None } else { Some((Bytes::from(chunk), Some(file))) })
+    })
+}
+
+// GDPR data portability, a ZIP with every recipe of the caller in several formats, photos are included as their URLs
+#[get("/me/export")]
+pub async fn export_my_recipes(db: Data<MongoRepo>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+    let email = user.email.unwrap_or("empty email".to_string());
+
+    let recipes = match db.get_recipes_by_email(email.as_str(), Document::new()).await {
+        Ok(recipes) => recipes,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    // Compressing is CPU bound, keep it off the async worker. The archive goes to a temporary file
+    // (removed when it's closed) that is streamed, a user with many recipes doesn't cost that much memory
+    let archive_email = email.clone();
+    let archive = web::block(move || -> zip::result::ZipResult<File> {
+        let mut file = build_archive(tempfile::tempfile()?, &archive_email, &recipes, version)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }).await;
+
+    match archive {
+        Ok(Ok(file)) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header(attachment(format!("recipes-{}.zip", slugify(&email))))
+            .streaming(file_stream(file)),
+        Ok(Err(err)) => HttpResponse::InternalServerError().body(err.to_string()),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(export_recipe)
+        .service(export_my_recipes);
+}
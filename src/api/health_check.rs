@@ -1,6 +1,12 @@
-use actix_web::{get, Responder};
-use actix_web::web::Json;
+use std::time::{Duration, Instant};
+
+use actix_web::{get, HttpResponse, Responder};
+use actix_web::web::{Data, Json};
+use serde::Serialize;
+
 use crate::api::util::Response;
+use crate::auth::keys::{AuthKeys, KeyStatus};
+use crate::repository::mongo_repo::MongoRepo;
 
 
 /*
@@ -13,3 +19,167 @@ pub async fn health_check() -> impl Responder {
         Response { message: "Server is UP".to_owned() }
     )
 }
+
+/// When the server started, for the uptime in the probes
+pub struct Uptime(pub Instant);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+pub struct ComponentStatus {
+    pub name: &'static str,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub status: Status,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub uptime_secs: u64,
+    pub components: Vec<ComponentStatus>,
+}
+
+fn millis(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1000.0 * 100.0).round() / 100.0
+}
+
+// Kubernetes liveness probe, the process answers. Dependencies are left to /health/ready,
+// restarting the pod doesn't help when MongoDB is down
+#[get("/health/live")]
+pub async fn health_live(uptime: Data<Uptime>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": Status::Up,
+        "uptime_secs": uptime.0.elapsed().as_secs(),
+    }))
+}
+
+fn mongo_component(ping: Result<Duration, mongodb::error::Error>) -> ComponentStatus {
+    match ping {
+        Ok(latency) => ComponentStatus { name: "mongodb", status: Status::Up, latency_ms: Some(millis(latency)), detail: None },
+        Err(err) => ComponentStatus { name: "mongodb", status: Status::Down, latency_ms: None, detail: Some(err.to_string()) },
+    }
+}
+
+fn keys_component(keys: &KeyStatus) -> ComponentStatus {
+    ComponentStatus {
+        name: "auth_keys",
+        status: if keys.is_fresh() { Status::Up } else { Status::Down },
+        latency_ms: None, // The keys are cached, nothing is fetched here
+        detail: Some(format!("{} keys fetched {}s ago, valid for {}s", keys.count, keys.age.as_secs(), keys.max_age.as_secs())),
+    }
+}
+
+/// Up when every component is up, 503 with the state of each component otherwise
+fn readiness_response(components: Vec<ComponentStatus>, uptime_secs: u64) -> HttpResponse {
+    let status = match components.iter().all(|component| component.status == Status::Up) {
+        true => Status::Up,
+        false => Status::Down,
+    };
+
+    let readiness = Readiness {
+        status,
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"), // Set by build.rs
+        uptime_secs,
+        components,
+    };
+
+    match status {
+        Status::Up => HttpResponse::Ok().json(readiness),
+        Status::Down => HttpResponse::ServiceUnavailable().json(readiness),
+    }
+}
+
+// Kubernetes readiness probe, 503 while MongoDB doesn't answer a ping or the keys for verifying tokens are stale
+#[get("/health/ready")]
+pub async fn health_ready(db: Data<MongoRepo>, auth_keys: Data<AuthKeys>, uptime: Data<Uptime>) -> HttpResponse {
+    let components = vec![mongo_component(db.ping().await), keys_component(&auth_keys.status())];
+    readiness_response(components, uptime.0.elapsed().as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use mongodb::error::Error;
+    use serde_json::{json, Value};
+
+    use crate::auth::keys::KeyStatus;
+
+    use super::{keys_component, mongo_component, readiness_response, Status};
+
+    fn keys(age: u64, max_age: u64, count: usize) -> KeyStatus {
+        KeyStatus { age: Duration::from_secs(age), max_age: Duration::from_secs(max_age), count }
+    }
+
+    fn mongo_down() -> Error {
+        Error::from(std::io::Error::new(std::io::ErrorKind::TimedOut, "Server selection timeout"))
+    }
+
+    async fn body(response: actix_web::HttpResponse) -> Value {
+        serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn components_report_their_state() {
+        let up = mongo_component(Ok(Duration::from_micros(1234)));
+        assert!(up.status == Status::Up);
+        assert_eq!(up.latency_ms, Some(1.23));
+
+        let down = mongo_component(Err(mongo_down()));
+        assert!(down.status == Status::Down);
+        assert_eq!(down.latency_ms, None);
+        assert!(down.detail.unwrap().contains("Server selection timeout"));
+
+        assert!(keys_component(&keys(10, 3600, 2)).status == Status::Up);
+        assert!(keys_component(&keys(3600, 3600, 2)).status == Status::Down); // Stale
+        assert!(keys_component(&keys(10, 3600, 0)).status == Status::Down); // No keys
+        assert_eq!(keys_component(&keys(10, 3600, 2)).detail.unwrap(), "2 keys fetched 10s ago, valid for 3600s");
+    }
+
+    #[actix_web::test]
+    async fn ready_when_every_component_is_up() {
+        let response = readiness_response(vec![mongo_component(Ok(Duration::from_millis(2))), keys_component(&keys(10, 3600, 2))], 42);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body(response).await;
+        assert_eq!(body["status"], "UP");
+        assert_eq!(body["uptime_secs"], 42);
+        assert_eq!(body["components"][0], json!({ "name": "mongodb", "status": "UP", "latency_ms": 2.0 }));
+        assert_eq!(body["components"][1]["status"], "UP");
+    }
+
+    #[actix_web::test]
+    async fn not_ready_when_mongodb_is_down() {
+        let response = readiness_response(vec![mongo_component(Err(mongo_down())), keys_component(&keys(10, 3600, 2))], 42);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = body(response).await;
+        assert_eq!(body["status"], "DOWN");
+        assert_eq!(body["components"][0]["status"], "DOWN");
+        assert_eq!(body["components"][1]["status"], "UP"); // Every component is reported, not only the first one down
+    }
+
+    #[actix_web::test]
+    async fn not_ready_when_the_keys_are_stale() {
+        let response = readiness_response(vec![mongo_component(Ok(Duration::from_millis(2))), keys_component(&keys(7200, 3600, 2))], 42);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = body(response).await;
+        assert_eq!(body["status"], "DOWN");
+        assert_eq!(body["components"][0]["status"], "UP");
+        assert_eq!(body["components"][1]["status"], "DOWN");
+    }
+}
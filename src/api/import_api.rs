@@ -0,0 +1,115 @@
+use actix_web::{get, HttpRequest, HttpResponse, post};
+use actix_web::http::header::{CONTENT_TYPE, LOCATION};
+use actix_web::web::{Data, Path, Payload, Query, ServiceConfig};
+use mongodb::bson::oid::ObjectId;
+use serde_json::Value;
+
+use crate::api::audit_api::request_context;
+use crate::api::util::{read_body, Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::importers::schema_org::{extract_json_ld, import_from_json_ld};
+use crate::jobs::archive_import::run_archive_import;
+use crate::models::import_model::{ImportJob, ImportJobParams, ImportPreview};
+use crate::repository::mongo_repo::MongoRepo;
+
+// Recipe pages are often a few hundred KB of markup, more than the default payload limit
+const IMPORT_MAX_BYTES: usize = 5 * 1024 * 1024;
+
+// Exports of a whole recipe collection, Paprika embeds the photos so they get big
+const ARCHIVE_MAX_BYTES: usize = 100 * 1024 * 1024;
+
+// Import a recipe found on the web, nothing is fetched by the server, the client sends what it has:
+// - Content-Type: text/html -> the page, the schema.org Recipe is taken from its JSON-LD blocks
+// - Content-Type: application/ld+json or application/json -> the JSON-LD document itself
+// The response is a preview, the user confirms it by sending the (possibly edited) recipe to POST /recipes
+#[post("/recipes/import")]
+pub async fn import_recipe(req: HttpRequest, payload: Payload, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+    let email = user.email.unwrap_or("empty email".to_string());
+
+    let content_type = req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let body = match read_body(payload, IMPORT_MAX_BYTES).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let documents = match content_type.as_str() {
+        "text/html" | "application/xhtml+xml" => extract_json_ld(&String::from_utf8_lossy(&body)),
+        "application/ld+json" | "application/json" => match serde_json::from_slice::<Value>(&body) {
+            Ok(document) => vec![document],
+            Err(err) => return HttpResponse::BadRequest().json(Response { message: format!("Invalid JSON-LD: {}", err) }),
+        },
+        _ => return HttpResponse::UnsupportedMediaType().json(Response {
+            message: "Use Content-Type text/html, application/ld+json or application/json".to_string(),
+        }),
+    };
+
+    match import_from_json_ld(&documents, &email) {
+        Some(recipe) => HttpResponse::Ok().json(ImportPreview::new(recipe)),
+        None => HttpResponse::UnprocessableEntity().json(Response { message: "No schema.org Recipe found".to_string() }),
+    }
+}
+
+// Import a whole collection exported from another recipe manager, ex ../imports?format=paprika
+// with the file as the request body. The recipes are saved directly (duplicates are skipped),
+// which can take a while, so the response is 202 with the job to poll at GET /imports/{id}
+#[post("/imports")]
+pub async fn start_archive_import(req: HttpRequest, db: Data<MongoRepo>, params: Query<ImportJobParams>, payload: Payload, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let archive = match read_body(payload, ARCHIVE_MAX_BYTES).await {
+        Ok(body) => body.to_vec(),
+        Err(response) => return response,
+    };
+
+    if archive.is_empty() {
+        return HttpResponse::BadRequest().json(Response { message: "The request body must be the exported file".to_string() });
+    }
+
+    let email = user.email.unwrap_or("empty email".to_string());
+    let mut job = ImportJob::new(user.user_id, email, params.format);
+    let job_id = ObjectId::new();
+    job.id = Some(job_id);
+
+    if let Err(err) = db.insert_import_job(&job).await {
+        return HttpResponse::InternalServerError().body(err.to_string());
+    }
+
+    let (request_id, client_ip) = request_context(&req);
+    actix_web::rt::spawn(run_archive_import(db.clone(), job.clone(), archive, request_id, client_ip));
+
+    HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/imports/{}", job_id.to_hex())))
+        .json(job)
+}
+
+#[get("/imports/{id}")]
+pub async fn get_import_job(db: Data<MongoRepo>, path: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.get_import_job(&path.into_inner(), &user.user_id).await {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::BadRequest().json(Response { message: "No import job with that ID".to_string() }),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(import_recipe)
+        .service(start_archive_import)
+        .service(get_import_job);
+}
@@ -0,0 +1,412 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Deref;
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::dev::Payload;
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::web::{self, Data};
+use futures::future::LocalBoxFuture;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::Serialize;
+
+use crate::api::util::read_body;
+use crate::api::version::unversioned;
+use crate::settings::Settings;
+
+/*
+    JSON request bodies. JsonBody<T> replaces actix's Json<T> so that every body is limited by
+    json.limit_bytes, or json.route_limits for the route, and a body that doesn't deserialize gets
+    a 400 naming the field and where in the body it is, in the JSON shape of our other errors:
+        { "message": "invalid type: integer `4`, expected a string", "field": "ingredients[2]", "line": 1, "column": 58 }
+    StrictJson<T> also rejects fields T doesn't have when json.strict is set, otherwise serde ignores them.
+    The JsonConfig registered in main gives actix's own Json<T> the same limit and error shape.
+ */
+
+#[derive(Debug, Serialize)]
+pub struct BodyError {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>, // Path like "ingredients[2]"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl BodyError {
+    fn message(message: impl Into<String>) -> Self {
+        BodyError { message: message.into(), field: None, line: None, column: None }
+    }
+}
+
+/// Deserialized request body, `STRICT` only matters when json.strict is set
+pub struct JsonBody<T, const STRICT: bool = false>(pub T);
+
+/// For the bodies json.strict applies to
+pub type StrictJson<T> = JsonBody<T, true>;
+
+impl<T, const STRICT: bool> JsonBody<T, STRICT> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const STRICT: bool> Deref for JsonBody<T, STRICT> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug, const STRICT: bool> fmt::Debug for JsonBody<T, STRICT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn error(response: HttpResponse, reason: &str) -> actix_web::Error {
+    InternalError::from_response(reason.to_string(), response).into()
+}
+
+/// The 400 of a body that doesn't deserialize, for extractors that check more than JsonBody<T> does
+pub fn invalid_body(body_error: BodyError) -> actix_web::Error {
+    log::debug!("Rejected request body: {}", body_error.message);
+    error(HttpResponse::BadRequest().json(body_error), "Invalid request body")
+}
+
+// application/json and types like application/merge-patch+json
+fn is_json(req: &HttpRequest) -> bool {
+    match req.mime_type() {
+        Ok(Some(mime)) => mime.subtype().as_str() == "json" || mime.suffix().is_some_and(|suffix| suffix.as_str() == "json"),
+        _ => false,
+    }
+}
+
+// serde_json appends " at line 1 column 5" to its messages, the response has them as fields
+fn without_position(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    message.strip_suffix(&position).unwrap_or(&message).to_string()
+}
+
+// `path` is where serde was when it failed, for a missing field that is the object the field is missing from
+// and for an unknown field that object followed by "?"
+fn field(path: &str, message: &str, unknown: Option<String>) -> Option<String> {
+    let (path, name) = match unknown {
+        Some(name) => (path.trim_end_matches('?').trim_end_matches('.'), Some(name)),
+        None => (path, message.strip_prefix("missing field `").and_then(|rest| rest.split('`').next()).map(str::to_string)),
+    };
+    match (path, name) {
+        ("." | "", None) => None,
+        ("." | "", Some(name)) => Some(name),
+        (path, None) => Some(path.to_string()),
+        (path, Some(name)) => Some(format!("{}.{}", path, name)),
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(body: &[u8], strict: bool) -> Result<T, BodyError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Err(BodyError::message("Request body is empty, expected JSON"));
+    }
+    let unknown: RefCell<Option<String>> = RefCell::new(None);
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+
+    let result = match strict {
+        true => serde_path_to_error::deserialize(Strict { inner: &mut deserializer, unknown: &unknown }),
+        false => serde_path_to_error::deserialize(&mut deserializer),
+    };
+
+    let value = result.map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.into_inner();
+        let message = without_position(&inner);
+        let position = (inner.line() > 0).then(|| (inner.line(), inner.column()));
+
+        BodyError {
+            field: if inner.is_data() { field(&path, &message, unknown.take()) } else { None },
+            message: if inner.is_syntax() || inner.is_eof() { format!("Invalid JSON: {}", message) } else { message },
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    })?;
+
+    // Anything but whitespace after the value
+    deserializer.end().map_err(|err| BodyError {
+        message: format!("Invalid JSON: {}", without_position(&err)),
+        field: None,
+        line: Some(err.line()),
+        column: Some(err.column()),
+    })?;
+    Ok(value)
+}
+
+/// A value that was read as serde_json::Value, like the recipes of a bulk request, deserialized like a body.
+/// `path` is where it is in the body, "" for the whole body. A Value has no line and column left so the error only names the field
+pub fn deserialize_value<T: DeserializeOwned>(value: serde_json::Value, path: &str, strict: bool) -> Result<T, BodyError> {
+    let unknown: RefCell<Option<String>> = RefCell::new(None);
+
+    let result = match strict {
+        true => serde_path_to_error::deserialize(Strict { inner: value, unknown: &unknown }),
+        false => serde_path_to_error::deserialize(value),
+    };
+
+    result.map_err(|err| {
+        let inner_path = err.path().to_string();
+        let message = err.into_inner().to_string();
+
+        BodyError {
+            field: match field(&inner_path, &message, unknown.take()) {
+                Some(field) if path.is_empty() => Some(field),
+                Some(field) => Some(format!("{}.{}", path, field)),
+                None => Some(path.to_string()).filter(|path| !path.is_empty()),
+            },
+            message,
+            line: None,
+            column: None,
+        }
+    })
+}
+
+impl<T: DeserializeOwned + 'static, const STRICT: bool> FromRequest for JsonBody<T, STRICT> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let payload = web::Payload::from_request(&req, payload).into_inner();
+
+        Box::pin(async move {
+            let settings = req.app_data::<Data<Settings>>().map(|settings| settings.json.clone()).unwrap_or_default();
+            let limit = settings.limit_for(req.match_pattern().as_deref().map(unversioned));
+
+            if !is_json(&req) {
+                let response = HttpResponse::UnsupportedMediaType().json(BodyError::message("Content-Type has to be application/json"));
+                return Err(error(response, "Content-Type is not JSON"));
+            }
+            let body = read_body(payload?, limit).await.map_err(|response| error(response, "Unreadable request body"))?;
+
+            deserialize::<T>(&body, STRICT && settings.strict)
+                .map(JsonBody)
+                .map_err(invalid_body)
+        })
+    }
+}
+
+/// Error handler of the JsonConfig in main, for handlers that still take actix's Json<T>
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = match &err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => HttpResponse::PayloadTooLarge().json(BodyError::message(err.to_string())),
+        JsonPayloadError::ContentType => HttpResponse::UnsupportedMediaType().json(BodyError::message("Content-Type has to be application/json")),
+        JsonPayloadError::Deserialize(inner) => HttpResponse::BadRequest().json(BodyError {
+            message: without_position(inner),
+            field: None,
+            line: Some(inner.line()),
+            column: Some(inner.column()),
+        }),
+        _ => HttpResponse::BadRequest().json(BodyError::message(err.to_string())),
+    };
+    error(response, "Invalid JSON body")
+}
+
+/*
+    Strict mode without deny_unknown_fields on the types, which would make it permanent: the derived
+    Deserialize passes the names of its fields to deserialize_struct, so a key that isn't one of them
+    can be turned into serde's unknown field error there. The error comes up from serde_json's own
+    parsing, so it gets the line and column of the key.
+ */
+struct Strict<'a, D> {
+    inner: D,
+    unknown: &'a RefCell<Option<String>>,
+}
+
+struct StrictVisitor<'a, V> {
+    inner: V,
+    fields: &'static [&'static str],
+    unknown: &'a RefCell<Option<String>>,
+}
+
+struct StrictMap<'a, A> {
+    inner: A,
+    fields: &'static [&'static str],
+    unknown: &'a RefCell<Option<String>>,
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Strict<'_, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.inner.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.inner.deserialize_struct(name, fields, StrictVisitor { inner: visitor, fields, unknown: self.unknown })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for StrictVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_map(StrictMap { inner: map, fields: self.fields, unknown: self.unknown })
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for StrictMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        let Some(key) = self.inner.next_key::<String>()? else {
+            return Ok(None);
+        };
+        if !self.fields.contains(&key.as_str()) {
+            let err = de::Error::unknown_field(&key, self.fields);
+            *self.unknown.borrow_mut() = Some(key);
+            return Err(err);
+        }
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Self::Error> {
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App};
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::recipe_patch::PatchOperation;
+    use crate::models::recipe_model::RecipeDTO;
+
+    const RECIPE: &str = r#"{
+  "title": "Pancakes",
+  "description": "",
+  "steps": [],
+  "photo_url": "",
+  "ingredients": ["3 dl flour", "6 dl milk", "3 eggs"],
+  "email": "cook@example.com",
+  "tags": []
+}"#;
+
+    fn rejected(body: &str, strict: bool) -> (String, Option<String>, Option<usize>, Option<usize>) {
+        let err = deserialize::<RecipeDTO>(body.as_bytes(), strict).err().unwrap();
+        (err.message, err.field, err.line, err.column)
+    }
+
+    #[test]
+    fn deserialize_reads_a_recipe() {
+        let recipe = deserialize::<RecipeDTO>(RECIPE.as_bytes(), true).unwrap();
+        assert_eq!(recipe.ingredients, vec!["3 dl flour", "6 dl milk", "3 eggs"]);
+    }
+
+    #[test]
+    fn wrong_type_names_the_element() {
+        let body = RECIPE.replace(r#""3 eggs""#, "3");
+        assert_eq!(rejected(&body, false), (
+            "invalid type: integer `3`, expected a string".to_string(),
+            Some("ingredients[2]".to_string()),
+            Some(6),
+            Some(46),
+        ));
+    }
+
+    #[test]
+    fn missing_field_names_the_field() {
+        let body = RECIPE.replace("  \"title\": \"Pancakes\",\n", "");
+        assert_eq!(rejected(&body, false), ("missing field `title`".to_string(), Some("title".to_string()), Some(8), Some(1)));
+    }
+
+    #[test]
+    fn unknown_field_only_when_strict() {
+        let body = RECIPE.replace("  \"tags\": []", "  \"tags\": [],\n  \"servings\": 4");
+
+        let (message, field, line, column) = rejected(&body, true);
+        assert!(message.starts_with("unknown field `servings`, expected one of `_id`, `title`"), "{}", message);
+        assert_eq!((field, line, column), (Some("servings".to_string()), Some(9), Some(12)));
+
+        assert!(deserialize::<RecipeDTO>(body.as_bytes(), false).is_ok());
+    }
+
+    #[test]
+    fn values_are_named_by_their_path_in_the_body() {
+        let recipe: Value = serde_json::from_str(&RECIPE.replace("  \"tags\": []", "  \"tags\": [],\n  \"servings\": 4")).unwrap();
+
+        let err = deserialize_value::<RecipeDTO>(recipe.clone(), "operations[1].recipe", true).err().unwrap();
+        assert_eq!((err.field.as_deref(), err.line, err.column), (Some("operations[1].recipe.servings"), None, None));
+        assert!(deserialize_value::<RecipeDTO>(recipe.clone(), "operations[1].recipe", false).is_ok());
+
+        let mut wrong = recipe;
+        wrong["ingredients"][1] = json!(6);
+        let err = deserialize_value::<RecipeDTO>(wrong, "operations[0].recipe", false).err().unwrap();
+        assert_eq!(err.field.as_deref(), Some("operations[0].recipe.ingredients[1]"));
+
+        // The whole body, like the operations of a JSON Patch
+        let operations = json!([{ "op": "add", "path": "/tags/-", "value": "vegan" }, { "op": "add", "value": "vegan" }]);
+        let err = deserialize_value::<Vec<PatchOperation>>(operations, "", false).err().unwrap();
+        assert_eq!((err.message.as_str(), err.field.as_deref()), ("missing field `path`", Some("[1].path")));
+        assert_eq!(deserialize_value::<Vec<PatchOperation>>(json!({}), "", false).err().unwrap().field, None);
+    }
+
+    #[test]
+    fn trailing_characters_are_invalid_json() {
+        let body = format!("{} }}", RECIPE);
+        assert_eq!(rejected(&body, false), ("Invalid JSON: trailing characters".to_string(), None, Some(9), Some(3)));
+
+        let (message, field, _, _) = rejected("", false);
+        assert_eq!((message.as_str(), field), ("Request body is empty, expected JSON", None));
+    }
+
+    async fn post(content_type: &str, body: String) -> (StatusCode, Value) {
+        let mut settings = Settings::default();
+        settings.json.route_limits.insert("/recipes".to_string(), 512); // Routes in json.route_limits have their own limit
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(settings))
+                .route("/recipes", web::post().to(|recipe: StrictJson<RecipeDTO>| async move { HttpResponse::Ok().json(json!({ "title": recipe.title })) })),
+        )
+        .await;
+
+        let req = TestRequest::post().uri("/recipes").insert_header((CONTENT_TYPE, content_type)).set_payload(body).to_request();
+        let response = call_service(&app, req).await;
+        (response.status(), read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn from_request_checks_the_content_type_and_size() {
+        let (status, body) = post("application/json", RECIPE.to_string()).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "title": "Pancakes" })));
+        let (status, _) = post("application/merge-patch+json", RECIPE.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = post("text/plain", RECIPE.to_string()).await;
+        assert_eq!((status, body), (StatusCode::UNSUPPORTED_MEDIA_TYPE, json!({ "message": "Content-Type has to be application/json" })));
+
+        let large = RECIPE.replace(r#""description": """#, &format!(r#""description": "{}""#, "x".repeat(512)));
+        let (status, body) = post("application/json", large).await;
+        assert_eq!((status, body), (StatusCode::PAYLOAD_TOO_LARGE, json!({ "message": "Request body is larger than 512 bytes" })));
+
+        let (status, body) = post("application/json", RECIPE.replace(r#""3 eggs""#, "3")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["field"], "ingredients[2]");
+    }
+}
@@ -0,0 +1,281 @@
+use actix_web::{delete, get, HttpResponse, post, put};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+use chrono::{Days, Utc};
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::api::json::JsonBody;
+use crate::api::util::{Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::exporters::ical::to_ics;
+use crate::models::meal_plan_model::{FeedParams, MealPlan, MealPlanDTO, MealPlanEntry, MealPlanEntryDTO, MealPlanQuery, WeekCopyRequest};
+use crate::repository::mongo_repo::MongoRepo;
+
+// Longest range for GET /me/mealplan, a year is more than any planner view shows
+const MAX_RANGE_DAYS: i64 = 366;
+
+// The .ics feed covers the last four weeks and the coming year
+const FEED_DAYS_BACK: u64 = 28;
+const FEED_DAYS_AHEAD: u64 = 365;
+
+fn no_plan_response() -> HttpResponse {
+    HttpResponse::BadRequest().json(Response { message: "No meal plan with that ID".to_string() })
+}
+
+// The planned recipe has to exist (and not be in the trash)
+async fn validate_entry(db: &MongoRepo, entry: &MealPlanEntryDTO) -> Result<ObjectId, HttpResponse> {
+    if entry.servings == Some(0) {
+        return Err(HttpResponse::BadRequest().json(Response { message: "servings must be at least 1".to_string() }));
+    }
+
+    match db.get_recipe_by_id(&entry.recipe_id).await.and_then(|recipe| recipe.id) {
+        Some(recipe_id) => Ok(recipe_id),
+        None => Err(HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID {}", entry.recipe_id) })),
+    }
+}
+
+#[post("/mealplans")]
+pub async fn create_meal_plan(db: Data<MongoRepo>, plan: JsonBody<MealPlanDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let now = mongodb::bson::DateTime::now();
+    let plan = MealPlan {
+        id: Some(ObjectId::new()),
+        owner_uid: user.user_id,
+        name: plan.into_inner().name,
+        feed_token: Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
+        created: now,
+        updated: now,
+    };
+
+    match db.insert_meal_plan(&plan).await {
+        Ok(_) => HttpResponse::Created().json(plan),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[get("/mealplans")]
+pub async fn get_meal_plans(db: Data<MongoRepo>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.get_meal_plans_by_owner(&user.user_id).await {
+        Ok(plans) => HttpResponse::Ok().json(plans),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[put("/mealplans/{id}")]
+pub async fn rename_meal_plan(db: Data<MongoRepo>, id: Path<String>, plan: JsonBody<MealPlanDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.rename_meal_plan(&id.into_inner(), &user.user_id, &plan.name).await {
+        Some(plan) => HttpResponse::Ok().json(plan),
+        None => no_plan_response(),
+    }
+}
+
+// Deletes the plan and all of its entries
+#[delete("/mealplans/{id}")]
+pub async fn delete_meal_plan(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.delete_meal_plan(&id.into_inner(), &user.user_id).await {
+        Ok(true) => HttpResponse::Ok().json(Response { message: "Meal plan deleted".to_string() }),
+        Ok(false) => no_plan_response(),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[post("/mealplans/{id}/entries")]
+pub async fn add_meal_plan_entry(db: Data<MongoRepo>, id: Path<String>, entry: JsonBody<MealPlanEntryDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let Some(plan_id) = db.get_meal_plan(&id.into_inner(), &user.user_id).await.and_then(|plan| plan.id) else {
+        return no_plan_response();
+    };
+
+    let entry = entry.into_inner();
+    let recipe_id = match validate_entry(&db, &entry).await {
+        Ok(recipe_id) => recipe_id,
+        Err(response) => return response,
+    };
+
+    let now = mongodb::bson::DateTime::now();
+    let entry = MealPlanEntry {
+        id: Some(ObjectId::new()),
+        plan_id,
+        owner_uid: user.user_id,
+        date: entry.date,
+        slot: entry.slot,
+        recipe_id,
+        servings: entry.servings,
+        note: entry.note,
+        created: now,
+        updated: now,
+    };
+
+    match db.insert_meal_plan_entry(&entry).await {
+        Ok(_) => HttpResponse::Created().json(entry),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[put("/mealplans/{id}/entries/{entry_id}")]
+pub async fn update_meal_plan_entry(db: Data<MongoRepo>, path: Path<(String, String)>, entry: JsonBody<MealPlanEntryDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, entry_id) = path.into_inner();
+    let Some(plan_id) = db.get_meal_plan(&id, &user.user_id).await.and_then(|plan| plan.id) else {
+        return no_plan_response();
+    };
+
+    let recipe_id = match validate_entry(&db, &entry).await {
+        Ok(recipe_id) => recipe_id,
+        Err(response) => return response,
+    };
+
+    match db.update_meal_plan_entry(plan_id, &entry_id, &user.user_id, &entry, recipe_id).await {
+        Some(entry) => HttpResponse::Ok().json(entry),
+        None => HttpResponse::BadRequest().json(Response { message: "No ID Match".to_string() }),
+    }
+}
+
+#[delete("/mealplans/{id}/entries/{entry_id}")]
+pub async fn delete_meal_plan_entry(db: Data<MongoRepo>, path: Path<(String, String)>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, entry_id) = path.into_inner();
+    let Some(plan_id) = db.get_meal_plan(&id, &user.user_id).await.and_then(|plan| plan.id) else {
+        return no_plan_response();
+    };
+
+    match db.delete_meal_plan_entry(plan_id, &entry_id, &user.user_id).await {
+        Ok(true) => HttpResponse::Ok().json(Response { message: "Entry removed from the meal plan".to_string() }),
+        Ok(false) => HttpResponse::BadRequest().json(Response { message: "No ID Match".to_string() }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// { "from": "2024-03-04", "to": "2024-03-11" } copies that week's entries a week ahead, returns the new entries.
+// The target week has to be empty, 409 otherwise
+#[post("/mealplans/{id}/copy-week")]
+pub async fn copy_meal_plan_week(db: Data<MongoRepo>, id: Path<String>, copy: JsonBody<WeekCopyRequest>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let Some(plan_id) = db.get_meal_plan(&id.into_inner(), &user.user_id).await.and_then(|plan| plan.id) else {
+        return no_plan_response();
+    };
+
+    if copy.from == copy.to {
+        return HttpResponse::BadRequest().json(Response { message: "from and to must be different weeks".to_string() });
+    }
+
+    match db.copy_meal_plan_week(plan_id, &user.user_id, copy.from, copy.to).await {
+        Ok(Some(entries)) => HttpResponse::Created().json(entries),
+        Ok(None) => HttpResponse::Conflict().json(Response {
+            message: format!("The week starting {} already has meals planned, remove them before copying a week onto it", copy.to),
+        }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// All planned meals of the caller between from and to (inclusive), with a summary of each recipe,
+// ex ../me/mealplan?from=2024-03-04&to=2024-03-10, plan_id limits it to one plan
+#[get("/me/mealplan")]
+pub async fn get_my_meal_plan(db: Data<MongoRepo>, params: Query<MealPlanQuery>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let params = params.into_inner();
+    let days = params.to.signed_duration_since(params.from).num_days();
+
+    if !(0..MAX_RANGE_DAYS).contains(&days) {
+        return HttpResponse::BadRequest().json(Response {
+            message: format!("to must be on or after from and at most {} days later", MAX_RANGE_DAYS - 1),
+        });
+    }
+
+    let mut filter = doc! {
+        "owner_uid": &user.user_id,
+        "date": {"$gte": params.from.to_string(), "$lte": params.to.to_string()},
+    };
+
+    if let Some(plan_id) = params.plan_id {
+        match ObjectId::parse_str(&plan_id) {
+            Ok(plan_id) => { filter.insert("plan_id", plan_id); }
+            Err(_) => return no_plan_response(),
+        }
+    }
+
+    match db.resolve_meal_plan_entries(filter).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// iCalendar feed to subscribe to, ../mealplans/{id}/calendar.ics?token=<feed_token of the plan>
+// Calendar apps can't send a Firebase token, so the secret token in the URL is the authentication
+#[get("/mealplans/{id}/calendar.ics")]
+pub async fn get_meal_plan_feed(db: Data<MongoRepo>, id: Path<String>, params: Query<FeedParams>) -> HttpResponse {
+    let Some(plan) = db.get_meal_plan_by_feed_token(&id.into_inner(), &params.token).await else {
+        return HttpResponse::NotFound().finish(); // Same answer for a wrong token and a missing plan
+    };
+
+    let today = Utc::now().date_naive();
+    let from = today - Days::new(FEED_DAYS_BACK);
+    let to = today + Days::new(FEED_DAYS_AHEAD);
+
+    let filter = doc! {
+        "plan_id": plan.id,
+        "date": {"$gte": from.to_string(), "$lte": to.to_string()},
+    };
+
+    match db.resolve_meal_plan_entries(filter).await {
+        Ok(entries) => HttpResponse::Ok()
+            .insert_header((CONTENT_TYPE, "text/calendar; charset=utf-8"))
+            .body(to_ics(&plan, &entries)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(create_meal_plan)
+        .service(get_meal_plans)
+        .service(rename_meal_plan)
+        .service(delete_meal_plan)
+        .service(add_meal_plan_entry)
+        .service(update_meal_plan_entry)
+        .service(delete_meal_plan_entry)
+        .service(copy_meal_plan_week)
+        .service(get_my_meal_plan)
+        .service(get_meal_plan_feed);
+}
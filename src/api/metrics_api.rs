@@ -0,0 +1,35 @@
+use actix_web::{get, HttpRequest, HttpResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::Data;
+
+use crate::api::util::Response;
+use crate::metrics::METRICS;
+use crate::settings::Settings;
+
+// Compares every byte so the time taken doesn't tell how much of the token was right
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Prometheus scrape target, on metrics.port or on the API port with "Authorization: Bearer <metrics.token>"
+#[get("/metrics")]
+pub async fn get_metrics(req: HttpRequest, settings: Data<Settings>) -> HttpResponse {
+    let token = settings.metrics.token.trim();
+
+    if !token.is_empty() {
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+
+        if !token_matches(token, given.trim()) {
+            return HttpResponse::Unauthorized().json(Response { message: "Missing or invalid metrics token".to_string() });
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(METRICS.render())
+}
@@ -1,3 +1,20 @@
 pub mod recipe_api;
 pub mod health_check;
 pub mod util;
+pub mod audit_api;
+pub mod revision_api;
+pub mod recipe_patch;
+pub mod trash_api;
+pub mod bulk_api;
+pub mod import_api;
+pub mod export_api;
+pub mod meal_plan_api;
+pub mod shopping_list_api;
+pub mod pantry_api;
+pub mod nutrition_api;
+pub mod dietary_api;
+pub mod substitution_api;
+pub mod cors;
+pub mod metrics_api;
+pub mod json;
+pub mod version;
@@ -0,0 +1,56 @@
+use actix_web::{get, HttpResponse};
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+
+use crate::api::util::{Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::models::nutrition_model::NutritionParams;
+use crate::nutrition::{compute_nutrition, dataset_version, FoodIndex};
+use crate::repository::mongo_repo::MongoRepo;
+
+// Calories, macros and micronutrients per serving and in total, cached on the recipe document until it changes.
+// The cache isn't part of Recipe, so it isn't in other recipe responses
+#[get("/recipes/{id}/nutrition")]
+pub async fn get_recipe_nutrition(db: Data<MongoRepo>, id: Path<String>, params: Query<NutritionParams>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    let id = id.into_inner();
+
+    let recipe = match db.get_recipe_by_id(id.as_str()).await {
+        Some(recipe) => recipe,
+        None => return HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) }),
+    };
+
+    if !params.refresh.unwrap_or(false) {
+        let cached = match recipe.id {
+            Some(recipe_id) => db.get_recipe_nutrition(recipe_id).await,
+            None => None,
+        };
+
+        if let Some(nutrition) = cached.filter(|nutrition| nutrition.recipe_version == recipe.version && nutrition.dataset_version == dataset_version()) {
+            return HttpResponse::Ok().json(nutrition);
+        }
+    }
+
+    let foods = match db.get_foods().await {
+        Ok(foods) if foods.is_empty() => return HttpResponse::ServiceUnavailable().json(Response { message: "The food database isn't loaded yet".to_string() }),
+        Ok(foods) => foods,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    let nutrition = compute_nutrition(&recipe, &FoodIndex::new(foods));
+
+    if let Some(recipe_id) = recipe.id {
+        if let Err(err) = db.save_recipe_nutrition(recipe_id, &nutrition).await {
+            log::warn!("Failed to cache nutrition of recipe {}: {}", id, err);
+        }
+    }
+
+    HttpResponse::Ok().json(nutrition)
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_recipe_nutrition);
+}
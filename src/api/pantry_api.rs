@@ -0,0 +1,260 @@
+use actix_web::{delete, get, HttpResponse, post, put};
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+use chrono::{Days, Utc};
+use mongodb::bson::oid::ObjectId;
+
+use crate::api::json::JsonBody;
+use crate::api::util::{Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::ingredients::{covers, ingredient_key, parse_ingredient};
+use crate::models::pantry_model::{CookableQuery, CookableRecipe, CookableResponse, PantryItem, PantryItemDTO};
+use crate::repository::mongo_repo::MongoRepo;
+
+// Nobody keeps these in the pantry, they don't count as missing
+const ALWAYS_AVAILABLE: [&str; 4] = ["water", "vatten", "ice", "ice cube"];
+
+// Recipes read for one cookable search, the most recently changed ones
+const COOKABLE_SCAN_LIMIT: i64 = 2000;
+
+// Regex for MongoDB that matches an ingredient line containing a word of a pantry item, None for an empty pantry.
+// `covers` only matches lines that contain every word of the pantry key, so this finds all of them and then some
+fn pantry_pattern(keys: &[String]) -> Option<String> {
+    let mut words: Vec<String> = keys
+        .iter()
+        .flat_map(|key| key.split(' '))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().fold(String::new(), |mut escaped, c| {
+            if "\\^$.|?*+()[]{}".contains(c) {
+                escaped.push('\\');
+                escaped.push(c);
+            } else if !c.is_ascii() && c.to_uppercase().ne(c.to_lowercase()) {
+                // The "i" option isn't guaranteed to fold non ASCII letters, "ägg" matches "Ägg" with [äÄ]
+                escaped.push('[');
+                escaped.extend(c.to_lowercase());
+                escaped.extend(c.to_uppercase());
+                escaped.push(']');
+            } else {
+                escaped.push(c);
+            }
+            escaped
+        }))
+        .collect();
+    words.sort();
+    words.dedup();
+
+    (!words.is_empty()).then(|| words.join("|"))
+}
+
+fn validate_item(item: &PantryItemDTO) -> Result<(), HttpResponse> {
+    if item.name.trim().is_empty() {
+        return Err(HttpResponse::BadRequest().json(Response { message: "name can't be empty".to_string() }));
+    }
+    if item.quantity.is_some_and(|quantity| quantity < 0.0) {
+        return Err(HttpResponse::BadRequest().json(Response { message: "quantity can't be negative".to_string() }));
+    }
+    Ok(())
+}
+
+// Items that expire first on top, items without an expiry date last
+#[get("/me/pantry")]
+pub async fn get_pantry(db: Data<MongoRepo>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.get_pantry(&user.user_id).await {
+        Ok(mut items) => {
+            items.sort_by_key(|item| (item.expires_on.is_none(), item.expires_on));
+            HttpResponse::Ok().json(items)
+        }
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[post("/me/pantry")]
+pub async fn add_pantry_item(db: Data<MongoRepo>, item: JsonBody<PantryItemDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    if let Err(response) = validate_item(&item) {
+        return response;
+    }
+
+    let item = item.into_inner();
+    let now = mongodb::bson::DateTime::now();
+    let item = PantryItem {
+        id: Some(ObjectId::new()),
+        owner_uid: user.user_id,
+        name: item.name.trim().to_string(),
+        quantity: item.quantity,
+        unit: item.unit,
+        expires_on: item.expires_on,
+        created: now,
+        updated: now,
+    };
+
+    match db.insert_pantry_item(&item).await {
+        Ok(_) => HttpResponse::Created().json(item),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[put("/me/pantry/{id}")]
+pub async fn update_pantry_item(db: Data<MongoRepo>, id: Path<String>, item: JsonBody<PantryItemDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    if let Err(response) = validate_item(&item) {
+        return response;
+    }
+
+    let mut item = item.into_inner();
+    item.name = item.name.trim().to_string();
+
+    match db.update_pantry_item(&id.into_inner(), &user.user_id, &item).await {
+        Some(item) => HttpResponse::Ok().json(item),
+        None => HttpResponse::BadRequest().json(Response { message: "No ID Match".to_string() }),
+    }
+}
+
+#[delete("/me/pantry/{id}")]
+pub async fn delete_pantry_item(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.delete_pantry_item(&id.into_inner(), &user.user_id).await {
+        Ok(true) => HttpResponse::Ok().json(Response { message: "Removed from the pantry".to_string() }),
+        Ok(false) => HttpResponse::BadRequest().json(Response { message: "No ID Match".to_string() }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Recipes ranked by how much of their ingredients the caller has in the pantry, amounts are not compared.
+// All recipes are readable by every user in this API, so both the caller's own and everyone else's are ranked,
+// of the COOKABLE_SCAN_LIMIT most recently changed recipes with at least one ingredient from the pantry.
+// Registered before GET /recipes/{id} so "cookable" isn't taken as an id
+#[get("/recipes/cookable")]
+pub async fn get_cookable_recipes(db: Data<MongoRepo>, params: Query<CookableQuery>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let threshold = params.threshold.unwrap_or(0.5);
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+
+    if !(0.0..=1.0).contains(&threshold) {
+        return HttpResponse::BadRequest().json(Response { message: "threshold must be between 0 and 1".to_string() });
+    }
+
+    let pantry = match db.get_pantry(&user.user_id).await {
+        Ok(pantry) => pantry,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    let expiring_before = Utc::now().date_naive() + Days::new(params.expiring_within_days.unwrap_or(3));
+    let is_expiring = |item: &PantryItem| item.expires_on.is_some_and(|date| date <= expiring_before);
+
+    let available: Vec<(String, &PantryItem)> = pantry.iter().map(|item| (ingredient_key(&item.name), item)).collect();
+
+    // Below the threshold anyway when none of the ingredients are in the pantry, unless the threshold is 0
+    let pattern = pantry_pattern(&available.iter().map(|(key, _)| key.clone()).collect::<Vec<String>>());
+    let recipes = match pattern {
+        None if threshold > 0.0 => Vec::new(),
+        pattern => match db.get_recipe_ingredients(pattern.filter(|_| threshold > 0.0).as_deref(), COOKABLE_SCAN_LIMIT).await {
+            Ok(recipes) => recipes,
+            Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+        },
+    };
+
+    let email = user.email.unwrap_or("empty email".to_string());
+    let mut cookable: Vec<CookableRecipe> = Vec::new();
+
+    for recipe in recipes {
+        let mut needed = 0;
+        let mut missing: Vec<String> = Vec::new();
+        let mut uses_expiring: Vec<String> = Vec::new();
+
+        for line in &recipe.ingredients {
+            let key = ingredient_key(&parse_ingredient(line).name);
+
+            if key.is_empty() || ALWAYS_AVAILABLE.contains(&key.as_str()) {
+                continue;
+            }
+            needed += 1;
+
+            match available.iter().find(|(pantry_key, _)| covers(pantry_key, &key)) {
+                Some((_, item)) if is_expiring(item) && !uses_expiring.contains(&item.name) => uses_expiring.push(item.name.clone()),
+                Some(_) => {}
+                None => missing.push(line.clone()),
+            }
+        }
+
+        if needed == 0 {
+            continue;
+        }
+
+        let match_ratio = (needed - missing.len()) as f64 / needed as f64;
+
+        if match_ratio >= threshold {
+            cookable.push(CookableRecipe {
+                recipe_id: recipe.id.to_hex(),
+                title: recipe.title,
+                photo_url: recipe.photo_url,
+                owned: recipe.email == email,
+                match_ratio: (match_ratio * 100.0).round() / 100.0,
+                missing,
+                uses_expiring,
+            });
+        }
+    }
+
+    // Best match first, among equals the recipe that uses the most of what is about to expire
+    cookable.sort_by(|a, b| b.match_ratio.total_cmp(&a.match_ratio)
+        .then_with(|| b.uses_expiring.len().cmp(&a.uses_expiring.len()))
+        .then_with(|| a.missing.len().cmp(&b.missing.len())));
+    cookable.truncate(limit);
+
+    let mut expiring: Vec<PantryItem> = pantry.iter().filter(|item| is_expiring(item)).cloned().collect();
+    expiring.sort_by_key(|item| item.expires_on);
+
+    HttpResponse::Ok().json(CookableResponse { expiring, recipes: cookable })
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_pantry)
+        .service(add_pantry_item)
+        .service(update_pantry_item)
+        .service(delete_pantry_item)
+        .service(get_cookable_recipes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pantry_pattern;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn pattern_has_every_word_once() {
+        assert_eq!(pantry_pattern(&keys(&["milk", "olive oil", "oil"])).as_deref(), Some("milk|oil|olive"));
+        assert_eq!(pantry_pattern(&keys(&[])), None);
+        assert_eq!(pantry_pattern(&keys(&["", ""])), None);
+    }
+
+    #[test]
+    fn pattern_escapes_and_folds_non_ascii() {
+        assert_eq!(pantry_pattern(&keys(&["c++ (x)"])).as_deref(), Some("\\(x\\)|c\\+\\+"));
+        assert_eq!(pantry_pattern(&keys(&["ägg"])).as_deref(), Some("[äÄ]gg"));
+    }
+}
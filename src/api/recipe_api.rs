@@ -1,46 +1,64 @@
-use actix_web::{delete, get, HttpResponse, patch, post, put};
-use actix_web::web::{Data, Json, Path, Query};
-use firebase_auth::FirebaseUser;
+use actix_web::{delete, get, HttpRequest, HttpResponse, patch, post, put};
+use actix_web::http::header::{CONTENT_TYPE, ETAG};
+use actix_web::web::{Data, Path, Query, ServiceConfig};
 use mongodb::bson::oid::ObjectId;
-
-use crate::api::util::{map_input_dto, PaginationParams, RecipeStatus, Response, unauthorized_response};
-use crate::models::recipe_model::{PhotoUrlChangeRequest, RecipeDTO, TitleChangeRequest};
+use serde_json::Value;
+
+use crate::api::audit_api::record_audit;
+use crate::api::dietary_api::dietary_query_filter;
+use crate::api::json::{deserialize_value, invalid_body, JsonBody, StrictJson};
+use crate::api::recipe_patch::{JSON_PATCH_CONTENT_TYPE, json_patch_to_update, MERGE_PATCH_CONTENT_TYPE, merge_patch_to_update, PatchError, PatchOperation};
+use crate::api::revision_api::{conflict_response, track_update, update_read_version, UpdateError};
+use crate::api::util::{field_diff, if_match_version, if_none_match, map_input_dto, PaginationParams, precondition_failed_response, recipe_etag, recipe_response, recipes_response, RecipeStatus, Response, unauthorized_response};
+use crate::api::version::{ApiVersion, RecipeBody};
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::AuditAction;
+use crate::models::dietary_model::DietaryQuery;
+use crate::models::recipe_model::{PhotoUrlChangeRequest, TitleChangeRequest};
 use crate::repository::mongo_repo::MongoRepo;
 
 /*
-    The Result<FirebaseUser, actix_web::Error> type in our handler function's parameters is a pattern in Actix-web that allows your handler to work with extractors that might fail.
-    In this context, FirebaseUser is an extractor provided by the firebase_auth crate that attempts to extract and validate a Firebase user from the request,
-    typically by looking at the Authorization header for a Firebase JWT token.
+    The Result<VerifiedUser, actix_web::Error> type in our handler function's parameters is a pattern in Actix-web that allows your handler to work with extractors that might fail.
+    In this context, VerifiedUser (auth/mod.rs) is an extractor that takes the Firebase user whose token AuthKeys::attach verified,
+    from the Authorization header, and fails when there was no valid Firebase JWT token.
 
-    Using Result<FirebaseUser, actix_web::Error> in our handler allows us to explicitly handle authentication failures.
+    Using Result<VerifiedUser, actix_web::Error> in our handler allows us to explicitly handle authentication failures.
     This is useful for customizing the response in case of errors, such as providing a specific error message or status code, as we've done with the unauthorized_response() function.
 
-    However it's not strictly necessary to use Result<FirebaseUser, actix_web::Error> if our application's logic does not require custom error handling for authentication failures
+    However it's not strictly necessary to use Result<VerifiedUser, actix_web::Error> if our application's logic does not require custom error handling for authentication failures
     but we want to provide 403 response which is not guaranteed without this implementation
  */
 
 #[post("/recipes")]
-pub async fn insert_recipe(db: Data<MongoRepo>, new_recipe: Json<RecipeDTO>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
+pub async fn insert_recipe(req: HttpRequest, db: Data<MongoRepo>, new_recipe: RecipeBody, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
     // Util function checking if we have a valid token in Auth Header
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
-    }
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
     // Take ownership of the inner `Recipe` to avoid cloning
+    // The id is generated here instead of by Mongo so the audit entry can reference it
     let new_recipe_dto = new_recipe.into_inner();
-    let recipe_entity = map_input_dto(new_recipe_dto, None, RecipeStatus::Created);
+    let object_id = ObjectId::new();
+    let recipe_entity = map_input_dto(new_recipe_dto, Some(object_id), RecipeStatus::Created);
+    let diff = field_diff(None, Some(&recipe_entity));
 
     match db.insert_recipe(recipe_entity).await {
-        Ok(recipe_id) => HttpResponse::Created().json(Response { message: format!("Recipe added with ID: {}", recipe_id) }),
+        Ok(recipe_id) => {
+            record_audit(&db, &req, &user, AuditAction::Create, &object_id.to_hex(), diff).await;
+            HttpResponse::Created().json(Response { message: format!("Recipe added with ID: {}", recipe_id) })
+        }
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()), // Bara om de blir Error i Servern
     }
 }
 
 #[put("/recipes/{id}")]
-pub async fn update_recipe_by_id(db: Data<MongoRepo>, id: Path<String>, new_recipe: Json<RecipeDTO>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
-    }
+pub async fn update_recipe_by_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, new_recipe: RecipeBody, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
     // Shadowing variable, overwriting
     let id = id.into_inner();
@@ -50,92 +68,254 @@ pub async fn update_recipe_by_id(db: Data<MongoRepo>, id: Path<String>, new_reci
     let new_recipe_dto = new_recipe.into_inner();
     let recipe_entity = map_input_dto(new_recipe_dto, object_id, RecipeStatus::Updated);
 
-    match db.update_recipe_by_id(id.as_str(), recipe_entity).await {
-        Some(recipe) => HttpResponse::Ok().json(recipe),
-        None => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
+    // Optimistic concurrency, with If-Match the update only goes through if the stored version still matches
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let (db, id, recipe_entity) = (&db, id.as_str(), recipe_entity.clone());
+        async move { Ok(db.update_recipe_by_id(id, recipe_entity, Some(before.version)).await) }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::Update, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
     }
 }
 
 #[patch("/recipes/{id}/imgurl")]
-pub async fn update_photo_url_by_recipe_id(db: Data<MongoRepo>, id: Path<String>, image_url: Json<PhotoUrlChangeRequest>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
-    }
+pub async fn update_photo_url_by_recipe_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, image_url: StrictJson<PhotoUrlChangeRequest>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
     let id = id.into_inner();
     let new_url = image_url.photo_url.to_owned();
 
-    match db.update_recipe_img_url(id.as_str(), new_url.as_str()).await {
-        Some(recipe) => HttpResponse::Ok().json(recipe),
-        None => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
+    // Optimistic concurrency, with If-Match the update only goes through if the stored version still matches
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let (db, id, new_url) = (&db, id.as_str(), new_url.as_str());
+        async move { Ok(db.update_recipe_img_url(id, new_url, Some(before.version)).await) }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::ImgUrlChange, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
     }
 }
 
 #[patch("/recipes/{id}/title")]
-pub async fn update_title_by_recipe_id(db: Data<MongoRepo>, id: Path<String>, title: Json<TitleChangeRequest>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
-    }
+pub async fn update_title_by_recipe_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, title: StrictJson<TitleChangeRequest>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
     let id = id.into_inner();
     let new_title = title.into_inner().title;
 
-    match db.update_title_by_recipe_id(id.as_str(), new_title.as_str()).await {
-        Some(recipe) => HttpResponse::Ok().json(recipe),
-        None => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
+    // Optimistic concurrency, with If-Match the update only goes through if the stored version still matches
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let (db, id, new_title) = (&db, id.as_str(), new_title.as_str());
+        async move { Ok(db.update_title_by_recipe_id(id, new_title, Some(before.version)).await) }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::TitleChange, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response {message: "No ID Match".to_string()}),
     }
 }
 
 
-#[get("/recipes/user")]
-pub async fn get_recipes_by_email(db: Data<MongoRepo>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
+// Generic partial update, the Content-Type decides the format:
+// application/merge-patch+json (RFC 7396) or application/json-patch+json (RFC 6902), see recipe_patch.rs.
+// Patches address the fields of the API version, "/ingredients/0" is a line in v1 and an ingredient object in v2
+#[patch("/recipes/{id}")]
+pub async fn patch_recipe_by_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, body: Result<JsonBody<Value>, actix_web::Error>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
-    // Check if user is authenticated, return unauthorized response if not
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
+    let id = id.into_inner();
+
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let content_type = req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or_default().trim());
+
+    if !matches!(content_type, Some(MERGE_PATCH_CONTENT_TYPE | JSON_PATCH_CONTENT_TYPE)) {
+        return HttpResponse::UnsupportedMediaType().json(Response {
+            message: format!("Use Content-Type {} or {}", MERGE_PATCH_CONTENT_TYPE, JSON_PATCH_CONTENT_TYPE),
+        });
     }
 
-    // Authentication succeeded, extract the email from the FirebaseUser
-    let user = firebase_user.unwrap(); // Safe due to the check above
+    // Limited and reported like every other JSON body, the 415 above names the patch formats
+    let body = match body {
+        Ok(body) => body.into_inner(),
+        Err(err) => return err.error_response(),
+    };
+
+    let operations = match content_type {
+        Some(JSON_PATCH_CONTENT_TYPE) => match deserialize_value::<Vec<PatchOperation>>(body.clone(), "", false) {
+            Ok(operations) => operations,
+            Err(body_error) => return invalid_body(body_error).error_response(),
+        },
+        _ => Vec::new(),
+    };
+
+    // A JSON Patch is resolved against the recipe it is applied to (indexes, test ops), so it is built again for every version that is read
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let update = match content_type {
+            Some(JSON_PATCH_CONTENT_TYPE) => json_patch_to_update(operations.clone(), &before, version),
+            _ => merge_patch_to_update(body.clone(), version),
+        };
+        let (db, id) = (&db, id.as_str());
+
+        async move {
+            let update = match update {
+                Ok(update) => update,
+                Err(PatchError::Invalid(message)) => return Err(HttpResponse::BadRequest().json(Response { message })),
+                Err(PatchError::TestFailed(message)) => return Err(HttpResponse::Conflict().json(Response { message })),
+            };
+            // Nothing to write, like a JSON Patch of only test operations (RFC 6902 applies it without changes)
+            if update.is_empty() {
+                return Err(recipe_response(version, before));
+            }
+            Ok(db.patch_recipe_by_id(id, update, Some(before.version)).await)
+        }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::Patch, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) }),
+    }
+}
+
+#[get("/recipes/user")]
+pub async fn get_recipes_by_email(db: Data<MongoRepo>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>, dietary: Query<DietaryQuery>) -> HttpResponse {
+
+    // Check if user is authenticated, return unauthorized response if not
+    // Authentication succeeded, extract the email from the VerifiedUser
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
     let email = user.email.unwrap_or("empty email".to_string());
 
-    match db.get_recipes_by_email(email.as_str()).await {
-        Ok(recipes) => HttpResponse::Ok().json(recipes),
+    // Optional ?diet=vegan,gluten_free&free_from=nuts
+    let filter = match dietary_query_filter(&dietary) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+
+    match db.get_recipes_by_email(email.as_str(), filter).await {
+        Ok(recipes) => recipes_response(version, recipes),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
 #[delete("/recipes/{id}")]
-pub async fn delete_recipe_by_id(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
-        return unauthorized_response();
-    }
+pub async fn delete_recipe_by_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
 
     let id = id.into_inner();
 
-    match db.delete_recipe_by_id(id.as_str()).await {
-        Some(_) => HttpResponse::Ok().json(Response { message: format!("Recipe with ID: {} deleted", id)}),
-        None => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) })
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let before = db.get_recipe_by_id(id.as_str()).await;
+
+    // If-Match is checked against the version that was read, and only that version is deleted
+    if before.as_ref().is_some_and(|before| !expected_version.matches(before.version)) {
+        return precondition_failed_response();
+    }
+    let read_version = before.as_ref().filter(|_| !expected_version.is_any()).map(|before| before.version);
+
+    // Soft delete, the recipe stays restorable from /me/trash until it is purged
+    match db.delete_recipe_by_id(id.as_str(), read_version).await {
+        Some(deleted) => {
+            record_audit(&db, &req, &user, AuditAction::Delete, &id, field_diff(before.as_ref(), Some(&deleted))).await;
+            HttpResponse::Ok().json(Response { message: format!("Recipe with ID: {} moved to trash", id)})
+        }
+        None => {
+            // The recipe still existing means it changed after it was read
+            if read_version.is_some() {
+                return precondition_failed_response();
+            }
+            HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) })
+        }
     }
 }
 
 #[get("/recipes/{id}")]
-pub async fn get_recipe_by_id(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
+pub async fn get_recipe_by_id(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
         return unauthorized_response();
     }
 
     let id = id.into_inner();
 
     match db.get_recipe_by_id(id.as_str()).await {
-        Some(recipe) => HttpResponse::Ok().json(recipe),
+        // Client already has this version cached, no need to send the body again
+        Some(recipe) if if_none_match(&req, &recipe_etag(&recipe)) => HttpResponse::NotModified()
+            .insert_header((ETAG, recipe_etag(&recipe)))
+            .finish(),
+        Some(recipe) => recipe_response(version, recipe),
         None => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) })
     }
 }
 
 #[get("/recipes/{id}/imgurl")]
-pub async fn get_recipe_img_url_by_id(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<FirebaseUser, actix_web::Error>) -> HttpResponse {
-    if let Err(_) = firebase_user {
+pub async fn get_recipe_img_url_by_id(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
         return unauthorized_response();
     }
 
@@ -152,8 +332,8 @@ pub async fn get_recipe_img_url_by_id(db: Data<MongoRepo>, id: Path<String>, fir
 // This setup allows the /recipes endpoint to accept page and per_page query parameters for
 // ex ../recipes?page=1&per_page=20 -> Ger Page 1 och 20 Resultat
 #[get("/recipes")]
-pub async fn get_all_recipes_pagination(db: Data<MongoRepo>, firebase_user: Result<FirebaseUser, actix_web::Error>, params: Query<PaginationParams>) -> HttpResponse {
-    if let Err(_) = firebase_user {
+pub async fn get_all_recipes_pagination(db: Data<MongoRepo>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>, params: Query<PaginationParams>, dietary: Query<DietaryQuery>) -> HttpResponse {
+    if firebase_user.is_err() {
         return unauthorized_response();
     }
 
@@ -161,11 +341,29 @@ pub async fn get_all_recipes_pagination(db: Data<MongoRepo>, firebase_user: Resu
     let page = params.page.unwrap_or(1);
     let per_page = params.per_page.unwrap_or(5);
 
-    match db.get_all_recipes_pageable(page, per_page).await {
-        Ok(recipes) => HttpResponse::Ok().json(recipes),
+    let filter = match dietary_query_filter(&dietary) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+
+    match db.get_all_recipes_pageable(page, per_page, filter).await {
+        Ok(recipes) => recipes_response(version, recipes),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-
-
+// Registered after the /recipes/... routes of other modules (api/version.rs), get_recipes_by_email before get_recipe_by_id,
+// which would take "user" as an id
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(insert_recipe)
+        .service(get_recipes_by_email)
+        .service(delete_recipe_by_id)
+        .service(update_recipe_by_id)
+        .service(get_recipe_by_id)
+        .service(get_all_recipes_pagination)
+        .service(update_photo_url_by_recipe_id)
+        .service(get_recipe_img_url_by_id)
+        .service(update_title_by_recipe_id)
+        .service(patch_recipe_by_id);
+}
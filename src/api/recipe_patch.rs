@@ -0,0 +1,487 @@
+use mongodb::bson::{Bson, Document};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::api::version::ApiVersion;
+use crate::ingredients::format_ingredient;
+use crate::models::ingredient_model::Ingredient;
+use crate::models::recipe_model::Recipe;
+
+/*
+    Generic partial updates for PATCH /recipes/{id}, supporting
+    - application/merge-patch+json (RFC 7396): { "title": "New", "description": null }
+    - application/json-patch+json (RFC 6902): [{ "op": "add", "path": "/tags/-", "value": "vegan" }]
+
+    Both formats are validated against PATCHABLE_FIELDS and translated into ONE update document
+    ($set / $unset) so the whole patch is applied atomically by find_one_and_update. A patch that
+    changes nothing, like {} or only "test" operations, gives an empty document and isn't written.
+    Patches address the recipe of the API version: on /api/v2 ingredients are objects, "/ingredients/0"
+    is { "quantity": 2, "unit": "dl", ... }, and they are written back as lines with format_ingredient.
+ */
+
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    Text,
+    TextList,
+    Ingredients, // Lines in v1, objects in v2
+}
+
+struct PatchableField {
+    name: &'static str,
+    kind: FieldKind,
+    removable: bool, // Only fields with a serde default on Recipe may be $unset
+}
+
+// Whitelist, anything else (_id, email, created, updated, version...) can't be patched
+const PATCHABLE_FIELDS: [PatchableField; 6] = [
+    PatchableField { name: "title", kind: FieldKind::Text, removable: false },
+    PatchableField { name: "description", kind: FieldKind::Text, removable: true },
+    PatchableField { name: "photo_url", kind: FieldKind::Text, removable: true },
+    PatchableField { name: "steps", kind: FieldKind::TextList, removable: false },
+    PatchableField { name: "ingredients", kind: FieldKind::Ingredients, removable: false },
+    PatchableField { name: "tags", kind: FieldKind::TextList, removable: false },
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Test { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    Invalid(String),    // 400, the patch itself is wrong or touches a non patchable path
+    TestFailed(String), // 409, a JSON Patch "test" operation didn't match the stored recipe
+}
+
+fn patchable_field(name: &str) -> Result<&'static PatchableField, PatchError> {
+    PATCHABLE_FIELDS
+        .iter()
+        .find(|field| field.name == name)
+        .ok_or_else(|| PatchError::Invalid(format!("Path `/{}` is not patchable", name)))
+}
+
+fn text_value(field: &PatchableField, value: &Value) -> Result<Bson, PatchError> {
+    value
+        .as_str()
+        .map(|text| Bson::String(text.to_string()))
+        .ok_or_else(|| PatchError::Invalid(format!("`{}` expects a string", field.name)))
+}
+
+fn typed_value(field: &PatchableField, value: &Value, version: ApiVersion) -> Result<Bson, PatchError> {
+    match field.kind {
+        FieldKind::Text => text_value(field, value),
+        FieldKind::Ingredients if version == ApiVersion::V2 => serde_json::from_value::<Vec<Ingredient>>(value.clone())
+            .map(|ingredients| Bson::Array(ingredients.iter().map(|ingredient| Bson::String(format_ingredient(ingredient))).collect()))
+            .map_err(|_| PatchError::Invalid(format!("`{}` expects an array of ingredients", field.name))),
+        FieldKind::TextList | FieldKind::Ingredients => value
+            .as_array()
+            .and_then(|items| items
+                .iter()
+                .map(|item| item.as_str().map(|text| Bson::String(text.to_string())))
+                .collect::<Option<Vec<Bson>>>())
+            .map(Bson::Array)
+            .ok_or_else(|| PatchError::Invalid(format!("`{}` expects an array of strings", field.name))),
+    }
+}
+
+// $set and $unset of a patch, a merge patch names every field once so they can't conflict
+struct UpdateBuilder {
+    version: ApiVersion,
+    set: Document,
+    unset: Document,
+}
+
+impl UpdateBuilder {
+    fn new(version: ApiVersion) -> Self {
+        UpdateBuilder { version, set: Document::new(), unset: Document::new() }
+    }
+
+    fn set(&mut self, field: &'static PatchableField, value: &Value) -> Result<(), PatchError> {
+        self.set.insert(field.name, typed_value(field, value, self.version)?);
+        Ok(())
+    }
+
+    fn unset(&mut self, field: &'static PatchableField) -> Result<(), PatchError> {
+        if !field.removable {
+            return Err(PatchError::Invalid(format!("`{}` is required and can't be removed", field.name)));
+        }
+        self.unset.insert(field.name, "");
+        Ok(())
+    }
+
+    // Empty when the patch changes nothing, there is nothing to write then
+    fn build(self) -> Document {
+        let mut update = Document::new();
+
+        if !self.set.is_empty() {
+            update.insert("$set", self.set);
+        }
+        if !self.unset.is_empty() {
+            update.insert("$unset", self.unset);
+        }
+
+        update
+    }
+}
+
+/// RFC 7396: every member replaces the field, `null` removes it. Arrays are always replaced as a whole
+pub fn merge_patch_to_update(patch: Value, version: ApiVersion) -> Result<Document, PatchError> {
+    let Value::Object(members) = patch else {
+        return Err(PatchError::Invalid("A merge patch must be a JSON object".to_string()));
+    };
+
+    let mut builder = UpdateBuilder::new(version);
+
+    for (name, value) in members {
+        let field = patchable_field(&name)?;
+
+        if value.is_null() {
+            builder.unset(field)?;
+        } else {
+            builder.set(field, &value)?;
+        }
+    }
+
+    Ok(builder.build())
+}
+
+// A location in the patchable part of a recipe, "/tags/2" is the field tags and the element "2"
+struct Location<'a> {
+    field: &'static PatchableField,
+    index: Option<&'a str>,
+}
+
+// "/tags/2" -> ["tags", "2"], "~1" and "~0" are decoded to "/" and "~" (RFC 6901)
+fn parse_pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    let rest = path
+        .strip_prefix('/')
+        .ok_or_else(|| PatchError::Invalid(format!("`{}` is not a JSON Pointer", path)))?;
+
+    Ok(rest.split('/').map(|segment| segment.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+fn locate<'a>(path: &str, segments: &'a [String]) -> Result<Location<'a>, PatchError> {
+    match segments {
+        [name] => Ok(Location { field: patchable_field(name)?, index: None }),
+        [name, index] => {
+            let field = patchable_field(name)?;
+            if field.kind == FieldKind::Text {
+                return Err(PatchError::Invalid(format!("`{}` is not a list", field.name)));
+            }
+            Ok(Location { field, index: Some(index) })
+        }
+        _ => Err(PatchError::Invalid(format!("Path `{}` is too deep", path))),
+    }
+}
+
+// Array indexes are digits only, "01" and "+1" are not indexes (RFC 6901)
+fn list_index(location: &Location, index: &str, len: usize, allow_end: bool) -> Result<usize, PatchError> {
+    let valid = !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()) && (index == "0" || !index.starts_with('0'));
+
+    match index.parse::<usize>() {
+        Ok(position) if valid && (position < len || (allow_end && position == len)) => Ok(position),
+        _ => Err(PatchError::Invalid(format!("Index `{}` is out of bounds for `{}`", index, location.field.name))),
+    }
+}
+
+/*
+    The patchable fields of a recipe as JSON, the operations of a JSON Patch are applied to it one
+    after another like RFC 6902 says: an index refers to the list as the operations before left it.
+ */
+struct PatchTarget {
+    fields: Map<String, Value>,
+}
+
+impl PatchTarget {
+    fn list(&mut self, location: &Location) -> Result<&mut Vec<Value>, PatchError> {
+        self.fields
+            .get_mut(location.field.name)
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| PatchError::Invalid(format!("`{}` is not a list", location.field.name)))
+    }
+
+    fn get(&mut self, path: &str) -> Result<Value, PatchError> {
+        let segments = parse_pointer(path)?;
+        let location = locate(path, &segments)?;
+
+        let value = match location.index {
+            None => self.fields.get(location.field.name).cloned(),
+            Some(index) => {
+                let list = self.list(&location)?;
+                let position = list_index(&location, index, list.len(), false)?;
+                list.get(position).cloned()
+            }
+        };
+        value.ok_or_else(|| PatchError::Invalid(format!("`{}` doesn't exist", path)))
+    }
+
+    fn add(&mut self, path: &str, value: Value) -> Result<(), PatchError> {
+        let segments = parse_pointer(path)?;
+        let location = locate(path, &segments)?;
+
+        match location.index {
+            None => {
+                self.fields.insert(location.field.name.to_string(), value);
+            }
+            Some("-") => self.list(&location)?.push(value),
+            Some(index) => {
+                let list = self.list(&location)?;
+                let position = list_index(&location, index, list.len(), true)?;
+                list.insert(position, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<Value, PatchError> {
+        let segments = parse_pointer(path)?;
+        let location = locate(path, &segments)?;
+
+        match location.index {
+            None => self.fields
+                .remove(location.field.name)
+                .ok_or_else(|| PatchError::Invalid(format!("`{}` doesn't exist", path))),
+            Some(index) => {
+                let list = self.list(&location)?;
+                let position = list_index(&location, index, list.len(), false)?;
+                Ok(list.remove(position))
+            }
+        }
+    }
+
+    fn replace(&mut self, path: &str, value: Value) -> Result<(), PatchError> {
+        self.remove(path)?;
+        self.add(path, value)
+    }
+}
+
+/// RFC 6902, applied to a copy of the stored recipe in the shape of `version`. The result is written with $set and $unset,
+/// the caller applies it with the recipe version as filter so a concurrent change can't be overwritten
+pub fn json_patch_to_update(operations: Vec<PatchOperation>, current: &Recipe, version: ApiVersion) -> Result<Document, PatchError> {
+    let Value::Object(stored) = serde_json::to_value(version.recipe(current.clone())).unwrap_or_default() else {
+        return Err(PatchError::Invalid("Recipe can't be patched".to_string()));
+    };
+    let original: Map<String, Value> = stored
+        .into_iter()
+        .filter(|(name, _)| PATCHABLE_FIELDS.iter().any(|field| field.name == name))
+        .collect();
+
+    let mut target = PatchTarget { fields: original.clone() };
+
+    for operation in operations {
+        match operation {
+            PatchOperation::Add { path, value } => target.add(&path, value)?,
+            PatchOperation::Remove { path } => {
+                target.remove(&path)?;
+            }
+            PatchOperation::Replace { path, value } => target.replace(&path, value)?,
+            PatchOperation::Test { path, value } => {
+                if target.get(&path).ok().as_ref() != Some(&value) {
+                    return Err(PatchError::TestFailed(format!("Test failed for `{}`", path)));
+                }
+            }
+            PatchOperation::Move { from, path } => {
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err(PatchError::Invalid(format!("`{}` can't be moved into itself", from)));
+                }
+                let value = target.remove(&from)?;
+                target.add(&path, value)?;
+            }
+            PatchOperation::Copy { from, path } => {
+                let value = target.get(&from)?;
+                target.add(&path, value)?;
+            }
+        }
+    }
+
+    // Only what changed is written, checked against the type of the field
+    let mut builder = UpdateBuilder::new(version);
+
+    for field in &PATCHABLE_FIELDS {
+        let before = original.get(field.name);
+        let after = target.fields.get(field.name);
+
+        match after {
+            _ if before == after => {}
+            Some(value) => builder.set(field, value)?,
+            None => builder.unset(field)?,
+        }
+    }
+
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Bson};
+    use serde_json::json;
+
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::api::version::ApiVersion;
+    use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+    use super::{json_patch_to_update, merge_patch_to_update, PatchError, PatchOperation};
+
+    fn recipe() -> Recipe {
+        recipe_with(vec!["3 dl flour", "2 eggs", "3 dl flour"])
+    }
+
+    fn recipe_with(ingredients: Vec<&str>) -> Recipe {
+        map_input_dto(RecipeDTO {
+            id: None,
+            title: "Pancakes".to_string(),
+            description: "Thin ones".to_string(),
+            steps: vec!["Whisk".to_string(), "Fry".to_string()],
+            photo_url: String::new(),
+            ingredients: ingredients.into_iter().map(str::to_string).collect(),
+            email: "cook@example.com".to_string(),
+            tags: vec!["breakfast".to_string()],
+            recipe_yield: None,
+            prep_time_minutes: None,
+            cook_time_minutes: None,
+            total_time_minutes: None,
+        }, None, RecipeStatus::Created)
+    }
+
+    fn patch(operations: serde_json::Value) -> Result<mongodb::bson::Document, PatchError> {
+        let operations: Vec<PatchOperation> = serde_json::from_value(operations).unwrap();
+        json_patch_to_update(operations, &recipe(), ApiVersion::V1)
+    }
+
+    fn patch_v2(recipe: &Recipe, operations: serde_json::Value) -> Result<mongodb::bson::Document, PatchError> {
+        json_patch_to_update(serde_json::from_value(operations).unwrap(), recipe, ApiVersion::V2)
+    }
+
+    fn set_of(update: &mongodb::bson::Document, field: &str) -> Bson {
+        update.get_document("$set").unwrap().get(field).cloned().unwrap()
+    }
+
+    #[test]
+    fn remove_by_index_removes_only_that_element() {
+        let update = patch(json!([{ "op": "remove", "path": "/ingredients/2" }])).unwrap();
+        assert_eq!(set_of(&update, "ingredients"), Bson::from(vec!["3 dl flour", "2 eggs"]));
+    }
+
+    #[test]
+    fn operations_apply_in_order() {
+        // The second add sees the list as the first one left it
+        let update = patch(json!([
+            { "op": "add", "path": "/tags/0", "value": "quick" },
+            { "op": "add", "path": "/tags/2", "value": "sweet" },
+            { "op": "remove", "path": "/tags/0" },
+        ])).unwrap();
+        assert_eq!(set_of(&update, "tags"), Bson::from(vec!["breakfast", "sweet"]));
+    }
+
+    #[test]
+    fn test_sees_earlier_operations() {
+        let update = patch(json!([
+            { "op": "replace", "path": "/title", "value": "Crêpes" },
+            { "op": "test", "path": "/title", "value": "Crêpes" },
+        ])).unwrap();
+        assert_eq!(set_of(&update, "title"), Bson::from("Crêpes"));
+
+        let failed = patch(json!([{ "op": "test", "path": "/steps/1", "value": "Whisk" }]));
+        assert!(matches!(failed, Err(PatchError::TestFailed(_))));
+    }
+
+    #[test]
+    fn move_and_copy() {
+        let update = patch(json!([
+            { "op": "move", "from": "/steps/1", "path": "/steps/0" },
+            { "op": "copy", "from": "/title", "path": "/tags/-" },
+        ])).unwrap();
+        assert_eq!(set_of(&update, "steps"), Bson::from(vec!["Fry", "Whisk"]));
+        assert_eq!(set_of(&update, "tags"), Bson::from(vec!["breakfast", "Pancakes"]));
+    }
+
+    #[test]
+    fn pointer_escapes_are_decoded() {
+        // "~1" is "/", so this is the field "title/x" which isn't patchable, not /title/x
+        let Err(PatchError::Invalid(message)) = patch(json!([{ "op": "replace", "path": "/title~1x", "value": "x" }])) else {
+            panic!("title/x was patched");
+        };
+        assert_eq!(message, "Path `/title/x` is not patchable");
+    }
+
+    #[test]
+    fn removing_a_field_unsets_it() {
+        let update = patch(json!([{ "op": "remove", "path": "/description" }])).unwrap();
+        assert_eq!(update.get_document("$unset").unwrap(), &doc! {"description": ""});
+
+        assert!(matches!(patch(json!([{ "op": "remove", "path": "/title" }])), Err(PatchError::Invalid(_))));
+    }
+
+    #[test]
+    fn invalid_patches() {
+        for operations in [
+            json!([{ "op": "replace", "path": "/email", "value": "other@example.com" }]),
+            json!([{ "op": "replace", "path": "/steps/2", "value": "Serve" }]),
+            json!([{ "op": "add", "path": "/steps/01", "value": "Serve" }]),
+            json!([{ "op": "replace", "path": "/title", "value": 4 }]),
+            json!([{ "op": "copy", "from": "/tags", "path": "/title" }]),
+            json!([{ "op": "add", "path": "/steps/0/x", "value": "Serve" }]),
+        ] {
+            assert!(matches!(patch(operations.clone()), Err(PatchError::Invalid(_))), "{} was accepted", operations);
+        }
+    }
+
+    #[test]
+    fn patches_without_changes_update_nothing() {
+        // Applied without errors (RFC 6902), the handler answers with the recipe as it is
+        assert_eq!(patch(json!([{ "op": "test", "path": "/title", "value": "Pancakes" }])).unwrap(), doc! {});
+        assert_eq!(patch(json!([
+            { "op": "add", "path": "/tags/-", "value": "sweet" },
+            { "op": "remove", "path": "/tags/1" },
+        ])).unwrap(), doc! {});
+        assert_eq!(merge_patch_to_update(json!({}), ApiVersion::V1).unwrap(), doc! {});
+    }
+
+    #[test]
+    fn merge_patch_sets_and_unsets() {
+        let update = merge_patch_to_update(json!({ "title": "Crêpes", "photo_url": null }), ApiVersion::V1).unwrap();
+        assert_eq!(update, doc! {"$set": {"title": "Crêpes"}, "$unset": {"photo_url": ""}});
+
+        assert!(matches!(merge_patch_to_update(json!({ "version": 3 }), ApiVersion::V1), Err(PatchError::Invalid(_))));
+        assert!(matches!(merge_patch_to_update(json!({ "title": null }), ApiVersion::V1), Err(PatchError::Invalid(_))));
+    }
+
+    #[test]
+    fn v2_patches_address_ingredient_objects() {
+        let recipe = recipe_with(vec!["2-3 ägg", "1/3 dl mjölk", "50 g smör (mjukt)"]);
+
+        // The lines that weren't touched are written back as they were
+        let update = patch_v2(&recipe, json!([
+            { "op": "replace", "path": "/ingredients/0", "value": { "quantity": 3, "unit": null, "name": "ägg", "note": null } },
+        ])).unwrap();
+        assert_eq!(set_of(&update, "ingredients"), Bson::from(vec!["3 ägg", "1/3 dl mjölk", "50 g smör (mjukt)"]));
+
+        let update = patch_v2(&recipe, json!([{ "op": "remove", "path": "/ingredients/0" }])).unwrap();
+        assert_eq!(set_of(&update, "ingredients"), Bson::from(vec!["1/3 dl mjölk", "50 g smör (mjukt)"]));
+
+        let update = patch_v2(&recipe, json!([{ "op": "add", "path": "/tags/-", "value": "fika" }])).unwrap();
+        assert!(update.get_document("$set").unwrap().get("ingredients").is_none());
+
+        // A line is an ingredient in v1 only
+        let lines = patch_v2(&recipe, json!([{ "op": "add", "path": "/ingredients/-", "value": "1 tsk salt" }]));
+        assert!(matches!(lines, Err(PatchError::Invalid(_))));
+    }
+
+    #[test]
+    fn v2_merge_patch_takes_ingredient_objects() {
+        let update = merge_patch_to_update(json!({ "ingredients": [{ "quantity": 2, "unit": "dl", "name": "milk", "note": "cold" }] }), ApiVersion::V2).unwrap();
+        assert_eq!(update, doc! {"$set": {"ingredients": ["2 dl milk, cold"]}});
+
+        assert!(matches!(merge_patch_to_update(json!({ "ingredients": ["2 dl milk"] }), ApiVersion::V2), Err(PatchError::Invalid(_))));
+        assert!(merge_patch_to_update(json!({ "ingredients": ["2 dl milk"] }), ApiVersion::V1).is_ok());
+    }
+}
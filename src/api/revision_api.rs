@@ -0,0 +1,276 @@
+use std::future::Future;
+
+use actix_web::{get, HttpRequest, HttpResponse, post};
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+use firebase_auth::FirebaseUser;
+
+use crate::api::audit_api::record_audit;
+use crate::api::util::{field_diff, if_match_version, IfMatch, precondition_failed_response, recipe_response, Response, unauthorized_response};
+use crate::api::version::ApiVersion;
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::AuditAction;
+use crate::models::recipe_model::Recipe;
+use crate::models::revision_model::{RecipeRevision, RevisionDiffParams, RevisionResponse};
+use crate::repository::mongo_repo::MongoRepo;
+
+// Attempts of an update without If-Match before it gives up on a recipe that keeps changing under it
+const UPDATE_ATTEMPTS: usize = 5;
+
+pub enum UpdateError {
+    NotFound,
+    PreconditionFailed, // The version of If-Match isn't the stored one
+    Conflict, // Changed by someone else on every attempt
+    Rejected(HttpResponse), // Returned instead of writing, like a JSON Patch that doesn't apply to the recipe that was read or changes nothing
+}
+
+/// Reads the recipe and has `write` update it filtered on the version that was read, so the recipe returned as
+/// `before` is exactly the document the update replaced and the revision history has no gaps. With If-Match
+/// (`expected_version`) a version it doesn't list is a precondition failure, without it a write that lost the race to a
+/// concurrent one reads the new version and is tried again
+pub async fn update_read_version<R, RF, W, WF>(expected_version: IfMatch, read: R, write: W) -> Result<(Recipe, Recipe), UpdateError>
+    where
+        R: Fn() -> RF,
+        RF: Future<Output = Option<Recipe>>,
+        W: Fn(Recipe) -> WF,
+        WF: Future<Output = Result<Option<Recipe>, HttpResponse>>,
+{
+    for _ in 0..UPDATE_ATTEMPTS {
+        let before = read().await.ok_or(UpdateError::NotFound)?;
+
+        if !expected_version.matches(before.version) {
+            return Err(UpdateError::PreconditionFailed);
+        }
+
+        match write(before.clone()).await.map_err(UpdateError::Rejected)? {
+            Some(after) => return Ok((before, after)),
+            None if !expected_version.is_any() => return Err(UpdateError::PreconditionFailed),
+            None => continue, // Gone or replaced since it was read, the next read tells
+        }
+    }
+
+    Err(UpdateError::Conflict)
+}
+
+pub fn conflict_response() -> HttpResponse {
+    HttpResponse::Conflict().json(Response { message: "Recipe kept changing while it was updated, fetch it again and retry".to_string() })
+}
+
+/// Called after every successful update: snapshots the version that was replaced into
+/// RecipeRevisions and writes the audit entry with the diff between the two versions
+pub async fn track_update(db: &MongoRepo, req: &HttpRequest, user: &FirebaseUser, action: AuditAction, id: &str, before: Recipe, after: &Recipe) {
+    let diff = field_diff(Some(&before), Some(after));
+
+    if let Some(recipe_id) = before.id {
+        if let Err(err) = db.insert_revision(recipe_id, before, &user.user_id).await {
+            log::error!("Failed to store revision for recipe {}: {}", id, err);
+        }
+    }
+
+    record_audit(db, req, user, action, id, diff).await;
+}
+
+fn revision_response(version: ApiVersion, revision: RecipeRevision) -> RevisionResponse {
+    RevisionResponse {
+        id: revision.id,
+        recipe_id: revision.recipe_id,
+        revision: revision.revision,
+        author_uid: revision.author_uid,
+        created: revision.created,
+        snapshot: version.recipe(revision.snapshot),
+    }
+}
+
+#[get("/recipes/{id}/revisions")]
+pub async fn get_recipe_revisions(db: Data<MongoRepo>, id: Path<String>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    let id = id.into_inner();
+
+    // The history of a recipe in the trash goes with it, until it is restored
+    if db.get_recipe_by_id(id.as_str()).await.is_none() {
+        return HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) });
+    }
+
+    match db.get_revisions_by_recipe_id(id.as_str()).await {
+        Ok(revisions) => HttpResponse::Ok().json(revisions.into_iter().map(|revision| revision_response(version, revision)).collect::<Vec<RevisionResponse>>()),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// ex ../recipes/{id}/revisions/diff?from=2&to=5 -> { field: { before, after } } for every changed field of the version's shape
+#[get("/recipes/{id}/revisions/diff")]
+pub async fn get_recipe_revision_diff(db: Data<MongoRepo>, id: Path<String>, params: Query<RevisionDiffParams>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    let id = id.into_inner();
+
+    let Some(current) = db.get_recipe_by_id(id.as_str()).await else {
+        return HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) });
+    };
+
+    let from = match db.get_revision(id.as_str(), params.from).await {
+        Some(revision) => revision.snapshot,
+        None => return HttpResponse::BadRequest().json(Response { message: format!("No revision {} for recipe with ID: {}", params.from, id) }),
+    };
+
+    // Without `to` we compare against the current version of the recipe
+    let to = match params.to {
+        Some(rev) => db.get_revision(id.as_str(), rev).await.map(|revision| revision.snapshot),
+        None => Some(current),
+    };
+
+    match to {
+        Some(to) => HttpResponse::Ok().json(field_diff(Some(&version.recipe(from)), Some(&version.recipe(to)))),
+        None => HttpResponse::BadRequest().json(Response { message: format!("No revision {} for recipe with ID: {}", params.to.unwrap_or_default(), id) }),
+    }
+}
+
+// Restoring never rewrites history, the current version is snapshotted as a new revision before it is replaced
+#[post("/recipes/{id}/revisions/{rev}/restore")]
+pub async fn restore_recipe_revision(req: HttpRequest, db: Data<MongoRepo>, path: Path<(String, u32)>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, rev) = path.into_inner();
+
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(response) => return response,
+    };
+
+    let revision = match db.get_revision(id.as_str(), rev).await {
+        Some(revision) => revision,
+        None => return HttpResponse::BadRequest().json(Response { message: format!("No revision {} for recipe with ID: {}", rev, id) }),
+    };
+
+    let update = update_read_version(expected_version, || db.get_recipe_by_id(id.as_str()), |before| {
+        let (db, id, snapshot) = (&db, id.as_str(), revision.snapshot.clone());
+        async move { Ok(db.restore_recipe_from_snapshot(id, snapshot, Some(before.version)).await) }
+    }).await;
+
+    match update {
+        Ok((before, recipe)) => {
+            track_update(&db, &req, &user, AuditAction::RevisionRestore, &id, before, &recipe).await;
+            recipe_response(version, recipe)
+        }
+        Err(UpdateError::PreconditionFailed) => precondition_failed_response(),
+        Err(UpdateError::Conflict) => conflict_response(),
+        Err(UpdateError::Rejected(response)) => response,
+        Err(UpdateError::NotFound) => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} found", id) }),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_recipe_revisions)
+        .service(get_recipe_revision_diff)
+        .service(restore_recipe_revision);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crate::api::util::{IfMatch, map_input_dto, RecipeStatus};
+    use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+    use super::{update_read_version, UpdateError};
+
+    // A stored recipe and the revisions taken from it, what the update of MongoRepo does with a version filter
+    struct Store {
+        recipe: RefCell<Recipe>,
+        revisions: RefCell<Vec<Recipe>>,
+    }
+
+    impl Store {
+        fn new() -> Self {
+            let recipe = map_input_dto(RecipeDTO {
+                id: None,
+                title: "Pancakes".to_string(),
+                description: String::new(),
+                steps: vec![],
+                photo_url: String::new(),
+                ingredients: vec![],
+                email: "cook@example.com".to_string(),
+                tags: vec![],
+                recipe_yield: None,
+                prep_time_minutes: None,
+                cook_time_minutes: None,
+                total_time_minutes: None,
+            }, None, RecipeStatus::Created);
+            Store { recipe: RefCell::new(recipe), revisions: RefCell::new(Vec::new()) }
+        }
+
+        async fn read(&self) -> Option<Recipe> {
+            Some(self.recipe.borrow().clone())
+        }
+
+        async fn write(&self, title: &str, version: u32) -> Option<Recipe> {
+            let mut recipe = self.recipe.borrow_mut();
+            if recipe.version != version {
+                return None;
+            }
+            recipe.title = title.to_string();
+            recipe.version += 1;
+            Some(recipe.clone())
+        }
+
+        async fn update(&self, title: &str, expected_version: IfMatch) -> Result<Recipe, UpdateError> {
+            let (before, after) = update_read_version(expected_version, || self.read(), |before| async move { Ok(self.write(title, before.version).await) }).await?;
+            self.revisions.borrow_mut().push(before);
+            Ok(after)
+        }
+    }
+
+    #[actix_web::test]
+    async fn interleaved_updates_keep_every_version() {
+        let store = Store::new();
+        let interleaved = Cell::new(false);
+
+        // Crêpes reads version 1, then Waffles reads and replaces version 1 before Crêpes writes
+        let (before, after) = update_read_version(IfMatch::Any, || store.read(), |before| {
+            let store = &store;
+            let first = !interleaved.replace(true);
+            async move {
+                if first {
+                    store.update("Waffles", IfMatch::Any).await.ok().unwrap();
+                }
+                Ok(store.write("Crêpes", before.version).await)
+            }
+        }).await.ok().unwrap();
+        store.revisions.borrow_mut().push(before);
+
+        assert_eq!(after.title, "Crêpes");
+        assert_eq!(after.version, 3);
+        let revisions: Vec<(String, u32)> = store.revisions.borrow().iter().map(|recipe| (recipe.title.clone(), recipe.version)).collect();
+        assert_eq!(revisions, vec![("Pancakes".to_string(), 1), ("Waffles".to_string(), 2)]);
+    }
+
+    #[actix_web::test]
+    async fn if_match_is_not_retried() {
+        let store = Store::new();
+        store.update("Waffles", IfMatch::Any).await.ok().unwrap();
+
+        assert!(matches!(store.update("Crêpes", Some(1).into()).await, Err(UpdateError::PreconditionFailed)));
+        assert!(matches!(store.update("Crêpes", IfMatch::Versions(vec![])).await, Err(UpdateError::PreconditionFailed)));
+        assert_eq!(store.update("Crêpes", Some(2).into()).await.ok().unwrap().version, 3);
+        assert_eq!(store.update("Blinis", IfMatch::Versions(vec![1, 3])).await.ok().unwrap().version, 4);
+        assert_eq!(store.revisions.borrow().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn gives_up_on_a_recipe_that_keeps_changing() {
+        let store = Store::new();
+        let result = update_read_version(IfMatch::Any, || store.read(), |_| async { Ok(None) }).await;
+        assert!(matches!(result, Err(UpdateError::Conflict)));
+
+        let missing = update_read_version(IfMatch::Any, || async { None }, |_| async { Ok(None) }).await;
+        assert!(matches!(missing, Err(UpdateError::NotFound)));
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use actix_web::{delete, get, HttpResponse, patch, post};
+use actix_web::web::{Data, Path, ServiceConfig};
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+
+use crate::api::json::JsonBody;
+use crate::api::util::{Response, unauthorized_response};
+use crate::auth::VerifiedUser;
+use crate::ingredients::aisles::aisle_for;
+use crate::ingredients::merge::IngredientMerger;
+use crate::ingredients::{parse_ingredient, yield_servings};
+use crate::models::recipe_model::Recipe;
+use crate::models::shopping_list_model::{CheckItemRequest, ManualItemRequest, ShareRequest, ShoppingItem, ShoppingList, ShoppingListRequest, ShoppingListView};
+use crate::repository::mongo_repo::MongoRepo;
+
+fn no_list_response() -> HttpResponse {
+    HttpResponse::BadRequest().json(Response { message: "No shopping list with that ID".to_string() })
+}
+
+fn list_response(list: Option<ShoppingList>) -> HttpResponse {
+    match list {
+        Some(list) => HttpResponse::Ok().json(ShoppingListView::from(list)),
+        None => HttpResponse::BadRequest().json(Response { message: "No ID Match".to_string() }),
+    }
+}
+
+// (recipe id, planned servings) for every recipe the list is made from, a recipe planned twice is in it twice
+async fn planned_recipes(db: &MongoRepo, owner_uid: &str, request: &ShoppingListRequest) -> Result<Vec<(ObjectId, Option<u32>)>, HttpResponse> {
+    if !request.recipe_ids.is_empty() {
+        return request.recipe_ids
+            .iter()
+            .map(|id| ObjectId::parse_str(id)
+                .map(|id| (id, None))
+                .map_err(|_| HttpResponse::BadRequest().json(Response { message: format!("Invalid recipe ID {}", id) })))
+            .collect();
+    }
+
+    let (Some(from), Some(to)) = (request.from, request.to) else {
+        return Err(HttpResponse::BadRequest().json(Response { message: "Send recipe_ids or a from and to date of the meal plan".to_string() }));
+    };
+
+    let mut filter = doc! {"owner_uid": owner_uid, "date": {"$gte": from.to_string(), "$lte": to.to_string()}};
+
+    if let Some(plan_id) = &request.plan_id {
+        match ObjectId::parse_str(plan_id) {
+            Ok(plan_id) => { filter.insert("plan_id", plan_id); }
+            Err(_) => return Err(HttpResponse::BadRequest().json(Response { message: "No meal plan with that ID".to_string() })),
+        }
+    }
+
+    match db.resolve_meal_plan_entries(filter).await {
+        Ok(entries) => Ok(entries
+            .into_iter()
+            .filter(|entry| entry.recipe.is_some()) // Recipes deleted after they were planned
+            .map(|entry| (entry.recipe_id, entry.servings))
+            .collect()),
+        Err(err) => Err(HttpResponse::InternalServerError().body(err.to_string())),
+    }
+}
+
+// Generates a list from { "recipe_ids": [..] } or the meal plan, { "from": "2024-03-04", "to": "2024-03-10", "plan_id": .. }
+#[post("/shopping-lists")]
+pub async fn create_shopping_list(db: Data<MongoRepo>, request: JsonBody<ShoppingListRequest>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let request = request.into_inner();
+    let planned = match planned_recipes(&db, &user.user_id, &request).await {
+        Ok(planned) => planned,
+        Err(response) => return response,
+    };
+
+    let mut ids: Vec<ObjectId> = planned.iter().map(|(id, _)| *id).collect();
+    ids.sort();
+    ids.dedup();
+
+    let recipes: HashMap<ObjectId, Recipe> = match db.get_recipes_by_ids(&ids).await {
+        Ok(recipes) => recipes.into_iter().filter_map(|recipe| recipe.id.map(|id| (id, recipe))).collect(),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    if let Some(missing) = ids.iter().find(|id| !recipes.contains_key(id)) {
+        return HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID {}", missing) });
+    }
+
+    // A list from recipe_ids is made of the caller's own recipes, the meal plan can have anyone's (see meal_plan_api.rs)
+    if !request.recipe_ids.is_empty() {
+        let email = user.email.as_deref().unwrap_or_default();
+        if let Some(other) = recipes.values().find(|recipe| !recipe.email.eq_ignore_ascii_case(email)) {
+            return HttpResponse::Forbidden().json(Response { message: format!("Recipe {} belongs to another user", other.id.map(|id| id.to_hex()).unwrap_or_default()) });
+        }
+    }
+
+    let mut merger = IngredientMerger::new();
+
+    for (recipe_id, servings) in &planned {
+        let recipe = &recipes[recipe_id];
+        // Scaled to the planned servings when both they and the servings of the recipe are known
+        let factor = match (servings, yield_servings(recipe.recipe_yield.as_deref())) {
+            (Some(servings), Some(recipe_servings)) => *servings as f64 / recipe_servings,
+            _ => 1.0,
+        };
+
+        for line in &recipe.ingredients {
+            merger.add(line, factor, &recipe.title);
+        }
+    }
+
+    let items = merger.finish().into_iter().map(|ingredient| ShoppingItem {
+        id: ObjectId::new(),
+        name: ingredient.name,
+        quantity: ingredient.quantity,
+        unit: ingredient.unit,
+        aisle: ingredient.aisle,
+        checked: false,
+        manual: false,
+        recipes: ingredient.recipes,
+    }).collect();
+
+    let default_name = match (request.from, request.to) {
+        (Some(from), Some(to)) if request.recipe_ids.is_empty() => format!("Shopping list {} - {}", from, to),
+        _ => "Shopping list".to_string(),
+    };
+
+    let now = mongodb::bson::DateTime::now();
+    let list = ShoppingList {
+        id: Some(ObjectId::new()),
+        owner_uid: user.user_id,
+        name: request.name.unwrap_or(default_name),
+        shared_with: Vec::new(),
+        items,
+        created: now,
+        updated: now,
+    };
+
+    match db.insert_shopping_list(&list).await {
+        Ok(_) => HttpResponse::Created().json(ShoppingListView::from(list)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Own lists and lists shared with the caller
+#[get("/shopping-lists")]
+pub async fn get_shopping_lists(db: Data<MongoRepo>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.get_shopping_lists(&user.user_id, user.email.as_deref()).await {
+        Ok(lists) => HttpResponse::Ok().json(lists.into_iter().map(ShoppingListView::from).collect::<Vec<ShoppingListView>>()),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[get("/shopping-lists/{id}")]
+pub async fn get_shopping_list(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.get_shopping_list(&id.into_inner(), &user.user_id, user.email.as_deref()).await {
+        Some(list) => HttpResponse::Ok().json(ShoppingListView::from(list)),
+        None => no_list_response(),
+    }
+}
+
+#[delete("/shopping-lists/{id}")]
+pub async fn delete_shopping_list(db: Data<MongoRepo>, id: Path<String>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    match db.delete_shopping_list(&id.into_inner(), &user.user_id).await {
+        Ok(true) => HttpResponse::Ok().json(Response { message: "Shopping list deleted".to_string() }),
+        Ok(false) => no_list_response(),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// { "text": "2 l mjölk" }
+#[post("/shopping-lists/{id}/items")]
+pub async fn add_shopping_item(db: Data<MongoRepo>, id: Path<String>, item: JsonBody<ManualItemRequest>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    if item.text.trim().is_empty() {
+        return HttpResponse::BadRequest().json(Response { message: "text can't be empty".to_string() });
+    }
+
+    let ingredient = parse_ingredient(&item.text);
+    let item = ShoppingItem {
+        id: ObjectId::new(),
+        aisle: aisle_for(&ingredient.name),
+        name: match ingredient.note {
+            Some(note) => format!("{} ({})", ingredient.name, note),
+            None => ingredient.name,
+        },
+        quantity: ingredient.quantity,
+        unit: ingredient.unit,
+        checked: false,
+        manual: true,
+        recipes: Vec::new(),
+    };
+
+    list_response(db.add_shopping_item(&id.into_inner(), &user.user_id, user.email.as_deref(), &item).await)
+}
+
+// Check off (or un-check) an item, { "checked": true }
+#[patch("/shopping-lists/{id}/items/{item_id}")]
+pub async fn check_shopping_item(db: Data<MongoRepo>, path: Path<(String, String)>, check: JsonBody<CheckItemRequest>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, item_id) = path.into_inner();
+
+    list_response(db.set_shopping_item_checked(&id, &user.user_id, user.email.as_deref(), &item_id, check.checked).await)
+}
+
+#[delete("/shopping-lists/{id}/items/{item_id}")]
+pub async fn remove_shopping_item(db: Data<MongoRepo>, path: Path<(String, String)>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, item_id) = path.into_inner();
+
+    list_response(db.remove_shopping_item(&id, &user.user_id, user.email.as_deref(), &item_id).await)
+}
+
+// Share with a household member by the email they sign in with, { "email": "..." }
+#[post("/shopping-lists/{id}/share")]
+pub async fn share_shopping_list(db: Data<MongoRepo>, id: Path<String>, share: JsonBody<ShareRequest>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let email = share.email.trim().to_lowercase();
+
+    if !email.contains('@') {
+        return HttpResponse::BadRequest().json(Response { message: "Invalid email".to_string() });
+    }
+
+    list_response(db.share_shopping_list(&id.into_inner(), &user.user_id, &email).await)
+}
+
+#[delete("/shopping-lists/{id}/share/{email}")]
+pub async fn unshare_shopping_list(db: Data<MongoRepo>, path: Path<(String, String)>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let (id, email) = path.into_inner();
+
+    list_response(db.unshare_shopping_list(&id, &user.user_id, &email.trim().to_lowercase()).await)
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(create_shopping_list)
+        .service(get_shopping_lists)
+        .service(get_shopping_list)
+        .service(delete_shopping_list)
+        .service(add_shopping_item)
+        .service(check_shopping_item)
+        .service(remove_shopping_item)
+        .service(share_shopping_list)
+        .service(unshare_shopping_list);
+}
@@ -0,0 +1,140 @@
+use actix_web::{get, HttpResponse, post};
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+use mongodb::bson::oid::ObjectId;
+
+use crate::api::json::JsonBody;
+use crate::api::util::{is_admin, Response, unauthorized_response};
+use crate::api::version::ApiVersion;
+use crate::auth::VerifiedUser;
+use crate::ingredients::allergens::dietary;
+use crate::ingredients::substitutions::{Avoid, parse_avoid, substitute};
+use crate::ingredients::units::canonical_unit;
+use crate::models::substitution_model::{SubstituteComponent, SubstitutionQuery, SubstitutionResponse, SubstitutionRule, SubstitutionRuleDTO};
+use crate::repository::mongo_repo::MongoRepo;
+use crate::settings::Settings;
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(Response { message })
+}
+
+// The recipe as it would be with the swaps, nothing is saved.
+// ../recipes/{id}/substitutions?ingredient=buttermilk or ?avoid=dairy,eggs, or both
+#[get("/recipes/{id}/substitutions")]
+pub async fn get_recipe_substitutions(db: Data<MongoRepo>, id: Path<String>, query: Query<SubstitutionQuery>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    let id = id.into_inner();
+    let query = query.into_inner();
+    let ingredient = query.ingredient.as_deref().map(str::trim).filter(|ingredient| !ingredient.is_empty());
+
+    let mut avoid: Vec<Avoid> = Vec::new();
+    for name in query.avoid.as_deref().unwrap_or_default().split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match parse_avoid(name) {
+            Some(value) => avoid.push(value),
+            None => return bad_request(format!("Unknown avoid {}, expected an allergen, dairy, meat or animal_products", name)),
+        }
+    }
+
+    if ingredient.is_none() && avoid.is_empty() {
+        return bad_request("Send ingredient, avoid or both".to_string());
+    }
+
+    let mut recipe = match db.get_recipe_by_id(id.as_str()).await {
+        Some(recipe) => recipe,
+        None => return bad_request(format!("No recipe with ID: {} found", id)),
+    };
+
+    let rules = match db.get_substitution_rules().await {
+        Ok(rules) => rules,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    let substitution = substitute(&recipe.ingredients, &rules, ingredient, &avoid);
+
+    recipe.dietary = Some(dietary(&substitution.ingredients, None));
+    recipe.ingredients = substitution.ingredients;
+
+    HttpResponse::Ok().json(SubstitutionResponse {
+        recipe: version.recipe(recipe),
+        swaps: substitution.swaps,
+        unresolved: substitution.unresolved,
+    })
+}
+
+#[get("/substitutions")]
+pub async fn get_substitution_rules(db: Data<MongoRepo>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    if firebase_user.is_err() {
+        return unauthorized_response();
+    }
+
+    match db.get_substitution_rules().await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Admin only, { "ingredient": "buttermilk", "replacement": [{ "name": "milk", "ratio": 0.94 }, { "name": "lemon juice", "ratio": 0.06 }], "notes": "..." }
+#[post("/substitutions")]
+pub async fn add_substitution_rule(db: Data<MongoRepo>, settings: Data<Settings>, rule: JsonBody<SubstitutionRuleDTO>, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    if !is_admin(&settings, &user) {
+        return HttpResponse::Forbidden().json(Response { message: "Admin privileges required".to_string() });
+    }
+
+    let rule = rule.into_inner();
+
+    if rule.ingredient.trim().is_empty() {
+        return bad_request("ingredient can't be empty".to_string());
+    }
+    if rule.replacement.is_empty() {
+        return bad_request("replacement needs at least one ingredient".to_string());
+    }
+
+    let mut replacement: Vec<SubstituteComponent> = Vec::new();
+    for component in rule.replacement {
+        if component.name.trim().is_empty() {
+            return bad_request("replacement names can't be empty".to_string());
+        }
+        if !component.ratio.is_finite() || component.ratio <= 0.0 {
+            return bad_request(format!("The ratio of {} has to be a positive number", component.name.trim()));
+        }
+
+        let unit = match component.unit.as_deref().map(str::trim).filter(|unit| !unit.is_empty()) {
+            Some(unit) => match canonical_unit(unit) {
+                Some(unit) => Some(unit.to_string()),
+                None => return bad_request(format!("Unknown unit {}", unit)),
+            },
+            None => None,
+        };
+
+        replacement.push(SubstituteComponent { name: component.name.trim().to_string(), ratio: component.ratio, unit });
+    }
+
+    let rule = SubstitutionRule {
+        id: Some(ObjectId::new()),
+        ingredient: rule.ingredient.trim().to_string(),
+        replacement,
+        notes: rule.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty()),
+        curated: false,
+        created_by: Some(user.user_id),
+        created: mongodb::bson::DateTime::now(),
+    };
+
+    match db.insert_substitution_rule(&rule).await {
+        Ok(_) => HttpResponse::Created().json(rule),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_recipe_substitutions)
+        .service(get_substitution_rules)
+        .service(add_substitution_rule);
+}
@@ -0,0 +1,55 @@
+use actix_web::{get, HttpRequest, HttpResponse, post};
+use actix_web::web::{Data, Path, ServiceConfig};
+
+use crate::api::audit_api::record_audit;
+use crate::api::util::{field_diff, recipe_response, recipes_response, Response, unauthorized_response};
+use crate::api::version::ApiVersion;
+use crate::auth::VerifiedUser;
+use crate::models::audit_model::AuditAction;
+use crate::repository::mongo_repo::MongoRepo;
+
+// Recipes the caller deleted that haven't been purged yet (TRASH_RETENTION_DAYS)
+#[get("/me/trash")]
+pub async fn get_trash(db: Data<MongoRepo>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+    let email = user.email.unwrap_or("empty email".to_string());
+
+    match db.get_trash_by_email(email.as_str()).await {
+        Ok(recipes) => recipes_response(version, recipes),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[post("/recipes/{id}/restore")]
+pub async fn restore_recipe(req: HttpRequest, db: Data<MongoRepo>, id: Path<String>, version: ApiVersion, firebase_user: Result<VerifiedUser, actix_web::Error>) -> HttpResponse {
+    let user = match firebase_user {
+        Ok(VerifiedUser(user)) => user,
+        Err(_) => return unauthorized_response(),
+    };
+
+    let id = id.into_inner();
+    let email = user.email.clone().unwrap_or("empty email".to_string());
+
+    let before = db.get_trashed_recipe_by_id(id.as_str()).await;
+
+    if before.as_ref().is_some_and(|recipe| recipe.email != email) {
+        return HttpResponse::Forbidden().json(Response { message: "Only the owner of the recipe can restore it".to_string() });
+    }
+
+    match db.restore_recipe_from_trash(id.as_str(), email.as_str()).await {
+        Some(recipe) => {
+            record_audit(&db, &req, &user, AuditAction::Restore, &id, field_diff(before.as_ref(), Some(&recipe))).await;
+            recipe_response(version, recipe)
+        }
+        None => HttpResponse::BadRequest().json(Response { message: format!("No recipe with ID: {} in the trash", id) }),
+    }
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg
+        .service(get_trash)
+        .service(restore_recipe);
+}
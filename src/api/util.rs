@@ -1,7 +1,16 @@
-use actix_web::HttpResponse;
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::web::{BytesMut, Payload};
+use futures::StreamExt;
+use actix_web::http::header::{HeaderName, ETAG, IF_MATCH, IF_NONE_MATCH};
+use firebase_auth::FirebaseUser;
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use crate::api::version::ApiVersion;
+use crate::metrics::METRICS;
 use crate::models::recipe_model::{Recipe, RecipeDTO};
+use crate::models::recipe_v2_model::VersionedRecipe;
+use crate::settings::Settings;
 
 #[derive(Serialize, Deserialize)]
 pub struct Response {
@@ -19,11 +28,184 @@ pub struct Response {
 /// Utility function to create a uniform unauthorized response
 pub fn unauthorized_response() -> HttpResponse {
     log::warn!("Unauthorized access attempt detected. Responding with 'Missing or invalid JWT Token'.");
+    METRICS.auth_failure();
     HttpResponse::Forbidden().json(Response {
         message: "Missing or invalid JWT Token".to_string(),
     })
 }
 
+/// Admins are listed by Firebase uid in admin.uids (ADMIN_UIDS)
+pub fn is_admin(settings: &Settings, user: &FirebaseUser) -> bool {
+    settings.is_admin_uid(&user.user_id)
+}
+
+/// Builds a { field: { before, after } } document for every top level field that differs,
+/// None on one side means the document was created (before) or deleted (after)
+pub fn field_diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Document {
+    let to_doc = |value: Option<&T>| value
+        .and_then(|v| mongodb::bson::to_document(v).ok())
+        .unwrap_or_default();
+
+    // Dietary labels are derived from the other fields, nobody changes them
+    let derived = ["dietary"];
+    let before = to_doc(before).into_iter().filter(|(key, _)| !derived.contains(&key.as_str())).collect::<Document>();
+    let after = to_doc(after).into_iter().filter(|(key, _)| !derived.contains(&key.as_str())).collect::<Document>();
+
+    let mut diff = Document::new();
+
+    for key in before.keys().chain(after.keys().filter(|key| !before.contains_key(key.as_str()))) {
+        let old_value = before.get(key).cloned().unwrap_or(Bson::Null);
+        let new_value = after.get(key).cloned().unwrap_or(Bson::Null);
+
+        if old_value != new_value {
+            diff.insert(key, doc! { "before": old_value, "after": new_value });
+        }
+    }
+
+    diff
+}
+
+/// Strong ETag for a recipe, derived from its version counter
+pub fn recipe_etag(recipe: &Recipe) -> String {
+    format!("\"{}\"", recipe.version)
+}
+
+/// 200 with the recipe as body and its ETag header, used by GET and by every mutation returning the recipe.
+/// The body has the shape of the API version, the ETag is the same in every version
+pub fn recipe_response(version: ApiVersion, recipe: Recipe) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response.insert_header((ETAG, recipe_etag(&recipe)));
+
+    response.json(version.recipe(recipe))
+}
+
+/// 200 with a list of recipes in the shape of the API version
+pub fn recipes_response(version: ApiVersion, recipes: Vec<Recipe>) -> HttpResponse {
+    HttpResponse::Ok().json(recipes.into_iter().map(|recipe| version.recipe(recipe)).collect::<Vec<VersionedRecipe>>())
+}
+
+pub fn precondition_failed_response() -> HttpResponse {
+    HttpResponse::PreconditionFailed().json(Response {
+        message: "Recipe was modified by someone else, fetch it again and retry".to_string(),
+    })
+}
+
+/// The versions a mutation accepts, from If-Match or the `version` of a bulk operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum IfMatch {
+    Any,                // No If-Match, or `*`
+    Versions(Vec<u32>), // Every version listed, empty when none of the ETags can match, which fails the precondition
+}
+
+impl IfMatch {
+    pub fn is_any(&self) -> bool {
+        *self == IfMatch::Any
+    }
+
+    pub fn matches(&self, version: u32) -> bool {
+        match self {
+            IfMatch::Any => true,
+            IfMatch::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+impl From<Option<u32>> for IfMatch {
+    fn from(version: Option<u32>) -> Self {
+        version.map_or(IfMatch::Any, |version| IfMatch::Versions(vec![version]))
+    }
+}
+
+// The entity tags of a list like `"3", W/"4"` as (weak, opaque tag without quotes), None when it isn't such a list.
+// Commas are allowed inside the quotes, so the list is read tag by tag instead of split
+fn entity_tags(value: &str) -> Option<Vec<(bool, &str)>> {
+    let mut tags = Vec::new();
+    let mut rest = value.trim_start_matches([' ', '\t', ',']);
+
+    while !rest.is_empty() {
+        let (weak, quoted) = match rest.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, rest),
+        };
+        let (tag, after) = quoted.strip_prefix('"')?.split_once('"')?;
+        tags.push((weak, tag));
+
+        // A tag is followed by the end of the list or a comma
+        let after = after.trim_start_matches([' ', '\t']);
+        if !after.is_empty() && !after.starts_with(',') {
+            return None;
+        }
+        rest = after.trim_start_matches([' ', '\t', ',']);
+    }
+
+    Some(tags)
+}
+
+// Every line of a header that may be sent more than once, as one list
+fn header_list(req: &HttpRequest, name: HeaderName) -> Option<String> {
+    let mut values = req.headers().get_all(name).peekable();
+    values.peek()?;
+
+    Some(values.map(|value| value.to_str().unwrap_or_default()).collect::<Vec<&str>>().join(","))
+}
+
+/// Reads the versions a mutation accepts from If-Match: `*`, or a list of our ETags like `"3", "4"`.
+/// The comparison is strong (RFC 9110), so weak ETags and tags that aren't versions never match and the mutation
+/// is answered with 412. A header that isn't an ETag list is answered with 400
+pub fn if_match_version(req: &HttpRequest) -> Result<IfMatch, HttpResponse> {
+    let Some(value) = header_list(req, IF_MATCH) else {
+        return Ok(IfMatch::Any);
+    };
+
+    if value.trim() == "*" {
+        return Ok(IfMatch::Any);
+    }
+
+    match entity_tags(&value) {
+        Some(tags) if !tags.is_empty() => Ok(IfMatch::Versions(tags
+            .into_iter()
+            .filter(|(weak, _)| !weak)
+            .filter_map(|(_, tag)| tag.parse().ok())
+            .collect())),
+        _ => Err(HttpResponse::BadRequest().json(Response {
+            message: "If-Match has to be * or a list of ETags like \"3\"".to_string(),
+        })),
+    }
+}
+
+/// True when If-None-Match contains `etag` (or `*`), the caller then answers 304 Not Modified.
+/// The comparison is weak (RFC 9110), W/"3" matches "3". A header that isn't an ETag list is ignored
+pub fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    let Some(value) = header_list(req, IF_NONE_MATCH) else {
+        return false;
+    };
+
+    value.trim() == "*" || entity_tags(&value)
+        .unwrap_or_default()
+        .into_iter()
+        .any(|(_, tag)| etag.trim_start_matches("W/").trim_matches('"') == tag)
+}
+
+/// Reads a raw request body of at most `limit` bytes, used by JsonBody<T> (api/json.rs) and by
+/// endpoints that take other content (HTML pages, archives) and need a bigger limit
+pub async fn read_body(mut payload: Payload, limit: usize) -> Result<BytesMut, HttpResponse> {
+    let mut body = BytesMut::new();
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|err| HttpResponse::BadRequest().json(Response { message: err.to_string() }))?;
+
+        if body.len() + chunk.len() > limit {
+            return Err(HttpResponse::PayloadTooLarge().json(Response {
+                message: format!("Request body is larger than {} bytes", limit),
+            }));
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
 #[derive(Deserialize)]
 pub struct PaginationParams {
     pub page: Option<u32>,
@@ -54,8 +236,16 @@ pub fn map_input_dto(input_recipe_dto: RecipeDTO, id: Option<ObjectId>, status:
                 ingredients: input_recipe_dto.ingredients,
                 email: input_recipe_dto.email,
                 tags: input_recipe_dto.tags,
+                recipe_yield: input_recipe_dto.recipe_yield,
+                prep_time_minutes: input_recipe_dto.prep_time_minutes,
+                cook_time_minutes: input_recipe_dto.cook_time_minutes,
+                total_time_minutes: input_recipe_dto.total_time_minutes,
                 created: Some(bson_date),
-                updated: bson_date
+                updated: bson_date,
+                version: 1,
+                deleted_at: None,
+                dietary: None, // Classified by the repository
+                dietary_override: None
             }
         }
         RecipeStatus::Updated => {
@@ -68,9 +258,82 @@ pub fn map_input_dto(input_recipe_dto: RecipeDTO, id: Option<ObjectId>, status:
                 ingredients: input_recipe_dto.ingredients,
                 email: input_recipe_dto.email,
                 tags: input_recipe_dto.tags,
+                recipe_yield: input_recipe_dto.recipe_yield,
+                prep_time_minutes: input_recipe_dto.prep_time_minutes,
+                cook_time_minutes: input_recipe_dto.cook_time_minutes,
+                total_time_minutes: input_recipe_dto.total_time_minutes,
                 created: None,
-                updated: bson_date
+                updated: bson_date,
+                version: 0, // Not written on update, the repository increments the stored version
+                deleted_at: None,
+                dietary: None, // Classified by the repository
+                dietary_override: None
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{IF_MATCH, IF_NONE_MATCH};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::{if_match_version, if_none_match, IfMatch};
+
+    fn if_match(values: &[&str]) -> Result<IfMatch, StatusCode> {
+        let req = values.iter().fold(TestRequest::default(), |req, value| req.append_header((IF_MATCH, *value)));
+        if_match_version(&req.to_http_request()).map_err(|response| response.status())
+    }
+
+    fn not_modified(values: &[&str], etag: &str) -> bool {
+        let req = values.iter().fold(TestRequest::default(), |req, value| req.append_header((IF_NONE_MATCH, *value)));
+        if_none_match(&req.to_http_request(), etag)
+    }
+
+    #[test]
+    fn if_match_reads_the_listed_versions() {
+        assert_eq!(if_match(&[]), Ok(IfMatch::Any));
+        assert_eq!(if_match(&[" * "]), Ok(IfMatch::Any));
+        assert_eq!(if_match(&["\"3\""]), Ok(IfMatch::Versions(vec![3])));
+        assert_eq!(if_match(&["\"3\", \"4\""]), Ok(IfMatch::Versions(vec![3, 4])));
+        assert_eq!(if_match(&["\"3\",\"4\" ,, \"5\""]), Ok(IfMatch::Versions(vec![3, 4, 5])));
+        assert_eq!(if_match(&["\"3\"", "\"4\""]), Ok(IfMatch::Versions(vec![3, 4]))); // One header line each
+
+        let versions = if_match(&["\"3\", \"4\""]).unwrap();
+        assert!(versions.matches(4) && !versions.matches(5) && !versions.is_any());
+        assert!(IfMatch::Any.matches(5));
+    }
+
+    #[test]
+    fn if_match_never_matches_weak_or_foreign_etags() {
+        // Valid headers, the mutation fails its precondition with 412
+        assert_eq!(if_match(&["W/\"3\""]), Ok(IfMatch::Versions(vec![])));
+        assert_eq!(if_match(&["W/\"3\", \"4\""]), Ok(IfMatch::Versions(vec![4])));
+        assert_eq!(if_match(&["\"abc\", \"a,b\""]), Ok(IfMatch::Versions(vec![])));
+        assert_eq!(if_match(&["\"-1\""]), Ok(IfMatch::Versions(vec![])));
+        assert!(!IfMatch::Versions(vec![]).matches(0));
+    }
+
+    #[test]
+    fn malformed_if_match_is_a_bad_request() {
+        for value in ["3", "\"3", "\"3\" \"4\"", "\"3\"x", "*, \"3\"", "W/3", ",", ""] {
+            assert_eq!(if_match(&[value]), Err(StatusCode::BAD_REQUEST), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        assert!(not_modified(&["\"3\""], "\"3\""));
+        assert!(not_modified(&["W/\"3\""], "\"3\""));
+        assert!(not_modified(&["\"2\", W/\"3\""], "\"3\""));
+        assert!(not_modified(&["\"2\"", "\"3\""], "\"3\""));
+        assert!(not_modified(&["*"], "\"3\""));
+
+        assert!(!not_modified(&[], "\"3\""));
+        assert!(!not_modified(&["\"2\""], "\"3\""));
+        assert!(!not_modified(&["\"33\""], "\"3\""));
+        assert!(!not_modified(&["3"], "\"3\"")); // Malformed, ignored
+        assert!(!not_modified(&["\"2\" \"3\""], "\"3\""));
+    }
+}
@@ -0,0 +1,397 @@
+use std::future::{ready, Ready};
+
+use actix_web::{FromRequest, HttpRequest, Scope};
+use actix_web::dev::{HttpServiceFactory, Payload, Service};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, LINK};
+use actix_web::web::{Data, ServiceConfig};
+use chrono::NaiveTime;
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::api::{audit_api, bulk_api, dietary_api, export_api, import_api, meal_plan_api, nutrition_api, pantry_api, recipe_api, revision_api, shopping_list_api, substitution_api, trash_api};
+use crate::api::json::{BodyError, deserialize_value, invalid_body, JsonBody, StrictJson};
+use crate::models::bulk_model::BulkRequest;
+use crate::models::recipe_model::{Recipe, RecipeDTO};
+use crate::models::recipe_v2_model::{RecipeV2, RecipeV2DTO, VersionedRecipe};
+use crate::settings::{ApiSettings, BulkSettings, DeprecationSettings, Settings};
+
+/*
+    API versions. Every route is served under /api/v1 and /api/v2, and with api.legacy_routes also
+    without a prefix, the paths from before versioning, which serve v1.
+    v1 has the recipe as it is stored: ingredients as lines and the owner as `email`. v2 has structured
+    ingredients and an `author` object (models/recipe_v2_model.rs), handlers that take or return a
+    recipe get the ApiVersion extractor and convert at the boundary. Other bodies are the same in both.
+    A version listed in api.deprecations gets the Deprecation (RFC 9745) and Sunset (RFC 8594) headers
+    on every response, and a Link to the same path in the version that replaces it.
+ */
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+            ApiVersion::V2 => "/api/v2",
+        }
+    }
+
+    /// The recipe in the shape of this version
+    pub fn recipe(&self, recipe: Recipe) -> VersionedRecipe {
+        match self {
+            ApiVersion::V1 => VersionedRecipe::V1(recipe),
+            ApiVersion::V2 => VersionedRecipe::V2(RecipeV2::from(recipe)),
+        }
+    }
+}
+
+/// The version of the scope that matched, legacy routes are v1
+impl FromRequest for ApiVersion {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(req.app_data::<ApiVersion>().copied().unwrap_or(ApiVersion::V1)))
+    }
+}
+
+/// "/api/v2/recipes/{id}" -> "/recipes/{id}", for what is configured by route once for all versions
+pub fn unversioned(path: &str) -> &str {
+    [ApiVersion::V1, ApiVersion::V2]
+        .iter()
+        .find_map(|version| path.strip_prefix(version.prefix()).filter(|rest| rest.starts_with('/')))
+        .unwrap_or(path)
+}
+
+/// A recipe body in the shape of the request's version, as the RecipeDTO that is stored
+pub struct RecipeBody(pub RecipeDTO);
+
+impl RecipeBody {
+    pub fn into_inner(self) -> RecipeDTO {
+        self.0
+    }
+}
+
+impl FromRequest for RecipeBody {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        match req.app_data::<ApiVersion>().copied().unwrap_or(ApiVersion::V1) {
+            ApiVersion::V1 => {
+                let body = StrictJson::<RecipeDTO>::from_request(req, payload);
+                Box::pin(async move { Ok(RecipeBody(body.await?.into_inner())) })
+            }
+            ApiVersion::V2 => {
+                let body = StrictJson::<RecipeV2DTO>::from_request(req, payload);
+                Box::pin(async move { Ok(RecipeBody(body.await?.into_inner().into())) })
+            }
+        }
+    }
+}
+
+/// A bulk request with the recipes in the shape of the request's version and at most bulk.max_operations operations.
+/// With json.strict the recipes are read as values first and then strictly, like the body of POST /recipes
+pub struct BulkBody(pub BulkRequest);
+
+impl BulkBody {
+    pub fn into_inner(self) -> BulkRequest {
+        self.0
+    }
+}
+
+fn bulk_recipe<T: DeserializeOwned + Into<RecipeDTO>>(index: usize, recipe: Value) -> Result<RecipeDTO, actix_web::Error> {
+    deserialize_value::<T>(recipe, &format!("operations[{}].recipe", index), true)
+        .map(Into::into)
+        .map_err(invalid_body)
+}
+
+impl FromRequest for BulkBody {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let version = req.app_data::<ApiVersion>().copied().unwrap_or(ApiVersion::V1);
+        let settings = req.app_data::<Data<Settings>>().map(|settings| (settings.json.strict, settings.bulk.max_operations));
+        let (strict, max_operations) = settings.unwrap_or_else(|| (false, BulkSettings::default().max_operations));
+
+        let body: LocalBoxFuture<'static, Result<BulkRequest, actix_web::Error>> = match (version, strict) {
+            (ApiVersion::V1, false) => {
+                let body = JsonBody::<BulkRequest>::from_request(req, payload);
+                Box::pin(async move { Ok(body.await?.into_inner()) })
+            }
+            (ApiVersion::V2, false) => {
+                let body = JsonBody::<BulkRequest<RecipeV2DTO>>::from_request(req, payload);
+                Box::pin(async move { Ok(body.await?.into_inner().into_stored()) })
+            }
+            (ApiVersion::V1, true) => {
+                let body = JsonBody::<BulkRequest<Value>>::from_request(req, payload);
+                Box::pin(async move { body.await?.into_inner().try_map(bulk_recipe::<RecipeDTO>) })
+            }
+            (ApiVersion::V2, true) => {
+                let body = JsonBody::<BulkRequest<Value>>::from_request(req, payload);
+                Box::pin(async move { body.await?.into_inner().try_map(bulk_recipe::<RecipeV2DTO>) })
+            }
+        };
+
+        Box::pin(async move {
+            let bulk = body.await?;
+
+            if bulk.operations.is_empty() || bulk.operations.len() > max_operations {
+                return Err(invalid_body(BodyError {
+                    message: format!("A bulk request must contain between 1 and {} operations", max_operations),
+                    field: Some("operations".to_string()),
+                    line: None,
+                    column: None,
+                }));
+            }
+            Ok(BulkBody(bulk))
+        })
+    }
+}
+
+// Header values of a deprecated version, built once
+#[derive(Clone)]
+struct Deprecation {
+    since: HeaderValue,
+    sunset: Option<HeaderValue>,
+    successor: ApiVersion,
+}
+
+impl Deprecation {
+    fn new(settings: &DeprecationSettings, successor: ApiVersion) -> Self {
+        // "@1792281600", a structured field date, and "Sun, 18 Apr 2027 00:00:00 GMT"
+        let since = settings.since.and_time(NaiveTime::MIN).and_utc().timestamp();
+        let sunset = settings.sunset.map(|date| date.and_time(NaiveTime::MIN).and_utc().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+
+        Deprecation {
+            since: HeaderValue::from_str(&format!("@{}", since)).unwrap_or_else(|_| HeaderValue::from_static("?1")),
+            sunset: sunset.and_then(|sunset| HeaderValue::from_str(&sunset).ok()),
+            successor,
+        }
+    }
+
+    fn insert_headers(&self, headers: &mut HeaderMap, path: &str) {
+        headers.insert(DEPRECATION, self.since.clone());
+        if let Some(sunset) = &self.sunset {
+            headers.insert(SUNSET, sunset.clone());
+        }
+        let link = format!("<{}{}>; rel=\"successor-version\"", self.successor.prefix(), unversioned(path));
+        if let Ok(link) = HeaderValue::from_str(&link) {
+            headers.append(LINK, link);
+        }
+    }
+}
+
+// Routes like /recipes/bulk go before recipe_api's /recipes/{id}. The handler is found by method as well,
+// but the route pattern middleware sees (rate limit group, body limit) is the first one matching the path
+fn routes(cfg: &mut ServiceConfig) {
+    bulk_api::configure(cfg);
+    import_api::configure(cfg);
+    pantry_api::configure(cfg);
+    recipe_api::configure(cfg);
+    export_api::configure(cfg);
+    audit_api::configure(cfg);
+    revision_api::configure(cfg);
+    trash_api::configure(cfg);
+    meal_plan_api::configure(cfg);
+    shopping_list_api::configure(cfg);
+    nutrition_api::configure(cfg);
+    dietary_api::configure(cfg);
+    substitution_api::configure(cfg);
+}
+
+fn versioned(prefix: &str, version: ApiVersion, deprecation: Option<Deprecation>, routes: fn(&mut ServiceConfig)) -> impl HttpServiceFactory {
+    Scope::new(prefix)
+        .app_data(version)
+        .configure(routes)
+        .wrap_fn(move |req, srv| {
+            let path = req.path().to_string();
+            let response = srv.call(req);
+            let deprecation = deprecation.clone();
+
+            async move {
+                let mut response = response.await?;
+                if let Some(deprecation) = deprecation {
+                    deprecation.insert_headers(response.headers_mut(), &path);
+                }
+                Ok(response)
+            }
+        })
+}
+
+/// Every versioned route, has to come after the unversioned ones (health checks, metrics) as the legacy scope matches any path
+pub fn configure(cfg: &mut ServiceConfig, settings: &ApiSettings) {
+    mount(cfg, settings, routes);
+}
+
+fn mount(cfg: &mut ServiceConfig, settings: &ApiSettings, routes: fn(&mut ServiceConfig)) {
+    let deprecation = |name: &str, successor: ApiVersion| settings.deprecations.get(name).map(|deprecation| Deprecation::new(deprecation, successor));
+
+    cfg.service(versioned(ApiVersion::V1.prefix(), ApiVersion::V1, deprecation("v1", ApiVersion::V2), routes));
+    cfg.service(versioned(ApiVersion::V2.prefix(), ApiVersion::V2, None, routes));
+    if settings.legacy_routes {
+        cfg.service(versioned("", ApiVersion::V1, deprecation("legacy", ApiVersion::V1), routes));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use actix_web::{App, HttpResponse, web};
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::LINK;
+    use actix_web::test::{call_service, init_service, read_body, read_body_json, TestRequest};
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::models::bulk_model::BulkOperation;
+
+    use super::*;
+
+    // Answers with the version the extractor saw
+    fn echo(cfg: &mut ServiceConfig) {
+        cfg.route("/recipes/{id}", web::get().to(|version: ApiVersion| async move { HttpResponse::Ok().body(format!("{:?}", version)) }));
+    }
+
+    fn settings(legacy_routes: bool, deprecations: &[(&str, DeprecationSettings)]) -> ApiSettings {
+        ApiSettings {
+            legacy_routes,
+            deprecations: deprecations.iter().map(|(name, deprecation)| (name.to_string(), *deprecation)).collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn deprecated(sunset: Option<NaiveDate>) -> DeprecationSettings {
+        DeprecationSettings { since: NaiveDate::from_ymd_opt(2026, 10, 18).unwrap(), sunset }
+    }
+
+    async fn bulk(version: ApiVersion, strict: bool, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let mut settings = Settings::default();
+        settings.json.strict = strict;
+        settings.bulk.max_operations = 2;
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(settings))
+                .app_data(version)
+                .route("/recipes/bulk", web::post().to(|bulk: BulkBody| async move {
+                    let recipes: Vec<(String, Vec<String>)> = bulk.into_inner().operations.into_iter().filter_map(|operation| match operation {
+                        BulkOperation::Create { recipe } | BulkOperation::Update { recipe, .. } => Some((recipe.email, recipe.ingredients)),
+                        BulkOperation::Delete { .. } => None,
+                    }).collect();
+                    HttpResponse::Ok().json(recipes)
+                })),
+        )
+        .await;
+
+        let response = call_service(&app, TestRequest::post().uri("/recipes/bulk").set_json(body).to_request()).await;
+        (response.status(), read_body_json(response).await)
+    }
+
+    fn create(recipe: serde_json::Value) -> serde_json::Value {
+        json!({ "op": "create", "recipe": recipe })
+    }
+
+    fn recipe_v1() -> serde_json::Value {
+        json!({ "title": "Pancakes", "description": "", "steps": [], "photo_url": "", "ingredients": ["3 dl flour"], "email": "cook@example.com", "tags": [] })
+    }
+
+    #[actix_web::test]
+    async fn bulk_bodies_are_limited_to_max_operations() {
+        let (status, body) = bulk(ApiVersion::V1, false, json!({ "operations": [] })).await;
+        assert_eq!((status, body["field"].as_str()), (StatusCode::BAD_REQUEST, Some("operations")));
+        assert_eq!(body["message"], "A bulk request must contain between 1 and 2 operations");
+
+        let three = json!({ "operations": [create(recipe_v1()), create(recipe_v1()), { "op": "delete", "id": "a" }] });
+        assert_eq!(bulk(ApiVersion::V1, false, three).await.0, StatusCode::BAD_REQUEST);
+
+        let two = json!({ "operations": [create(recipe_v1()), { "op": "delete", "id": "a" }] });
+        assert_eq!(bulk(ApiVersion::V1, false, two).await, (StatusCode::OK, json!([["cook@example.com", ["3 dl flour"]]])));
+    }
+
+    #[actix_web::test]
+    async fn bulk_recipes_are_strict_like_single_ones() {
+        let mut unknown = recipe_v1();
+        unknown["servings"] = json!(4);
+        let body = json!({ "operations": [{ "op": "delete", "id": "a" }, { "op": "update", "id": "b", "recipe": unknown }] });
+
+        let (status, error) = bulk(ApiVersion::V1, true, body.clone()).await;
+        assert_eq!((status, error["field"].as_str()), (StatusCode::BAD_REQUEST, Some("operations[1].recipe.servings")));
+        assert_eq!(bulk(ApiVersion::V1, false, body).await.0, StatusCode::OK);
+
+        // v2 recipes are read strictly as v2 and stored as v1
+        let mut recipe_v2 = json!({ "title": "Pancakes", "description": "", "steps": [], "photo_url": "", "tags": [],
+            "ingredients": [{ "quantity": 3, "unit": "dl", "name": "flour", "note": null }], "author": { "email": "cook@example.com" } });
+        let (status, recipes) = bulk(ApiVersion::V2, true, json!({ "operations": [create(recipe_v2.clone())] })).await;
+        assert_eq!((status, recipes), (StatusCode::OK, json!([["cook@example.com", ["3 dl flour"]]])));
+
+        recipe_v2["email"] = json!("cook@example.com");
+        let (status, error) = bulk(ApiVersion::V2, true, json!({ "operations": [create(recipe_v2)] })).await;
+        assert_eq!((status, error["field"].as_str()), (StatusCode::BAD_REQUEST, Some("operations[0].recipe.email")));
+    }
+
+    #[test]
+    fn strips_the_version_prefix() {
+        assert_eq!(unversioned("/api/v1/recipes"), "/recipes");
+        assert_eq!(unversioned("/api/v2/recipes/abc"), "/recipes/abc");
+        assert_eq!(unversioned("/recipes"), "/recipes");
+        assert_eq!(unversioned("/api/v2recipes"), "/api/v2recipes");
+        assert_eq!(unversioned("/api/v3/recipes"), "/api/v3/recipes");
+    }
+
+    #[actix_web::test]
+    async fn version_defaults_to_v1() {
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(ApiVersion::extract(&req).await.unwrap(), ApiVersion::V1);
+
+        let req = TestRequest::default().app_data(ApiVersion::V2).to_http_request();
+        assert_eq!(ApiVersion::extract(&req).await.unwrap(), ApiVersion::V2);
+    }
+
+    #[actix_web::test]
+    async fn scopes_set_their_version() {
+        let app = init_service(App::new().configure(|cfg| mount(cfg, &settings(true, &[]), echo))).await;
+
+        for (path, version) in [("/api/v1/recipes/1", "V1"), ("/api/v2/recipes/1", "V2"), ("/recipes/1", "V1")] {
+            let response = call_service(&app, TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+            assert!(response.headers().get(DEPRECATION).is_none());
+            assert_eq!(read_body(response).await, version.as_bytes(), "{}", path);
+        }
+
+        let app = init_service(App::new().configure(|cfg| mount(cfg, &settings(false, &[]), echo))).await;
+        let response = call_service(&app, TestRequest::get().uri("/recipes/1").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = call_service(&app, TestRequest::get().uri("/api/v1/recipes/1").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn deprecated_versions_link_their_successor() {
+        let sunset = NaiveDate::from_ymd_opt(2027, 4, 18);
+        let settings = settings(true, &[("legacy", deprecated(sunset)), ("v1", deprecated(None))]);
+        let app = init_service(App::new().configure(|cfg| mount(cfg, &settings, echo))).await;
+
+        let response = call_service(&app, TestRequest::get().uri("/recipes/1").to_request()).await;
+        assert_eq!(response.headers().get(DEPRECATION).unwrap(), "@1792281600");
+        assert_eq!(response.headers().get(SUNSET).unwrap(), "Sun, 18 Apr 2027 00:00:00 GMT");
+        assert_eq!(response.headers().get(LINK).unwrap(), "</api/v1/recipes/1>; rel=\"successor-version\"");
+
+        let response = call_service(&app, TestRequest::get().uri("/api/v1/recipes/1").to_request()).await;
+        assert_eq!(response.headers().get(DEPRECATION).unwrap(), "@1792281600");
+        assert!(response.headers().get(SUNSET).is_none());
+        assert_eq!(response.headers().get(LINK).unwrap(), "</api/v2/recipes/1>; rel=\"successor-version\"");
+
+        let response = call_service(&app, TestRequest::get().uri("/api/v2/recipes/1").to_request()).await;
+        assert!(response.headers().get(DEPRECATION).is_none());
+        assert!(response.headers().get(LINK).is_none());
+    }
+}
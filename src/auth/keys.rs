@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use actix_web::HttpMessage;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::AUTHORIZATION;
+use firebase_auth::FirebaseUser;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::auth::VerifiedUser;
+
+/*
+    The public keys Firebase ID tokens are signed with. Google rotates them, so we keep track of when
+    they were fetched and how long Google says they are valid (the max-age of the key endpoint), and
+    fetch them again before that runs out, see jobs::auth_keys.
+    The tokens are verified the same way as firebase-auth does it (RS256, key by kid, audience and issuer
+    of the project), but with the keys fetched here, FirebaseAuth can only be created by fetching them itself.
+    The current keys verify the token of each request in the middleware in main, see attach.
+ */
+
+const JWK_URL: &str = "https://www.googleapis.com/service_accounts/v1/jwk/securetoken@system.gserviceaccount.com";
+const FALLBACK_MAX_AGE: Duration = Duration::from_secs(60); // Same as firebase-auth when there is no max-age
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct Jwk {
+    kid: String,
+    n: String,
+    e: String,
+}
+
+#[derive(Deserialize)]
+struct KeyResponse {
+    keys: Vec<Jwk>,
+}
+
+struct LoadedKeys {
+    keys: HashMap<String, DecodingKey>, // By kid
+    fetched: Instant,
+    max_age: Duration,
+}
+
+pub struct KeyStatus {
+    pub age: Duration,
+    pub max_age: Duration,
+    pub count: usize,
+}
+
+impl KeyStatus {
+    pub fn is_fresh(&self) -> bool {
+        self.count > 0 && self.age < self.max_age
+    }
+}
+
+pub struct AuthKeys {
+    project_id: String,
+    current: RwLock<LoadedKeys>,
+}
+
+fn max_age(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .filter_map(|directive| directive.trim().split_once('='))
+        .find(|(key, _)| key.trim_end().eq_ignore_ascii_case("max-age"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+// Keys that aren't valid RSA keys are left out, tokens signed with them are rejected as signed with an unknown key
+fn decoding_keys(jwks: Vec<Jwk>) -> HashMap<String, DecodingKey> {
+    jwks.into_iter()
+        .filter_map(|jwk| {
+            DecodingKey::from_rsa_components(&jwk.n, &jwk.e)
+                .map_err(|err| log::warn!("Skipping Firebase public key {}: {}", jwk.kid, err))
+                .ok()
+                .map(|key| (jwk.kid, key))
+        })
+        .collect()
+}
+
+async fn load() -> Result<LoadedKeys, String> {
+    let fetched = Instant::now();
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let response = client
+        .get(JWK_URL)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("Unable to fetch the Firebase public keys: {}", err))?;
+
+    let max_age = response
+        .headers()
+        .get(reqwest::header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(max_age)
+        .unwrap_or(FALLBACK_MAX_AGE);
+
+    let keys = response
+        .json::<KeyResponse>()
+        .await
+        .map_err(|err| format!("Unable to read the Firebase public keys: {}", err))?;
+
+    Ok(LoadedKeys { keys: decoding_keys(keys.keys), fetched, max_age })
+}
+
+fn verify(keys: &HashMap<String, DecodingKey>, project_id: &str, token: &str) -> Result<FirebaseUser, String> {
+    let header = decode_header(token).map_err(|err| err.to_string())?;
+    if header.alg != Algorithm::RS256 {
+        return Err(format!("Token signed with {:?}, not RS256", header.alg));
+    }
+
+    let kid = header.kid.ok_or("Token without a key id")?;
+    let key = keys.get(&kid).ok_or_else(|| format!("Token signed with an unknown key {}", kid))?;
+
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_audience(&[project_id]);
+    validation.set_issuer(&[format!("https://securetoken.google.com/{}", project_id)]);
+
+    decode::<FirebaseUser>(token, key, &validation)
+        .map(|data| data.claims)
+        .map_err(|err| err.to_string())
+}
+
+impl AuthKeys {
+    pub async fn load(project_id: &str) -> Result<Self, String> {
+        let keys = load().await?;
+        Ok(AuthKeys { project_id: project_id.to_string(), current: RwLock::new(keys) })
+    }
+
+    /// Fetches the keys again, the old ones stay in use if that fails
+    pub async fn refresh(&self) -> Result<(), String> {
+        let keys = load().await?;
+        *self.current.write().unwrap() = keys;
+        Ok(())
+    }
+
+    pub fn status(&self) -> KeyStatus {
+        let current = self.current.read().unwrap();
+        KeyStatus { age: current.fetched.elapsed(), max_age: current.max_age, count: current.keys.len() }
+    }
+
+    /// Verifies the bearer token with the current keys, once per request, in an auth span so the logs show the outcome.
+    /// A valid token puts the VerifiedUser in the request extensions, where the handlers' extractor and the
+    /// rate limiter find it, and the user id on the request span
+    pub fn attach(&self, req: &mut ServiceRequest) {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+
+        if let Some(token) = token {
+            let request_span = tracing::Span::current();
+            let _auth = tracing::debug_span!("auth").entered();
+
+            let verified = verify(&self.current.read().unwrap().keys, &self.project_id, token);
+            match verified {
+                Ok(user) => {
+                    request_span.record("user_id", user.user_id.as_str());
+                    tracing::debug!("Token verified");
+                    req.extensions_mut().insert(VerifiedUser(user));
+                }
+                Err(err) => tracing::debug!("Token rejected: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::{max_age, verify, KeyStatus};
+
+    #[test]
+    fn max_age_of_the_cache_control() {
+        assert_eq!(max_age("public, max-age=19845, must-revalidate, no-transform"), Some(Duration::from_secs(19845)));
+        assert_eq!(max_age("Max-Age = 60"), Some(Duration::from_secs(60)));
+        assert_eq!(max_age("s-maxage=10,max-age=20"), Some(Duration::from_secs(20)));
+        assert_eq!(max_age("public, must-revalidate"), None);
+        assert_eq!(max_age("max-age=soon"), None);
+        assert_eq!(max_age("max-age=-1"), None);
+        assert_eq!(max_age(""), None);
+    }
+
+    #[test]
+    fn keys_are_fresh_until_the_max_age() {
+        let status = |age, count| KeyStatus { age: Duration::from_secs(age), max_age: Duration::from_secs(3600), count };
+
+        assert!(status(0, 2).is_fresh());
+        assert!(status(3599, 2).is_fresh());
+        assert!(!status(3600, 2).is_fresh());
+        assert!(!status(0, 0).is_fresh()); // Nothing to verify tokens with
+    }
+
+    #[test]
+    fn tokens_need_a_known_rs256_key() {
+        let keys = HashMap::new();
+        let rejected = |token: &str| verify(&keys, "project", token).err().unwrap_or_default();
+
+        assert!(verify(&keys, "project", "not a token").is_err());
+
+        let hs256 = encode(&Header { kid: Some("rotated".to_string()), ..Header::default() }, &serde_json::json!({}), &EncodingKey::from_secret(b"secret")).unwrap();
+        assert!(rejected(&hs256).contains("not RS256"));
+
+        // {"alg":"RS256","kid":"rotated","typ":"JWT"} and {"alg":"RS256","typ":"JWT"}, the signature isn't checked before the key is found
+        assert_eq!(rejected("eyJhbGciOiJSUzI1NiIsImtpZCI6InJvdGF0ZWQiLCJ0eXAiOiJKV1QifQ.e30.c2ln"), "Token signed with an unknown key rotated");
+        assert_eq!(rejected("eyJhbGciOiJSUzI1NiIsInR5cCI6IkpXVCJ9.e30.c2ln"), "Token without a key id");
+    }
+}
@@ -0,0 +1,56 @@
+pub mod keys;
+
+use std::future::{ready, Ready};
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use actix_web::dev::Payload;
+use actix_web::error::ErrorUnauthorized;
+use firebase_auth::FirebaseUser;
+
+/// The Firebase user of a request with a valid token. The token is verified once, by AuthKeys::attach
+/// (see keys.rs), and the extractor takes the user from the request extensions. The FirebaseUser extractor
+/// of firebase-auth would verify it again
+#[derive(Clone)]
+pub struct VerifiedUser(pub FirebaseUser);
+
+impl FromRequest for VerifiedUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(req
+            .extensions()
+            .get::<VerifiedUser>()
+            .cloned()
+            .ok_or_else(|| ErrorUnauthorized("Missing or invalid token")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn user(user_id: &str) -> FirebaseUser {
+        serde_json::from_value(serde_json::json!({
+            "iss": "https://securetoken.google.com/project", "aud": "project", "sub": user_id,
+            "iat": 0, "exp": 0, "auth_time": 0, "user_id": user_id,
+            "firebase": { "sign_in_provider": "password", "identities": {} },
+        }))
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn verified_user_comes_from_the_extensions() {
+        let req = TestRequest::default().to_http_request();
+        let err = VerifiedUser::extract(&req).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(VerifiedUser(user("uid-1")));
+        let VerifiedUser(user) = VerifiedUser::extract(&req).await.unwrap();
+        assert_eq!(user.user_id, "uid-1");
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::{Seek, Write};
+
+use serde::Serialize;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::api::version::ApiVersion;
+use crate::exporters::markdown::to_markdown;
+use crate::exporters::schema_org::to_json_ld;
+use crate::exporters::slugify;
+use crate::models::recipe_model::Recipe;
+use crate::models::recipe_v2_model::VersionedRecipe;
+
+#[derive(Serialize)]
+struct Photo<'a> {
+    folder: String,
+    url: &'a str,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    exported_at: String,
+    email: &'a str,
+    recipe_count: usize,
+    // Photos are links, the server doesn't fetch URLs given by users, they are in recipe.json as well
+    photos: Vec<Photo<'a>>,
+}
+
+fn folder(recipe: &Recipe) -> String {
+    let id = recipe.id.map(|id| id.to_hex()).unwrap_or_default();
+    format!("recipes/{}-{}", slugify(&recipe.title), id)
+}
+
+/// Data portability export of a user: recipes.json with everything plus a folder per recipe
+/// with the raw JSON, schema.org JSON-LD and Markdown. The JSON has the recipe shape of `version`.
+/// Written to `writer`, a temporary file for the export endpoint, so the archive doesn't have to fit in memory
+pub fn build_archive<W: Write + Seek>(writer: W, email: &str, recipes: &[Recipe], version: ApiVersion) -> zip::result::ZipResult<W> {
+    let mut zip = ZipWriter::new(writer);
+    let text = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let manifest = Manifest {
+        exported_at: mongodb::bson::DateTime::now().try_to_rfc3339_string().unwrap_or_default(),
+        email,
+        recipe_count: recipes.len(),
+        photos: recipes
+            .iter()
+            .filter(|recipe| !recipe.photo_url.is_empty())
+            .map(|recipe| Photo { folder: folder(recipe), url: &recipe.photo_url })
+            .collect(),
+    };
+
+    zip.start_file("export.json", text)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest).unwrap_or_default())?;
+
+    let shaped: Vec<VersionedRecipe> = recipes.iter().map(|recipe| version.recipe(recipe.clone())).collect();
+    zip.start_file("recipes.json", text)?;
+    zip.write_all(&serde_json::to_vec_pretty(&shaped).unwrap_or_default())?;
+
+    for (recipe, shaped) in recipes.iter().zip(&shaped) {
+        let folder = folder(recipe);
+
+        zip.start_file(format!("{}/recipe.json", folder), text)?;
+        zip.write_all(&serde_json::to_vec_pretty(shaped).unwrap_or_default())?;
+
+        zip.start_file(format!("{}/recipe.jsonld", folder), text)?;
+        zip.write_all(&serde_json::to_vec_pretty(&to_json_ld(recipe)).unwrap_or_default())?;
+
+        zip.start_file(format!("{}/recipe.md", folder), text)?;
+        zip.write_all(to_markdown(recipe).as_bytes())?;
+    }
+
+    zip.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::Value;
+    use zip::ZipArchive;
+
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::api::version::ApiVersion;
+    use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+    use super::build_archive;
+
+    fn recipe(title: &str, photo_url: &str) -> Recipe {
+        map_input_dto(RecipeDTO {
+            id: None,
+            title: title.to_string(),
+            description: String::new(),
+            steps: vec!["Cook".to_string()],
+            photo_url: photo_url.to_string(),
+            ingredients: vec!["1 egg".to_string()],
+            email: "cook@example.com".to_string(),
+            tags: Vec::new(),
+            recipe_yield: None,
+            prep_time_minutes: None,
+            cook_time_minutes: None,
+            total_time_minutes: None,
+        }, Some(ObjectId::new()), RecipeStatus::Created)
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn archive_has_a_folder_per_recipe_and_lists_photos() {
+        let recipes = vec![recipe("Soup", "https://example.com/soup.jpg"), recipe("Bread", "")];
+        let bytes = build_archive(Cursor::new(Vec::new()), "cook@example.com", &recipes, ApiVersion::V1).unwrap().into_inner();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let soup = format!("recipes/soup-{}", recipes[0].id.unwrap().to_hex());
+        let bread = format!("recipes/bread-{}", recipes[1].id.unwrap().to_hex());
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        let mut expected = vec!["export.json".to_string(), "recipes.json".to_string()];
+        for folder in [&soup, &bread] {
+            for file in ["recipe.json", "recipe.jsonld", "recipe.md"] {
+                expected.push(format!("{}/{}", folder, file));
+            }
+        }
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let manifest: Value = serde_json::from_str(&read(&mut archive, "export.json")).unwrap();
+        assert_eq!(manifest["email"], "cook@example.com");
+        assert_eq!(manifest["recipe_count"], 2);
+        assert_eq!(manifest["photos"], serde_json::json!([{"folder": soup, "url": "https://example.com/soup.jpg"}]));
+
+        let all: Vec<Value> = serde_json::from_str(&read(&mut archive, "recipes.json")).unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(read(&mut archive, &format!("{}/recipe.md", bread)).starts_with("# Bread"));
+        assert_eq!(all[0]["ingredients"], serde_json::json!(["1 egg"]));
+    }
+
+    #[test]
+    fn archive_of_v2_has_v2_recipes() {
+        let recipes = vec![recipe("Soup", "")];
+        let bytes = build_archive(Cursor::new(Vec::new()), "cook@example.com", &recipes, ApiVersion::V2).unwrap().into_inner();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let all: Vec<Value> = serde_json::from_str(&read(&mut archive, "recipes.json")).unwrap();
+        let soup: Value = serde_json::from_str(&read(&mut archive, &format!("recipes/soup-{}/recipe.json", recipes[0].id.unwrap().to_hex()))).unwrap();
+        for recipe in [&all[0], &soup] {
+            assert_eq!(recipe["author"], serde_json::json!({ "email": "cook@example.com" }));
+            assert_eq!(recipe["ingredients"][0]["name"], "egg");
+            assert!(recipe.get("email").is_none());
+        }
+    }
+}
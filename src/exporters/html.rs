@@ -0,0 +1,65 @@
+use crate::exporters::schema_org::to_json_ld;
+use crate::exporters::summary_line;
+use crate::models::recipe_model::Recipe;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Standalone printable page, the JSON-LD is embedded so the file can be imported again
+pub fn to_html(recipe: &Recipe) -> String {
+    // "</" can't appear inside a script element
+    let json_ld = to_json_ld(recipe).to_string().replace("</", "<\\/");
+
+    let mut body = format!("<h1>{}</h1>\n", escape(&recipe.title));
+
+    if !recipe.photo_url.is_empty() {
+        body.push_str(&format!("<img src=\"{}\" alt=\"{}\">\n", escape(&recipe.photo_url), escape(&recipe.title)));
+    }
+    if !recipe.description.is_empty() {
+        body.push_str(&format!("<p>{}</p>\n", escape(&recipe.description)));
+    }
+
+    let summary = summary_line(recipe);
+    if !summary.is_empty() {
+        body.push_str(&format!("<p class=\"summary\">{}</p>\n", escape(&summary)));
+    }
+
+    body.push_str("<h2>Ingredients</h2>\n<ul>\n");
+    for ingredient in &recipe.ingredients {
+        body.push_str(&format!("<li>{}</li>\n", escape(ingredient)));
+    }
+
+    body.push_str("</ul>\n<h2>Steps</h2>\n<ol>\n");
+    for step in &recipe.steps {
+        body.push_str(&format!("<li>{}</li>\n", escape(step)));
+    }
+    body.push_str("</ol>\n");
+
+    if !recipe.tags.is_empty() {
+        let tags: Vec<String> = recipe.tags.iter().map(|tag| escape(tag)).collect();
+        body.push_str(&format!("<p class=\"tags\">{}</p>\n", tags.join(", ")));
+    }
+
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: Georgia, serif; max-width: 40em; margin: 2em auto; line-height: 1.5; }}
+img {{ max-width: 100%; }}
+.summary, .tags {{ color: #555; }}
+@media print {{ img {{ max-height: 10cm; }} }}
+</style>
+<script type="application/ld+json">{json_ld}</script>
+</head>
+<body>
+{body}</body>
+</html>
+"#, title = escape(&recipe.title), json_ld = json_ld, body = body)
+}
@@ -0,0 +1,203 @@
+use chrono::{DateTime, NaiveTime, Utc};
+
+use crate::models::meal_plan_model::{MealPlan, MealSlot, ResolvedMealPlanEntry};
+
+/*
+    iCalendar (RFC 5545) feed of a meal plan, for calendar apps that subscribe to a URL.
+    Every entry becomes an event at the usual time of its slot, in "floating" local time
+    (no time zone) so dinner shows up at 18:00 wherever the user is.
+ */
+
+fn slot_time(slot: MealSlot) -> NaiveTime {
+    let (hour, minute) = match slot {
+        MealSlot::Breakfast => (8, 0),
+        MealSlot::Lunch => (12, 0),
+        MealSlot::Dinner => (18, 0),
+        MealSlot::Snack => (15, 0),
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or_default()
+}
+
+fn slot_label(slot: MealSlot) -> &'static str {
+    match slot {
+        MealSlot::Breakfast => "Breakfast",
+        MealSlot::Lunch => "Lunch",
+        MealSlot::Dinner => "Dinner",
+        MealSlot::Snack => "Snack",
+    }
+}
+
+// TEXT values escape backslash, semicolon, comma and newlines
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Content lines are at most 75 octets, longer lines continue on the next line after a space
+fn push_line(ics: &mut String, line: &str) {
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            length = 1;
+        }
+        ics.push(c);
+        length += c.len_utf8();
+    }
+
+    ics.push_str("\r\n");
+}
+
+pub fn to_ics(plan: &MealPlan, entries: &[ResolvedMealPlanEntry]) -> String {
+    let mut ics = String::new();
+
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//rc-mongo-api//Meal plan//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, "METHOD:PUBLISH");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape(&plan.name)));
+
+    for entry in entries {
+        let title = entry.recipe.as_ref().map(|recipe| recipe.title.as_str()).unwrap_or("Deleted recipe");
+        let start = entry.date.and_time(slot_time(entry.slot));
+        let duration = entry.recipe.as_ref().and_then(|recipe| recipe.total_time_minutes).unwrap_or(30).max(15);
+        let stamp = DateTime::<Utc>::from_timestamp_millis(entry.updated.timestamp_millis()).unwrap_or_default();
+
+        let mut description: Vec<String> = Vec::new();
+        if let Some(servings) = entry.servings {
+            description.push(format!("{} servings", servings));
+        }
+        if let Some(note) = &entry.note {
+            description.push(note.clone());
+        }
+
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@rc-mongo-api", entry.id.to_hex()));
+        push_line(&mut ics, &format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")));
+        push_line(&mut ics, &format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")));
+        push_line(&mut ics, &format!("DURATION:PT{}M", duration));
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&format!("{}: {}", slot_label(entry.slot), title))));
+        if !description.is_empty() {
+            push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&description.join("\n"))));
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+
+    use crate::models::meal_plan_model::{MealPlan, MealSlot, RecipeSummary, ResolvedMealPlanEntry};
+
+    use super::{escape, push_line, to_ics};
+
+    fn plan() -> MealPlan {
+        MealPlan {
+            id: Some(ObjectId::new()),
+            owner_uid: "uid".to_string(),
+            name: "Vecka 10, hemma".to_string(),
+            feed_token: "token".to_string(),
+            created: mongodb::bson::DateTime::from_millis(0),
+            updated: mongodb::bson::DateTime::from_millis(0),
+        }
+    }
+
+    fn entry(slot: MealSlot, recipe: Option<(&str, Option<u32>)>) -> ResolvedMealPlanEntry {
+        let recipe_id = ObjectId::new();
+
+        ResolvedMealPlanEntry {
+            id: ObjectId::new(),
+            plan_id: ObjectId::new(),
+            date: "2024-03-04".parse().unwrap(),
+            slot,
+            recipe_id,
+            servings: None,
+            note: None,
+            updated: mongodb::bson::DateTime::from_millis(1_709_550_000_000),
+            recipe: recipe.map(|(title, total_time_minutes)| RecipeSummary {
+                id: recipe_id,
+                title: title.to_string(),
+                photo_url: String::new(),
+                recipe_yield: None,
+                total_time_minutes,
+                tags: vec![],
+            }),
+        }
+    }
+
+    // Unfolded content lines of the events, one Vec per VEVENT
+    fn events(ics: &str) -> Vec<Vec<String>> {
+        let unfolded = ics.replace("\r\n ", "");
+        let mut events = Vec::new();
+        let mut event: Option<Vec<String>> = None;
+
+        for line in unfolded.split("\r\n") {
+            match line {
+                "BEGIN:VEVENT" => event = Some(Vec::new()),
+                "END:VEVENT" => events.extend(event.take()),
+                line => if let Some(event) = event.as_mut() { event.push(line.to_string()) },
+            }
+        }
+
+        events
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape("Lax, potatis; dill\\sås\r\nmed citron\nKaffe"), r"Lax\, potatis\; dill\\sås\nmed citron\nKaffe");
+    }
+
+    #[test]
+    fn long_lines_are_folded_at_75_octets() {
+        let mut ics = String::new();
+        let line = format!("SUMMARY:{}", "Räksmörgås med ägg och majonnäs, ".repeat(6));
+        push_line(&mut ics, &line);
+
+        let lines: Vec<&str> = ics.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines.len() > 3);
+        assert!(lines.iter().all(|folded| folded.len() <= 75), "{:?}", lines);
+        assert!(lines[1..].iter().all(|folded| folded.starts_with(' ')));
+        assert_eq!(ics.replace("\r\n ", ""), format!("{}\r\n", line)); // No character split between lines
+
+        let mut short = String::new();
+        push_line(&mut short, "BEGIN:VCALENDAR");
+        assert_eq!(short, "BEGIN:VCALENDAR\r\n");
+    }
+
+    #[test]
+    fn events_start_at_the_slot_and_last_the_cooking_time() {
+        let mut dinner = entry(MealSlot::Dinner, Some(("Köttbullar", Some(45))));
+        dinner.servings = Some(4);
+        dinner.note = Some("Dubbel sats".to_string());
+        let entries = vec![entry(MealSlot::Breakfast, Some(("Gröt", Some(5)))), dinner, entry(MealSlot::Lunch, Some(("Soppa", None)))];
+
+        let ics = to_ics(&plan(), &entries);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("X-WR-CALNAME:Vecka 10\\, hemma\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+
+        let events = events(&ics);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0][0], format!("UID:{}@rc-mongo-api", entries[0].id.to_hex()));
+        assert_eq!(events[0][1], "DTSTAMP:20240304T110000Z");
+        assert_eq!(events[0][2..5], ["DTSTART:20240304T080000", "DURATION:PT15M", "SUMMARY:Breakfast: Gröt"]); // At least 15 minutes
+        assert_eq!(events[1][2..], ["DTSTART:20240304T180000", "DURATION:PT45M", "SUMMARY:Dinner: Köttbullar", "DESCRIPTION:4 servings\\nDubbel sats"]);
+        assert_eq!(events[2][2..], ["DTSTART:20240304T120000", "DURATION:PT30M", "SUMMARY:Lunch: Soppa"]); // 30 minutes without a time
+    }
+
+    #[test]
+    fn deleted_recipes_stay_in_the_calendar() {
+        let ics = to_ics(&plan(), &[entry(MealSlot::Snack, None)]);
+
+        assert_eq!(events(&ics)[0][2..], ["DTSTART:20240304T150000", "DURATION:PT30M", "SUMMARY:Snack: Deleted recipe"]);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::exporters::summary_line;
+use crate::models::recipe_model::Recipe;
+
+pub fn to_markdown(recipe: &Recipe) -> String {
+    let mut markdown = format!("# {}\n\n", recipe.title);
+
+    if !recipe.photo_url.is_empty() {
+        markdown.push_str(&format!("![{}]({})\n\n", recipe.title, recipe.photo_url));
+    }
+    if !recipe.description.is_empty() {
+        markdown.push_str(&format!("{}\n\n", recipe.description));
+    }
+
+    let summary = summary_line(recipe);
+    if !summary.is_empty() {
+        markdown.push_str(&format!("_{}_\n\n", summary));
+    }
+
+    markdown.push_str("## Ingredients\n\n");
+    for ingredient in &recipe.ingredients {
+        markdown.push_str(&format!("- {}\n", ingredient));
+    }
+
+    markdown.push_str("\n## Steps\n\n");
+    for (index, step) in recipe.steps.iter().enumerate() {
+        markdown.push_str(&format!("{}. {}\n", index + 1, step));
+    }
+
+    if !recipe.tags.is_empty() {
+        let tags: Vec<String> = recipe.tags.iter().map(|tag| format!("`{}`", tag)).collect();
+        markdown.push_str(&format!("\nTags: {}\n", tags.join(" ")));
+    }
+
+    markdown
+}
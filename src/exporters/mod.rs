@@ -0,0 +1,45 @@
+pub mod archive;
+pub mod html;
+pub mod ical;
+pub mod markdown;
+pub mod pdf;
+pub mod schema_org;
+
+use crate::models::recipe_model::Recipe;
+
+/// File name friendly version of a title, "Mormors Kanelbullar!" -> "mormors-kanelbullar"
+pub fn slugify(title: &str) -> String {
+    let slug = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+
+    if slug.is_empty() { "recipe".to_string() } else { slug }
+}
+
+/// "Yield: 4 servings · Prep: 15 min · Total: 1 h 10 min", empty when the recipe has none of them
+pub fn summary_line(recipe: &Recipe) -> String {
+    let minutes = |minutes: u32| match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{} min", m),
+        (h, 0) => format!("{} h", h),
+        (h, m) => format!("{} h {} min", h, m),
+    };
+
+    let mut parts: Vec<String> = Vec::new();
+
+    if let Some(recipe_yield) = &recipe.recipe_yield {
+        parts.push(format!("Yield: {}", recipe_yield));
+    }
+    for (label, value) in [("Prep", recipe.prep_time_minutes), ("Cook", recipe.cook_time_minutes), ("Total", recipe.total_time_minutes)] {
+        if let Some(value) = value {
+            parts.push(format!("{}: {}", label, minutes(value)));
+        }
+    }
+
+    parts.join(" · ")
+}
@@ -0,0 +1,278 @@
+use crate::exporters::summary_line;
+use crate::models::recipe_model::Recipe;
+
+/*
+    A minimal, dependency free PDF 1.4 writer for printing a recipe on A4.
+    Only the standard Helvetica fonts are used (every PDF reader has them, nothing is embedded),
+    which limits the text to the WinAnsi character set, other characters are printed as "?".
+ */
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+struct Line {
+    text: String,
+    size: f32,
+    bold: bool,
+    indent: f32,
+}
+
+// WinAnsiEncoding is Latin-1 plus some typography in 0x80-0x9F
+fn win_ansi(c: char) -> u8 {
+    match c {
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        c if (c as u32) < 0x80 || ((c as u32) >= 0xA0 && (c as u32) <= 0xFF) => c as u8,
+        _ => b'?',
+    }
+}
+
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+
+    for c in text.chars() {
+        match win_ansi(c) {
+            byte @ (b'(' | b')' | b'\\') => bytes.extend_from_slice(&[b'\\', byte]),
+            byte => bytes.push(byte),
+        }
+    }
+
+    bytes.push(b')');
+    bytes
+}
+
+// Helvetica averages about half an em per character, close enough for wrapping recipe text
+fn wrap(text: &str, size: f32, indent: f32) -> Vec<String> {
+    let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN - indent) / (size * 0.5)) as usize;
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn push_paragraph(lines: &mut Vec<Line>, text: &str, size: f32, bold: bool, indent: f32) {
+    for (index, wrapped) in wrap(text, size, indent).into_iter().enumerate() {
+        // Continuation lines of a list item are indented a bit further than the marker
+        let indent = if index == 0 { indent } else { indent + size };
+        lines.push(Line { text: wrapped, size, bold, indent });
+    }
+}
+
+fn layout(recipe: &Recipe) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let blank = |size: f32| Line { text: String::new(), size, bold: false, indent: 0.0 };
+
+    push_paragraph(&mut lines, &recipe.title, 22.0, true, 0.0);
+    lines.push(blank(8.0));
+
+    if !recipe.description.is_empty() {
+        push_paragraph(&mut lines, &recipe.description, 11.0, false, 0.0);
+        lines.push(blank(6.0));
+    }
+
+    let summary = summary_line(recipe);
+    if !summary.is_empty() {
+        push_paragraph(&mut lines, &summary, 10.0, false, 0.0);
+        lines.push(blank(6.0));
+    }
+
+    push_paragraph(&mut lines, "Ingredients", 15.0, true, 0.0);
+    for ingredient in &recipe.ingredients {
+        push_paragraph(&mut lines, &format!("• {}", ingredient), 11.0, false, 8.0);
+    }
+    lines.push(blank(8.0));
+
+    push_paragraph(&mut lines, "Steps", 15.0, true, 0.0);
+    for (index, step) in recipe.steps.iter().enumerate() {
+        push_paragraph(&mut lines, &format!("{}. {}", index + 1, step), 11.0, false, 8.0);
+        lines.push(blank(4.0));
+    }
+
+    if !recipe.tags.is_empty() {
+        lines.push(blank(6.0));
+        push_paragraph(&mut lines, &format!("Tags: {}", recipe.tags.join(", ")), 9.0, false, 0.0);
+    }
+
+    lines
+}
+
+// One content stream per page
+fn paginate(lines: Vec<Line>) -> Vec<Vec<u8>> {
+    let mut pages: Vec<Vec<u8>> = Vec::new();
+    let mut content: Vec<u8> = Vec::new();
+    let mut y = PAGE_HEIGHT - MARGIN;
+
+    for line in lines {
+        let height = line.size * 1.35;
+
+        if y - height < MARGIN {
+            pages.push(std::mem::take(&mut content));
+            y = PAGE_HEIGHT - MARGIN;
+        }
+
+        y -= height;
+
+        if line.text.is_empty() {
+            continue;
+        }
+
+        let font = if line.bold { "F2" } else { "F1" };
+        content.extend_from_slice(format!("BT /{} {} Tf {:.1} {:.1} Td ", font, line.size, MARGIN + line.indent, y).as_bytes());
+        content.extend_from_slice(&pdf_string(&line.text));
+        content.extend_from_slice(b" Tj ET\n");
+    }
+
+    pages.push(content);
+    pages
+}
+
+pub fn to_pdf(recipe: &Recipe) -> Vec<u8> {
+    let pages = paginate(layout(recipe));
+
+    // Object numbers: 1 catalog, 2 page tree, 3-4 fonts, then a page object and its content stream per page
+    let page_ids: Vec<usize> = (0..pages.len()).map(|index| 5 + index * 2).collect();
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+
+    for (page_id, content) in page_ids.iter().zip(pages) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH, PAGE_HEIGHT, page_id + 1).into_bytes());
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(&content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets: Vec<usize> = Vec::with_capacity(objects.len());
+
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref_offset).as_bytes());
+
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+    use super::{pdf_string, to_pdf, wrap};
+
+    fn recipe(steps: usize) -> Recipe {
+        map_input_dto(RecipeDTO {
+            id: None,
+            title: "Smörgåstårta (stor)".to_string(),
+            description: "Till kalaset".to_string(),
+            steps: (1..=steps).map(|step| format!("Steg {}", step)).collect(),
+            photo_url: String::new(),
+            ingredients: vec!["1 limpa".to_string()],
+            email: "cook@example.com".to_string(),
+            tags: Vec::new(),
+            recipe_yield: None,
+            prep_time_minutes: None,
+            cook_time_minutes: None,
+            total_time_minutes: None,
+        }, None, RecipeStatus::Created)
+    }
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack.windows(needle.len()).filter(|window| *window == needle).count()
+    }
+
+    #[test]
+    fn strings_are_escaped_and_win_ansi_encoded() {
+        assert_eq!(pdf_string("a (b) \\ c"), b"(a \\(b\\) \\\\ c)".to_vec());
+        assert_eq!(pdf_string("ö – €"), vec![b'(', 0xF6, b' ', 0x96, b' ', 0x80, b')']);
+        assert_eq!(pdf_string("🍰 ż"), b"(? ?)".to_vec());
+    }
+
+    #[test]
+    fn long_text_wraps_on_words() {
+        let text = "word ".repeat(100);
+        let lines = wrap(&text, 11.0, 0.0);
+
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| !line.starts_with(' ') && !line.ends_with(' ')));
+        assert_eq!(lines.join(" "), text.trim());
+        assert_eq!(wrap("", 11.0, 0.0), vec![String::new()]);
+    }
+
+    #[test]
+    fn document_structure() {
+        let pdf = to_pdf(&recipe(3));
+
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert_eq!(count(&pdf, b"/Type /Page /Parent"), 1);
+        assert_eq!(count(&pdf, b"(Sm\xF6rg\xE5st\xE5rta \\(stor\\))"), 1);
+
+        // Every xref entry points at the start of its object, the table is ASCII after the (binary) objects
+        let xref = pdf.windows(6).rposition(|window| window == b"\nxref\n").unwrap() + 1;
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let startxref: usize = table.lines().skip_while(|line| *line != "startxref").nth(1).unwrap().parse().unwrap();
+        assert_eq!(startxref, xref);
+
+        let entries: Vec<usize> = table
+            .lines()
+            .skip(3)
+            .take_while(|line| !line.starts_with("trailer"))
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        assert_eq!(entries.len(), 6);
+        for (index, offset) in entries.into_iter().enumerate() {
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", index + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn long_recipes_get_more_pages() {
+        let pdf = to_pdf(&recipe(120));
+        let pages = count(&pdf, b"/Type /Page /Parent");
+
+        assert!(pages > 1);
+        assert_eq!(count(&pdf, format!("/Count {} ", pages).as_bytes()), 1);
+        assert_eq!(count(&pdf, b"(120. Steg 120)"), 1);
+    }
+}
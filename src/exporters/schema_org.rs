@@ -0,0 +1,145 @@
+use serde_json::{json, Map, Value};
+
+use crate::models::recipe_model::Recipe;
+
+// Counterpart of importers/schema_org.rs, the output validates as https://schema.org/Recipe
+pub fn to_json_ld(recipe: &Recipe) -> Value {
+    let mut json_ld = Map::new();
+
+    json_ld.insert("@context".to_string(), json!("https://schema.org"));
+    json_ld.insert("@type".to_string(), json!("Recipe"));
+    json_ld.insert("name".to_string(), json!(recipe.title));
+
+    if let Some(id) = recipe.id {
+        json_ld.insert("identifier".to_string(), json!(id.to_hex()));
+    }
+    if !recipe.description.is_empty() {
+        json_ld.insert("description".to_string(), json!(recipe.description));
+    }
+    if !recipe.photo_url.is_empty() {
+        json_ld.insert("image".to_string(), json!([recipe.photo_url]));
+    }
+
+    json_ld.insert("author".to_string(), json!({"@type": "Person", "email": recipe.email}));
+    json_ld.insert("recipeIngredient".to_string(), json!(recipe.ingredients));
+    json_ld.insert("recipeInstructions".to_string(), Value::Array(recipe.steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| json!({"@type": "HowToStep", "position": index + 1, "text": step}))
+        .collect()));
+
+    if !recipe.tags.is_empty() {
+        json_ld.insert("keywords".to_string(), json!(recipe.tags.join(", ")));
+    }
+    if let Some(recipe_yield) = &recipe.recipe_yield {
+        json_ld.insert("recipeYield".to_string(), json!(recipe_yield));
+    }
+
+    // Durations are ISO 8601, PT1H10M
+    for (key, minutes) in [("prepTime", recipe.prep_time_minutes), ("cookTime", recipe.cook_time_minutes), ("totalTime", recipe.total_time_minutes)] {
+        if let Some(minutes) = minutes {
+            let duration = match (minutes / 60, minutes % 60) {
+                (0, m) => format!("PT{}M", m),
+                (h, 0) => format!("PT{}H", h),
+                (h, m) => format!("PT{}H{}M", h, m),
+            };
+            json_ld.insert(key.to_string(), json!(duration));
+        }
+    }
+
+    if let Some(created) = recipe.created.and_then(|created| created.try_to_rfc3339_string().ok()) {
+        json_ld.insert("datePublished".to_string(), json!(created));
+    }
+    if let Ok(updated) = recipe.updated.try_to_rfc3339_string() {
+        json_ld.insert("dateModified".to_string(), json!(updated));
+    }
+
+    Value::Object(json_ld)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::json;
+
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::importers::schema_org::{find_recipe, map_recipe};
+    use crate::models::recipe_model::RecipeDTO;
+
+    use super::to_json_ld;
+
+    fn dto() -> RecipeDTO {
+        RecipeDTO {
+            id: None,
+            title: "Kanelbullar".to_string(),
+            description: "Med kardemumma".to_string(),
+            steps: vec!["Baka".to_string(), "Grädda".to_string()],
+            photo_url: "https://example.com/bullar.jpg".to_string(),
+            ingredients: vec!["5 dl mjölk".to_string(), "50 g jäst".to_string()],
+            email: "cook@example.com".to_string(),
+            tags: vec!["fika".to_string(), "bakning".to_string()],
+            recipe_yield: Some("30 bullar".to_string()),
+            prep_time_minutes: Some(45),
+            cook_time_minutes: Some(10),
+            total_time_minutes: Some(125),
+        }
+    }
+
+    #[test]
+    fn recipe_properties() {
+        let id = ObjectId::new();
+        let json_ld = to_json_ld(&map_input_dto(dto(), Some(id), RecipeStatus::Created));
+
+        assert_eq!(json_ld["@context"], "https://schema.org");
+        assert_eq!(json_ld["@type"], "Recipe");
+        assert_eq!(json_ld["name"], "Kanelbullar");
+        assert_eq!(json_ld["identifier"], id.to_hex());
+        assert_eq!(json_ld["image"], json!(["https://example.com/bullar.jpg"]));
+        assert_eq!(json_ld["author"], json!({"@type": "Person", "email": "cook@example.com"}));
+        assert_eq!(json_ld["recipeIngredient"], json!(["5 dl mjölk", "50 g jäst"]));
+        assert_eq!(json_ld["recipeInstructions"], json!([
+            {"@type": "HowToStep", "position": 1, "text": "Baka"},
+            {"@type": "HowToStep", "position": 2, "text": "Grädda"},
+        ]));
+        assert_eq!(json_ld["keywords"], "fika, bakning");
+        assert_eq!(json_ld["recipeYield"], "30 bullar");
+        assert_eq!((&json_ld["prepTime"], &json_ld["cookTime"], &json_ld["totalTime"]), (&json!("PT45M"), &json!("PT10M"), &json!("PT2H5M")));
+        assert!(json_ld["datePublished"].is_string());
+        assert!(json_ld["dateModified"].is_string());
+    }
+
+    #[test]
+    fn empty_properties_are_left_out() {
+        let json_ld = to_json_ld(&map_input_dto(RecipeDTO {
+            description: String::new(),
+            photo_url: String::new(),
+            tags: Vec::new(),
+            recipe_yield: None,
+            prep_time_minutes: Some(60),
+            cook_time_minutes: None,
+            total_time_minutes: None,
+            ..dto()
+        }, None, RecipeStatus::Created));
+
+        for key in ["identifier", "description", "image", "keywords", "recipeYield", "cookTime", "totalTime"] {
+            assert!(json_ld.get(key).is_none(), "{} should be left out", key);
+        }
+        assert_eq!(json_ld["prepTime"], "PT1H");
+    }
+
+    #[test]
+    fn importer_reads_the_export_back() {
+        let json_ld = to_json_ld(&map_input_dto(dto(), None, RecipeStatus::Created));
+        let imported = map_recipe(find_recipe(&json_ld).unwrap(), "cook@example.com");
+
+        let original = dto();
+        assert_eq!(imported.title, original.title);
+        assert_eq!(imported.description, original.description);
+        assert_eq!(imported.steps, original.steps);
+        assert_eq!(imported.ingredients, original.ingredients);
+        assert_eq!(imported.photo_url, original.photo_url);
+        assert_eq!(imported.tags, original.tags);
+        assert_eq!(imported.recipe_yield, original.recipe_yield);
+        assert_eq!((imported.prep_time_minutes, imported.cook_time_minutes, imported.total_time_minutes), (Some(45), Some(10), Some(125)));
+    }
+}
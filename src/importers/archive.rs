@@ -0,0 +1,141 @@
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::importers::{mealie, mealmaster, paprika, tandoor};
+use crate::models::import_model::{ArchiveFormat, ImportIssue};
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    Reading of the archives other recipe managers export, shared by the format specific importers.
+    Everything is unpacked in memory, so the entries are capped to keep a zip bomb from taking the server down.
+ */
+
+const MAX_ENTRY_BYTES: u64 = 20 * 1024 * 1024;
+const MAX_TOTAL_BYTES: u64 = 200 * 1024 * 1024;
+
+/// One recipe of an archive, or why it couldn't be read
+pub type ParsedRecipe = Result<RecipeDTO, ImportIssue>;
+
+pub fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+fn read_limited(reader: impl Read, name: &str) -> Result<Vec<u8>, String> {
+    let mut content = Vec::new();
+    reader
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_end(&mut content)
+        .map_err(|err| format!("Failed to read {}: {}", name, err))?;
+
+    if content.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(format!("{} is larger than {} MB", name, MAX_ENTRY_BYTES / 1024 / 1024));
+    }
+
+    Ok(content)
+}
+
+/// (name, content) of the files in a zip archive, zips inside the zip (Tandoor) are unpacked as well
+pub fn zip_files(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    collect_zip_files(bytes, true, &mut files, &mut total)?;
+    Ok(files)
+}
+
+fn collect_zip_files(bytes: &[u8], unpack_nested: bool, files: &mut Vec<(String, Vec<u8>)>, total: &mut u64) -> Result<(), String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|err| format!("Invalid zip archive: {}", err))?;
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(|err| format!("Invalid zip archive: {}", err))?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let name = file.name().to_string();
+        let content = read_limited(file, &name)?;
+
+        *total += content.len() as u64;
+        if *total > MAX_TOTAL_BYTES {
+            return Err(format!("The archive is larger than {} MB unpacked", MAX_TOTAL_BYTES / 1024 / 1024));
+        }
+
+        if unpack_nested && is_zip(&content) {
+            collect_zip_files(&content, false, files, total)?;
+        } else {
+            files.push((name, content));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn gunzip(bytes: &[u8], name: &str) -> Result<Vec<u8>, String> {
+    read_limited(GzDecoder::new(bytes), name)
+}
+
+/// The JSON documents of an upload that is either a single JSON file or a zip with JSON files,
+/// other files in the zip (images) are ignored
+pub fn json_documents(bytes: &[u8]) -> Result<Vec<Result<Value, ImportIssue>>, String> {
+    if !is_zip(bytes) {
+        let document = serde_json::from_slice::<Value>(bytes).map_err(|err| format!("Invalid JSON: {}", err))?;
+        return Ok(vec![Ok(document)]);
+    }
+
+    Ok(zip_files(bytes)?
+        .into_iter()
+        .filter(|(name, _)| name.to_lowercase().ends_with(".json"))
+        .map(|(name, content)| serde_json::from_slice::<Value>(&content).map_err(|err| ImportIssue {
+            title: name,
+            reason: format!("Invalid JSON: {}", err),
+        }))
+        .collect())
+}
+
+/// First of `keys` that is present and not null, the recipe managers have changed casing between versions
+pub fn field<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|key| value.get(*key).filter(|value| !value.is_null()))
+}
+
+pub fn field_text(value: &Value, keys: &[&str]) -> Option<String> {
+    match field(value, keys)? {
+        Value::String(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// ["a", "b"] or [{ "name": "a" }, { "name": "b" }], used for tags, categories and keywords
+pub fn names(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().or_else(|| item.get("name").and_then(Value::as_str)))
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect(),
+        Some(Value::String(text)) => text.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A recipe has to have a title, everything else can be filled in afterwards
+pub fn require_title(recipe: RecipeDTO, source: &str) -> ParsedRecipe {
+    if recipe.title.is_empty() {
+        return Err(ImportIssue { title: source.to_string(), reason: "No title found".to_string() });
+    }
+    Ok(recipe)
+}
+
+/// Recipes of an uploaded archive, Err when the archive itself can't be read
+pub fn parse_archive(format: ArchiveFormat, bytes: &[u8], email: &str) -> Result<Vec<ParsedRecipe>, String> {
+    match format {
+        ArchiveFormat::Paprika => paprika::parse(bytes, email),
+        ArchiveFormat::Mealie => mealie::parse(bytes, email),
+        ArchiveFormat::Tandoor => tandoor::parse(bytes, email),
+        ArchiveFormat::Mealmaster => mealmaster::parse(bytes, email),
+    }
+}
@@ -0,0 +1,171 @@
+use serde_json::Value;
+
+use crate::importers::archive::{field, field_text, json_documents, names, ParsedRecipe, require_title};
+use crate::importers::{clean_text, text_duration_minutes};
+use crate::ingredients::format_ingredient;
+use crate::ingredients::units::canonical_unit;
+use crate::models::ingredient_model::Ingredient;
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    Mealie (https://mealie.io) recipe JSON, either a single recipe, a list or a zip with one JSON file per recipe.
+    Mealie has used both snake_case and camelCase keys between versions, so both are looked up.
+    Ingredients are structured (quantity, unit, food, note) unless the user disabled amounts,
+    then everything is in "note" (or "display"/"originalText").
+ */
+
+fn ingredient_line(ingredient: &Value) -> Option<String> {
+    if let Some(text) = ingredient.as_str() {
+        return Some(clean_text(text)).filter(|text| !text.is_empty());
+    }
+
+    let food = field(ingredient, &["food"]).and_then(|food| field_text(food, &["name"]));
+    let note = field_text(ingredient, &["note"]);
+
+    match food {
+        Some(food) => {
+            let unit = field(ingredient, &["unit"]).and_then(|unit| field_text(unit, &["name", "abbreviation"]));
+
+            Some(format_ingredient(&Ingredient {
+                quantity: field(ingredient, &["quantity"]).and_then(Value::as_f64).filter(|quantity| *quantity > 0.0),
+                unit: unit.map(|unit| canonical_unit(&unit).map(str::to_string).unwrap_or(unit)),
+                name: food,
+                note,
+                line: None,
+            }))
+        }
+        None => field_text(ingredient, &["display", "originalText", "original_text"])
+            .or(note)
+            .map(|text| clean_text(&text))
+            .filter(|text| !text.is_empty()),
+    }
+}
+
+fn steps(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(steps)) => steps
+            .iter()
+            .filter_map(|step| step.as_str().map(str::to_string).or_else(|| field_text(step, &["text"])))
+            .map(|step| clean_text(&step))
+            .filter(|step| !step.is_empty())
+            .collect(),
+        Some(Value::String(text)) => text.lines().map(clean_text).filter(|step| !step.is_empty()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn map_recipe(recipe: &Value, email: &str) -> RecipeDTO {
+    let duration = |keys: &[&str]| field_text(recipe, keys).as_deref().and_then(text_duration_minutes);
+
+    let mut tags = names(field(recipe, &["tags"]));
+    for category in names(field(recipe, &["recipeCategory", "recipe_category"])) {
+        if !tags.iter().any(|tag| tag.eq_ignore_ascii_case(&category)) {
+            tags.push(category);
+        }
+    }
+
+    let ingredients = match field(recipe, &["recipeIngredient", "recipe_ingredient"]) {
+        Some(Value::Array(items)) => items.iter().filter_map(ingredient_line).collect(),
+        _ => Vec::new(),
+    };
+
+    RecipeDTO {
+        id: None,
+        title: field_text(recipe, &["name"]).map(|name| clean_text(&name)).unwrap_or_default(),
+        description: field_text(recipe, &["description"]).map(|text| clean_text(&text)).unwrap_or_default(),
+        steps: steps(field(recipe, &["recipeInstructions", "recipe_instructions"])),
+        photo_url: String::new(), // Mealie serves the images itself, they aren't part of the recipe JSON
+        ingredients,
+        email: email.to_string(),
+        tags,
+        recipe_yield: field_text(recipe, &["recipeYield", "recipe_yield", "recipeServings", "recipe_servings"]),
+        prep_time_minutes: duration(&["prepTime", "prep_time"]),
+        cook_time_minutes: duration(&["performTime", "perform_time", "cookTime", "cook_time"]),
+        total_time_minutes: duration(&["totalTime", "total_time"]),
+    }
+}
+
+// A document is a recipe, a list of recipes or { "recipes": [...] }
+fn recipes_of(document: &Value) -> Vec<&Value> {
+    match document {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(object) => match object.get("recipes") {
+            Some(Value::Array(items)) => items.iter().collect(),
+            _ => vec![document],
+        },
+        _ => Vec::new(),
+    }
+}
+
+pub fn parse(bytes: &[u8], email: &str) -> Result<Vec<ParsedRecipe>, String> {
+    let mut recipes = Vec::new();
+
+    for document in json_documents(bytes)? {
+        match document {
+            Ok(document) => recipes.extend(recipes_of(&document)
+                .into_iter()
+                .map(|recipe| require_title(map_recipe(recipe, email), "Mealie recipe"))),
+            Err(issue) => recipes.push(Err(issue)),
+        }
+    }
+
+    Ok(recipes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+
+    #[test]
+    fn maps_structured_and_plain_ingredients_in_both_casings() {
+        let export = json!({"recipes": [
+            {
+                "name": "Kanelbullar",
+                "description": "Fika",
+                "recipeYield": "30 bullar",
+                "prepTime": "45 min",
+                "performTime": "PT10M",
+                "tags": [{"name": "fika"}],
+                "recipeCategory": [{"name": "Bakverk"}, {"name": "FIKA"}],
+                "recipeIngredient": [
+                    {"quantity": 5, "unit": {"name": "deciliter"}, "food": {"name": "mjölk"}, "note": null},
+                    {"quantity": 0, "unit": null, "food": {"name": "kanel"}, "note": "efter smak"},
+                    {"quantity": null, "food": null, "note": "1 nypa salt"},
+                    "2 ägg",
+                ],
+                "recipeInstructions": [{"text": "Baka."}, {"text": " "}, {"text": "Grädda."}],
+            },
+            {
+                "name": "Soppa",
+                "recipe_ingredient": [{"food": null, "display": "1 l buljong"}],
+                "recipe_instructions": "Koka.\nServera.",
+                "total_time": "1:30",
+            },
+        ]});
+
+        let recipes = parse(export.to_string().as_bytes(), "cook@example.com").unwrap();
+        assert_eq!(recipes.len(), 2);
+
+        let buns = recipes[0].as_ref().unwrap();
+        assert_eq!(buns.ingredients, vec!["5 dl mjölk", "kanel, efter smak", "1 nypa salt", "2 ägg"]);
+        assert_eq!(buns.steps, vec!["Baka.", "Grädda."]);
+        assert_eq!(buns.tags, vec!["fika", "Bakverk"]);
+        assert_eq!(buns.recipe_yield.as_deref(), Some("30 bullar"));
+        assert_eq!((buns.prep_time_minutes, buns.cook_time_minutes), (Some(45), Some(10)));
+
+        let soup = recipes[1].as_ref().unwrap();
+        assert_eq!(soup.ingredients, vec!["1 l buljong"]);
+        assert_eq!(soup.steps, vec!["Koka.", "Servera."]);
+        assert_eq!(soup.total_time_minutes, Some(90));
+    }
+
+    #[test]
+    fn invalid_json_fails_the_import() {
+        assert!(parse(b"{ not json", "cook@example.com").is_err());
+
+        let recipes = parse(br#"[{"description": "No name"}]"#, "cook@example.com").unwrap();
+        assert_eq!(recipes[0].as_ref().unwrap_err().reason, "No title found");
+    }
+}
@@ -0,0 +1,270 @@
+use crate::importers::archive::{ParsedRecipe, require_title};
+use crate::importers::clean_text;
+use crate::ingredients::units::canonical_unit;
+use crate::ingredients::{format_ingredient, parse_quantity};
+use crate::models::ingredient_model::Ingredient;
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    MealMaster (.mmf / .mm) is the plain text format of the old DOS program, still common in recipe collections.
+    A file holds any number of recipes:
+
+    MMMMM----- Recipe via Meal-Master (tm) v8.05
+          Title: Chocolate Cake
+     Categories: Desserts, Cakes
+          Yield: 12 servings
+
+          2 c  Flour
+        1/2 ts Salt
+
+      Mix everything and bake...
+
+    MMMMM
+
+    Ingredients are fixed columns, quantity in 0-6, a two letter unit code in 8-9 and the text from 11,
+    sometimes in two columns side by side (the second starting at 41). A text starting with "-" continues
+    the previous ingredient. Everything after the ingredients are the directions, a paragraph per step.
+ */
+
+const SECOND_COLUMN: usize = 41;
+
+// Old files are Latin-1 more often than UTF-8
+fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+fn is_start(line: &str) -> bool {
+    (line.starts_with("MMMMM") || line.starts_with("-----")) && line.to_lowercase().contains("meal-master")
+}
+
+fn is_end(line: &str) -> bool {
+    line == "MMMMM" || line == "-----"
+}
+
+// "MMMMM--------SAUCE--------" between ingredient groups
+fn is_section_header(line: &str) -> bool {
+    line.starts_with("MMMMM-") || line.starts_with("-----")
+}
+
+struct Column {
+    quantity: Option<f64>,
+    unit: String,
+    text: String,
+}
+
+fn parse_column(chars: &[char]) -> Option<Column> {
+    if chars.len() < 12 || chars[7] != ' ' || chars[10] != ' ' {
+        return None;
+    }
+
+    let quantity: String = chars[..7].iter().collect();
+    let unit: String = chars[8..10].iter().collect::<String>().trim().to_string();
+    let text: String = chars[11..].iter().collect::<String>().trim().to_string();
+
+    if !quantity.chars().all(|c| c.is_ascii_digit() || " /.-".contains(c))
+        || !unit.chars().all(|c| c.is_ascii_alphabetic())
+        || text.is_empty() {
+        return None;
+    }
+
+    Some(Column { quantity: parse_quantity(&quantity), unit, text })
+}
+
+// One or two ingredients, None when the line isn't in the ingredient layout
+fn parse_ingredient_line(line: &str) -> Option<Vec<Column>> {
+    let chars: Vec<char> = line.trim_end().chars().collect();
+    let first = parse_column(&chars)?;
+
+    if chars.len() > SECOND_COLUMN && chars[SECOND_COLUMN - 1] == ' ' {
+        if let Some(second) = parse_column(&chars[SECOND_COLUMN..]) {
+            let first = parse_column(&chars[..SECOND_COLUMN])?;
+            return Some(vec![first, second]);
+        }
+    }
+
+    Some(vec![first])
+}
+
+fn to_ingredient(column: Column) -> Ingredient {
+    // "sm", "md" and "lg" are sizes ("1 lg onion"), "x" is "per serving"
+    let (unit, size) = match column.unit.as_str() {
+        "" | "x" => (None, None),
+        "t" => (Some("tsp".to_string()), None),
+        "T" => (Some("tbsp".to_string()), None),
+        "sm" => (None, Some("small")),
+        "md" => (None, Some("medium")),
+        "lg" => (None, Some("large")),
+        code => (Some(canonical_unit(code).map(str::to_string).unwrap_or(code.to_string())), None),
+    };
+
+    Ingredient {
+        quantity: column.quantity,
+        unit,
+        name: match size {
+            Some(size) => format!("{} {}", size, clean_text(&column.text)),
+            None => clean_text(&column.text),
+        },
+        note: None,
+        line: None,
+    }
+}
+
+fn parse_recipe(lines: &[&str], email: &str) -> ParsedRecipe {
+    let mut recipe = RecipeDTO {
+        id: None,
+        title: String::new(),
+        description: String::new(),
+        steps: Vec::new(),
+        photo_url: String::new(),
+        ingredients: Vec::new(),
+        email: email.to_string(),
+        tags: Vec::new(),
+        recipe_yield: None,
+        prep_time_minutes: None,
+        cook_time_minutes: None,
+        total_time_minutes: None,
+    };
+    let mut ingredients: Vec<Ingredient> = Vec::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut in_directions = false;
+
+    for line in lines {
+        let trimmed = line.trim();
+
+        if !in_directions {
+            if let Some((key, value)) = trimmed.split_once(':') {
+                let value = value.trim().to_string();
+
+                match key.trim().to_lowercase().as_str() {
+                    "title" => { recipe.title = clean_text(&value); continue; }
+                    "categories" => {
+                        recipe.tags = value.split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty() && !tag.eq_ignore_ascii_case("none"))
+                            .map(str::to_string)
+                            .collect();
+                        continue;
+                    }
+                    "yield" | "servings" => { recipe.recipe_yield = Some(value).filter(|value| !value.is_empty()); continue; }
+                    _ => {}
+                }
+            }
+
+            if trimmed.is_empty() || is_section_header(trimmed) {
+                continue;
+            }
+
+            if let Some(columns) = parse_ingredient_line(line) {
+                for column in columns {
+                    match ingredients.last_mut() {
+                        Some(previous) if column.quantity.is_none() && column.unit.is_empty() && column.text.starts_with('-') => {
+                            previous.name = format!("{} {}", previous.name, clean_text(column.text.trim_start_matches('-')));
+                        }
+                        _ => ingredients.push(to_ingredient(column)),
+                    }
+                }
+                continue;
+            }
+
+            in_directions = true;
+        }
+
+        if trimmed.is_empty() {
+            if !paragraph.is_empty() {
+                recipe.steps.push(clean_text(&paragraph.join(" ")));
+                paragraph.clear();
+            }
+        } else {
+            paragraph.push(trimmed.to_string());
+        }
+    }
+
+    if !paragraph.is_empty() {
+        recipe.steps.push(clean_text(&paragraph.join(" ")));
+    }
+
+    recipe.ingredients = ingredients.iter().map(format_ingredient).collect();
+
+    require_title(recipe, "MealMaster recipe")
+}
+
+pub fn parse(bytes: &[u8], email: &str) -> Result<Vec<ParsedRecipe>, String> {
+    let text = decode(bytes);
+    let mut recipes = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        if is_start(trimmed) {
+            // A recipe without end marker ends where the next one starts
+            if let Some(body) = current.replace(Vec::new()) {
+                recipes.push(parse_recipe(&body, email));
+            }
+        } else if is_end(trimmed) {
+            if let Some(body) = current.take() {
+                recipes.push(parse_recipe(&body, email));
+            }
+        } else if let Some(body) = current.as_mut() {
+            body.push(line);
+        }
+    }
+
+    if let Some(body) = current {
+        recipes.push(parse_recipe(&body, email));
+    }
+
+    if recipes.is_empty() {
+        return Err("No MealMaster recipes found, the file should start with a \"MMMMM----- Recipe via Meal-Master\" line".to_string());
+    }
+
+    Ok(recipes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    const CAKE: &[u8] = include_bytes!("../../fixtures/import/cake.mmf");
+
+    #[test]
+    fn parses_every_recipe_of_the_fixture() {
+        let recipes: Vec<_> = parse(CAKE, "cook@example.com").unwrap().into_iter().map(Result::unwrap).collect();
+        assert_eq!(recipes.len(), 2);
+
+        let cake = &recipes[0];
+        assert_eq!(cake.title, "Chocolate Cake");
+        assert_eq!(cake.email, "cook@example.com");
+        assert_eq!(cake.tags, vec!["Desserts", "Cakes"]);
+        assert_eq!(cake.recipe_yield.as_deref(), Some("12 servings"));
+        assert_eq!(cake.ingredients, vec!["2 cup Flour", "1 large Egg", "0.5 tsp Salt", "1 tbsp Butter softened", "1 cup Sugar"]);
+        assert_eq!(cake.steps, vec!["Mix everything together and pour into a greased pan.", "Bake at 350F for 30 minutes."]);
+
+        let toast = &recipes[1];
+        assert_eq!(toast.title, "Toast");
+        assert!(toast.tags.is_empty());
+        assert_eq!(toast.recipe_yield.as_deref(), Some("1"));
+        assert_eq!(toast.ingredients.len(), 1);
+        assert_eq!(toast.steps, vec!["Toast it."]);
+    }
+
+    #[test]
+    fn latin1_files_and_missing_end_markers() {
+        let text = b"MMMMM----- Recipe via Meal-Master (tm) v8.05\n      Title: Sm\xF6rg\xE5st\xE5rta\n\n      1    Limpa\n  Bred.\n";
+        let recipes = parse(text, "cook@example.com").unwrap();
+
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].as_ref().unwrap().title, "Smörgåstårta");
+    }
+
+    #[test]
+    fn recipes_without_title_and_files_without_recipes() {
+        let recipes = parse(b"MMMMM----- Recipe via Meal-Master (tm) v8.05\n      1    Limpa\nMMMMM\n", "cook@example.com").unwrap();
+        assert_eq!(recipes[0].as_ref().unwrap_err().reason, "No title found");
+
+        assert!(parse(b"Just some text", "cook@example.com").is_err());
+    }
+}
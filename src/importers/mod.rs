@@ -0,0 +1,190 @@
+pub mod archive;
+pub mod mealie;
+pub mod mealmaster;
+pub mod paprika;
+pub mod schema_org;
+pub mod tandoor;
+
+// Helpers shared by the importers, the sources are full of markup and HTML entities
+
+/// Removes HTML tags, decodes the common entities and collapses whitespace
+pub fn clean_text(input: &str) -> String {
+    let mut without_tags = String::with_capacity(input.len());
+    let mut in_tag = false;
+
+    for c in input.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                without_tags.push(' ');
+            }
+            _ if !in_tag => without_tags.push(c),
+            _ => {}
+        }
+    }
+
+    decode_entities(&without_tags)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // Entities are short, anything longer is a plain ampersand
+        let decoded = rest[1..].find(';').filter(|end| *end <= 8).and_then(|end| {
+            let entity = &rest[1..=end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "nbsp" => Some(' '),
+                "deg" => Some('°'),
+                "ndash" => Some('–'),
+                "mdash" => Some('—'),
+                "hellip" => Some('…'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "times" => Some('×'),
+                "frac14" => Some('¼'),
+                "frac12" => Some('½'),
+                "frac34" => Some('¾'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse::<u32>().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end + 2))
+        });
+
+        match decoded {
+            Some((c, len)) => {
+                output.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// ISO 8601 durations as used by schema.org ("PT1H30M", "P0DT0H20M") to whole minutes
+pub fn iso_duration_minutes(duration: &str) -> Option<u32> {
+    let duration = duration.trim().strip_prefix('P')?;
+    let (date_part, time_part) = duration.split_once('T').unwrap_or((duration, ""));
+
+    let mut seconds: f64 = 0.0;
+
+    for (part, units) in [(date_part, [('W', 604800.0), ('D', 86400.0)].as_slice()), (time_part, [('H', 3600.0), ('M', 60.0), ('S', 1.0)].as_slice())] {
+        let mut number = String::new();
+
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' || c == ',' {
+                number.push(if c == ',' { '.' } else { c });
+                continue;
+            }
+
+            let (_, unit_seconds) = units.iter().find(|(unit, _)| *unit == c)?;
+            seconds += number.parse::<f64>().ok()? * unit_seconds;
+            number.clear();
+        }
+
+        if !number.is_empty() {
+            return None;
+        }
+    }
+
+    Some((seconds / 60.0).round() as u32)
+}
+
+/// Durations written by hand in recipe managers, "15 mins", "1 hr 30 min", "1:30", "45" (minutes)
+/// or ISO 8601 like schema.org
+pub fn text_duration_minutes(text: &str) -> Option<u32> {
+    let text = text.trim().to_lowercase();
+
+    if text.is_empty() {
+        return None;
+    }
+    if text.starts_with('p') {
+        return iso_duration_minutes(&text.to_uppercase());
+    }
+    if let Some((hours, minutes)) = text.split_once(':') {
+        return Some(hours.trim().parse::<u32>().ok()? * 60 + minutes.trim().parse::<u32>().ok()?);
+    }
+
+    let mut minutes: f64 = 0.0;
+    let mut number: Option<f64> = None;
+    let mut found = false;
+
+    // "1hr30min" is split into "1 hr 30 min"
+    let mut spaced = String::with_capacity(text.len());
+    for c in text.chars() {
+        if spaced.chars().last().is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit() && c != '.' && c != ',' && last != '.' && last != ',') {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+    }
+
+    for token in spaced.split_whitespace() {
+        if let Ok(value) = token.replace(',', ".").parse::<f64>() {
+            number = Some(value);
+            continue;
+        }
+
+        let Some(value) = number.take() else { continue };
+        if token.starts_with('h') || token.starts_with("tim") {
+            minutes += value * 60.0;
+            found = true;
+        } else if token.starts_with('m') {
+            minutes += value;
+            found = true;
+        } else if token.starts_with('d') {
+            minutes += value * 24.0 * 60.0;
+            found = true;
+        }
+    }
+
+    // A number without a unit is minutes
+    if let Some(value) = number {
+        minutes += value;
+        found = true;
+    }
+
+    found.then_some(minutes.round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::iso_duration_minutes;
+
+    #[test]
+    fn iso_durations() {
+        assert_eq!(iso_duration_minutes("PT1H30M"), Some(90));
+        assert_eq!(iso_duration_minutes("P0DT0H30M"), Some(30));
+        assert_eq!(iso_duration_minutes("P1D"), Some(1440));
+        assert_eq!(iso_duration_minutes("PT90S"), Some(2));
+        assert_eq!(iso_duration_minutes("PT0,5H"), Some(30));
+        assert_eq!(iso_duration_minutes(" PT10M "), Some(10));
+        assert_eq!(iso_duration_minutes("30 minutes"), None);
+        assert_eq!(iso_duration_minutes("PT10"), None);
+        assert_eq!(iso_duration_minutes("PT10X"), None);
+    }
+}
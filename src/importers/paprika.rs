@@ -0,0 +1,149 @@
+use serde_json::Value;
+
+use crate::importers::archive::{field_text, gunzip, is_zip, names, ParsedRecipe, require_title, zip_files};
+use crate::importers::{clean_text, text_duration_minutes};
+use crate::models::import_model::ImportIssue;
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    Paprika (https://www.paprikaapp.com) exports a .paprikarecipes file, a zip with one gzipped JSON
+    document per recipe (.paprikarecipe). Ingredients and directions are single strings with one item per line
+    and the times are free text ("15 mins"). Photos are embedded as base64 in photo_data, we only store
+    photo URLs so image_url is used when the recipe was clipped from the web.
+ */
+
+fn lines(text: Option<String>) -> Vec<String> {
+    text.unwrap_or_default()
+        .lines()
+        .map(clean_text)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+pub fn map_recipe(recipe: &Value, email: &str) -> RecipeDTO {
+    let text = |key: &str| field_text(recipe, &[key]);
+    let duration = |key: &str| text(key).as_deref().and_then(text_duration_minutes);
+
+    // Paprika has separate notes, they are kept after the description
+    let description = [text("description"), text("notes")]
+        .into_iter()
+        .flatten()
+        .map(|text| clean_text(&text))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+
+    RecipeDTO {
+        id: None,
+        title: text("name").map(|name| clean_text(&name)).unwrap_or_default(),
+        description,
+        steps: lines(text("directions")),
+        photo_url: text("image_url").unwrap_or_default(),
+        ingredients: lines(text("ingredients")),
+        email: email.to_string(),
+        tags: names(recipe.get("categories")),
+        recipe_yield: text("servings"),
+        prep_time_minutes: duration("prep_time"),
+        cook_time_minutes: duration("cook_time"),
+        total_time_minutes: duration("total_time"),
+    }
+}
+
+fn parse_entry(name: &str, content: &[u8], email: &str) -> ParsedRecipe {
+    let issue = |reason: String| ImportIssue { title: name.to_string(), reason };
+
+    let json = gunzip(content, name).map_err(issue)?;
+    let recipe = serde_json::from_slice::<Value>(&json).map_err(|err| issue(format!("Invalid JSON: {}", err)))?;
+
+    require_title(map_recipe(&recipe, email), name)
+}
+
+/// A .paprikarecipes archive, or a single .paprikarecipe
+pub fn parse(bytes: &[u8], email: &str) -> Result<Vec<ParsedRecipe>, String> {
+    if !is_zip(bytes) {
+        return Ok(vec![parse_entry("recipe.paprikarecipe", bytes, email)]);
+    }
+
+    Ok(zip_files(bytes)?
+        .iter()
+        .filter(|(name, _)| name.to_lowercase().ends_with(".paprikarecipe"))
+        .map(|(name, content)| parse_entry(name, content, email))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use serde_json::json;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::parse;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn paprika_archive(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn maps_a_paprika_recipe() {
+        let recipe = json!({
+            "name": "Pannkakor",
+            "description": "Tunna",
+            "notes": "Mormors recept",
+            "ingredients": "3 dl vetemjöl\n\n6 dl mjölk\n3 ägg",
+            "directions": "Vispa.\nStek.",
+            "categories": ["Frukost", "Vegetariskt"],
+            "servings": "4",
+            "prep_time": "10 mins",
+            "cook_time": "1 hr 5 min",
+            "image_url": "https://example.com/pannkakor.jpg",
+            "photo_data": "aGVq",
+        });
+        let archive = paprika_archive(&[("Pannkakor.paprikarecipe", gzip(recipe.to_string().as_bytes()))]);
+
+        let recipes = parse(&archive, "cook@example.com").unwrap();
+        let recipe = recipes[0].as_ref().unwrap();
+
+        assert_eq!(recipe.title, "Pannkakor");
+        assert_eq!(recipe.description, "Tunna\n\nMormors recept");
+        assert_eq!(recipe.ingredients, vec!["3 dl vetemjöl", "6 dl mjölk", "3 ägg"]);
+        assert_eq!(recipe.steps, vec!["Vispa.", "Stek."]);
+        assert_eq!(recipe.tags, vec!["Frukost", "Vegetariskt"]);
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("4"));
+        assert_eq!((recipe.prep_time_minutes, recipe.cook_time_minutes, recipe.total_time_minutes), (Some(10), Some(65), None));
+        assert_eq!(recipe.photo_url, "https://example.com/pannkakor.jpg");
+    }
+
+    #[test]
+    fn broken_entries_are_issues_and_other_files_are_ignored() {
+        let archive = paprika_archive(&[
+            ("ok.paprikarecipe", gzip(br#"{"name": "Soup"}"#)),
+            ("not-gzip.paprikarecipe", b"{}".to_vec()),
+            ("no-title.paprikarecipe", gzip(br#"{"ingredients": "1 egg"}"#)),
+            ("photo.jpg", vec![0xFF, 0xD8]),
+        ]);
+
+        let recipes = parse(&archive, "cook@example.com").unwrap();
+        assert_eq!(recipes.len(), 3);
+        assert_eq!(recipes[0].as_ref().unwrap().title, "Soup");
+        assert_eq!(recipes[1].as_ref().unwrap_err().title, "not-gzip.paprikarecipe");
+        assert_eq!(recipes[2].as_ref().unwrap_err().reason, "No title found");
+
+        // A single .paprikarecipe isn't zipped
+        let single = parse(&gzip(br#"{"name": "Bread"}"#), "cook@example.com").unwrap();
+        assert_eq!(single[0].as_ref().unwrap().title, "Bread");
+    }
+}
@@ -0,0 +1,241 @@
+use serde_json::Value;
+
+use crate::importers::{clean_text, iso_duration_minutes};
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    Importer for schema.org/Recipe (https://schema.org/Recipe), which nearly every recipe blog embeds as
+    <script type="application/ld+json"> for search engines. The Recipe object can be the document itself,
+    one entry of an array, part of an "@graph" or the "mainEntity" of a WebPage, and most properties come
+    in several shapes (string, array, nested HowToStep/ImageObject...), which the helpers below flatten.
+ */
+
+/// All JSON-LD blocks embedded in an HTML page, blocks that aren't valid JSON are skipped
+pub fn extract_json_ld(html: &str) -> Vec<Value> {
+    let lowercase = html.to_ascii_lowercase(); // Same byte offsets as `html` since only ASCII changes
+    let mut blocks = Vec::new();
+    let mut position = 0;
+
+    while let Some(start) = lowercase[position..].find("<script") {
+        let tag_start = position + start;
+        let Some(tag_len) = lowercase[tag_start..].find('>') else { break };
+        let content_start = tag_start + tag_len + 1;
+        let Some(content_len) = lowercase[content_start..].find("</script") else { break };
+        let content_end = content_start + content_len;
+
+        if lowercase[tag_start..content_start].contains("application/ld+json") {
+            if let Ok(value) = serde_json::from_str::<Value>(html[content_start..content_end].trim()) {
+                blocks.push(value);
+            }
+        }
+
+        position = content_end;
+    }
+
+    blocks
+}
+
+fn is_recipe(value: &Value) -> bool {
+    match value.get("@type") {
+        Some(Value::String(kind)) => kind == "Recipe" || kind.ends_with("/Recipe"),
+        Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind.as_str().is_some_and(|kind| kind == "Recipe" || kind.ends_with("/Recipe"))),
+        _ => false,
+    }
+}
+
+/// Depth first search for the first object typed as Recipe
+pub fn find_recipe(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_recipe),
+        Value::Object(_) if is_recipe(value) => Some(value),
+        Value::Object(object) => ["@graph", "mainEntity", "mainEntityOfPage", "itemListElement", "item"]
+            .iter()
+            .filter_map(|key| object.get(*key))
+            .find_map(find_recipe),
+        _ => None,
+    }
+}
+
+// "text" | ["a", "b"] | [{ "text": ".." }] -> list of cleaned, non empty strings
+fn text_list(value: Option<&Value>) -> Vec<String> {
+    let mut texts = Vec::new();
+    collect_texts(value, &mut texts);
+    texts
+}
+
+fn collect_texts(value: Option<&Value>, texts: &mut Vec<String>) {
+    match value {
+        Some(Value::String(text)) => {
+            let text = clean_text(text);
+            if !text.is_empty() {
+                texts.push(text);
+            }
+        }
+        Some(Value::Number(number)) => texts.push(number.to_string()),
+        Some(Value::Array(items)) => items.iter().for_each(|item| collect_texts(Some(item), texts)),
+        // HowToSection has its steps in itemListElement, HowToStep/HowToDirection their text in "text" (or "name")
+        Some(Value::Object(object)) => match object.get("itemListElement") {
+            Some(elements) => collect_texts(Some(elements), texts),
+            None => collect_texts(object.get("text").or_else(|| object.get("name")), texts),
+        },
+        _ => {}
+    }
+}
+
+// A plain string with all instructions is split into one step per line
+fn instructions(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(text)) => text
+            .lines()
+            .map(clean_text)
+            .filter(|step| !step.is_empty())
+            .collect(),
+        _ => text_list(value),
+    }
+}
+
+fn first_text(value: Option<&Value>) -> Option<String> {
+    text_list(value).into_iter().next()
+}
+
+// image can be a URL, a list of URLs or ImageObject(s) with "url"
+fn image_url(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(url) => Some(url.trim().to_string()),
+        Value::Array(items) => items.iter().find_map(|item| image_url(Some(item))),
+        Value::Object(object) => image_url(object.get("url").or_else(|| object.get("contentUrl"))),
+        _ => None,
+    }
+}
+
+// keywords is usually "a, b, c" but sometimes an array, category and cuisine are added as tags too
+fn tags(recipe: &Value) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for key in ["keywords", "recipeCategory", "recipeCuisine"] {
+        for text in text_list(recipe.get(key)) {
+            for tag in text.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+                if !tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                    tags.push(tag.to_string());
+                }
+            }
+        }
+    }
+
+    tags
+}
+
+/// Maps a schema.org Recipe object onto our RecipeDTO, the recipe is owned by `email`
+pub fn map_recipe(recipe: &Value, email: &str) -> RecipeDTO {
+    let duration = |key: &str| recipe.get(key).and_then(Value::as_str).and_then(iso_duration_minutes);
+
+    RecipeDTO {
+        id: None,
+        title: first_text(recipe.get("name").or_else(|| recipe.get("headline"))).unwrap_or_default(),
+        description: first_text(recipe.get("description")).unwrap_or_default(),
+        steps: instructions(recipe.get("recipeInstructions")),
+        photo_url: image_url(recipe.get("image")).unwrap_or_default(),
+        ingredients: text_list(recipe.get("recipeIngredient").or_else(|| recipe.get("ingredients"))),
+        email: email.to_string(),
+        tags: tags(recipe),
+        // recipeYield is often ["4", "4 servings"], the last one is the most descriptive
+        recipe_yield: text_list(recipe.get("recipeYield")).pop(),
+        prep_time_minutes: duration("prepTime"),
+        cook_time_minutes: duration("cookTime"),
+        total_time_minutes: duration("totalTime"),
+    }
+}
+
+/// Looks for a schema.org Recipe in a JSON-LD document or in the JSON-LD blocks of an HTML page
+pub fn import_from_json_ld(documents: &[Value], email: &str) -> Option<RecipeDTO> {
+    documents
+        .iter()
+        .find_map(find_recipe)
+        .map(|recipe| map_recipe(recipe, email))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{extract_json_ld, find_recipe, import_from_json_ld, map_recipe};
+
+    const WORDPRESS_GRAPH: &str = include_str!("../../fixtures/import/wordpress_graph.html");
+    const PLAIN_RECIPE: &str = include_str!("../../fixtures/import/plain_recipe.html");
+    const RECIPE_JSON_LD: &str = include_str!("../../fixtures/import/recipe.jsonld");
+
+    #[test]
+    fn extract_json_ld_finds_blocks_regardless_of_tag_case() {
+        let blocks = extract_json_ld(WORDPRESS_GRAPH);
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].get("@graph").is_some());
+
+        let blocks = extract_json_ld(PLAIN_RECIPE);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0]["name"], "Quick Tomato Soup");
+    }
+
+    #[test]
+    fn extract_json_ld_skips_other_scripts_and_invalid_json() {
+        let html = r#"<script>var a = 1;</script>
+            <script type="application/ld+json">{ not json</script>
+            <script type="application/ld+json">{"@type": "Recipe"}</script>"#;
+
+        assert_eq!(extract_json_ld(html), vec![json!({"@type": "Recipe"})]);
+        assert!(extract_json_ld("<script type=\"application/ld+json\">{}").is_empty());
+    }
+
+    #[test]
+    fn find_recipe_searches_graph_and_array_types() {
+        let graph = &extract_json_ld(WORDPRESS_GRAPH)[0];
+        assert_eq!(find_recipe(graph).unwrap()["name"], "Kanelbullar");
+
+        let document: Value = serde_json::from_str(RECIPE_JSON_LD).unwrap();
+        assert_eq!(find_recipe(&document).unwrap()["headline"], "Pancakes");
+
+        let page = json!({"@type": "WebPage", "mainEntity": [{"@type": "Person"}, {"@type": "http://schema.org/Recipe", "name": "Soup"}]});
+        assert_eq!(find_recipe(&page).unwrap()["name"], "Soup");
+
+        assert!(find_recipe(&json!({"@type": "WebSite", "@graph": [{"@type": "Person"}]})).is_none());
+    }
+
+    #[test]
+    fn map_recipe_flattens_sections_entities_and_yields() {
+        let graph = &extract_json_ld(WORDPRESS_GRAPH)[0];
+        let recipe = map_recipe(find_recipe(graph).unwrap(), "anna@example.com");
+
+        assert_eq!(recipe.title, "Kanelbullar");
+        assert_eq!(recipe.description, "Klassiska kanelbullar med kardemumma & pärlsocker.");
+        assert_eq!(recipe.email, "anna@example.com");
+        assert_eq!(recipe.photo_url, "https://example.com/img/kanelbullar.jpg");
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("30 bullar"));
+        assert_eq!(recipe.ingredients.len(), 6);
+        assert_eq!(recipe.ingredients[0], "50 g jäst");
+        assert_eq!(recipe.steps, vec![
+            "Smält smöret och häll i mjölken.",
+            "Smula jästen i en bunke och rör ut den med lite av degvätskan.",
+            "Grädda mitt i ugnen på 225°C i 5–6 minuter.",
+        ]);
+        assert_eq!(recipe.tags, vec!["fika", "bakning", "kanel", "Bakverk", "Svensk"]);
+        assert_eq!((recipe.prep_time_minutes, recipe.cook_time_minutes, recipe.total_time_minutes), (Some(45), Some(10), Some(120)));
+    }
+
+    #[test]
+    fn map_recipe_handles_plain_strings_and_image_objects() {
+        let recipe = import_from_json_ld(&extract_json_ld(PLAIN_RECIPE), "anna@example.com").unwrap();
+        assert_eq!(recipe.photo_url, "https://example.org/photos/tomato-soup.jpg");
+        assert_eq!(recipe.steps.len(), 3);
+        assert_eq!(recipe.steps[2], "Blend until smooth.");
+        assert_eq!(recipe.tags, vec!["soup", "vegetarian"]);
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("4 servings"));
+        assert_eq!(recipe.total_time_minutes, Some(30));
+
+        let document: Value = serde_json::from_str(RECIPE_JSON_LD).unwrap();
+        let recipe = import_from_json_ld(&[document], "anna@example.com").unwrap();
+        assert_eq!(recipe.title, "Pancakes");
+        assert_eq!(recipe.photo_url, "https://example.net/pancakes.png");
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("8"));
+        assert_eq!(recipe.steps[0], "Whisk flour and half of the milk to a smooth batter.");
+        assert_eq!(recipe.total_time_minutes, None);
+    }
+}
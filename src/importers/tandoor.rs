@@ -0,0 +1,152 @@
+use serde_json::Value;
+
+use crate::importers::archive::{field, field_text, json_documents, names, ParsedRecipe, require_title};
+use crate::importers::clean_text;
+use crate::ingredients::format_ingredient;
+use crate::ingredients::units::canonical_unit;
+use crate::models::ingredient_model::Ingredient;
+use crate::models::recipe_model::RecipeDTO;
+
+/*
+    Tandoor (https://tandoor.dev) exports a zip with one zip per recipe, each holding a recipe.json and the image.
+    A Tandoor recipe is a list of steps where every step has its own instruction and ingredients,
+    we flatten them into our ingredient and step lists. Times are whole minutes.
+ */
+
+fn ingredient_line(ingredient: &Value) -> Option<String> {
+    // Headers are section titles like "For the sauce", not ingredients
+    if ingredient.get("is_header").and_then(Value::as_bool).unwrap_or(false) {
+        return None;
+    }
+
+    let no_amount = ingredient.get("no_amount").and_then(Value::as_bool).unwrap_or(false);
+    let food = field(ingredient, &["food"]).and_then(|food| field_text(food, &["name"]))?;
+    let unit = field(ingredient, &["unit"]).and_then(|unit| field_text(unit, &["name"]));
+
+    Some(format_ingredient(&Ingredient {
+        quantity: field(ingredient, &["amount"]).and_then(Value::as_f64).filter(|amount| *amount > 0.0 && !no_amount),
+        unit: unit.filter(|_| !no_amount).map(|unit| canonical_unit(&unit).map(str::to_string).unwrap_or(unit)),
+        name: clean_text(&food),
+        note: field_text(ingredient, &["note"]),
+        line: None,
+    }))
+}
+
+pub fn map_recipe(recipe: &Value, email: &str) -> RecipeDTO {
+    let mut ingredients = Vec::new();
+    let mut steps = Vec::new();
+
+    if let Some(Value::Array(recipe_steps)) = field(recipe, &["steps"]) {
+        for step in recipe_steps {
+            if let Some(Value::Array(items)) = field(step, &["ingredients"]) {
+                ingredients.extend(items.iter().filter_map(ingredient_line));
+            }
+
+            let instruction = field_text(step, &["instruction"]).unwrap_or_default();
+            steps.extend(instruction.lines().map(clean_text).filter(|line| !line.is_empty()));
+        }
+    }
+
+    let minutes = |key: &str| field(recipe, &[key]).and_then(Value::as_u64).map(|minutes| minutes as u32).filter(|minutes| *minutes > 0);
+    let working_time = minutes("working_time");
+    let waiting_time = minutes("waiting_time");
+
+    // "4 portions" from servings and servings_text
+    let recipe_yield = field(recipe, &["servings"]).and_then(Value::as_u64).filter(|servings| *servings > 0).map(|servings| {
+        match field_text(recipe, &["servings_text"]) {
+            Some(text) => format!("{} {}", servings, text),
+            None => servings.to_string(),
+        }
+    });
+
+    RecipeDTO {
+        id: None,
+        title: field_text(recipe, &["name"]).map(|name| clean_text(&name)).unwrap_or_default(),
+        description: field_text(recipe, &["description"]).map(|text| clean_text(&text)).unwrap_or_default(),
+        steps,
+        photo_url: String::new(), // The image is a file in the zip, we only store URLs
+        ingredients,
+        email: email.to_string(),
+        tags: names(field(recipe, &["keywords"])),
+        recipe_yield,
+        prep_time_minutes: working_time,
+        cook_time_minutes: waiting_time,
+        total_time_minutes: match (working_time, waiting_time) {
+            (None, None) => None,
+            (working, waiting) => Some(working.unwrap_or(0) + waiting.unwrap_or(0)),
+        },
+    }
+}
+
+pub fn parse(bytes: &[u8], email: &str) -> Result<Vec<ParsedRecipe>, String> {
+    Ok(json_documents(bytes)?
+        .into_iter()
+        .map(|document| document.and_then(|recipe| require_title(map_recipe(&recipe, email), "recipe.json")))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use serde_json::json;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::parse;
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn flattens_steps_from_a_zip_of_zips() {
+        let recipe = json!({
+            "name": "Lasagne",
+            "description": "Söndagsmiddag",
+            "keywords": [{"name": "pasta"}, {"name": "ugn"}],
+            "working_time": 30,
+            "waiting_time": 45,
+            "servings": 4,
+            "servings_text": "portioner",
+            "steps": [
+                {"instruction": "Gör köttfärssåsen.", "ingredients": [
+                    {"is_header": true, "food": {"name": "Sås"}},
+                    {"amount": 500, "unit": {"name": "gram"}, "food": {"name": "köttfärs"}, "note": ""},
+                    {"amount": 1, "unit": {"name": "pinch"}, "food": {"name": "salt"}, "no_amount": true},
+                ]},
+                {"instruction": "Varva.\n\nGrädda.", "ingredients": [
+                    {"amount": 12, "unit": null, "food": {"name": "lasagneplattor"}},
+                ]},
+            ],
+        }).to_string();
+        let inner = zip(&[("recipe.json", recipe.as_bytes()), ("image.jpg", &[0xFF, 0xD8])]);
+        let export = zip(&[("1.zip", &inner)]);
+
+        let recipes = parse(&export, "cook@example.com").unwrap();
+        assert_eq!(recipes.len(), 1);
+
+        let lasagne = recipes[0].as_ref().unwrap();
+        assert_eq!(lasagne.title, "Lasagne");
+        assert_eq!(lasagne.ingredients, vec!["500 g köttfärs", "salt", "12 lasagneplattor"]);
+        assert_eq!(lasagne.steps, vec!["Gör köttfärssåsen.", "Varva.", "Grädda."]);
+        assert_eq!(lasagne.tags, vec!["pasta", "ugn"]);
+        assert_eq!(lasagne.recipe_yield.as_deref(), Some("4 portioner"));
+        assert_eq!((lasagne.prep_time_minutes, lasagne.cook_time_minutes, lasagne.total_time_minutes), (Some(30), Some(45), Some(75)));
+    }
+
+    #[test]
+    fn single_recipe_json_without_times() {
+        let recipes = parse(br#"{"name": "Toast", "steps": [], "working_time": 0}"#, "cook@example.com").unwrap();
+        let toast = recipes[0].as_ref().unwrap();
+
+        assert_eq!(toast.title, "Toast");
+        assert_eq!(toast.total_time_minutes, None);
+        assert_eq!(toast.recipe_yield, None);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::ingredients::normalize_name;
+use crate::models::shopping_list_model::Aisle;
+
+/*
+    Which part of the store an ingredient is found in, by keywords in English and Swedish.
+    It's a heuristic, the first group with a match wins so the order of the groups matters
+    ("frozen peas" is Frozen and not Produce). Short keywords have to be a whole word ("te" is tea,
+    not the "te" in "tomater"), longer ones also match inside Swedish compound words ("vetemjöl").
+ */
+
+const AISLE_KEYWORDS: [(Aisle, &[&str]); 9] = [
+    (Aisle::Frozen, &["frozen", "fryst", "frysta", "glass", "ice cream"]),
+    (Aisle::Produce, &[
+        "onion", "lök", "garlic", "tomato", "tomat", "potato", "potatis", "carrot", "morot", "morötter", "lettuce", "sallad",
+        "apple", "äpple", "lemon", "citron", "lime", "banana", "banan", "spinach", "spenat", "cucumber", "gurka", "basil",
+        "parsley", "persilja", "coriander", "koriander", "cilantro", "dill", "ginger", "ingefära", "avocado", "avokado",
+        "mushroom", "svamp", "champinjon", "broccoli", "cabbage", "kål", "zucchini", "squash", "celery", "selleri", "berries",
+        "bär", "orange", "apelsin", "leek", "chili", "bell pepper", "paprika", "pear", "päron", "mango", "herbs", "kale",
+    ]),
+    (Aisle::Meat, &["chicken", "kyckling", "beef", "nötkött", "pork", "fläsk", "bacon", "ham", "skinka", "mince", "färs", "sausage", "korv", "lamb", "lamm", "turkey", "kalkon"]),
+    (Aisle::Fish, &["fish", "fisk", "salmon", "lax", "tuna", "tonfisk", "shrimp", "prawn", "räkor", "cod", "torsk", "sill", "herring"]),
+    (Aisle::Dairy, &[
+        "milk", "mjölk", "butter", "smör", "cream", "grädde", "cheese", "ost", "yogurt", "yoghurt", "egg", "eggs", "ägg",
+        "crème fraiche", "creme fraiche", "fil", "filmjölk", "kvarg", "quark", "parmesan", "mozzarella", "feta", "halloumi",
+    ]),
+    (Aisle::Bakery, &["bread", "bröd", "tortilla", "bun", "baguette", "pita", "knäckebröd"]),
+    (Aisle::Spices, &[
+        "salt", "pepper", "peppar", "cinnamon", "kanel", "cumin", "spiskummin", "oregano", "thyme", "timjan", "vanilla",
+        "vanilj", "cardamom", "kardemumma", "nutmeg", "muskot", "curry", "bay leaf", "lagerblad", "turmeric", "gurkmeja", "spice", "krydd",
+    ]),
+    (Aisle::Beverages, &["wine", "vin", "beer", "öl", "juice", "coffee", "kaffe", "tea", "te", "soda", "lemonad"]),
+    (Aisle::Pantry, &[
+        "flour", "mjöl", "sugar", "socker", "rice", "ris", "pasta", "spaghetti", "noodles", "nudlar", "oil", "olja", "vinegar",
+        "vinäger", "ättika", "honey", "honung", "yeast", "jäst", "baking powder", "bakpulver", "bikarbonat", "beans", "bönor",
+        "lentils", "linser", "stock", "buljong", "fond", "sauce", "sås", "canned", "krossade", "oats", "havre", "nuts", "nötter",
+        "almond", "mandel", "chocolate", "choklad", "cocoa", "kakao", "syrup", "sirap", "soy", "soja", "ketchup", "senap", "mustard",
+    ]),
+];
+
+fn matches(name: &str, keyword: &str) -> bool {
+    if keyword.chars().count() < 4 {
+        return name.split(' ').any(|word| word == keyword);
+    }
+    name.contains(keyword)
+}
+
+pub fn aisle_for(ingredient_name: &str) -> Aisle {
+    let name = normalize_name(ingredient_name);
+
+    AISLE_KEYWORDS
+        .iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| matches(&name, keyword)))
+        .map(|(aisle, _)| *aisle)
+        .unwrap_or(Aisle::Other)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::shopping_list_model::Aisle;
+
+    use super::aisle_for;
+
+    #[test]
+    fn keywords_in_english_and_swedish() {
+        assert_eq!(aisle_for("Red onions"), Aisle::Produce);
+        assert_eq!(aisle_for("gul lök"), Aisle::Produce);
+        assert_eq!(aisle_for("kycklingfilé"), Aisle::Meat);
+        assert_eq!(aisle_for("Rökt lax"), Aisle::Fish);
+        assert_eq!(aisle_for("crème fraiche"), Aisle::Dairy);
+        assert_eq!(aisle_for("vetemjöl"), Aisle::Pantry);
+        assert_eq!(aisle_for("Knäckebröd"), Aisle::Bakery);
+        assert_eq!(aisle_for("stött kardemumma"), Aisle::Spices);
+        assert_eq!(aisle_for("something else"), Aisle::Other);
+    }
+
+    #[test]
+    fn earlier_groups_win() {
+        assert_eq!(aisle_for("frozen peas"), Aisle::Frozen);
+        assert_eq!(aisle_for("frysta bär"), Aisle::Frozen);
+        assert_eq!(aisle_for("tomato sauce"), Aisle::Produce);
+    }
+
+    #[test]
+    fn short_keywords_are_whole_words() {
+        assert_eq!(aisle_for("te"), Aisle::Beverages);
+        assert_eq!(aisle_for("grönt te"), Aisle::Beverages);
+        assert_eq!(aisle_for("tomater"), Aisle::Produce);
+        assert_eq!(aisle_for("ost"), Aisle::Dairy);
+        assert_eq!(aisle_for("postej"), Aisle::Other);
+        assert_eq!(aisle_for("egg"), Aisle::Dairy);
+        assert_eq!(aisle_for("eggplant"), Aisle::Other);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeSet;
+
+use crate::ingredients::{covers, ingredient_key, parse_ingredient};
+use crate::models::dietary_model::{Allergen, Dietary, DietaryLabel, DietaryOverride};
+
+/*
+    Ingredient to allergen mapping, English and Swedish names, and the dietary labels derived from it.
+    Every keyword that covers an ingredient name counts (see covers() in mod.rs), except keywords that are
+    part of a longer keyword that also matches: "coconut milk" overrides "milk", "jordnötssmör" overrides "smör".
+    That is how the exceptions below work, they are entries with the allergens of the whole name.
+    It's a best effort from the names alone and the owner can override the result. Ingredients that match no keyword
+    are listed in `unknown_ingredients`, the recipe gets no derived labels and isn't found by free_from filters while
+    there are any, the plain ingredients at the end of the table keep that list short.
+
+    Keywords go through ingredient_key() like the ingredient names, "färs" becomes "fär" and is too short to match the
+    end of a compound word, so the compounds are listed.
+    Bump TABLE_VERSION when changing the table, stored recipes are reclassified at startup (jobs/dietary_backfill.rs).
+ */
+
+pub const TABLE_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Origin {
+    Any,    // Plants, or animal products that show up as an allergen (milk, eggs, fish...)
+    Meat,   // Not vegetarian or pescatarian, gelatin and lard included
+    Animal, // Vegetarian but not vegan, like honey
+}
+
+use Allergen::*;
+
+const NONE: &[Allergen] = &[];
+
+const INGREDIENTS: &[(&str, &[Allergen], Origin)] = &[
+    // Cereals containing gluten
+    ("wheat", &[Gluten], Origin::Any), ("vete", &[Gluten], Origin::Any), ("flour", &[Gluten], Origin::Any), ("mjöl", &[Gluten], Origin::Any),
+    ("bread", &[Gluten], Origin::Any), ("bröd", &[Gluten], Origin::Any), ("breadcrumb", &[Gluten], Origin::Any), ("ströbröd", &[Gluten], Origin::Any),
+    ("panko", &[Gluten], Origin::Any), ("pasta", &[Gluten], Origin::Any), ("spaghetti", &[Gluten], Origin::Any), ("macaroni", &[Gluten], Origin::Any),
+    ("makaroner", &[Gluten], Origin::Any), ("penne", &[Gluten], Origin::Any), ("fusilli", &[Gluten], Origin::Any), ("lasagne", &[Gluten], Origin::Any),
+    ("lasagna", &[Gluten], Origin::Any), ("tagliatelle", &[Gluten], Origin::Any), ("noodle", &[Gluten], Origin::Any), ("nudlar", &[Gluten], Origin::Any),
+    ("couscous", &[Gluten], Origin::Any), ("bulgur", &[Gluten], Origin::Any), ("barley", &[Gluten], Origin::Any), ("rye", &[Gluten], Origin::Any),
+    ("råg", &[Gluten], Origin::Any), ("spelt", &[Gluten], Origin::Any), ("dinkel", &[Gluten], Origin::Any), ("semolina", &[Gluten], Origin::Any),
+    ("durum", &[Gluten], Origin::Any), ("oat", &[Gluten], Origin::Any), ("havre", &[Gluten], Origin::Any), ("havregryn", &[Gluten], Origin::Any),
+    ("seitan", &[Gluten], Origin::Any), ("tortilla", &[Gluten], Origin::Any), ("pita", &[Gluten], Origin::Any), ("naan", &[Gluten], Origin::Any),
+    ("cracker", &[Gluten], Origin::Any), ("biscuit", &[Gluten], Origin::Any), ("kex", &[Gluten], Origin::Any), ("malt", &[Gluten], Origin::Any),
+    ("beer", &[Gluten], Origin::Any), ("öl", &[Gluten], Origin::Any), ("puff pastry", &[Gluten, Milk], Origin::Any), ("smördeg", &[Gluten, Milk], Origin::Any),
+    ("egg noodle", &[Gluten, Eggs], Origin::Any), ("äggnudlar", &[Gluten, Eggs], Origin::Any),
+    // Gluten free in spite of the name
+    ("buckwheat", NONE, Origin::Any), ("bovete", NONE, Origin::Any), ("rice flour", NONE, Origin::Any), ("rismjöl", NONE, Origin::Any),
+    ("corn flour", NONE, Origin::Any), ("cornflour", NONE, Origin::Any), ("majsmjöl", NONE, Origin::Any), ("majsstärkelse", NONE, Origin::Any),
+    ("potato flour", NONE, Origin::Any), ("potatismjöl", NONE, Origin::Any), ("coconut flour", NONE, Origin::Any), ("kokosmjöl", NONE, Origin::Any),
+    ("chickpea flour", NONE, Origin::Any), ("kikärtsmjöl", NONE, Origin::Any), ("rice noodle", NONE, Origin::Any), ("risnudlar", NONE, Origin::Any),
+    ("corn tortilla", NONE, Origin::Any), ("majstortilla", NONE, Origin::Any), ("gluten free", NONE, Origin::Any), ("glutenfri", NONE, Origin::Any),
+    ("gluten free flour", NONE, Origin::Any), ("gluten free pasta", NONE, Origin::Any), ("gluten free bread", NONE, Origin::Any),
+    ("glutenfritt mjöl", NONE, Origin::Any), ("glutenfri pasta", NONE, Origin::Any), ("glutenfritt bröd", NONE, Origin::Any),
+    ("tamari", &[Soybeans], Origin::Any),
+    // Crustaceans
+    ("shrimp", &[Crustaceans], Origin::Any), ("prawn", &[Crustaceans], Origin::Any), ("räka", &[Crustaceans], Origin::Any), ("räkor", &[Crustaceans], Origin::Any),
+    ("crab", &[Crustaceans], Origin::Any), ("krabba", &[Crustaceans], Origin::Any), ("lobster", &[Crustaceans], Origin::Any), ("hummer", &[Crustaceans], Origin::Any),
+    ("crayfish", &[Crustaceans], Origin::Any), ("kräfta", &[Crustaceans], Origin::Any), ("kräftor", &[Crustaceans], Origin::Any),
+    ("langoustine", &[Crustaceans], Origin::Any), ("scampi", &[Crustaceans], Origin::Any),
+    // Eggs
+    ("egg", &[Eggs], Origin::Any), ("ägg", &[Eggs], Origin::Any), ("äggula", &[Eggs], Origin::Any), ("äggulor", &[Eggs], Origin::Any),
+    ("äggvita", &[Eggs], Origin::Any), ("äggvitor", &[Eggs], Origin::Any), ("mayonnaise", &[Eggs], Origin::Any), ("mayo", &[Eggs], Origin::Any),
+    ("majonnäs", &[Eggs], Origin::Any), ("aioli", &[Eggs], Origin::Any), ("meringue", &[Eggs], Origin::Any), ("maräng", &[Eggs], Origin::Any),
+    ("vegan mayo", NONE, Origin::Any), ("vegan mayonnaise", NONE, Origin::Any), ("vegansk majonnäs", NONE, Origin::Any),
+    // Fish
+    ("fish", &[Fish], Origin::Any), ("fisk", &[Fish], Origin::Any), ("salmon", &[Fish], Origin::Any), ("lax", &[Fish], Origin::Any),
+    ("gravlax", &[Fish], Origin::Any), ("laxfilé", &[Fish], Origin::Any), ("cod", &[Fish], Origin::Any), ("torsk", &[Fish], Origin::Any),
+    ("tuna", &[Fish], Origin::Any), ("tonfisk", &[Fish], Origin::Any), ("haddock", &[Fish], Origin::Any), ("kolja", &[Fish], Origin::Any),
+    ("herring", &[Fish], Origin::Any), ("sill", &[Fish], Origin::Any), ("strömming", &[Fish], Origin::Any), ("mackerel", &[Fish], Origin::Any),
+    ("makrill", &[Fish], Origin::Any), ("anchovy", &[Fish], Origin::Any), ("anchovie", &[Fish], Origin::Any), ("ansjovis", &[Fish], Origin::Any),
+    ("sardine", &[Fish], Origin::Any), ("sardin", &[Fish], Origin::Any), ("trout", &[Fish], Origin::Any), ("öring", &[Fish], Origin::Any),
+    ("pollock", &[Fish], Origin::Any), ("sej", &[Fish], Origin::Any), ("halibut", &[Fish], Origin::Any), ("hälleflundra", &[Fish], Origin::Any),
+    ("tilapia", &[Fish], Origin::Any), ("pike perch", &[Fish], Origin::Any), ("gös", &[Fish], Origin::Any), ("caviar", &[Fish], Origin::Any),
+    ("kaviar", &[Fish], Origin::Any), ("löjrom", &[Fish], Origin::Any), ("fish sauce", &[Fish], Origin::Any), ("fisksås", &[Fish], Origin::Any),
+    ("worcestershire", &[Fish], Origin::Any), ("fiskbuljong", &[Fish], Origin::Any), ("fiskfond", &[Fish], Origin::Any),
+    // Peanuts
+    ("peanut", &[Peanuts], Origin::Any), ("jordnöt", &[Peanuts], Origin::Any), ("jordnötter", &[Peanuts], Origin::Any),
+    ("peanut butter", &[Peanuts], Origin::Any), ("jordnötssmör", &[Peanuts], Origin::Any), ("peanut oil", &[Peanuts], Origin::Any),
+    // Soybeans
+    ("soy", &[Soybeans], Origin::Any), ("soya", &[Soybeans], Origin::Any), ("soja", &[Soybeans], Origin::Any), ("soy sauce", &[Soybeans, Gluten], Origin::Any),
+    ("sojasås", &[Soybeans, Gluten], Origin::Any), ("tofu", &[Soybeans], Origin::Any), ("tempeh", &[Soybeans], Origin::Any), ("miso", &[Soybeans], Origin::Any),
+    ("edamame", &[Soybeans], Origin::Any), ("sojafärs", &[Soybeans], Origin::Any), ("soy milk", &[Soybeans], Origin::Any), ("sojamjölk", &[Soybeans], Origin::Any),
+    ("sojadryck", &[Soybeans], Origin::Any), ("hoisin", &[Soybeans, Gluten], Origin::Any), ("teriyaki", &[Soybeans, Gluten], Origin::Any),
+    // Milk
+    ("milk", &[Milk], Origin::Any), ("mjölk", &[Milk], Origin::Any), ("butter", &[Milk], Origin::Any), ("smör", &[Milk], Origin::Any),
+    ("cream", &[Milk], Origin::Any), ("grädde", &[Milk], Origin::Any), ("cheese", &[Milk], Origin::Any), ("ost", &[Milk], Origin::Any),
+    ("fetaost", &[Milk], Origin::Any), ("färskost", &[Milk], Origin::Any), ("getost", &[Milk], Origin::Any), ("parmesanost", &[Milk], Origin::Any),
+    ("västerbottensost", &[Milk], Origin::Any), ("prästost", &[Milk], Origin::Any), ("mozzarella", &[Milk], Origin::Any), ("parmesan", &[Milk], Origin::Any),
+    ("parmigiano", &[Milk], Origin::Any), ("cheddar", &[Milk], Origin::Any), ("ricotta", &[Milk], Origin::Any), ("mascarpone", &[Milk], Origin::Any),
+    ("halloumi", &[Milk], Origin::Any), ("feta", &[Milk], Origin::Any), ("brie", &[Milk], Origin::Any), ("gruyere", &[Milk], Origin::Any),
+    ("gruyère", &[Milk], Origin::Any), ("gouda", &[Milk], Origin::Any), ("pecorino", &[Milk], Origin::Any), ("paneer", &[Milk], Origin::Any),
+    ("yogurt", &[Milk], Origin::Any), ("yoghurt", &[Milk], Origin::Any), ("kefir", &[Milk], Origin::Any), ("crème fraiche", &[Milk], Origin::Any),
+    ("creme fraiche", &[Milk], Origin::Any), ("gräddfil", &[Milk], Origin::Any), ("ghee", &[Milk], Origin::Any), ("whey", &[Milk], Origin::Any),
+    ("vassle", &[Milk], Origin::Any), ("kvarg", &[Milk], Origin::Any), ("quark", &[Milk], Origin::Any), ("keso", &[Milk], Origin::Any),
+    ("ice cream", &[Milk], Origin::Any), ("glass", &[Milk], Origin::Any), ("milk chocolate", &[Milk], Origin::Any), ("mjölkchoklad", &[Milk], Origin::Any),
+    // Not dairy in spite of the name
+    ("coconut milk", NONE, Origin::Any), ("coconut cream", NONE, Origin::Any), ("kokosmjölk", NONE, Origin::Any), ("kokosgrädde", NONE, Origin::Any),
+    ("rice milk", NONE, Origin::Any), ("rismjölk", NONE, Origin::Any), ("oat milk", &[Gluten], Origin::Any), ("havremjölk", &[Gluten], Origin::Any),
+    ("havredryck", &[Gluten], Origin::Any), ("oat cream", &[Gluten], Origin::Any), ("havregrädde", &[Gluten], Origin::Any),
+    ("cream of tartar", NONE, Origin::Any), ("cocoa butter", NONE, Origin::Any), ("kakaosmör", NONE, Origin::Any), ("butter bean", NONE, Origin::Any),
+    ("vegan butter", NONE, Origin::Any), ("vegan cheese", NONE, Origin::Any), ("plant milk", NONE, Origin::Any), ("vegansk ost", NONE, Origin::Any),
+    ("växtbaserat smör", NONE, Origin::Any),
+    // Tree nuts
+    ("nut", &[Nuts], Origin::Any), ("nöt", &[Nuts], Origin::Any), ("nötter", &[Nuts], Origin::Any), ("almond", &[Nuts], Origin::Any),
+    ("mandel", &[Nuts], Origin::Any), ("mandlar", &[Nuts], Origin::Any), ("hazelnut", &[Nuts], Origin::Any), ("hasselnöt", &[Nuts], Origin::Any),
+    ("hasselnötter", &[Nuts], Origin::Any), ("walnut", &[Nuts], Origin::Any), ("valnöt", &[Nuts], Origin::Any), ("valnötter", &[Nuts], Origin::Any),
+    ("cashew", &[Nuts], Origin::Any), ("cashewnötter", &[Nuts], Origin::Any), ("pecan", &[Nuts], Origin::Any), ("pekannötter", &[Nuts], Origin::Any),
+    ("pistachio", &[Nuts], Origin::Any), ("pistasch", &[Nuts], Origin::Any), ("pistaschnötter", &[Nuts], Origin::Any), ("brazil nut", &[Nuts], Origin::Any),
+    ("paranötter", &[Nuts], Origin::Any), ("macadamia", &[Nuts], Origin::Any), ("marzipan", &[Nuts], Origin::Any), ("marsipan", &[Nuts], Origin::Any),
+    ("mandelmassa", &[Nuts], Origin::Any), ("praline", &[Nuts, Milk], Origin::Any), ("nutella", &[Nuts, Milk], Origin::Any),
+    ("almond milk", &[Nuts], Origin::Any), ("mandelmjölk", &[Nuts], Origin::Any), ("mandeldryck", &[Nuts], Origin::Any), ("almond flour", &[Nuts], Origin::Any),
+    ("mandelmjöl", &[Nuts], Origin::Any), ("almond butter", &[Nuts], Origin::Any), ("nutmeg", NONE, Origin::Any), ("coconut", NONE, Origin::Any),
+    // Celery, mustard, sesame, lupin
+    ("celery", &[Celery], Origin::Any), ("celeriac", &[Celery], Origin::Any), ("selleri", &[Celery], Origin::Any), ("celery salt", &[Celery], Origin::Any),
+    ("mustard", &[Mustard], Origin::Any), ("senap", &[Mustard], Origin::Any), ("dijon", &[Mustard], Origin::Any), ("senapsfrö", &[Mustard], Origin::Any),
+    ("sesame", &[Sesame], Origin::Any), ("sesam", &[Sesame], Origin::Any), ("sesamfrö", &[Sesame], Origin::Any), ("tahini", &[Sesame], Origin::Any),
+    ("hummus", &[Sesame], Origin::Any), ("sesamolja", &[Sesame], Origin::Any), ("lupin", &[Lupin], Origin::Any), ("lupine", &[Lupin], Origin::Any),
+    // Sulphites
+    ("wine", &[Sulphites], Origin::Any), ("vin", &[Sulphites], Origin::Any), ("rödvin", &[Sulphites], Origin::Any), ("vitvin", &[Sulphites], Origin::Any),
+    ("sherry", &[Sulphites], Origin::Any), ("marsala", &[Sulphites], Origin::Any), ("wine vinegar", &[Sulphites], Origin::Any), ("vinäger", &[Sulphites], Origin::Any),
+    ("balsamic", &[Sulphites], Origin::Any), ("balsamvinäger", &[Sulphites], Origin::Any), ("dried apricot", &[Sulphites], Origin::Any),
+    ("torkade aprikoser", &[Sulphites], Origin::Any),
+    // Molluscs
+    ("mussel", &[Molluscs], Origin::Any), ("musslor", &[Molluscs], Origin::Any), ("clam", &[Molluscs], Origin::Any), ("oyster", &[Molluscs], Origin::Any),
+    ("ostron", &[Molluscs], Origin::Any), ("squid", &[Molluscs], Origin::Any), ("bläckfisk", &[Molluscs], Origin::Any), ("octopus", &[Molluscs], Origin::Any),
+    ("calamari", &[Molluscs], Origin::Any), ("scallop", &[Molluscs], Origin::Any), ("pilgrimsmusslor", &[Molluscs], Origin::Any),
+    ("snail", &[Molluscs], Origin::Any), ("escargot", &[Molluscs], Origin::Any), ("oyster sauce", &[Molluscs], Origin::Any), ("ostronsås", &[Molluscs], Origin::Any),
+    // Meat
+    ("meat", NONE, Origin::Meat), ("kött", NONE, Origin::Meat), ("beef", NONE, Origin::Meat), ("steak", NONE, Origin::Meat),
+    ("biff", NONE, Origin::Meat), ("entrecote", NONE, Origin::Meat), ("entrecôte", NONE, Origin::Meat), ("oxfilé", NONE, Origin::Meat),
+    ("mince", NONE, Origin::Meat), ("köttfärs", NONE, Origin::Meat), ("nötfärs", NONE, Origin::Meat), ("fläskfärs", NONE, Origin::Meat),
+    ("blandfärs", NONE, Origin::Meat), ("lammfärs", NONE, Origin::Meat), ("kycklingfärs", NONE, Origin::Meat), ("viltfärs", NONE, Origin::Meat),
+    ("pork", NONE, Origin::Meat), ("fläsk", NONE, Origin::Meat), ("fläskfilé", NONE, Origin::Meat), ("fläskkarré", NONE, Origin::Meat),
+    ("bacon", NONE, Origin::Meat), ("ham", NONE, Origin::Meat),
+    ("skinka", NONE, Origin::Meat), ("sausage", NONE, Origin::Meat), ("korv", NONE, Origin::Meat), ("salami", NONE, Origin::Meat),
+    ("chorizo", NONE, Origin::Meat), ("pepperoni", NONE, Origin::Meat), ("prosciutto", NONE, Origin::Meat), ("pancetta", NONE, Origin::Meat),
+    ("lamb", NONE, Origin::Meat), ("lamm", NONE, Origin::Meat), ("chicken", NONE, Origin::Meat), ("kyckling", NONE, Origin::Meat),
+    ("kycklingfilé", NONE, Origin::Meat), ("kycklinglår", NONE, Origin::Meat), ("kycklingbröst", NONE, Origin::Meat), ("kycklingbuljong", NONE, Origin::Meat),
+    ("turkey", NONE, Origin::Meat), ("kalkon", NONE, Origin::Meat), ("duck", NONE, Origin::Meat), ("anka", NONE, Origin::Meat),
+    ("veal", NONE, Origin::Meat), ("kalv", NONE, Origin::Meat), ("venison", NONE, Origin::Meat), ("hjort", NONE, Origin::Meat),
+    ("älg", NONE, Origin::Meat), ("renskav", NONE, Origin::Meat), ("liver", NONE, Origin::Meat), ("lever", NONE, Origin::Meat),
+    ("meatball", NONE, Origin::Meat), ("köttbullar", NONE, Origin::Meat), ("köttbuljong", NONE, Origin::Meat), ("oxbuljong", NONE, Origin::Meat),
+    ("kalvfond", NONE, Origin::Meat), ("bone broth", NONE, Origin::Meat), ("gelatin", NONE, Origin::Meat), ("gelatine", NONE, Origin::Meat),
+    ("gelatinblad", NONE, Origin::Meat), ("lard", NONE, Origin::Meat), ("ister", NONE, Origin::Meat), ("blodpudding", NONE, Origin::Meat),
+    // Vegetarian replacements
+    ("vegokorv", NONE, Origin::Any), ("vegofärs", NONE, Origin::Any), ("vegetarian sausage", NONE, Origin::Any), ("vegan sausage", NONE, Origin::Any),
+    ("vegetable stock", NONE, Origin::Any), ("grönsaksbuljong", NONE, Origin::Any), ("grönsaksfond", NONE, Origin::Any), ("eggplant", NONE, Origin::Any),
+    // Other animal products
+    ("honey", NONE, Origin::Animal), ("honung", NONE, Origin::Animal),
+    // Plain ingredients without any of the allergens
+    ("salt", NONE, Origin::Any), ("pepper", NONE, Origin::Any), ("peppar", NONE, Origin::Any), ("svartpeppar", NONE, Origin::Any),
+    ("sugar", NONE, Origin::Any), ("socker", NONE, Origin::Any), ("syrup", NONE, Origin::Any), ("sirap", NONE, Origin::Any),
+    ("water", NONE, Origin::Any), ("vatten", NONE, Origin::Any), ("oil", NONE, Origin::Any), ("olja", NONE, Origin::Any),
+    ("olive oil", NONE, Origin::Any), ("olivolja", NONE, Origin::Any), ("rapsolja", NONE, Origin::Any), ("onion", NONE, Origin::Any),
+    ("lök", NONE, Origin::Any), ("gul lök", NONE, Origin::Any), ("rödlök", NONE, Origin::Any), ("purjolök", NONE, Origin::Any),
+    ("garlic", NONE, Origin::Any), ("vitlök", NONE, Origin::Any), ("vitlöksklyfta", NONE, Origin::Any), ("tomato", NONE, Origin::Any),
+    ("tomat", NONE, Origin::Any), ("tomater", NONE, Origin::Any), ("krossade tomater", NONE, Origin::Any), ("tomatpuré", NONE, Origin::Any),
+    ("tomato paste", NONE, Origin::Any), ("potato", NONE, Origin::Any), ("potatis", NONE, Origin::Any), ("carrot", NONE, Origin::Any),
+    ("morot", NONE, Origin::Any), ("morötter", NONE, Origin::Any), ("rice", NONE, Origin::Any), ("ris", NONE, Origin::Any),
+    ("lemon", NONE, Origin::Any), ("citron", NONE, Origin::Any), ("lime", NONE, Origin::Any), ("apple", NONE, Origin::Any),
+    ("äpple", NONE, Origin::Any), ("banana", NONE, Origin::Any), ("banan", NONE, Origin::Any), ("paprika", NONE, Origin::Any),
+    ("chili", NONE, Origin::Any), ("cucumber", NONE, Origin::Any), ("gurka", NONE, Origin::Any), ("spinach", NONE, Origin::Any),
+    ("spenat", NONE, Origin::Any), ("mushroom", NONE, Origin::Any), ("champinjoner", NONE, Origin::Any), ("svamp", NONE, Origin::Any),
+    ("broccoli", NONE, Origin::Any), ("zucchini", NONE, Origin::Any), ("cabbage", NONE, Origin::Any), ("vitkål", NONE, Origin::Any),
+    ("bean", NONE, Origin::Any), ("bönor", NONE, Origin::Any), ("lentil", NONE, Origin::Any), ("linser", NONE, Origin::Any),
+    ("chickpea", NONE, Origin::Any), ("kikärtor", NONE, Origin::Any), ("corn", NONE, Origin::Any), ("majs", NONE, Origin::Any),
+    ("pea", NONE, Origin::Any), ("ärtor", NONE, Origin::Any), ("parsley", NONE, Origin::Any), ("persilja", NONE, Origin::Any),
+    ("basil", NONE, Origin::Any), ("basilika", NONE, Origin::Any), ("dill", NONE, Origin::Any), ("thyme", NONE, Origin::Any),
+    ("timjan", NONE, Origin::Any), ("oregano", NONE, Origin::Any), ("cinnamon", NONE, Origin::Any), ("kanel", NONE, Origin::Any),
+    ("cardamom", NONE, Origin::Any), ("kardemumma", NONE, Origin::Any), ("ginger", NONE, Origin::Any), ("ingefära", NONE, Origin::Any),
+    ("cumin", NONE, Origin::Any), ("spiskummin", NONE, Origin::Any), ("vanilla", NONE, Origin::Any), ("vanilj", NONE, Origin::Any),
+    ("baking powder", NONE, Origin::Any), ("bakpulver", NONE, Origin::Any), ("baking soda", NONE, Origin::Any), ("bikarbonat", NONE, Origin::Any),
+    ("yeast", NONE, Origin::Any), ("jäst", NONE, Origin::Any), ("cocoa", NONE, Origin::Any), ("kakao", NONE, Origin::Any),
+];
+
+struct Classification {
+    allergens: BTreeSet<Allergen>,
+    meat: bool,
+    animal: bool,
+    unknown: Vec<String>, // Names that matched no keyword
+}
+
+// The ingredient keys of the table are computed once per recipe, the table is small
+fn classify(lines: &[String]) -> Classification {
+    let table: Vec<(String, &[Allergen], Origin)> = INGREDIENTS
+        .iter()
+        .map(|(keyword, allergens, origin)| (ingredient_key(keyword), *allergens, *origin))
+        .collect();
+
+    let mut classification = Classification { allergens: BTreeSet::new(), meat: false, animal: false, unknown: Vec::new() };
+
+    for line in lines {
+        let parsed = parse_ingredient(line);
+        let name = ingredient_key(&parsed.name);
+        if name.is_empty() {
+            continue;
+        }
+
+        let matches: Vec<&(String, &[Allergen], Origin)> = table.iter().filter(|(keyword, _, _)| covers(keyword, &name)).collect();
+        if matches.is_empty() && !classification.unknown.contains(&parsed.name) {
+            classification.unknown.push(parsed.name.clone());
+        }
+
+        for (keyword, allergens, origin) in &matches {
+            // "milk" doesn't count when "coconut milk" matched too
+            if matches.iter().any(|(longer, _, _)| longer != keyword && longer.contains(keyword.as_str())) {
+                continue;
+            }
+
+            classification.allergens.extend(allergens.iter());
+            classification.meat |= *origin == Origin::Meat;
+            classification.animal |= *origin == Origin::Animal;
+        }
+    }
+
+    classification
+}
+
+fn labels(classification: &Classification) -> Vec<DietaryLabel> {
+    let has = |allergen: Allergen| classification.allergens.contains(&allergen);
+    let seafood = has(Fish) || has(Crustaceans) || has(Molluscs);
+
+    DietaryLabel::ALL
+        .into_iter()
+        .filter(|label| match label {
+            DietaryLabel::Vegan => !classification.meat && !seafood && !classification.animal && !has(Milk) && !has(Eggs),
+            DietaryLabel::Vegetarian => !classification.meat && !seafood,
+            DietaryLabel::Pescatarian => !classification.meat,
+            DietaryLabel::GlutenFree => !has(Gluten),
+            DietaryLabel::DairyFree => !has(Milk),
+            DietaryLabel::EggFree => !has(Eggs),
+            DietaryLabel::NutFree => !has(Nuts) && !has(Peanuts),
+        })
+        .collect()
+}
+
+/// Labels and allergens of a recipe from its ingredient lines, the owner's override wins where it is set.
+/// MongoRepo::dietary_stage computes the same in the update pipelines, keep them in line
+pub fn dietary(ingredients: &[String], owner_override: Option<&DietaryOverride>) -> Dietary {
+    let classification = classify(ingredients);
+    let derived_labels = labels(&classification);
+    let derived_allergens: Vec<Allergen> = classification.allergens.iter().copied().collect();
+    let known = classification.unknown.is_empty();
+
+    let owner_labels = owner_override.and_then(|o| o.labels.clone());
+    let owner_allergens = owner_override.and_then(|o| o.allergens.clone());
+
+    Dietary {
+        labels: owner_labels.unwrap_or_else(|| if known { derived_labels.clone() } else { Vec::new() }),
+        complete: known || owner_allergens.is_some(),
+        allergens: owner_allergens.unwrap_or_else(|| derived_allergens.clone()),
+        derived_labels,
+        derived_allergens,
+        unknown_ingredients: classification.unknown,
+        table_version: TABLE_VERSION,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::dietary_model::{Allergen, DietaryLabel, DietaryOverride};
+
+    use super::{classify, dietary, INGREDIENTS, TABLE_VERSION};
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn every_keyword_of_the_table_is_known_with_its_allergens() {
+        for (keyword, allergens, _) in INGREDIENTS {
+            let classification = classify(&lines(&[keyword]));
+
+            assert!(classification.unknown.is_empty(), "{} is unknown", keyword);
+            for allergen in allergens.iter() {
+                assert!(classification.allergens.contains(allergen), "{} lacks {:?}", keyword, allergen);
+            }
+        }
+    }
+
+    #[test]
+    fn longer_keywords_override_the_shorter_ones() {
+        assert!(dietary(&lines(&["4 dl coconut milk"]), None).derived_allergens.is_empty());
+        assert!(dietary(&lines(&["4 dl kokosmjölk"]), None).derived_allergens.is_empty());
+        assert_eq!(dietary(&lines(&["2 msk jordnötssmör"]), None).derived_allergens, vec![Allergen::Peanuts]);
+        assert_eq!(dietary(&lines(&["3 dl vetemjöl"]), None).derived_allergens, vec![Allergen::Gluten]);
+        assert!(dietary(&lines(&["3 dl rismjöl"]), None).derived_allergens.is_empty());
+    }
+
+    #[test]
+    fn labels_follow_meat_seafood_and_animal_products() {
+        let labels = |ingredients: &[&str]| dietary(&lines(ingredients), None).labels;
+
+        assert_eq!(labels(&["500 g kycklingfilé", "1 gul lök"]), vec![
+            DietaryLabel::GlutenFree, DietaryLabel::DairyFree, DietaryLabel::EggFree, DietaryLabel::NutFree,
+        ]);
+        assert_eq!(labels(&["400 g laxfilé", "salt"]), vec![
+            DietaryLabel::Pescatarian, DietaryLabel::GlutenFree, DietaryLabel::DairyFree, DietaryLabel::EggFree, DietaryLabel::NutFree,
+        ]);
+        assert!(!labels(&["2 msk honung", "1 citron"]).contains(&DietaryLabel::Vegan));
+        assert!(labels(&["2 msk honung", "1 citron"]).contains(&DietaryLabel::Vegetarian));
+        assert!(labels(&["2 dl ris", "1 burk kikärtor", "1 tsk spiskummin"]).contains(&DietaryLabel::Vegan));
+    }
+
+    #[test]
+    fn unknown_ingredients_derive_no_labels_and_are_not_complete() {
+        let classified = dietary(&lines(&["2 dl ris", "1 st quinoa-kaka"]), None);
+
+        assert_eq!(classified.unknown_ingredients, vec!["quinoa-kaka".to_string()]);
+        assert!(classified.labels.is_empty());
+        assert!(!classified.complete);
+        // What is known is still derived, substitutions.rs uses it per line
+        assert!(classified.derived_labels.contains(&DietaryLabel::Vegan));
+        assert_eq!(classified.table_version, TABLE_VERSION);
+    }
+
+    #[test]
+    fn owner_override_wins_and_completes_the_allergens() {
+        let owner_override = DietaryOverride {
+            labels: Some(vec![DietaryLabel::GlutenFree]),
+            allergens: Some(vec![Allergen::Milk]),
+            reason: Some("Gluten free flour".to_string()),
+            overridden_by: "uid".to_string(),
+            overridden_at: mongodb::bson::DateTime::now(),
+        };
+
+        let classified = dietary(&lines(&["3 dl mjöl", "1 dl quinoa-kaka"]), Some(&owner_override));
+
+        assert_eq!(classified.labels, vec![DietaryLabel::GlutenFree]);
+        assert_eq!(classified.allergens, vec![Allergen::Milk]);
+        assert_eq!(classified.derived_allergens, vec![Allergen::Gluten]);
+        assert!(classified.complete);
+    }
+}
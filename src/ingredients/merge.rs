@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::ingredients::aisles::aisle_for;
+use crate::ingredients::units::{Dimension, display_unit, to_base_unit};
+use crate::ingredients::{ingredient_key, parse_ingredient};
+use crate::models::shopping_list_model::Aisle;
+
+/*
+    Consolidates the ingredient lines of several recipes into one line per ingredient for the shopping list.
+    Lines are merged when the ingredient key is the same (see ingredient_key) and the
+    amounts can be added: grams with kilos, dl with tablespoons and so on, or the same other unit ("2 cloves" + "1 clove").
+    Amounts that can't be added ("1 can" and "400 g" tomatoes) stay separate lines.
+ */
+
+#[derive(Debug, Clone)]
+pub struct MergedIngredient {
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub aisle: Aisle,
+    pub recipes: Vec<String>, // Titles of the recipes that need it
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum AmountKind {
+    Base(Dimension),
+    Unit(String),
+    Count,   // "3 eggs"
+    Unknown, // "salt"
+}
+
+#[derive(Default)]
+pub struct IngredientMerger {
+    items: Vec<(AmountKind, MergedIngredient)>,
+    index: HashMap<(String, AmountKind), usize>,
+}
+
+impl IngredientMerger {
+    pub fn new() -> Self {
+        IngredientMerger::default()
+    }
+
+    /// Adds an ingredient line of `recipe`, scaled by `factor` (planned servings / servings of the recipe)
+    pub fn add(&mut self, line: &str, factor: f64, recipe: &str) {
+        let ingredient = parse_ingredient(line);
+        let key_name = ingredient_key(&ingredient.name);
+
+        if key_name.is_empty() {
+            return;
+        }
+
+        let (kind, quantity) = match (ingredient.quantity, ingredient.unit.as_deref()) {
+            (Some(quantity), Some(unit)) => match to_base_unit(quantity * factor, unit) {
+                Some((dimension, base)) => (AmountKind::Base(dimension), Some(base)),
+                None => (AmountKind::Unit(unit.to_string()), Some(quantity * factor)),
+            },
+            (Some(quantity), None) => (AmountKind::Count, Some(quantity * factor)),
+            (None, _) => (AmountKind::Unknown, None),
+        };
+
+        let key = (key_name, kind.clone());
+
+        match self.index.get(&key) {
+            Some(position) => {
+                let (_, item) = &mut self.items[*position];
+                item.quantity = match (item.quantity, quantity) {
+                    (Some(total), Some(quantity)) => Some(total + quantity),
+                    (total, quantity) => total.or(quantity),
+                };
+                if !item.recipes.iter().any(|title| title == recipe) {
+                    item.recipes.push(recipe.to_string());
+                }
+            }
+            None => {
+                self.index.insert(key, self.items.len());
+                self.items.push((kind, MergedIngredient {
+                    aisle: aisle_for(&ingredient.name),
+                    name: ingredient.name,
+                    quantity,
+                    unit: None,
+                    recipes: vec![recipe.to_string()],
+                }));
+            }
+        }
+    }
+
+    /// The merged ingredients in display units, sorted by aisle and name.
+    /// An ingredient without amount ("salt") is folded into the same ingredient with an amount when there is one
+    pub fn finish(self) -> Vec<MergedIngredient> {
+        let mut items = self.items;
+        let mut with_amount: HashMap<String, usize> = HashMap::new();
+
+        for ((name, kind), position) in &self.index {
+            if *kind != AmountKind::Unknown {
+                with_amount.entry(name.clone()).and_modify(|first| *first = (*first).min(*position)).or_insert(*position);
+            }
+        }
+
+        for ((name, kind), position) in &self.index {
+            if *kind != AmountKind::Unknown {
+                continue;
+            }
+            if let Some(target) = with_amount.get(name) {
+                let recipes = std::mem::take(&mut items[*position].1.recipes);
+                let target = &mut items[*target].1;
+                for recipe in recipes {
+                    if !target.recipes.contains(&recipe) {
+                        target.recipes.push(recipe);
+                    }
+                }
+            }
+        }
+
+        let mut merged: Vec<MergedIngredient> = items
+            .into_iter()
+            .filter(|(_, item)| !item.recipes.is_empty()) // Folded into another item above
+            .map(|(kind, mut item)| {
+                match (kind, item.quantity) {
+                    (AmountKind::Base(dimension), Some(base)) => {
+                        let (quantity, unit) = display_unit(dimension, base);
+                        item.quantity = Some(quantity);
+                        item.unit = Some(unit.to_string());
+                    }
+                    (AmountKind::Unit(unit), quantity) => {
+                        item.quantity = quantity.map(|quantity| (quantity * 100.0).round() / 100.0);
+                        item.unit = Some(unit);
+                    }
+                    (_, quantity) => item.quantity = quantity.map(|quantity| (quantity * 100.0).round() / 100.0),
+                }
+                item
+            })
+            .collect();
+
+        merged.sort_by(|a, b| a.aisle.cmp(&b.aisle).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::shopping_list_model::Aisle;
+
+    use super::{IngredientMerger, MergedIngredient};
+
+    fn merge(recipes: &[(&str, f64, &[&str])]) -> Vec<MergedIngredient> {
+        let mut merger = IngredientMerger::new();
+        for (title, factor, lines) in recipes {
+            for line in *lines {
+                merger.add(line, *factor, title);
+            }
+        }
+        merger.finish()
+    }
+
+    fn find<'a>(items: &'a [MergedIngredient], name: &str) -> Vec<&'a MergedIngredient> {
+        items.iter().filter(|item| item.name.eq_ignore_ascii_case(name)).collect()
+    }
+
+    #[test]
+    fn amounts_in_units_of_the_same_dimension_are_added() {
+        let items = merge(&[
+            ("Pannkakor", 1.0, &["3 dl mjölk", "2 msk smör"]),
+            ("Bullar", 1.0, &["5 dl mjölk", "50 g smör"]),
+            ("Bröd", 1.0, &["0.5 l mjölk", "800 g vetemjöl", "0.4 kg vetemjöl"]),
+        ]);
+
+        let milk = find(&items, "mjölk");
+        assert_eq!(milk.len(), 1);
+        assert_eq!((milk[0].quantity, milk[0].unit.as_deref()), (Some(1.3), Some("l")));
+        assert_eq!(milk[0].recipes, vec!["Pannkakor", "Bullar", "Bröd"]);
+
+        let flour = find(&items, "vetemjöl");
+        assert_eq!((flour[0].quantity, flour[0].unit.as_deref()), (Some(1.2), Some("kg")));
+        assert_eq!(flour[0].recipes, vec!["Bröd"]);
+
+        // Volume and mass can't be added
+        assert_eq!(find(&items, "smör").len(), 2);
+    }
+
+    #[test]
+    fn counts_other_units_and_plurals() {
+        let items = merge(&[
+            ("Soup", 1.0, &["2 onions", "2 cloves garlic", "1 can tomatoes"]),
+            ("Stew", 1.0, &["1 onion", "1 clove garlic", "400 g tomatoes"]),
+        ]);
+
+        let onions = find(&items, "onions");
+        assert_eq!((onions[0].quantity, onions[0].unit.as_deref()), (Some(3.0), None));
+        assert_eq!(onions[0].recipes, vec!["Soup", "Stew"]);
+
+        let garlic = find(&items, "garlic");
+        assert_eq!((garlic[0].quantity, garlic[0].unit.as_deref()), (Some(3.0), Some("clove")));
+
+        assert_eq!(find(&items, "tomatoes").len(), 2);
+    }
+
+    #[test]
+    fn scaling_and_lines_without_amount() {
+        let items = merge(&[
+            ("Pannkakor", 2.0, &["3 dl mjölk", "salt"]),
+            ("Omelett", 1.0, &["1 krm salt", "smör"]),
+            ("Gröt", 1.0, &["smör"]),
+        ]);
+
+        let milk = find(&items, "mjölk");
+        assert_eq!((milk[0].quantity, milk[0].unit.as_deref()), (Some(6.0), Some("dl")));
+
+        // "salt" without amount is folded into "1 krm salt"
+        let salt = find(&items, "salt");
+        assert_eq!(salt.len(), 1);
+        assert_eq!(salt[0].quantity, Some(1.0));
+        assert_eq!(salt[0].recipes, vec!["Omelett", "Pannkakor"]);
+
+        let butter = find(&items, "smör");
+        assert_eq!((butter[0].quantity, butter[0].unit.as_deref()), (None, None));
+        assert_eq!(butter[0].recipes, vec!["Omelett", "Gröt"]);
+    }
+
+    #[test]
+    fn sorted_by_aisle_then_name() {
+        let items = merge(&[("Mix", 1.0, &["1 dl socker", "2 äpplen", "1 dl Mjölk", "1 citron", "", "  "])]);
+        let order: Vec<(Aisle, &str)> = items.iter().map(|item| (item.aisle, item.name.as_str())).collect();
+
+        assert_eq!(order, vec![(Aisle::Produce, "citron"), (Aisle::Produce, "äpplen"), (Aisle::Dairy, "Mjölk"), (Aisle::Pantry, "socker")]);
+    }
+}
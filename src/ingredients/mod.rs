@@ -0,0 +1,226 @@
+pub mod aisles;
+pub mod allergens;
+pub mod merge;
+pub mod substitutions;
+pub mod units;
+
+use crate::ingredients::units::canonical_unit;
+use crate::models::ingredient_model::Ingredient;
+
+/*
+    Parsing of the free text ingredient lines we store in Recipe.ingredients,
+    "2 1/2 dl vetemjöl, sifted" -> { quantity: 2.5, unit: "dl", name: "vetemjöl", note: "sifted" }.
+    Lines that don't start with a quantity are kept whole as the name ("salt and pepper").
+ */
+
+fn unicode_fraction(c: char) -> Option<f64> {
+    match c {
+        '¼' => Some(0.25),
+        '½' => Some(0.5),
+        '¾' => Some(0.75),
+        '⅓' => Some(1.0 / 3.0),
+        '⅔' => Some(2.0 / 3.0),
+        '⅛' => Some(0.125),
+        _ => None,
+    }
+}
+
+// "2", "2.5", "2,5", "1/2", "½", "2½" and ranges "2-3" (the lower bound is used)
+pub fn parse_number(token: &str) -> Option<f64> {
+    let token = token.split(['-', '–']).next()?;
+
+    if let Some((numerator, denominator)) = token.split_once('/') {
+        let denominator = denominator.parse::<f64>().ok().filter(|d| *d != 0.0)?;
+        return Some(numerator.parse::<f64>().ok()? / denominator);
+    }
+
+    let fraction: f64 = token.chars().filter_map(unicode_fraction).sum();
+    let digits: String = token.chars().filter(|c| unicode_fraction(*c).is_none()).collect();
+
+    if digits.is_empty() {
+        return (fraction > 0.0).then_some(fraction);
+    }
+
+    Some(digits.replace(',', ".").parse::<f64>().ok()? + fraction)
+}
+
+/// Servings of a recipe from its free text yield, "4 servings" -> 4, None when it doesn't start with a number ("one loaf")
+pub fn yield_servings(recipe_yield: Option<&str>) -> Option<f64> {
+    recipe_yield
+        .and_then(|recipe_yield| recipe_yield.split_whitespace().next())
+        .and_then(parse_number)
+        .filter(|servings| *servings > 0.0)
+}
+
+/// Sum of the leading number tokens, "1 1/2" -> 1.5, None when the text has no number
+pub fn parse_quantity(text: &str) -> Option<f64> {
+    text.split_whitespace()
+        .map(parse_number)
+        .try_fold(None, |sum: Option<f64>, number| number.map(|number| Some(sum.unwrap_or(0.0) + number)))
+        .flatten()
+}
+
+pub fn parse_ingredient(line: &str) -> Ingredient {
+    let line = line.trim();
+    let mut tokens = line.split_whitespace().peekable();
+    let mut quantity: Option<f64> = None;
+
+    // "1 1/2" is two number tokens that are added
+    while let Some(number) = tokens.peek().and_then(|token| parse_number(token)) {
+        quantity = Some(quantity.unwrap_or(0.0) + number);
+        tokens.next();
+    }
+
+    let unit = quantity
+        .and_then(|_| tokens.peek().and_then(|token| canonical_unit(token)))
+        .map(|unit| {
+            tokens.next();
+            unit.to_string()
+        });
+
+    let rest = tokens.collect::<Vec<&str>>().join(" ");
+    let rest = if rest.is_empty() { line.to_string() } else { rest };
+
+    // "vetemjöl, sifted" or "butter (soft)"
+    let (name, note) = match rest.find([',', '(']) {
+        Some(index) if index > 0 => {
+            let note = rest[index..].trim_matches([',', '(', ')', ' ']).to_string();
+            (rest[..index].trim().to_string(), Some(note).filter(|note| !note.is_empty()))
+        }
+        _ => (rest.trim().to_string(), None),
+    };
+
+    Ingredient { quantity, unit, name, note, line: Some(line.to_string()) }
+}
+
+/// Back to a single line, used when an importer has structured ingredients and for the ingredients of /api/v2.
+/// An ingredient that still has the fields of the line it was parsed from is that line, so "2-3 ägg",
+/// "1/3 dl" and "smör (mjukt)" are kept as written
+pub fn format_ingredient(ingredient: &Ingredient) -> String {
+    if let Some(line) = ingredient.line.as_deref().filter(|line| parse_ingredient(line) == *ingredient) {
+        return line.to_string();
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+
+    if let Some(quantity) = ingredient.quantity {
+        // 2.0 -> "2", 0.333.. -> "0.33"
+        let formatted = format!("{:.2}", quantity);
+        parts.push(formatted.trim_end_matches('0').trim_end_matches('.').to_string());
+    }
+    if let Some(unit) = &ingredient.unit {
+        parts.push(unit.clone());
+    }
+    parts.push(ingredient.name.clone());
+
+    let line = parts.join(" ");
+
+    match &ingredient.note {
+        Some(note) => format!("{}, {}", line, note),
+        None => line,
+    }
+}
+
+/// Lowercase, letters and digits only, single spaces, so "Vetemjöl " and "vetemjöl" compare equal
+pub fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// "onions" and "onion" are the same ingredient, but not "hummus" or "glass"
+fn singular(word: &str) -> &str {
+    if word.ends_with("oes") {
+        &word[..word.len() - 2]
+    } else if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") && !word.ends_with("us") && !word.ends_with("is") {
+        &word[..word.len() - 1]
+    } else {
+        word
+    }
+}
+
+/// What ingredients are compared by, the normalized name with English plurals made singular
+pub fn ingredient_key(name: &str) -> String {
+    normalize_name(name)
+        .split(' ')
+        .map(singular)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Order independent fingerprint of the ingredient names of a recipe (quantities are ignored),
+/// FNV-1a so the value is stable between builds
+pub fn ingredient_fingerprint(lines: &[String]) -> String {
+    let mut names: Vec<String> = lines
+        .iter()
+        .map(|line| normalize_name(&parse_ingredient(line).name))
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in names.join("|").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Whether `available` (what the user has, a pantry item) covers the ingredient `needed`, both ingredient keys.
+/// Same name, a word of it ("mjölk" covers "kall mjölk") or the end of a Swedish compound word ("mjöl" covers "vetemjöl")
+pub fn covers(available: &str, needed: &str) -> bool {
+    if available.is_empty() {
+        return false;
+    }
+    if available == needed || (available.contains(' ') && needed.contains(available)) {
+        return true;
+    }
+
+    needed.split(' ').any(|word| word == available || (available.chars().count() >= 4 && word.ends_with(available)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{covers, ingredient_key};
+
+    #[test]
+    fn keys_are_normalized_and_singular() {
+        assert_eq!(ingredient_key("  Red Onions "), "red onion");
+        assert_eq!(ingredient_key("Tomatoes"), "tomato");
+        assert_eq!(ingredient_key("crème-fraiche"), "crème fraiche");
+        assert_eq!(ingredient_key("Vetemjöl"), "vetemjöl");
+        // Not plurals
+        assert_eq!(ingredient_key("hummus"), "hummus");
+        assert_eq!(ingredient_key("glass"), "glass");
+        assert_eq!(ingredient_key("anis"), "anis");
+        assert_eq!(ingredient_key("gas"), "gas");
+        assert_eq!(ingredient_key(""), "");
+    }
+
+    #[test]
+    fn covers_same_name_words_and_compound_endings() {
+        assert!(covers("mjölk", "mjölk"));
+        assert!(covers("mjölk", "kall mjölk"));
+        assert!(covers("mjöl", "vetemjöl"));
+        assert!(covers("olive oil", "extra virgin olive oil"));
+        assert!(covers(&ingredient_key("Eggs"), &ingredient_key("egg")));
+    }
+
+    #[test]
+    fn covers_is_not_a_substring_match() {
+        assert!(!covers("", "mjölk"));
+        assert!(!covers("mjölk", "mjöl"));
+        assert!(!covers("kall mjölk", "mjölk"));
+        assert!(!covers("olive oil", "oil"));
+        // Short names have to be a whole word
+        assert!(!covers("ris", "kycklingris"));
+        assert!(covers("ris", "basmati ris"));
+        assert!(!covers("milk", "milkshake"));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::ingredients::allergens::dietary;
+use crate::ingredients::{format_ingredient, ingredient_key, parse_ingredient};
+use crate::models::dietary_model::{Allergen, Dietary, DietaryLabel};
+use crate::models::ingredient_model::Ingredient;
+use crate::models::substitution_model::{SubstituteComponent, SubstitutionRule, SubstitutionSwap};
+
+/*
+    Ingredient substitutions. A rule replaces one ingredient with one or more components, each with a ratio
+    per unit of the original: "1 cup buttermilk" with 0.94 milk + 0.06 lemon juice is "0.94 cup milk" and
+    "0.06 cup lemon juice". Components with a unit of their own are for counted things, "2 eggs" with
+    1 tbsp ground flaxseed + 3 tbsp water per egg.
+    Whether a rule suits someone avoiding dairy, eggs or meat is worked out by classifying its components
+    with the allergen table (allergens.rs), so rules don't need to be tagged by hand.
+
+    The rules below are seeded into the SubstitutionRules collection at startup (jobs/substitution_seed.rs),
+    admins add more through POST /substitutions.
+ */
+
+type Curated = (&'static str, &'static [(&'static str, f64, Option<&'static str>)], &'static str);
+
+const CURATED: &[Curated] = &[
+    // Dairy
+    ("buttermilk", &[("milk", 0.94, None), ("lemon juice", 0.06, None)], "Stir and let it stand for 5 minutes until it thickens"),
+    ("buttermilk", &[("soy milk", 0.94, None), ("lemon juice", 0.06, None)], "Any unsweetened plant milk works"),
+    ("kärnmjölk", &[("mjölk", 0.94, None), ("citronsaft", 0.06, None)], "Rör om och låt stå i 5 minuter"),
+    ("milk", &[("oat milk", 1.0, None)], "Use an unsweetened one for savoury dishes"),
+    ("milk", &[("soy milk", 1.0, None)], "Closest to milk in protein, good for baking"),
+    ("milk", &[("rice milk", 1.0, None)], "Thin and sweet, free from gluten, soy and nuts"),
+    ("mjölk", &[("havredryck", 1.0, None)], "Välj en osötad till matlagning"),
+    ("butter", &[("vegan butter", 1.0, None)], "Works one to one in baking"),
+    ("butter", &[("olive oil", 0.75, None)], "For frying and savoury dishes, not for pastry"),
+    ("smör", &[("växtbaserat smör", 1.0, None)], "Fungerar lika bra i bakning"),
+    ("smör", &[("rapsolja", 0.75, None)], "Till stekning och matlagning, inte till mördeg"),
+    ("heavy cream", &[("coconut cream", 1.0, None)], "Chill the can and use the solid part for whipping"),
+    ("cream", &[("oat cream", 1.0, None)], "For sauces and soups, it doesn't whip"),
+    ("cream", &[("coconut cream", 1.0, None)], "Tastes of coconut"),
+    ("grädde", &[("havregrädde", 1.0, None)], "Till såser och soppor, går inte att vispa"),
+    ("grädde", &[("kokosgrädde", 1.0, None)], "Smakar kokos"),
+    ("sour cream", &[("greek yogurt", 1.0, None)], "Thicker and more tangy"),
+    ("sour cream", &[("coconut cream", 1.0, None), ("lemon juice", 0.05, None)], "Dairy free, stir the lemon juice in"),
+    ("crème fraiche", &[("greek yogurt", 1.0, None)], "Add it at the end, it splits when boiled"),
+    ("parmesan", &[("nutritional yeast", 0.5, None)], "Half the amount, for the savoury taste"),
+    // Eggs
+    ("egg", &[("ground flaxseed", 1.0, Some("tbsp")), ("water", 3.0, Some("tbsp"))], "Whisk and let it thicken for 5 minutes, binds but doesn't make things rise"),
+    ("egg", &[("unsweetened applesauce", 60.0, Some("ml"))], "For cakes and muffins, makes them moist"),
+    ("ägg", &[("linfrö", 1.0, Some("tbsp")), ("vatten", 3.0, Some("tbsp"))], "Malda linfrön, låt svälla i 5 minuter"),
+    ("mayonnaise", &[("greek yogurt", 1.0, None)], "Lighter and more tangy"),
+    ("mayonnaise", &[("vegan mayo", 1.0, None)], "Works one to one"),
+    // Gluten
+    ("flour", &[("gluten free flour", 1.0, None)], "Use a blend with xanthan gum for bread"),
+    ("vetemjöl", &[("glutenfritt mjöl", 1.0, None)], "Välj en mix med xantangummi till bröd"),
+    ("pasta", &[("gluten free pasta", 1.0, None)], "Cook it a minute shorter than the package says"),
+    ("breadcrumb", &[("ground almonds", 1.0, None)], "Browns faster, lower the heat"),
+    ("soy sauce", &[("tamari", 1.0, None)], "Gluten free soy sauce"),
+    ("soy sauce", &[("coconut aminos", 1.0, None)], "Free from soy, sweeter and less salty"),
+    ("sojasås", &[("tamari", 1.0, None)], "Glutenfri soja"),
+    ("cornstarch", &[("potato flour", 1.0, None)], "Add it at the end, it thins out when boiled for long"),
+    // Nuts
+    ("peanut butter", &[("tahini", 1.0, None)], "Thinner and more bitter, add a little honey"),
+    ("walnut", &[("sunflower seed", 1.0, None)], "Toast them first"),
+    ("almond", &[("pumpkin seed", 1.0, None)], "Toast them first"),
+    // Meat
+    ("ground beef", &[("cooked brown lentils", 1.0, None)], "Brown the onions well and add a splash of soy sauce"),
+    ("köttfärs", &[("vegofärs", 1.0, None)], "Steks på samma sätt"),
+    ("chicken stock", &[("vegetable stock", 1.0, None)], ""),
+    ("kycklingbuljong", &[("grönsaksbuljong", 1.0, None)], ""),
+    ("fish sauce", &[("soy sauce", 1.0, None)], "Add a squeeze of lime"),
+    ("gelatin", &[("agar agar", 0.33, None)], "Agar sets firmer and has to boil for a minute"),
+    ("honey", &[("maple syrup", 1.0, None)], ""),
+    ("honung", &[("lönnsirap", 1.0, None)], ""),
+    // Sulphites, celery and the ones you just ran out of
+    ("white wine", &[("vegetable stock", 0.94, None), ("lemon juice", 0.06, None)], ""),
+    ("red wine", &[("vegetable stock", 1.0, None)], "Add a little tomato paste for colour"),
+    ("celery", &[("fennel", 1.0, None)], ""),
+    ("lemon juice", &[("lime juice", 1.0, None)], ""),
+    ("brown sugar", &[("sugar", 0.9, None), ("molasses", 0.1, None)], ""),
+    ("baking powder", &[("baking soda", 0.25, None), ("cream of tartar", 0.5, None)], "Use it right away, it starts working when wet"),
+];
+
+/// The curated rules as stored, without ids
+pub fn curated_rules() -> Vec<SubstitutionRule> {
+    let now = mongodb::bson::DateTime::now();
+
+    CURATED
+        .iter()
+        .map(|(ingredient, components, notes)| SubstitutionRule {
+            id: None,
+            ingredient: ingredient.to_string(),
+            replacement: components
+                .iter()
+                .map(|(name, ratio, unit)| SubstituteComponent { name: name.to_string(), ratio: *ratio, unit: unit.map(str::to_string) })
+                .collect(),
+            notes: Some(notes.to_string()).filter(|notes| !notes.is_empty()),
+            curated: true,
+            created_by: None,
+            created: now,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Avoid {
+    Allergen(Allergen),
+    Meat,           // Fish and seafood too, what vegetarians don't eat
+    AnimalProducts, // What vegans don't eat
+}
+
+/// An allergen ("milk", "eggs"...), a few everyday names for them, "meat" or "animal_products"
+pub fn parse_avoid(name: &str) -> Option<Avoid> {
+    match name.trim().to_lowercase().as_str() {
+        "dairy" | "lactose" => Some(Avoid::Allergen(Allergen::Milk)),
+        "egg" => Some(Avoid::Allergen(Allergen::Eggs)),
+        "nut" | "tree_nuts" => Some(Avoid::Allergen(Allergen::Nuts)),
+        "peanut" => Some(Avoid::Allergen(Allergen::Peanuts)),
+        "soy" => Some(Avoid::Allergen(Allergen::Soybeans)),
+        "shellfish" => Some(Avoid::Allergen(Allergen::Crustaceans)),
+        "meat" => Some(Avoid::Meat),
+        "animal_products" | "vegan" => Some(Avoid::AnimalProducts),
+        name => Allergen::ALL.into_iter().find(|allergen| allergen.as_str() == name).map(Avoid::Allergen),
+    }
+}
+
+fn contains(dietary: &Dietary, avoid: Avoid) -> bool {
+    match avoid {
+        Avoid::Allergen(allergen) => dietary.derived_allergens.contains(&allergen),
+        Avoid::Meat => !dietary.derived_labels.contains(&DietaryLabel::Vegetarian),
+        Avoid::AnimalProducts => !dietary.derived_labels.contains(&DietaryLabel::Vegan),
+    }
+}
+
+fn suits(rule: &SubstitutionRule, avoid: &[Avoid]) -> bool {
+    let components: Vec<String> = rule.replacement.iter().map(|component| component.name.clone()).collect();
+    let dietary = dietary(&components, None);
+
+    !avoid.iter().any(|avoid| contains(&dietary, *avoid))
+}
+
+// The line is the ingredient asked for, "milk" names "milk" and "whole milk" but not "buttermilk" or "milk chocolate"
+fn names(wanted: &str, key: &str) -> bool {
+    key == wanted || key.strip_suffix(wanted).is_some_and(|rest| rest.ends_with(' '))
+}
+
+// The rule has to name what the line is, "milk" replaces "oat milk" and "kall mjölk" but "almond" doesn't replace "almond milk"
+fn replaces(rule_key: &str, key: &str) -> bool {
+    let Some(rest) = key.strip_suffix(rule_key) else {
+        return false;
+    };
+
+    !rule_key.is_empty() && (rest.is_empty() || rest.ends_with(' ') || (rule_key.chars().count() >= 4 && !rule_key.contains(' ')))
+}
+
+// "2 cups buttermilk" -> ["1.88 cup milk", "0.12 cup lemon juice"]
+fn apply(original: &Ingredient, rule: &SubstitutionRule) -> Vec<String> {
+    rule.replacement
+        .iter()
+        .map(|component| format_ingredient(&Ingredient {
+            quantity: original.quantity.map(|quantity| quantity * component.ratio),
+            unit: original.quantity.and(component.unit.clone().or(original.unit.clone())),
+            name: component.name.clone(),
+            note: None,
+            line: None,
+        }))
+        .collect()
+}
+
+pub struct Substitution {
+    pub ingredients: Vec<String>,
+    pub swaps: Vec<SubstitutionSwap>,
+    pub unresolved: Vec<String>,
+}
+
+/// Swaps the lines matching `ingredient` and the lines containing anything in `avoid`.
+/// The rules for the most specific ingredient name win, "buttermilk" over "milk", and the first one that
+/// suits `avoid` is applied, the others of the same name are returned as alternatives
+pub fn substitute(lines: &[String], rules: &[SubstitutionRule], ingredient: Option<&str>, avoid: &[Avoid]) -> Substitution {
+    let wanted = ingredient.map(ingredient_key).filter(|key| !key.is_empty());
+    let rule_keys: Vec<String> = rules.iter().map(|rule| ingredient_key(&rule.ingredient)).collect();
+    let mut substitution = Substitution { ingredients: Vec::new(), swaps: Vec::new(), unresolved: Vec::new() };
+
+    for line in lines {
+        let parsed = parse_ingredient(line);
+        let key = ingredient_key(&parsed.name);
+
+        let asked_for = wanted.as_ref().is_some_and(|wanted| names(wanted, &key));
+        let line_dietary = dietary(std::slice::from_ref(line), None);
+        let avoided = avoid.iter().any(|avoid| contains(&line_dietary, *avoid));
+
+        if !asked_for && !avoided {
+            substitution.ingredients.push(line.clone());
+            continue;
+        }
+
+        let mut candidates: Vec<(usize, &SubstitutionRule)> = rules
+            .iter()
+            .zip(&rule_keys)
+            .filter(|(rule, rule_key)| replaces(rule_key, &key) && suits(rule, avoid))
+            .map(|(rule, rule_key)| (rule_key.chars().count(), rule))
+            .collect();
+        candidates.sort_by_key(|(specificity, _)| std::cmp::Reverse(*specificity)); // Stable, curated rules stay before the ones added later
+
+        let Some((specificity, rule)) = candidates.first().copied() else {
+            substitution.unresolved.push(line.clone());
+            substitution.ingredients.push(line.clone());
+            continue;
+        };
+
+        let replacement = apply(&parsed, rule);
+        substitution.ingredients.extend(replacement.iter().cloned());
+        substitution.swaps.push(SubstitutionSwap {
+            line: line.clone(),
+            replacement,
+            rule: rule.clone(),
+            alternatives: candidates
+                .iter()
+                .skip(1)
+                .filter(|(other, _)| *other == specificity)
+                .map(|(_, rule)| (*rule).clone())
+                .collect(),
+        });
+    }
+
+    substitution
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ingredients::parse_ingredient;
+    use crate::models::dietary_model::Allergen;
+
+    use super::{apply, curated_rules, names, replaces, substitute, Avoid};
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn asking_for_an_ingredient_names_it_in_one_direction() {
+        assert!(names("milk", "milk"));
+        assert!(names("milk", "whole milk"));
+        assert!(names("mjölk", "kall mjölk"));
+        assert!(!names("milk", "buttermilk"));
+        assert!(!names("buttermilk", "milk"));
+        assert!(!names("milk", "milk chocolate"));
+    }
+
+    #[test]
+    fn rules_replace_the_name_and_compound_words() {
+        assert!(replaces("milk", "oat milk"));
+        assert!(replaces("mjölk", "kall mjölk"));
+        assert!(replaces("mjöl", "vetemjöl"));
+        assert!(!replaces("almond", "almond milk"));
+        assert!(!replaces("egg", "nutmeg"));
+        assert!(!replaces("", "milk"));
+    }
+
+    #[test]
+    fn apply_scales_the_components() {
+        let rules = curated_rules();
+        let buttermilk = rules.iter().find(|rule| rule.ingredient == "buttermilk").unwrap();
+        let egg = rules.iter().find(|rule| rule.ingredient == "egg").unwrap();
+
+        assert_eq!(apply(&parse_ingredient("2 cups buttermilk"), buttermilk), vec!["1.88 cup milk", "0.12 cup lemon juice"]);
+        // Components with a unit of their own are per counted original
+        assert_eq!(apply(&parse_ingredient("2 eggs"), egg), vec!["2 tbsp ground flaxseed", "6 tbsp water"]);
+        // Without a quantity there is nothing to scale
+        assert_eq!(apply(&parse_ingredient("buttermilk"), buttermilk), vec!["milk", "lemon juice"]);
+    }
+
+    #[test]
+    fn substitute_swaps_only_the_ingredient_asked_for() {
+        let rules = curated_rules();
+        let substitution = substitute(&lines(&["2 dl milk", "1 dl buttermilk", "1 pinch salt"]), &rules, Some("Milk"), &[]);
+
+        assert_eq!(substitution.ingredients, vec!["2 dl oat milk", "1 dl buttermilk", "1 pinch salt"]);
+        assert_eq!(substitution.swaps.len(), 1);
+        assert_eq!(substitution.swaps[0].line, "2 dl milk");
+        assert_eq!(substitution.swaps[0].alternatives.len(), 2);
+        assert!(substitution.unresolved.is_empty());
+    }
+
+    #[test]
+    fn substitute_picks_the_most_specific_rule_that_suits() {
+        let rules = curated_rules();
+        let avoid = [Avoid::Allergen(Allergen::Milk), Avoid::Allergen(Allergen::Gluten)];
+        let substitution = substitute(&lines(&["2 dl milk", "1 dl buttermilk", "50 g cashews"]), &rules, None, &avoid);
+
+        // "oat milk" contains gluten, buttermilk has rules of its own and "milk" with "lemon juice" contains milk
+        assert_eq!(substitution.swaps[0].replacement, vec!["2 dl soy milk"]);
+        assert_eq!(substitution.swaps[1].rule.ingredient, "buttermilk");
+        assert_eq!(substitution.swaps[1].replacement, vec!["0.94 dl soy milk", "0.06 dl lemon juice"]);
+        // Not avoided, no rule needed
+        assert!(substitution.ingredients.contains(&"50 g cashews".to_string()));
+        assert!(substitution.unresolved.is_empty());
+
+        let nut_free = substitute(&lines(&["50 g cashews"]), &rules, None, &[Avoid::Allergen(Allergen::Nuts)]);
+        assert_eq!(nut_free.unresolved, vec!["50 g cashews"]);
+        assert_eq!(nut_free.ingredients, vec!["50 g cashews"]);
+    }
+}
@@ -0,0 +1,129 @@
+// Canonical unit and the spellings (English, Swedish and MealMaster abbreviations) that map to it
+const UNITS: [(&str, &[&str]); 25] = [
+    ("mg", &["mg", "milligram", "milligrams"]),
+    ("g", &["g", "gr", "gram", "grams", "gramm"]),
+    ("kg", &["kg", "kilo", "kilos", "kilogram", "kilograms"]),
+    ("ml", &["ml", "milliliter", "milliliters", "millilitre", "millilitres"]),
+    ("cl", &["cl", "centiliter", "centiliters"]),
+    ("dl", &["dl", "deciliter", "deciliters"]),
+    ("l", &["l", "lt", "liter", "liters", "litre", "litres"]),
+    ("krm", &["krm", "kryddmått"]),
+    ("tsp", &["tsp", "ts", "teaspoon", "teaspoons", "tsk", "tesked", "teskedar"]),
+    ("tbsp", &["tbsp", "tbs", "tb", "tablespoon", "tablespoons", "msk", "matsked", "matskedar"]),
+    ("cup", &["cup", "cups", "c"]),
+    ("pint", &["pint", "pints", "pt"]),
+    ("quart", &["quart", "quarts", "qt"]),
+    ("gallon", &["gallon", "gallons", "gal", "ga"]),
+    ("oz", &["oz", "ounce", "ounces"]),
+    ("lb", &["lb", "lbs", "pound", "pounds"]),
+    ("pinch", &["pinch", "pinches", "pn", "nypa", "nypor"]),
+    ("dash", &["dash", "dashes", "ds"]),
+    ("drop", &["drop", "drops", "dr"]),
+    ("clove", &["clove", "cloves", "klyfta", "klyftor"]),
+    ("slice", &["slice", "slices", "sl", "skiva", "skivor"]),
+    ("can", &["can", "cans", "cn", "burk", "burkar"]),
+    ("bunch", &["bunch", "bunches", "bn", "knippe"]),
+    ("pkg", &["pkg", "pk", "package", "packages", "paket", "förp", "förpackning"]),
+    ("pcs", &["pcs", "piece", "pieces", "ea", "st", "stycken"]),
+];
+
+/// Canonical unit for a spelling found in an ingredient line, case insensitive and ignoring a trailing "."
+pub fn canonical_unit(token: &str) -> Option<&'static str> {
+    let token = token.trim_end_matches('.').to_lowercase();
+
+    UNITS
+        .iter()
+        .find(|(_, spellings)| spellings.contains(&token.as_str()))
+        .map(|(unit, _)| *unit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Mass,   // Base unit g
+    Volume, // Base unit ml
+}
+
+// How much of the base unit one unit is, cup/pint/quart/gallon are US measures
+fn base_factor(unit: &str) -> Option<(Dimension, f64)> {
+    let factor = match unit {
+        "mg" => (Dimension::Mass, 0.001),
+        "g" => (Dimension::Mass, 1.0),
+        "kg" => (Dimension::Mass, 1000.0),
+        "oz" => (Dimension::Mass, 28.3495),
+        "lb" => (Dimension::Mass, 453.592),
+        "ml" => (Dimension::Volume, 1.0),
+        "krm" => (Dimension::Volume, 1.0),
+        "tsp" => (Dimension::Volume, 5.0),
+        "cl" => (Dimension::Volume, 10.0),
+        "tbsp" => (Dimension::Volume, 15.0),
+        "dl" => (Dimension::Volume, 100.0),
+        "cup" => (Dimension::Volume, 236.588),
+        "pint" => (Dimension::Volume, 473.176),
+        "quart" => (Dimension::Volume, 946.353),
+        "l" => (Dimension::Volume, 1000.0),
+        "gallon" => (Dimension::Volume, 3785.41),
+        _ => return None,
+    };
+    Some(factor)
+}
+
+/// Mass in g and volume in ml so amounts in different units can be added, other units (pcs, clove..) are None
+pub fn to_base_unit(quantity: f64, unit: &str) -> Option<(Dimension, f64)> {
+    base_factor(unit).map(|(dimension, factor)| (dimension, quantity * factor))
+}
+
+// Whole spoons read better than ml for small amounts
+fn whole(quantity: f64) -> bool {
+    quantity > 0.0 && (quantity - quantity.round()).abs() < 0.01
+}
+
+/// A base amount in the unit that reads best, 1500 g -> 1.5 kg, 250 ml -> 2.5 dl, 30 ml -> 2 tbsp
+pub fn display_unit(dimension: Dimension, base_quantity: f64) -> (f64, &'static str) {
+    let (quantity, unit) = match dimension {
+        Dimension::Mass if base_quantity >= 1000.0 => (base_quantity / 1000.0, "kg"),
+        Dimension::Mass => (base_quantity, "g"),
+        Dimension::Volume if base_quantity >= 1000.0 => (base_quantity / 1000.0, "l"),
+        Dimension::Volume if base_quantity >= 100.0 => (base_quantity / 100.0, "dl"),
+        Dimension::Volume if whole(base_quantity / 15.0) => (base_quantity / 15.0, "tbsp"),
+        Dimension::Volume if base_quantity < 15.0 && whole(base_quantity / 5.0) => (base_quantity / 5.0, "tsp"),
+        Dimension::Volume => (base_quantity, "ml"),
+    };
+
+    ((quantity * 100.0).round() / 100.0, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_unit, display_unit, Dimension, to_base_unit};
+
+    #[test]
+    fn spellings_map_to_canonical_units() {
+        assert_eq!(canonical_unit("msk"), Some("tbsp"));
+        assert_eq!(canonical_unit("Tsk."), Some("tsp"));
+        assert_eq!(canonical_unit("DL"), Some("dl"));
+        assert_eq!(canonical_unit("klyftor"), Some("clove"));
+        assert_eq!(canonical_unit("c"), Some("cup"));
+        assert_eq!(canonical_unit("st"), Some("pcs"));
+        assert_eq!(canonical_unit("mjölk"), None);
+    }
+
+    #[test]
+    fn base_units() {
+        assert_eq!(to_base_unit(2.0, "kg"), Some((Dimension::Mass, 2000.0)));
+        assert_eq!(to_base_unit(3.0, "dl"), Some((Dimension::Volume, 300.0)));
+        assert_eq!(to_base_unit(2.0, "tbsp"), Some((Dimension::Volume, 30.0)));
+        assert_eq!(to_base_unit(2.0, "clove"), None);
+    }
+
+    #[test]
+    fn display_units() {
+        assert_eq!(display_unit(Dimension::Mass, 1500.0), (1.5, "kg"));
+        assert_eq!(display_unit(Dimension::Mass, 250.0), (250.0, "g"));
+        assert_eq!(display_unit(Dimension::Volume, 1300.0), (1.3, "l"));
+        assert_eq!(display_unit(Dimension::Volume, 250.0), (2.5, "dl"));
+        assert_eq!(display_unit(Dimension::Volume, 45.0), (3.0, "tbsp"));
+        assert_eq!(display_unit(Dimension::Volume, 10.0), (2.0, "tsp"));
+        assert_eq!(display_unit(Dimension::Volume, 40.0), (40.0, "ml"));
+        assert_eq!(display_unit(Dimension::Volume, 1.0 / 3.0 * 100.0), (33.33, "ml"));
+    }
+}
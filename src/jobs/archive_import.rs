@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use actix_web::web::{self, Data};
+use mongodb::bson::Document;
+use mongodb::bson::oid::ObjectId;
+
+use crate::api::util::{field_diff, map_input_dto, RecipeStatus};
+use crate::importers::archive::parse_archive;
+use crate::ingredients::{ingredient_fingerprint, normalize_name};
+use crate::models::audit_model::{AuditAction, AuditEntry};
+use crate::models::import_model::{ImportIssue, ImportJob, ImportJobStatus};
+use crate::repository::mongo_repo::MongoRepo;
+
+// The job document is written every PROGRESS_INTERVAL recipes, not after each one
+const PROGRESS_INTERVAL: u32 = 10;
+
+// Duplicates have the same title and the same ingredients, regardless of amounts and order
+fn duplicate_key(title: &str, ingredients: &[String]) -> (String, String) {
+    (normalize_name(title), ingredient_fingerprint(ingredients))
+}
+
+async fn save_progress(db: &MongoRepo, job: &mut ImportJob) {
+    job.updated = mongodb::bson::DateTime::now();
+
+    if let Err(err) = db.save_import_job(job).await {
+        log::error!("Failed to save progress of import job {:?}: {}", job.id, err);
+    }
+}
+
+/// Started by POST /imports, parses the archive and saves every recipe that isn't a duplicate of one the user
+/// already has (or one earlier in the same archive). The request is long gone, so its id and client ip are passed along for the audit log
+pub async fn run_archive_import(db: Data<MongoRepo>, mut job: ImportJob, archive: Vec<u8>, request_id: Option<String>, client_ip: Option<String>) {
+    job.status = ImportJobStatus::Running;
+    save_progress(&db, &mut job).await;
+
+    // Unzipping and parsing is CPU bound, off the async workers
+    let format = job.format;
+    let email = job.email.clone();
+    let parsed = web::block(move || parse_archive(format, &archive, &email)).await;
+
+    let recipes = match parsed {
+        Ok(Ok(recipes)) => recipes,
+        Ok(Err(err)) => {
+            job.status = ImportJobStatus::Failed;
+            job.error = Some(err);
+            return save_progress(&db, &mut job).await;
+        }
+        Err(err) => {
+            job.status = ImportJobStatus::Failed;
+            job.error = Some(err.to_string());
+            return save_progress(&db, &mut job).await;
+        }
+    };
+
+    let mut known: HashSet<(String, String)> = match db.get_recipes_by_email(&job.email, Document::new()).await {
+        Ok(existing) => existing.iter().map(|recipe| duplicate_key(&recipe.title, &recipe.ingredients)).collect(),
+        Err(err) => {
+            job.status = ImportJobStatus::Failed;
+            job.error = Some(format!("Failed to load existing recipes: {}", err));
+            return save_progress(&db, &mut job).await;
+        }
+    };
+
+    job.total = recipes.len() as u32;
+    save_progress(&db, &mut job).await;
+
+    for parsed in recipes {
+        match parsed {
+            Err(issue) => {
+                job.failed += 1;
+                job.add_issue(issue);
+            }
+            Ok(recipe) if !known.insert(duplicate_key(&recipe.title, &recipe.ingredients)) => {
+                job.duplicates += 1;
+                job.add_issue(ImportIssue { title: recipe.title, reason: "Duplicate of an existing recipe".to_string() });
+            }
+            Ok(recipe) => {
+                let object_id = ObjectId::new();
+                let recipe_entity = map_input_dto(recipe, Some(object_id), RecipeStatus::Created);
+                let diff = field_diff(None, Some(&recipe_entity));
+                let title = recipe_entity.title.clone();
+
+                match db.insert_recipe(recipe_entity).await {
+                    Ok(_) => {
+                        let entry = AuditEntry {
+                            id: None,
+                            actor_uid: job.owner_uid.clone(),
+                            action: AuditAction::Create,
+                            target_id: object_id.to_hex(),
+                            timestamp: mongodb::bson::DateTime::now(),
+                            request_id: request_id.clone(),
+                            client_ip: client_ip.clone(),
+                            diff,
+                        };
+
+                        if let Err(err) = db.insert_audit_entry(entry).await {
+                            log::error!("Failed to write audit entry for import of {}: {}", object_id, err);
+                        }
+
+                        job.imported += 1;
+                        job.add_recipe_id(object_id.to_hex());
+                    }
+                    Err(err) => {
+                        job.failed += 1;
+                        job.add_issue(ImportIssue { title, reason: err.to_string() });
+                    }
+                }
+            }
+        }
+
+        job.processed += 1;
+        if job.processed.is_multiple_of(PROGRESS_INTERVAL) {
+            save_progress(&db, &mut job).await;
+        }
+    }
+
+    job.status = ImportJobStatus::Completed;
+    save_progress(&db, &mut job).await;
+
+    log::info!("Import job {:?} done: {} imported, {} duplicates, {} failed", job.id, job.imported, job.duplicates, job.failed);
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use crate::auth::keys::AuthKeys;
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Background task started from main, fetches the Firebase public keys again when half of
+/// their max-age has passed, so a failed fetch has time to be retried before they are stale
+pub async fn refresh_auth_keys_periodically(keys: Data<AuthKeys>) {
+    let mut wait = keys.status().max_age / 2;
+
+    loop {
+        actix_web::rt::time::sleep(wait.max(RETRY_INTERVAL)).await;
+
+        wait = match keys.refresh().await {
+            Ok(_) => {
+                let status = keys.status();
+                log::debug!("Refreshed {} Firebase public keys, valid for {:?}", status.count, status.max_age);
+                status.max_age / 2
+            }
+            Err(err) => {
+                log::warn!("{}, retrying in {:?}", err, RETRY_INTERVAL);
+                RETRY_INTERVAL
+            }
+        };
+    }
+}
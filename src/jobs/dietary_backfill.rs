@@ -0,0 +1,29 @@
+use actix_web::web::Data;
+
+use crate::repository::mongo_repo::MongoRepo;
+
+/// Started from main, classifies the recipes stored before dietary labels existed or before
+/// the last change of the mapping table (TABLE_VERSION in ingredients/allergens.rs)
+pub async fn classify_stored_recipes(db: Data<MongoRepo>) {
+    let recipes = match db.get_recipes_to_classify().await {
+        Ok(recipes) => recipes,
+        Err(err) => {
+            log::error!("Failed to find recipes to classify: {}", err);
+            return;
+        }
+    };
+
+    if recipes.is_empty() {
+        return;
+    }
+
+    let mut count = 0;
+    for recipe in &recipes {
+        match db.classify_recipe(recipe).await {
+            Ok(()) => count += 1,
+            Err(err) => log::warn!("Failed to classify recipe {:?}: {}", recipe.id, err),
+        }
+    }
+
+    log::info!("Classified dietary labels and allergens of {} recipes", count);
+}
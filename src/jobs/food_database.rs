@@ -0,0 +1,22 @@
+use actix_web::web::Data;
+
+use crate::nutrition::FOODS_CSV;
+use crate::nutrition::food_csv::parse_foods;
+use crate::repository::mongo_repo::MongoRepo;
+
+/// Started from main, loads the food table compiled into the binary into the Foods collection
+/// so it always matches the version of the app that is running
+pub async fn sync_food_database(db: Data<MongoRepo>) {
+    let foods = match parse_foods(FOODS_CSV) {
+        Ok(foods) => foods,
+        Err(err) => {
+            log::error!("Failed to read the bundled food table: {}", err);
+            return;
+        }
+    };
+
+    match db.sync_foods(&foods).await {
+        Ok(()) => log::info!("Loaded {} foods into the food database", foods.len()),
+        Err(err) => log::error!("Failed to load the food database: {}", err),
+    }
+}
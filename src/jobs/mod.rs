@@ -0,0 +1,6 @@
+pub mod trash_purge;
+pub mod archive_import;
+pub mod food_database;
+pub mod dietary_backfill;
+pub mod substitution_seed;
+pub mod auth_keys;
@@ -0,0 +1,14 @@
+use actix_web::web::Data;
+
+use crate::ingredients::substitutions::curated_rules;
+use crate::repository::mongo_repo::MongoRepo;
+
+/// Started from main, stores the curated substitution rules of this version of the app
+pub async fn seed_substitution_rules(db: Data<MongoRepo>) {
+    let rules = curated_rules();
+
+    match db.sync_curated_substitutions(&rules).await {
+        Ok(()) => log::info!("Loaded {} curated substitution rules", rules.len()),
+        Err(err) => log::error!("Failed to load the curated substitution rules: {}", err),
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::web::Data;
+use mongodb::bson::Document;
+
+use crate::models::audit_model::{AuditAction, AuditEntry};
+use crate::repository::mongo_repo::MongoRepo;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// Recipes deleted before this have been in the trash for `retention_days`, no retention overflows
+fn deleted_before(now: SystemTime, retention_days: u64) -> mongodb::bson::DateTime {
+    let retention = Duration::from_secs(retention_days.saturating_mul(DAY_SECS));
+    mongodb::bson::DateTime::from_system_time(now.checked_sub(retention).unwrap_or(UNIX_EPOCH))
+}
+
+/// Background task started from main, once an hour it permanently removes recipes
+/// that have been in the trash for longer than `retention_days`
+pub async fn purge_trash_periodically(db: Data<MongoRepo>, retention_days: u64) {
+    let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await; // First tick completes immediately, so we also purge at startup
+
+        match db.purge_trash(deleted_before(SystemTime::now(), retention_days)).await {
+            Ok(ids) => {
+                if !ids.is_empty() {
+                    log::info!("Purged {} recipes from the trash", ids.len());
+                }
+
+                for id in ids {
+                    let entry = AuditEntry {
+                        id: None,
+                        actor_uid: "system".to_string(),
+                        action: AuditAction::Purge,
+                        target_id: id.to_hex(),
+                        timestamp: mongodb::bson::DateTime::now(),
+                        request_id: None,
+                        client_ip: None,
+                        diff: Document::new(),
+                    };
+
+                    if let Err(err) = db.insert_audit_entry(entry).await {
+                        log::error!("Failed to write audit entry for purge of {}: {}", id, err);
+                    }
+                }
+            }
+            Err(err) => log::error!("Failed to purge trash: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::deleted_before;
+
+    #[test]
+    fn retention_never_overflows() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert_eq!(deleted_before(now, 30).timestamp_millis(), (1_700_000_000 - 30 * 86_400) * 1000);
+        assert_eq!(deleted_before(now, 30_000).timestamp_millis(), (1_700_000_000 - 30_000 * 86_400) * 1000); // Before 1970 is fine
+        assert_eq!(deleted_before(now, u64::MAX).timestamp_millis(), 0); // Further back than a SystemTime goes
+    }
+}
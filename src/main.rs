@@ -1,59 +1,171 @@
-use std::env;
-use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
 
-use actix_cors::Cors;
 use actix_web::{App, HttpServer};
-use actix_web::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
-use actix_web::middleware::Logger;
-use actix_web::web::Data;
+use actix_web::dev::Service;
+use actix_web::web::{Data, JsonConfig};
+use clap::Parser;
 
-use crate::api::health_check::health_check;
-use crate::api::recipe_api::{delete_recipe_by_id, get_all_recipes_pagination, get_recipe_by_id, get_recipe_img_url_by_id, get_recipes_by_email, insert_recipe, update_photo_url_by_recipe_id, update_recipe_by_id, update_title_by_recipe_id};
+use crate::api::cors::cors;
+use crate::api::health_check::{health_check, health_live, health_ready, Uptime};
+use crate::api::json::json_error_handler;
+use crate::api::metrics_api::get_metrics;
+use crate::api::version;
+use crate::jobs::auth_keys::refresh_auth_keys_periodically;
+use crate::jobs::dietary_backfill::classify_stored_recipes;
+use crate::jobs::food_database::sync_food_database;
+use crate::jobs::substitution_seed::seed_substitution_rules;
+use crate::jobs::trash_purge::purge_trash_periodically;
+use crate::metrics::track_request;
 use crate::models::app_data::AppData;
+use crate::ratelimit::{RateLimit, RateLimiter};
+use crate::settings::{Cli, Settings};
+use crate::telemetry::otlp::export_spans_periodically;
+use crate::telemetry::request::trace_request;
 
 mod models;
 mod repository;
 mod api;
+mod auth;
+mod exporters;
+mod importers;
+mod ingredients;
+mod jobs;
+mod metrics;
+mod nutrition;
+mod ratelimit;
+mod settings;
+mod telemetry;
 
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let uptime = Data::new(Uptime(Instant::now()));
+    let cli = Cli::parse();
+
+    // Defaults < config.toml < environment < flags, all problems are printed before we give up
+    let (settings, problems) = match Settings::load(&cli) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    // The configuration is printed even when it's invalid, that's when it's needed the most
+    if cli.print_config {
+        print!("{}", settings.redacted());
+        for warning in settings.warnings() {
+            eprintln!("Insecure configuration: {}", warning);
+        }
+    }
+    if !problems.is_empty() {
+        eprintln!("{}", Settings::problems_message(&problems));
+        std::process::exit(2);
+    }
+    if cli.print_config {
+        return Ok(());
+    }
+
+    // JSON or text lines on stderr, with the request id of the request they were logged in
+    if let Err(err) = telemetry::init(&settings.log, &settings.otlp) {
+        eprintln!("Failed to initialize logging: {}", err);
+        std::process::exit(2);
+    }
+
+    log::info!("Starting with the {} profile", settings.profile);
+    for warning in settings.warnings() {
+        log::warn!("Insecure configuration: {}", warning);
+    }
+
     // So we can access db + firebase auth throughout the app in a shared state
-    // We need to convert it to an io::ErrorKind since the main function's error type is this type
-    let app_data = AppData::new().await.map_err(|err| Error::new(ErrorKind::Other, err))?;
+    let app_data = match AppData::new(&settings).await {
+        Ok(app_data) => app_data,
+        Err(err) => {
+            log::error!("Startup failed: {}", err);
+            std::process::exit(1);
+        }
+    };
     let db = Data::new(app_data.db);
-    let firebase_auth = Data::new(app_data.firebase_auth);
+    let auth_keys = Data::new(app_data.auth_keys);
+    let rate_limiter = Data::new(RateLimiter::new(&settings.rate_limit, db.clone()).await);
+    let bind_address = settings.bind_address();
+    let shutdown_timeout = Duration::from_secs(settings.server.shutdown_timeout_secs);
+    let metrics_address = settings.metrics_address();
+    let metrics_on_api_port = settings.metrics.enabled && metrics_address.is_none();
+    let json_limit = settings.json.limit_bytes;
+    let settings = Data::new(settings);
+    let shutdown_db = db.clone();
+
+    // Recipes stay in the trash for retention.trash_days before they are permanently removed
+    actix_web::rt::spawn(purge_trash_periodically(db.clone(), settings.retention.trash_days));
+    actix_web::rt::spawn(sync_food_database(db.clone()));
+    actix_web::rt::spawn(classify_stored_recipes(db.clone()));
+    actix_web::rt::spawn(seed_substitution_rules(db.clone()));
+    actix_web::rt::spawn(refresh_auth_keys_periodically(auth_keys.clone()));
+    let exporter = telemetry::exporter();
+    if let Some(exporter) = exporter.clone() {
+        actix_web::rt::spawn(export_spans_periodically(exporter));
+    }
 
-    env::set_var("RUST_LOG", "actix_web=info, warn"); // Configure the logging level
-    env_logger::init(); // Initialize the logger
+    // Prometheus scrapes /metrics on a port of its own, which doesn't have to be reachable from outside
+    let metrics_server = match metrics_address {
+        Some(address) => {
+            let metrics_settings = settings.clone();
+            let server = HttpServer::new(move || App::new().app_data(metrics_settings.clone()).service(get_metrics))
+                .workers(1)
+                .disable_signals() // Stopped after the API server below
+                .bind(&address)?
+                .run();
+            let handle = server.handle();
+
+            log::info!("Serving metrics on {}:{}", address.0, address.1);
+            actix_web::rt::spawn(server);
+            Some(handle)
+        }
+        None => None,
+    };
 
     // The move keyword attached to the closure gives it, HttpServer, ownership of the MongoDB configuration.
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin() // Allow all origins
-            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]) // Specify the allowed methods
-            .allowed_headers(vec![AUTHORIZATION, ACCEPT, CONTENT_TYPE])
-            .supports_credentials() // If you need to support credentials (cookies, session, etc.)
-            .max_age(3600); // Set the max age for the preflight cache
+        let request_auth_keys = auth_keys.clone();
 
         App::new()
-            .wrap(cors)
-            .wrap(Logger::new("%r %U %a - %s")) // Add the Logger middleware
+            .wrap(RateLimit::new(rate_limiter.clone())) // Inside the auth keys, keyed by the uid of a verified token
+            .wrap_fn(move |mut req, srv| {
+                // Verifies the token with the latest keys for the VerifiedUser extractor, see auth/keys.rs
+                request_auth_keys.attach(&mut req);
+                srv.call(req)
+            })
+            .wrap(cors(settings.cors()))
+            .wrap_fn(track_request) // Outside CORS, so rejected preflights are counted too
+            .wrap_fn(trace_request) // Outermost, request id and span for everything below
             .app_data(db.clone())
-            .app_data(firebase_auth.clone())
-            .service(insert_recipe)
-            .service(get_recipes_by_email)
+            .app_data(auth_keys.clone())
+            .app_data(uptime.clone())
+            .app_data(settings.clone())
+            .app_data(JsonConfig::default().limit(json_limit).error_handler(json_error_handler)) // For Json<T>, the handlers take JsonBody<T>
             .service(health_check)
-            .service(delete_recipe_by_id)
-            .service(update_recipe_by_id)
-            .service(get_recipe_by_id)
-            .service(get_all_recipes_pagination)
-            .service(update_photo_url_by_recipe_id)
-            .service(get_recipe_img_url_by_id)
-            .service(update_title_by_recipe_id)
+            .service(health_live)
+            .service(health_ready)
+            .configure(|cfg| {
+                if metrics_on_api_port {
+                    cfg.service(get_metrics); // Behind metrics.token
+                }
+            })
+            .configure(|cfg| version::configure(cfg, &settings.api)) // /api/v1, /api/v2 and the legacy paths, after the unversioned routes
     })
-        //.bind(("127.0.0.1", 8080))?
-        .bind(("0.0.0.0", 8082))? //for docker network
+        .shutdown_timeout(shutdown_timeout.as_secs()) // On SIGTERM, stop accepting and let in-flight requests finish
+        .bind(bind_address)? // server.host 0.0.0.0 by default, for docker network
         .run()
-        .await
+        .await?;
+
+    // Stopped by a signal, the workers are done with the database
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.stop(true).await;
+    }
+    shutdown_db.shutdown(shutdown_timeout).await;
+    if let Some(exporter) = exporter {
+        exporter.export().await; // The spans of the last requests
+    }
+    Ok(())
 }
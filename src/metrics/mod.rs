@@ -0,0 +1,329 @@
+pub mod pool;
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Instant;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use tracing::Instrument;
+
+use crate::metrics::pool::PoolMetrics;
+
+/*
+    Prometheus metrics, served as text by GET /metrics (api/metrics_api.rs).
+    One registry for the process: the request middleware, the MongoRepo methods, unauthorized_response
+    and the MongoDB pool events all record into METRICS.
+    Routes are labelled with their pattern ("/recipes/{id}") so IDs don't create new series.
+ */
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+// Seconds, upper bounds of the histogram buckets
+const HTTP_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const MONGO_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>, // Per bucket, made cumulative when written
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| seconds <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, String, u16), Histogram>>, // method, route, status
+    requests_in_flight: AtomicI64,
+    mongo_operations: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>, // MongoRepo method, outcome
+    auth_failures: AtomicU64,
+    rate_limited: Mutex<BTreeMap<&'static str, u64>>, // Route group
+    pub pool: PoolMetrics,
+}
+
+// Label values in quotes, with \ " and newlines escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Whether a MongoRepo method succeeded, for the outcome label
+pub trait Outcome {
+    fn outcome(&self) -> &'static str;
+}
+
+impl<T, E> Outcome for Result<T, E> {
+    fn outcome(&self) -> &'static str {
+        match self {
+            Ok(_) => "ok",
+            Err(_) => "error",
+        }
+    }
+}
+
+// Nothing matched, the methods returning Option turn driver errors into None with ok_recorded() which counts them as "error"
+impl<T> Outcome for Option<T> {
+    fn outcome(&self) -> &'static str {
+        match self {
+            Some(_) => "ok",
+            None => "none",
+        }
+    }
+}
+
+tokio::task_local! {
+    // Per observe_mongo call, set when ok_recorded() discarded an error
+    static FAILED: Cell<bool>;
+}
+
+/// ok() for the driver results of the MongoRepo methods returning Option, the error counts as the outcome of the
+/// operation instead of looking like nothing matched
+pub trait OkRecorded<T> {
+    fn ok_recorded(self) -> Option<T>;
+}
+
+impl<T> OkRecorded<T> for Result<T, mongodb::error::Error> {
+    fn ok_recorded(self) -> Option<T> {
+        match self {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let _ = FAILED.try_with(|failed| failed.set(true));
+                log::warn!("MongoDB operation failed: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Runs a MongoRepo method body in a mongo span and records how long it took, `operation` is the method name.
+/// The collection and the shape of the filter are filled in from the commands the driver sends, see telemetry/mongo.rs
+pub async fn observe_mongo<F>(operation: &'static str, operation_future: F) -> F::Output where F: Future, F::Output: Outcome {
+    let started = Instant::now();
+    let span = tracing::debug_span!(
+        "mongo",
+        operation,
+        collection = tracing::field::Empty,
+        filter = tracing::field::Empty,
+        otel.name = format!("MongoRepo.{}", operation),
+        otel.status_code = tracing::field::Empty,
+    );
+    let (result, failed) = FAILED
+        .scope(Cell::new(false), async {
+            let result = operation_future.instrument(span.clone()).await;
+            (result, FAILED.with(Cell::get))
+        })
+        .await;
+    let outcome = if failed { "error" } else { result.outcome() };
+
+    if outcome == "error" {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    METRICS.mongo_operations
+        .lock()
+        .unwrap()
+        .entry((operation, outcome))
+        .or_insert_with(|| Histogram::new(MONGO_BUCKETS))
+        .observe(started.elapsed().as_secs_f64());
+
+    result
+}
+
+// Decrements on drop, so requests whose client went away are not counted forever
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        METRICS.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        METRICS.requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Middleware (App::wrap_fn) counting requests and their latency per method, route and status
+pub fn track_request<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let in_flight = InFlight::start();
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string()); // 404s share one series
+    let response = srv.call(req);
+
+    async move {
+        let result = response.await;
+        drop(in_flight);
+
+        let status = match &result {
+            Ok(response) => response.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+
+        METRICS.requests
+            .lock()
+            .unwrap()
+            .entry((method, route, status.as_u16()))
+            .or_insert_with(|| Histogram::new(HTTP_BUCKETS))
+            .observe(started.elapsed().as_secs_f64());
+
+        result
+    }
+}
+
+impl Metrics {
+    /// A request without a valid Firebase token
+    pub fn auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A request turned away with 429 by the rate limiter
+    pub fn rate_limited(&self, group: &'static str) {
+        *self.rate_limited.lock().unwrap().entry(group).or_default() += 1;
+    }
+
+    /// Prometheus text exposition format 0.0.4
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        {
+            let requests = self.requests.lock().unwrap();
+
+            header(&mut out, "http_requests_total", "counter", "HTTP requests by method, route and status");
+            for ((method, route, status), histogram) in requests.iter() {
+                let _ = writeln!(out, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", escape(method), escape(route), status, histogram.count);
+            }
+
+            header(&mut out, "http_request_duration_seconds", "histogram", "HTTP request latency by method, route and status");
+            for ((method, route, status), histogram) in requests.iter() {
+                let labels = format!("method=\"{}\",route=\"{}\",status=\"{}\"", escape(method), escape(route), status);
+                histogram.write(&mut out, "http_request_duration_seconds", &labels);
+            }
+        }
+
+        header(&mut out, "http_requests_in_flight", "gauge", "HTTP requests being handled");
+        let _ = writeln!(out, "http_requests_in_flight {}", self.requests_in_flight.load(Ordering::Relaxed));
+
+        header(&mut out, "auth_failures_total", "counter", "Requests rejected for a missing or invalid Firebase token");
+        let _ = writeln!(out, "auth_failures_total {}", self.auth_failures.load(Ordering::Relaxed));
+
+        header(&mut out, "rate_limited_requests_total", "counter", "Requests rejected with 429 by route group");
+        for (group, count) in self.rate_limited.lock().unwrap().iter() {
+            let _ = writeln!(out, "rate_limited_requests_total{{group=\"{}\"}} {}", group, count);
+        }
+
+        header(&mut out, "mongodb_operation_duration_seconds", "histogram", "Duration of MongoRepo methods by method and outcome");
+        for ((operation, outcome), histogram) in self.mongo_operations.lock().unwrap().iter() {
+            let labels = format!("operation=\"{}\",outcome=\"{}\"", operation, outcome);
+            histogram.write(&mut out, "mongodb_operation_duration_seconds", &labels);
+        }
+
+        self.pool.render(&mut out);
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{observe_mongo, Histogram, Metrics, OkRecorded, HTTP_BUCKETS, METRICS};
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(0.05);
+        histogram.observe(0.1); // Upper bounds are inclusive
+        histogram.observe(0.5);
+        histogram.observe(30.0); // Only in +Inf
+
+        let mut out = String::new();
+        histogram.write(&mut out, "latency_seconds", "route=\"/\"");
+
+        assert_eq!(out, concat!(
+            "latency_seconds_bucket{route=\"/\",le=\"0.1\"} 2\n",
+            "latency_seconds_bucket{route=\"/\",le=\"1\"} 3\n",
+            "latency_seconds_bucket{route=\"/\",le=\"+Inf\"} 4\n",
+            "latency_seconds_sum{route=\"/\"} 30.65\n",
+            "latency_seconds_count{route=\"/\"} 4\n",
+        ));
+    }
+
+    #[test]
+    fn render_writes_every_family_with_escaped_labels() {
+        let metrics = Metrics::default();
+        let mut histogram = Histogram::new(HTTP_BUCKETS);
+        histogram.observe(0.2);
+        metrics.requests.lock().unwrap().insert(("GET".to_string(), "/recipes/\"{id}\"".to_string(), 200), histogram);
+        metrics.requests_in_flight.store(3, Ordering::Relaxed);
+        metrics.auth_failure();
+        metrics.rate_limited("writes");
+        metrics.rate_limited("writes");
+
+        let out = metrics.render();
+
+        assert!(out.contains("# TYPE http_requests_total counter\n"));
+        assert!(out.contains("http_requests_total{method=\"GET\",route=\"/recipes/\\\"{id}\\\"\",status=\"200\"} 1\n"));
+        assert!(out.contains("http_request_duration_seconds_bucket{method=\"GET\",route=\"/recipes/\\\"{id}\\\"\",status=\"200\",le=\"0.25\"} 1\n"));
+        assert!(out.contains("http_requests_in_flight 3\n"));
+        assert!(out.contains("auth_failures_total 1\n"));
+        assert!(out.contains("rate_limited_requests_total{group=\"writes\"} 2\n"));
+        assert!(out.contains("# TYPE mongodb_operation_duration_seconds histogram\n"));
+        // Every sample line belongs to a family with HELP and TYPE
+        for line in out.lines().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let family = ["_bucket", "_sum", "_count"].iter().fold(name, |name, suffix| name.strip_suffix(suffix).unwrap_or(name));
+            assert!(out.contains(&format!("# TYPE {} ", family)), "{} has no TYPE", line);
+        }
+    }
+
+    #[actix_web::test]
+    async fn discarded_errors_count_as_error_not_none() {
+        let failed: Option<()> = observe_mongo("test_discarded_error", async {
+            Err::<(), _>(mongodb::error::Error::from(std::io::Error::other("connection reset"))).ok_recorded()
+        }).await;
+        let missing: Option<()> = observe_mongo("test_nothing_matched", async { Ok::<_, mongodb::error::Error>(None).ok_recorded()? }).await;
+
+        assert!(failed.is_none() && missing.is_none());
+        let operations = METRICS.mongo_operations.lock().unwrap();
+        assert_eq!(operations[&("test_discarded_error", "error")].count, 1);
+        assert_eq!(operations[&("test_nothing_matched", "none")].count, 1);
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use mongodb::event::cmap::{
+    CmapEventHandler, ConnectionCheckedInEvent, ConnectionCheckedOutEvent, ConnectionCheckoutFailedEvent, ConnectionCheckoutFailedReason,
+    ConnectionClosedEvent, ConnectionCreatedEvent, PoolClearedEvent,
+};
+
+use crate::metrics::{header, METRICS};
+
+// MongoDB connection pool stats from the driver's CMAP events, summed over the servers of the deployment
+
+#[derive(Default)]
+pub struct PoolMetrics {
+    max_size: AtomicU64, // Per server
+    connections: AtomicI64,
+    in_use: AtomicI64,
+    checkout_timeouts: AtomicU64,
+    checkout_errors: AtomicU64,
+    cleared: AtomicU64,
+}
+
+/// Registered as cmap_event_handler on the client options, see MongoRepo::connect
+pub struct PoolEvents;
+
+impl CmapEventHandler for PoolEvents {
+    fn handle_pool_cleared_event(&self, _event: PoolClearedEvent) {
+        METRICS.pool.cleared.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn handle_connection_created_event(&self, _event: ConnectionCreatedEvent) {
+        METRICS.pool.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn handle_connection_closed_event(&self, _event: ConnectionClosedEvent) {
+        METRICS.pool.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn handle_connection_checkout_failed_event(&self, event: ConnectionCheckoutFailedEvent) {
+        match event.reason {
+            ConnectionCheckoutFailedReason::Timeout => METRICS.pool.checkout_timeouts.fetch_add(1, Ordering::Relaxed),
+            _ => METRICS.pool.checkout_errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn handle_connection_checked_out_event(&self, _event: ConnectionCheckedOutEvent) {
+        METRICS.pool.in_use.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn handle_connection_checked_in_event(&self, _event: ConnectionCheckedInEvent) {
+        METRICS.pool.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl PoolMetrics {
+    pub fn set_max_size(&self, max_size: u32) {
+        self.max_size.store(max_size as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn render(&self, out: &mut String) {
+        header(out, "mongodb_pool_max_connections", "gauge", "Maximum size of the connection pool of each server");
+        let _ = writeln!(out, "mongodb_pool_max_connections {}", self.max_size.load(Ordering::Relaxed));
+
+        header(out, "mongodb_pool_connections", "gauge", "Open connections to MongoDB");
+        let _ = writeln!(out, "mongodb_pool_connections {}", self.connections.load(Ordering::Relaxed));
+
+        header(out, "mongodb_pool_connections_in_use", "gauge", "Connections checked out by an operation");
+        let _ = writeln!(out, "mongodb_pool_connections_in_use {}", self.in_use.load(Ordering::Relaxed));
+
+        header(out, "mongodb_pool_checkout_failures_total", "counter", "Operations that didn't get a connection");
+        let _ = writeln!(out, "mongodb_pool_checkout_failures_total{{reason=\"timeout\"}} {}", self.checkout_timeouts.load(Ordering::Relaxed));
+        let _ = writeln!(out, "mongodb_pool_checkout_failures_total{{reason=\"connection_error\"}} {}", self.checkout_errors.load(Ordering::Relaxed));
+
+        header(out, "mongodb_pool_cleared_total", "counter", "Times a pool was cleared after a network error");
+        let _ = writeln!(out, "mongodb_pool_cleared_total {}", self.cleared.load(Ordering::Relaxed));
+    }
+}
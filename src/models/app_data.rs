@@ -1,33 +1,27 @@
-use std::env;
-
-use dotenv::dotenv;
-use firebase_auth::FirebaseAuth;
-
+use crate::auth::keys::AuthKeys;
 use crate::repository::mongo_repo::MongoRepo;
+use crate::settings::Settings;
 
 pub struct AppData {
     pub db: MongoRepo,
-    pub firebase_auth: FirebaseAuth
+    pub auth_keys: AuthKeys
 }
 
 pub type AsyncError = Box<dyn std::error::Error + Send + Sync>; // Send + Sync För att det är async runtime
 
 impl AppData {
-    /// Initializes `AppData` with database and FirebaseAuth configurations.
+    /// Initializes `AppData` with the database and the Firebase public keys.
     /// Returns `AppData` on success or a thread-safe error on failure, compatible with async environments.
     /// The error is boxed to allow for multiple error types to be returned
-    pub async fn new() -> Result<Self, AsyncError> {
-        dotenv().ok(); // Load .env file if it exists
-
+    pub async fn new(settings: &Settings) -> Result<Self, AsyncError> {
         // Initialize MongoDB connection
-        let db = MongoRepo::init().await;
-
-        // Retrieve Firebase ID from environment variable
-        let firebase_id = env::var("FIREBASE_ID").expect("FIREBASE_ID environment variable not set");
+        let db = MongoRepo::init(&settings.mongo, settings.retention.audit_days)
+            .await
+            .map_err(|err| format!("Failed to connect to MongoDB: {}", err))?;
 
-        // Initialize FirebaseAuth with the Firebase ID
-        let firebase_auth = FirebaseAuth::new(&firebase_id).await;
+        // Fetch the public keys Firebase ID tokens of the project are verified with
+        let auth_keys = AuthKeys::load(&settings.firebase.project_id).await?;
 
-        Ok(Self { db, firebase_auth })
+        Ok(Self { db, auth_keys })
     }
 }
@@ -0,0 +1,43 @@
+use mongodb::bson::Document;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    ImgUrlChange,
+    TitleChange,
+    Patch,
+    Delete, // Moved to the trash
+    RevisionRestore,
+    Restore, // Out of the trash
+    Purge,   // Permanently removed from the trash by the background job
+    DietaryOverride,
+}
+
+// Append-only, entries are never updated or deleted by the API (only by the TTL index)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub actor_uid: String,
+    pub action: AuditAction,
+    pub target_id: String,
+    pub timestamp: mongodb::bson::DateTime,
+    pub request_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub diff: Document, // { field: { before, after } } for every changed field
+}
+
+// Admin query for ../audit?target_id=..&actor_uid=..&from=..&to=.. (from/to are RFC 3339)
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub target_id: Option<String>,
+    pub actor_uid: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
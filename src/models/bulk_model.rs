@@ -0,0 +1,80 @@
+use std::convert::Infallible;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::recipe_model::RecipeDTO;
+use crate::models::recipe_v2_model::VersionedRecipe;
+
+// `R` is the recipe of the API version, RecipeV2DTO on /api/v2, see api/version.rs
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation<R = RecipeDTO> {
+    Create { recipe: R },
+    Update { id: String, recipe: R, version: Option<u32> }, // version works like If-Match
+    Delete { id: String, version: Option<u32> },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest<R = RecipeDTO> {
+    #[serde(default = "default_ordered")]
+    pub ordered: bool, // Ordered stops at the first failure, unordered runs every operation
+    pub operations: Vec<BulkOperation<R>>,
+}
+
+impl<R> BulkRequest<R> {
+    /// The same operations with every recipe converted, `convert` gets the index of the operation
+    pub fn try_map<S, E>(self, mut convert: impl FnMut(usize, R) -> Result<S, E>) -> Result<BulkRequest<S>, E> {
+        let operations = self.operations
+            .into_iter()
+            .enumerate()
+            .map(|(index, operation)| Ok(match operation {
+                BulkOperation::Create { recipe } => BulkOperation::Create { recipe: convert(index, recipe)? },
+                BulkOperation::Update { id, recipe, version } => BulkOperation::Update { id, recipe: convert(index, recipe)?, version },
+                BulkOperation::Delete { id, version } => BulkOperation::Delete { id, version },
+            }))
+            .collect::<Result<Vec<BulkOperation<S>>, E>>()?;
+
+        Ok(BulkRequest { ordered: self.ordered, operations })
+    }
+}
+
+// The recipes of another version as the RecipeDTO that is stored
+impl<R: Into<RecipeDTO>> BulkRequest<R> {
+    pub fn into_stored(self) -> BulkRequest {
+        let Ok(request) = self.try_map(|_, recipe| Ok::<RecipeDTO, Infallible>(recipe.into()));
+        request
+    }
+}
+
+fn default_ordered() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkItemStatus {
+    Created,
+    Updated,
+    Deleted,
+    Failed,
+    Skipped, // Not executed because an earlier operation failed in ordered mode
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub status: BulkItemStatus,
+    pub code: u16, // The status code the single item endpoint would have answered with
+    pub id: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipe: Option<VersionedRecipe>, // Updated, in the shape of the API version like PUT /recipes/{id} answers
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkResponse {
+    pub ordered: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
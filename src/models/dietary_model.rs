@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+// The 14 major allergens of EU regulation 1169/2011, annex II
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Allergen {
+    Gluten, // Cereals containing gluten: wheat, rye, barley, oats, spelt
+    Crustaceans,
+    Eggs,
+    Fish,
+    Peanuts,
+    Soybeans,
+    Milk,
+    Nuts, // Tree nuts: almonds, hazelnuts, walnuts, cashews, pecans, Brazil nuts, pistachios, macadamia
+    Celery,
+    Mustard,
+    Sesame,
+    Sulphites,
+    Lupin,
+    Molluscs,
+}
+
+impl Allergen {
+    pub const ALL: [Allergen; 14] = [
+        Allergen::Gluten, Allergen::Crustaceans, Allergen::Eggs, Allergen::Fish, Allergen::Peanuts, Allergen::Soybeans, Allergen::Milk,
+        Allergen::Nuts, Allergen::Celery, Allergen::Mustard, Allergen::Sesame, Allergen::Sulphites, Allergen::Lupin, Allergen::Molluscs,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Allergen::Gluten => "gluten",
+            Allergen::Crustaceans => "crustaceans",
+            Allergen::Eggs => "eggs",
+            Allergen::Fish => "fish",
+            Allergen::Peanuts => "peanuts",
+            Allergen::Soybeans => "soybeans",
+            Allergen::Milk => "milk",
+            Allergen::Nuts => "nuts",
+            Allergen::Celery => "celery",
+            Allergen::Mustard => "mustard",
+            Allergen::Sesame => "sesame",
+            Allergen::Sulphites => "sulphites",
+            Allergen::Lupin => "lupin",
+            Allergen::Molluscs => "molluscs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DietaryLabel {
+    Vegan,
+    Vegetarian,
+    Pescatarian,
+    GlutenFree,
+    DairyFree,
+    EggFree,
+    NutFree, // Neither peanuts nor tree nuts
+}
+
+impl DietaryLabel {
+    pub const ALL: [DietaryLabel; 7] = [
+        DietaryLabel::Vegan, DietaryLabel::Vegetarian, DietaryLabel::Pescatarian, DietaryLabel::GlutenFree,
+        DietaryLabel::DairyFree, DietaryLabel::EggFree, DietaryLabel::NutFree,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DietaryLabel::Vegan => "vegan",
+            DietaryLabel::Vegetarian => "vegetarian",
+            DietaryLabel::Pescatarian => "pescatarian",
+            DietaryLabel::GlutenFree => "gluten_free",
+            DietaryLabel::DairyFree => "dairy_free",
+            DietaryLabel::EggFree => "egg_free",
+            DietaryLabel::NutFree => "nut_free",
+        }
+    }
+}
+
+// Maintained by MongoRepo on every insert and update, never written by clients
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dietary {
+    pub labels: Vec<DietaryLabel>, // What the recipe is filtered on, the owner's override when there is one
+    pub allergens: Vec<Allergen>,
+    pub derived_labels: Vec<DietaryLabel>, // From the ingredients we know
+    pub derived_allergens: Vec<Allergen>,
+    #[serde(default)]
+    pub unknown_ingredients: Vec<String>, // Not in the table, no labels are derived while there are any
+    #[serde(default)]
+    pub complete: bool, // `allergens` is the whole list (no unknown ingredients or set by the owner), what free_from filters on
+    pub table_version: u32, // See ingredients/allergens.rs
+}
+
+// Set by the owner when the ingredients don't tell the whole story ("gluten free flour", "vegan cheese"...)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DietaryOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<DietaryLabel>>, // None keeps the derived labels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allergens: Option<Vec<Allergen>>, // None keeps the derived allergens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub overridden_by: String, // Firebase uid
+    pub overridden_at: mongodb::bson::DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DietaryOverrideRequest {
+    pub labels: Option<Vec<DietaryLabel>>,
+    pub allergens: Option<Vec<Allergen>>,
+    pub reason: Option<String>,
+}
+
+// List endpoints, ../recipes?diet=vegan,gluten_free&free_from=nuts,sesame
+#[derive(Debug, Deserialize)]
+pub struct DietaryQuery {
+    pub diet: Option<String>,      // Comma separated labels the recipes must all have
+    pub free_from: Option<String>, // Comma separated allergens the recipes must not contain
+}
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonld,
+    Markdown,
+    Html,
+    Pdf,
+}
+
+// ../recipes/{id}/export?format=pdf, defaults to JSON-LD
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
@@ -0,0 +1,144 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::recipe_model::RecipeDTO;
+
+// Returned by the import endpoints, nothing is saved until the user confirms with POST /recipes
+#[derive(Debug, Serialize)]
+pub struct ImportPreview {
+    pub recipe: RecipeDTO,
+    pub warnings: Vec<String>, // Fields that couldn't be found in the source, for the user to fill in
+}
+
+impl ImportPreview {
+    pub fn new(recipe: RecipeDTO) -> Self {
+        let mut warnings = Vec::new();
+
+        if recipe.title.is_empty() {
+            warnings.push("No title found".to_string());
+        }
+        if recipe.ingredients.is_empty() {
+            warnings.push("No ingredients found".to_string());
+        }
+        if recipe.steps.is_empty() {
+            warnings.push("No instructions found".to_string());
+        }
+        if recipe.photo_url.is_empty() {
+            warnings.push("No photo found".to_string());
+        }
+
+        ImportPreview { recipe, warnings }
+    }
+}
+
+// Exports of other recipe managers that can be imported with POST /imports?format=..
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Paprika,    // .paprikarecipes, a zip of gzipped JSON recipes
+    Mealie,     // JSON recipe(s) or a zip of them
+    Tandoor,    // Zip of zips with a recipe.json each, or a single recipe.json
+    Mealmaster, // .mmf text files, several recipes per file
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportJobParams {
+    pub format: ArchiveFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportJobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed, // The archive couldn't be read at all, see `error`
+}
+
+// A recipe of the archive that wasn't imported
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportIssue {
+    pub title: String,
+    pub reason: String,
+}
+
+// The job is a single document (16 MB at most) that is rewritten on every progress update,
+// the lists hold the first entries and the counters have the totals
+pub const MAX_IMPORT_RECIPE_IDS: usize = 1000;
+pub const MAX_IMPORT_ISSUES: usize = 200;
+
+// Progress of an archive import, polled by the client with GET /imports/{id}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportJob {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub owner_uid: String,
+    pub email: String,
+    pub format: ArchiveFormat,
+    pub status: ImportJobStatus,
+    pub total: u32,     // Recipes found in the archive
+    pub processed: u32, // imported + duplicates + failed
+    pub imported: u32,
+    pub duplicates: u32,
+    pub failed: u32,
+    pub recipe_ids: Vec<String>,   // The first MAX_IMPORT_RECIPE_IDS imported recipes
+    pub issues: Vec<ImportIssue>,  // The first MAX_IMPORT_ISSUES duplicates and failures
+    pub error: Option<String>,
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+impl ImportJob {
+    pub fn new(owner_uid: String, email: String, format: ArchiveFormat) -> Self {
+        let now = mongodb::bson::DateTime::now();
+
+        ImportJob {
+            id: None,
+            owner_uid,
+            email,
+            format,
+            status: ImportJobStatus::Queued,
+            total: 0,
+            processed: 0,
+            imported: 0,
+            duplicates: 0,
+            failed: 0,
+            recipe_ids: Vec::new(),
+            issues: Vec::new(),
+            error: None,
+            created: now,
+            updated: now,
+        }
+    }
+
+    pub fn add_recipe_id(&mut self, id: String) {
+        if self.recipe_ids.len() < MAX_IMPORT_RECIPE_IDS {
+            self.recipe_ids.push(id);
+        }
+    }
+
+    pub fn add_issue(&mut self, issue: ImportIssue) {
+        if self.issues.len() < MAX_IMPORT_ISSUES {
+            self.issues.push(issue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveFormat, ImportIssue, ImportJob, MAX_IMPORT_ISSUES, MAX_IMPORT_RECIPE_IDS};
+
+    #[test]
+    fn job_lists_are_capped() {
+        let mut job = ImportJob::new("uid".to_string(), "cook@example.com".to_string(), ArchiveFormat::Mealie);
+
+        for index in 0..MAX_IMPORT_RECIPE_IDS + 10 {
+            job.add_recipe_id(index.to_string());
+            job.add_issue(ImportIssue { title: index.to_string(), reason: "Duplicate".to_string() });
+        }
+
+        assert_eq!(job.recipe_ids.len(), MAX_IMPORT_RECIPE_IDS);
+        assert_eq!(job.issues.len(), MAX_IMPORT_ISSUES);
+        assert_eq!(job.issues.last().unwrap().title, (MAX_IMPORT_ISSUES - 1).to_string());
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+// Structured form of an ingredient line, "2 1/2 dl vetemjöl, sifted"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ingredient {
+    pub quantity: Option<f64>,
+    pub unit: Option<String>, // Canonical unit, see ingredients/units.rs
+    pub name: String,
+    pub note: Option<String>,
+    // The line it was parsed from, written back as it was while the fields above are unchanged, see format_ingredient
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
+}
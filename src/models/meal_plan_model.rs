@@ -0,0 +1,122 @@
+use chrono::NaiveDate;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MealSlot {
+    Breakfast,
+    Lunch,
+    Dinner,
+    Snack,
+}
+
+impl MealSlot {
+    // In the order of the day, used to sort the plan
+    pub const ALL: [MealSlot; 4] = [MealSlot::Breakfast, MealSlot::Lunch, MealSlot::Dinner, MealSlot::Snack];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MealSlot::Breakfast => "breakfast",
+            MealSlot::Lunch => "lunch",
+            MealSlot::Dinner => "dinner",
+            MealSlot::Snack => "snack",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MealPlan {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub owner_uid: String,
+    pub name: String,
+    pub feed_token: String, // Secret in the .ics URL, calendar apps can't send a Firebase token
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+// One recipe in one slot of a day, dates are stored as "YYYY-MM-DD" so they sort and range query as strings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MealPlanEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub plan_id: ObjectId,
+    pub owner_uid: String,
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+    pub recipe_id: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub servings: Option<u32>, // Overrides the yield of the recipe, for the shopping list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MealPlanDTO {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MealPlanEntryDTO {
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+    pub recipe_id: String,
+    pub servings: Option<u32>,
+    pub note: Option<String>,
+}
+
+// Copies the week starting at `from` to the week starting at `to`
+#[derive(Debug, Deserialize)]
+pub struct WeekCopyRequest {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+// ../me/mealplan?from=2024-03-04&to=2024-03-10&plan_id=.. (from/to inclusive)
+#[derive(Debug, Deserialize)]
+pub struct MealPlanQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub plan_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedParams {
+    pub token: String,
+}
+
+// The parts of a recipe shown in the planner
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecipeSummary {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+    #[serde(default)]
+    pub photo_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipe_yield: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_minutes: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+// Entry joined with its recipe, recipe is None when the recipe has been deleted since it was planned
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResolvedMealPlanEntry {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub plan_id: ObjectId,
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+    pub recipe_id: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub servings: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub updated: mongodb::bson::DateTime,
+    pub recipe: Option<RecipeSummary>,
+}
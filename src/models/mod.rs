@@ -1,3 +1,16 @@
-
 pub mod app_data;
 pub mod recipe_model;
+pub mod audit_model;
+pub mod revision_model;
+pub mod bulk_model;
+pub mod import_model;
+pub mod export_model;
+pub mod ingredient_model;
+pub mod meal_plan_model;
+pub mod shopping_list_model;
+pub mod pantry_model;
+pub mod nutrition_model;
+pub mod dietary_model;
+pub mod substitution_model;
+pub mod rate_limit_model;
+pub mod recipe_v2_model;
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+// Energy, macros and the micronutrients we track, per 100 g on a Food and as absolute amounts on a recipe
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Nutrients {
+    pub energy_kcal: f64,
+    pub protein_g: f64,
+    pub fat_g: f64,
+    pub saturated_fat_g: f64,
+    pub carbohydrate_g: f64,
+    pub sugars_g: f64,
+    pub fiber_g: f64,
+    pub sodium_mg: f64,
+    pub calcium_mg: f64,
+    pub iron_mg: f64,
+    pub potassium_mg: f64,
+    pub vitamin_c_mg: f64,
+}
+
+impl Nutrients {
+    fn combine(&self, other: &Nutrients, f: impl Fn(f64, f64) -> f64) -> Nutrients {
+        Nutrients {
+            energy_kcal: f(self.energy_kcal, other.energy_kcal),
+            protein_g: f(self.protein_g, other.protein_g),
+            fat_g: f(self.fat_g, other.fat_g),
+            saturated_fat_g: f(self.saturated_fat_g, other.saturated_fat_g),
+            carbohydrate_g: f(self.carbohydrate_g, other.carbohydrate_g),
+            sugars_g: f(self.sugars_g, other.sugars_g),
+            fiber_g: f(self.fiber_g, other.fiber_g),
+            sodium_mg: f(self.sodium_mg, other.sodium_mg),
+            calcium_mg: f(self.calcium_mg, other.calcium_mg),
+            iron_mg: f(self.iron_mg, other.iron_mg),
+            potassium_mg: f(self.potassium_mg, other.potassium_mg),
+            vitamin_c_mg: f(self.vitamin_c_mg, other.vitamin_c_mg),
+        }
+    }
+
+    /// Adds `grams` of a food, `per_100g` being its composition
+    pub fn add_food(&self, per_100g: &Nutrients, grams: f64) -> Nutrients {
+        self.combine(per_100g, |total, amount| total + amount * grams / 100.0)
+    }
+
+    /// Every value divided by `divisor` and rounded to one decimal
+    pub fn divided(&self, divisor: f64) -> Nutrients {
+        self.combine(self, |value, _| (value / divisor * 10.0).round() / 10.0)
+    }
+}
+
+// One food of the composition table (data/nutrition/foods.csv), keyed by its USDA FDC id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Food {
+    #[serde(rename = "_id")]
+    pub fdc_id: String,
+    pub description: String,
+    pub aliases: Vec<String>, // Names used in ingredient lines, English and Swedish
+    pub per_100g: Nutrients,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density_g_per_ml: Option<f64>, // For volume units, water (1.0) is assumed when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub piece_g: Option<f64>, // Weight of one egg, onion, garlic clove, bread slice...
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IngredientNutrition {
+    pub line: String,
+    pub matched: bool, // False when the line isn't counted, `reason` says why
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fdc_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub food: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grams: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// Cached on the recipe, valid as long as the recipe version and the food table are the same
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeNutrition {
+    pub recipe_version: u32,
+    pub dataset_version: String,
+    pub servings: f64,
+    pub servings_from_yield: bool, // False when recipe_yield has no number and the recipe counts as 1 serving
+    pub per_serving: Nutrients,
+    pub total: Nutrients,
+    pub ingredients: Vec<IngredientNutrition>,
+    pub computed: mongodb::bson::DateTime,
+}
+
+// ../recipes/{id}/nutrition?refresh=true
+#[derive(Debug, Deserialize)]
+pub struct NutritionParams {
+    pub refresh: Option<bool>, // Recompute even when the cached value is still valid
+}
@@ -0,0 +1,64 @@
+use chrono::NaiveDate;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PantryItem {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub owner_uid: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_on: Option<NaiveDate>,
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PantryItemDTO {
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub expires_on: Option<NaiveDate>,
+}
+
+// ../recipes/cookable?threshold=0.75&limit=20&expiring_within_days=3
+#[derive(Debug, Deserialize)]
+pub struct CookableQuery {
+    pub threshold: Option<f64>,            // Share of the ingredients that must be in the pantry, 0.0 - 1.0, default 0.5
+    pub limit: Option<usize>,              // Default 20
+    pub expiring_within_days: Option<u64>, // Default 3
+}
+
+// What the cookable search needs of a recipe
+#[derive(Debug, Deserialize)]
+pub struct RecipeIngredients {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+    #[serde(default)]
+    pub photo_url: String,
+    pub email: String,
+    pub ingredients: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CookableRecipe {
+    pub recipe_id: String,
+    pub title: String,
+    pub photo_url: String,
+    pub owned: bool,
+    pub match_ratio: f64,
+    pub missing: Vec<String>,       // Ingredient lines not covered by the pantry
+    pub uses_expiring: Vec<String>, // Pantry items about to expire that the recipe uses
+}
+
+#[derive(Debug, Serialize)]
+pub struct CookableResponse {
+    pub expiring: Vec<PantryItem>, // Use these first, expired items included
+    pub recipes: Vec<CookableRecipe>,
+}
@@ -0,0 +1,13 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+// Token bucket of one client and route group in the RateLimits collection, only used by the mongo rate limit backend
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimitBucket {
+    #[serde(rename = "_id")]
+    pub key: String, // "<route group>:<uid:... or ip:...>"
+    pub tokens: f64, // Left after the request that updated it
+    pub allowed: bool, // Whether that request got a token
+    pub updated: DateTime,
+    pub expires: DateTime, // When the bucket is full again, the TTL index removes it after that
+}
@@ -1,19 +1,39 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::models::dietary_model::{Dietary, DietaryOverride};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Recipe {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub title: String,
+    #[serde(default)] // Can be removed with a PATCH
     pub description: String,
     pub steps: Vec<String>,
     pub ingredients: Vec<String>,
     pub email: String,
     pub tags: Vec<String>,
+    #[serde(default)] // Can be removed with a PATCH
     pub photo_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipe_yield: Option<String>, // Free text like "4 servings" or "12 cookies"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prep_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cook_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_minutes: Option<u32>,
     pub created: Option<mongodb::bson::DateTime>, // Då vi inte vill create alltid
-    pub updated: mongodb::bson::DateTime
+    pub updated: mongodb::bson::DateTime,
+    #[serde(default)] // Recipes stored before versioning was added are version 0
+    pub version: u32, // Bumped by every update, exposed as the ETag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<mongodb::bson::DateTime>, // Set when the recipe is in the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dietary: Option<Dietary>, // Labels and allergens, maintained by the repository on every write
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dietary_override: Option<DietaryOverride>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +47,14 @@ pub struct RecipeDTO {
     pub ingredients: Vec<String>,
     pub email: String,
     pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipe_yield: Option<String>, // Free text like "4 servings" or "12 cookies"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prep_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cook_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_minutes: Option<u32>,
     // Created & Updated will be done in the code not from request
 }
 
@@ -0,0 +1,178 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::ingredients::{format_ingredient, parse_ingredient};
+use crate::models::dietary_model::{Dietary, DietaryOverride};
+use crate::models::ingredient_model::Ingredient;
+use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+/*
+    The recipe of /api/v2: ingredients as objects instead of lines and the owner as an author object
+    instead of an email field. Recipes are still stored in the v1 shape, these are converted from
+    and to Recipe and RecipeDTO at the API boundary, see api/version.rs.
+ */
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Author {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecipeV2 {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub title: String,
+    pub description: String,
+    pub steps: Vec<String>,
+    pub ingredients: Vec<Ingredient>,
+    pub author: Author,
+    pub tags: Vec<String>,
+    pub photo_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipe_yield: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prep_time_minutes: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cook_time_minutes: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_time_minutes: Option<u32>,
+    pub created: Option<mongodb::bson::DateTime>,
+    pub updated: mongodb::bson::DateTime,
+    pub version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<mongodb::bson::DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dietary: Option<Dietary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dietary_override: Option<DietaryOverride>,
+}
+
+/// A recipe in the shape of an API version, for bodies that have recipes next to other fields
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum VersionedRecipe {
+    V1(Recipe),
+    V2(RecipeV2),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecipeV2DTO {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub title: String,
+    pub description: String,
+    pub steps: Vec<String>,
+    pub photo_url: String,
+    pub ingredients: Vec<Ingredient>,
+    pub author: Author,
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipe_yield: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prep_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cook_time_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_minutes: Option<u32>,
+}
+
+impl From<Recipe> for RecipeV2 {
+    fn from(recipe: Recipe) -> Self {
+        RecipeV2 {
+            id: recipe.id,
+            title: recipe.title,
+            description: recipe.description,
+            steps: recipe.steps,
+            ingredients: recipe.ingredients.iter().map(|line| parse_ingredient(line)).collect(),
+            author: Author { email: recipe.email },
+            tags: recipe.tags,
+            photo_url: recipe.photo_url,
+            recipe_yield: recipe.recipe_yield,
+            prep_time_minutes: recipe.prep_time_minutes,
+            cook_time_minutes: recipe.cook_time_minutes,
+            total_time_minutes: recipe.total_time_minutes,
+            created: recipe.created,
+            updated: recipe.updated,
+            version: recipe.version,
+            deleted_at: recipe.deleted_at,
+            dietary: recipe.dietary,
+            dietary_override: recipe.dietary_override,
+        }
+    }
+}
+
+// Ingredients become lines again, "2 dl milk, cold", or the lines they were if the client didn't change them
+impl From<RecipeV2DTO> for RecipeDTO {
+    fn from(recipe: RecipeV2DTO) -> Self {
+        RecipeDTO {
+            id: recipe.id,
+            title: recipe.title,
+            description: recipe.description,
+            steps: recipe.steps,
+            photo_url: recipe.photo_url,
+            ingredients: recipe.ingredients.iter().map(format_ingredient).collect(),
+            email: recipe.author.email,
+            tags: recipe.tags,
+            recipe_yield: recipe.recipe_yield,
+            prep_time_minutes: recipe.prep_time_minutes,
+            cook_time_minutes: recipe.cook_time_minutes,
+            total_time_minutes: recipe.total_time_minutes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::models::recipe_model::RecipeDTO;
+
+    use super::{RecipeV2, RecipeV2DTO};
+
+    const LINES: [&str; 4] = ["2-3 ägg", "1/3 dl mjölk", "50 g smör (mjukt)", "2 msk socker"];
+
+    // What a client sends back after reading the recipe from /api/v2, with the ingredients it was given
+    fn round_trip(edit: impl FnOnce(&mut serde_json::Value)) -> RecipeDTO {
+        let recipe = map_input_dto(RecipeDTO {
+            id: None,
+            title: "Kladdkaka".to_string(),
+            description: String::new(),
+            steps: vec![],
+            photo_url: String::new(),
+            ingredients: LINES.iter().map(|line| line.to_string()).collect(),
+            email: "cook@example.com".to_string(),
+            tags: vec![],
+            recipe_yield: None,
+            prep_time_minutes: None,
+            cook_time_minutes: None,
+            total_time_minutes: None,
+        }, None, RecipeStatus::Created);
+
+        let mut body = serde_json::to_value(RecipeV2::from(recipe)).unwrap();
+        edit(&mut body);
+        serde_json::from_value::<RecipeV2DTO>(body).unwrap().into()
+    }
+
+    #[test]
+    fn unchanged_ingredients_keep_their_lines() {
+        assert_eq!(round_trip(|_| {}).ingredients, LINES);
+    }
+
+    #[test]
+    fn edited_ingredients_are_formatted_and_the_rest_kept() {
+        let recipe = round_trip(|body| {
+            body["ingredients"][1]["quantity"] = json!(2);
+            body["ingredients"][2]["note"] = json!(null);
+        });
+        assert_eq!(recipe.ingredients, ["2-3 ägg", "2 dl mjölk", "50 g smör", "2 msk socker"]);
+
+        // Without the lines it is what the fields say, a range is its low end and units are canonical
+        let recipe = round_trip(|body| {
+            for ingredient in body["ingredients"].as_array_mut().unwrap() {
+                ingredient.as_object_mut().unwrap().remove("line");
+            }
+        });
+        assert_eq!(recipe.ingredients, ["2 ägg", "0.33 dl mjölk", "50 g smör, mjukt", "2 tbsp socker"]);
+    }
+}
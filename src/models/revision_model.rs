@@ -0,0 +1,36 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::recipe_model::Recipe;
+use crate::models::recipe_v2_model::VersionedRecipe;
+
+// A snapshot of a recipe as it looked before an update, revisions are numbered from 1 per recipe
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecipeRevision {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub recipe_id: ObjectId,
+    pub revision: u32,
+    pub author_uid: String, // The user whose update replaced this version
+    pub created: mongodb::bson::DateTime,
+    pub snapshot: Recipe,
+}
+
+// A revision as it is sent, the snapshot has the shape of the API version
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub recipe_id: ObjectId,
+    pub revision: u32,
+    pub author_uid: String,
+    pub created: mongodb::bson::DateTime,
+    pub snapshot: VersionedRecipe,
+}
+
+// ../revisions/diff?from=1&to=3, leaving out `to` compares against the current recipe
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffParams {
+    pub from: u32,
+    pub to: Option<u32>,
+}
@@ -0,0 +1,119 @@
+use chrono::NaiveDate;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+// Declared in the order you usually walk through a store, the list is sorted by it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Aisle {
+    Produce,
+    Bakery,
+    Meat,
+    Fish,
+    Dairy,
+    Frozen,
+    Pantry,
+    Spices,
+    Beverages,
+    Other,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShoppingItem {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub aisle: Aisle,
+    pub checked: bool,
+    pub manual: bool, // Added by hand, not from a recipe
+    #[serde(default)]
+    pub recipes: Vec<String>, // Titles of the recipes that need it
+}
+
+// Shared lists can be read, checked off and added to by the household members in shared_with (emails),
+// only the owner can share or delete it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShoppingList {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub owner_uid: String,
+    pub name: String,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    pub items: Vec<ShoppingItem>,
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+// Either recipe_ids or a date range of the meal planner (optionally one plan)
+#[derive(Debug, Deserialize)]
+pub struct ShoppingListRequest {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub recipe_ids: Vec<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub plan_id: Option<String>,
+}
+
+// A manual item is free text, "2 l mjölk"
+#[derive(Debug, Deserialize)]
+pub struct ManualItemRequest {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckItemRequest {
+    pub checked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AisleGroup {
+    pub aisle: Aisle,
+    pub items: Vec<ShoppingItem>,
+}
+
+// What the API returns, the items grouped by aisle
+#[derive(Debug, Serialize)]
+pub struct ShoppingListView {
+    #[serde(rename = "_id")]
+    pub id: Option<ObjectId>,
+    pub owner_uid: String,
+    pub name: String,
+    pub shared_with: Vec<String>,
+    pub aisles: Vec<AisleGroup>,
+    pub created: mongodb::bson::DateTime,
+    pub updated: mongodb::bson::DateTime,
+}
+
+impl From<ShoppingList> for ShoppingListView {
+    fn from(list: ShoppingList) -> Self {
+        let mut items = list.items;
+        items.sort_by(|a, b| a.aisle.cmp(&b.aisle).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+
+        let mut aisles: Vec<AisleGroup> = Vec::new();
+        for item in items {
+            match aisles.last_mut() {
+                Some(group) if group.aisle == item.aisle => group.items.push(item),
+                _ => aisles.push(AisleGroup { aisle: item.aisle, items: vec![item] }),
+            }
+        }
+
+        ShoppingListView {
+            id: list.id,
+            owner_uid: list.owner_uid,
+            name: list.name,
+            shared_with: list.shared_with,
+            aisles,
+            created: list.created,
+            updated: list.updated,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::recipe_v2_model::VersionedRecipe;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubstituteComponent {
+    pub name: String,
+    pub ratio: f64, // Amount per 1 of the replaced ingredient, in its unit unless `unit` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>, // "1 egg" = 1 tbsp flaxseed + 3 tbsp water
+}
+
+// "buttermilk" = 0.94 milk + 0.06 lemon juice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubstitutionRule {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub ingredient: String,
+    pub replacement: Vec<SubstituteComponent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    pub curated: bool, // Shipped with the app (ingredients/substitutions.rs), false for rules added by admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>, // Firebase uid of the admin
+    pub created: mongodb::bson::DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubstitutionRuleDTO {
+    pub ingredient: String,
+    pub replacement: Vec<SubstituteComponent>,
+    pub notes: Option<String>,
+}
+
+// ../recipes/{id}/substitutions?ingredient=buttermilk&avoid=dairy,eggs
+#[derive(Debug, Deserialize)]
+pub struct SubstitutionQuery {
+    pub ingredient: Option<String>, // The ingredient you don't have
+    pub avoid: Option<String>,      // Comma separated allergens, "dairy", "meat" or "animal_products"
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubstitutionSwap {
+    pub line: String,             // The ingredient line of the recipe
+    pub replacement: Vec<String>, // The lines it was swapped for
+    pub rule: SubstitutionRule,
+    pub alternatives: Vec<SubstitutionRule>, // Other rules that would also work
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubstitutionResponse {
+    pub recipe: VersionedRecipe, // With the swaps applied and its dietary labels recomputed, not saved
+    pub swaps: Vec<SubstitutionSwap>,
+    pub unresolved: Vec<String>, // Lines that needed a swap but no rule fits
+}
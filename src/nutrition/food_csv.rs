@@ -0,0 +1,161 @@
+use crate::models::nutrition_model::{Food, Nutrients};
+
+/*
+    Reader for the food composition table in data/nutrition/foods.csv, a subset of USDA SR Legacy
+    (https://fdc.nal.usda.gov) with the common recipe ingredients. Nutrients are per 100 g, aliases
+    are separated by "|" and an empty density or piece weight means unknown.
+    Columns are found by their header so the file can get more columns without breaking the reader.
+ */
+
+// RFC 4180 fields: quoted fields may contain commas, a quote inside them is written ""
+fn split_record(line: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    fields.push(field);
+    fields
+}
+
+pub fn parse_foods(csv: &str) -> Result<Vec<Food>, String> {
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header = split_record(lines.next().ok_or("The food table is empty")?);
+
+    let column = |name: &str| header
+        .iter()
+        .position(|column| column.trim() == name)
+        .ok_or(format!("The food table has no {} column", name));
+
+    let fdc_id = column("fdc_id")?;
+    let description = column("description")?;
+    let aliases = column("aliases")?;
+    let density = column("density_g_per_ml")?;
+    let piece = column("piece_g")?;
+    let nutrient_columns = [
+        "energy_kcal", "protein_g", "fat_g", "saturated_fat_g", "carbohydrate_g", "sugars_g",
+        "fiber_g", "sodium_mg", "calcium_mg", "iron_mg", "potassium_mg", "vitamin_c_mg",
+    ].into_iter().map(column).collect::<Result<Vec<usize>, String>>()?;
+
+    let mut foods: Vec<Food> = Vec::new();
+
+    for (index, line) in lines.enumerate() {
+        let record = split_record(line);
+        let line_number = index + 2;
+        let text = |column: usize| record.get(column).map(|value| value.trim()).unwrap_or_default();
+
+        let number = |column: usize| -> Result<Option<f64>, String> {
+            match text(column) {
+                "" => Ok(None),
+                value => value
+                    .parse::<f64>()
+                    .map(Some)
+                    .map_err(|_| format!("Line {}: \"{}\" is not a number", line_number, value)),
+            }
+        };
+
+        let mut values = [0.0; 12];
+        for (value, column) in values.iter_mut().zip(&nutrient_columns) {
+            *value = number(*column)?.unwrap_or(0.0); // Missing nutrient values count as 0
+        }
+        let [energy_kcal, protein_g, fat_g, saturated_fat_g, carbohydrate_g, sugars_g, fiber_g, sodium_mg, calcium_mg, iron_mg, potassium_mg, vitamin_c_mg] = values;
+
+        if text(fdc_id).is_empty() {
+            return Err(format!("Line {}: fdc_id is missing", line_number));
+        }
+
+        foods.push(Food {
+            fdc_id: text(fdc_id).to_string(),
+            description: text(description).to_string(),
+            aliases: text(aliases)
+                .split('|')
+                .map(str::trim)
+                .filter(|alias| !alias.is_empty())
+                .map(str::to_string)
+                .collect(),
+            per_100g: Nutrients {
+                energy_kcal,
+                protein_g,
+                fat_g,
+                saturated_fat_g,
+                carbohydrate_g,
+                sugars_g,
+                fiber_g,
+                sodium_mg,
+                calcium_mg,
+                iron_mg,
+                potassium_mg,
+                vitamin_c_mg,
+            },
+            density_g_per_ml: number(density)?,
+            piece_g: number(piece)?,
+        });
+    }
+
+    Ok(foods)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nutrition::FOODS_CSV;
+
+    use super::{parse_foods, split_record};
+
+    #[test]
+    fn records_with_quotes_and_empty_fields() {
+        assert_eq!(split_record("a,b,c"), vec!["a", "b", "c"]);
+        assert_eq!(split_record(r#"1,"Wheat flour, white",flour|mjöl"#), vec!["1", "Wheat flour, white", "flour|mjöl"]);
+        assert_eq!(split_record(r#""Say ""cheese""",,"#), vec![r#"Say "cheese""#, "", ""]);
+        assert_eq!(split_record(""), vec![""]);
+    }
+
+    #[test]
+    fn parses_by_header_name() {
+        let csv = "piece_g,fdc_id,description,aliases,energy_kcal,protein_g,fat_g,saturated_fat_g,carbohydrate_g,sugars_g,fiber_g,sodium_mg,calcium_mg,iron_mg,potassium_mg,vitamin_c_mg,density_g_per_ml,extra\n\
+                   50,01123,\"Egg, whole\", egg | ägg ,143,12.56,9.51,3.126,0.72,0.37,,142,56,1.75,138,0,1.03,x\n\
+                   \n\
+                   ,02047,Salt,salt,0,0,0,0,0,0,0,38758,24,0.33,8,0,,\n";
+        let foods = parse_foods(csv).unwrap();
+
+        assert_eq!(foods.len(), 2);
+        assert_eq!(foods[0].fdc_id, "01123");
+        assert_eq!(foods[0].description, "Egg, whole");
+        assert_eq!(foods[0].aliases, vec!["egg", "ägg"]);
+        assert_eq!((foods[0].per_100g.energy_kcal, foods[0].per_100g.fiber_g), (143.0, 0.0));
+        assert_eq!((foods[0].density_g_per_ml, foods[0].piece_g), (Some(1.03), Some(50.0)));
+        assert_eq!((foods[1].density_g_per_ml, foods[1].piece_g), (None, None));
+        assert_eq!(foods[1].per_100g.sodium_mg, 38758.0);
+    }
+
+    #[test]
+    fn errors_name_the_line_or_column() {
+        assert_eq!(parse_foods("").unwrap_err(), "The food table is empty");
+        assert_eq!(parse_foods("fdc_id,description\n").unwrap_err(), "The food table has no aliases column");
+
+        let header = FOODS_CSV.lines().next().unwrap();
+        let bad_number = format!("{}\n1,Egg,egg,lots,,,,,,,,,,,,,\n", header);
+        assert_eq!(parse_foods(&bad_number).unwrap_err(), "Line 2: \"lots\" is not a number");
+        let no_id = format!("{}\n,Egg,egg,1,,,,,,,,,,,,,\n", header);
+        assert_eq!(parse_foods(&no_id).unwrap_err(), "Line 2: fdc_id is missing");
+    }
+
+    #[test]
+    fn bundled_table_is_valid() {
+        let foods = parse_foods(FOODS_CSV).unwrap();
+
+        assert_eq!(foods.len(), FOODS_CSV.lines().filter(|line| !line.trim().is_empty()).count() - 1);
+        assert!(foods.iter().all(|food| !food.aliases.is_empty()));
+    }
+}
@@ -0,0 +1,247 @@
+pub mod food_csv;
+
+use crate::ingredients::units::{Dimension, to_base_unit};
+use crate::ingredients::{ingredient_key, parse_ingredient, yield_servings};
+use crate::models::ingredient_model::Ingredient;
+use crate::models::nutrition_model::{Food, IngredientNutrition, Nutrients, RecipeNutrition};
+use crate::models::recipe_model::Recipe;
+
+/*
+    Nutrition estimate of a recipe from the bundled food composition table. Every ingredient line is parsed,
+    its name matched against the aliases of the foods and the amount converted to grams, by the food's density
+    for volumes and its piece weight for counted things ("2 eggs", "3 cloves garlic").
+    Lines that can't be matched or weighed are kept in the result with matched = false and a reason,
+    so the client can show how complete the estimate is.
+ */
+
+/// The table compiled into the binary, synced into the Foods collection at startup (see jobs/food_database.rs)
+pub const FOODS_CSV: &str = include_str!("../../data/nutrition/foods.csv");
+
+/// Changes whenever the bundled table changes, cached estimates from an older table are recomputed
+pub fn dataset_version() -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in FOODS_CSV.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+pub struct FoodIndex {
+    foods: Vec<Food>,
+    aliases: Vec<(String, usize)>, // (ingredient key of the alias, index in foods)
+}
+
+impl FoodIndex {
+    pub fn new(foods: Vec<Food>) -> Self {
+        let aliases = foods
+            .iter()
+            .enumerate()
+            .flat_map(|(index, food)| food.aliases.iter().map(move |alias| (ingredient_key(alias), index)))
+            .filter(|(key, _)| !key.is_empty())
+            .collect();
+
+        FoodIndex { foods, aliases }
+    }
+
+    /// The food with the most specific alias in the name, "brown sugar" is brown sugar and not sugar.
+    /// The alias has to be whole words of the name, unlike `covers` a compound word isn't matched by its end:
+    /// "buttermilk" and "kärnmjölk" aren't milk, Swedish compounds that are the same food are aliases of their own ("vetemjöl")
+    pub fn find(&self, name: &str) -> Option<&Food> {
+        let key = format!(" {} ", ingredient_key(name));
+
+        self.aliases
+            .iter()
+            .filter(|(alias, _)| key.contains(&format!(" {} ", alias)))
+            .max_by_key(|(alias, _)| alias.chars().count())
+            .map(|(_, index)| &self.foods[*index])
+    }
+}
+
+// Rough weights of the units that aren't a mass or a volume, pcs/clove/slice come from the food
+fn unit_grams(unit: &str, food: &Food) -> Option<f64> {
+    match unit {
+        "pinch" => Some(0.36),
+        "dash" => Some(0.6),
+        "drop" => Some(0.05),
+        "can" => Some(400.0),
+        "bunch" => Some(25.0),
+        "pcs" | "clove" | "slice" => food.piece_g,
+        _ => None, // pkg, the size varies too much
+    }
+}
+
+fn grams(ingredient: &Ingredient, food: &Food) -> Result<f64, String> {
+    let quantity = ingredient.quantity.ok_or("No amount")?;
+    let unit = ingredient.unit.as_deref().unwrap_or("pcs"); // "2 eggs"
+
+    match to_base_unit(quantity, unit) {
+        Some((Dimension::Mass, grams)) => Ok(grams),
+        Some((Dimension::Volume, ml)) => Ok(ml * food.density_g_per_ml.unwrap_or(1.0)),
+        None => unit_grams(unit, food)
+            .map(|grams| quantity * grams)
+            .ok_or(format!("No weight known for {} of {}", unit, food.description)),
+    }
+}
+
+fn ingredient_nutrition(line: &str, index: &FoodIndex) -> (IngredientNutrition, Option<(Nutrients, f64)>) {
+    let ingredient = parse_ingredient(line);
+    let unmatched = |reason: String, food: Option<&Food>| IngredientNutrition {
+        line: line.to_string(),
+        matched: false,
+        fdc_id: food.map(|food| food.fdc_id.clone()),
+        food: food.map(|food| food.description.clone()),
+        grams: None,
+        reason: Some(reason),
+    };
+
+    let Some(food) = index.find(&ingredient.name) else {
+        return (unmatched("Not in the food table".to_string(), None), None);
+    };
+
+    match grams(&ingredient, food) {
+        Ok(grams) => {
+            let grams = (grams * 10.0).round() / 10.0;
+            let nutrition = IngredientNutrition {
+                line: line.to_string(),
+                matched: true,
+                fdc_id: Some(food.fdc_id.clone()),
+                food: Some(food.description.clone()),
+                grams: Some(grams),
+                reason: None,
+            };
+            (nutrition, Some((food.per_100g.clone(), grams)))
+        }
+        Err(reason) => (unmatched(reason, Some(food)), None),
+    }
+}
+
+/// Totals and per serving values, a recipe without a number in recipe_yield counts as one serving
+pub fn compute_nutrition(recipe: &Recipe, index: &FoodIndex) -> RecipeNutrition {
+    let mut total = Nutrients::default();
+    let mut ingredients: Vec<IngredientNutrition> = Vec::new();
+
+    for line in recipe.ingredients.iter().filter(|line| !line.trim().is_empty()) {
+        let (nutrition, amount) = ingredient_nutrition(line, index);
+
+        if let Some((per_100g, grams)) = amount {
+            total = total.add_food(&per_100g, grams);
+        }
+        ingredients.push(nutrition);
+    }
+
+    let servings = yield_servings(recipe.recipe_yield.as_deref());
+
+    RecipeNutrition {
+        recipe_version: recipe.version,
+        dataset_version: dataset_version(),
+        servings: servings.unwrap_or(1.0),
+        servings_from_yield: servings.is_some(),
+        per_serving: total.divided(servings.unwrap_or(1.0)),
+        total: total.divided(1.0),
+        ingredients,
+        computed: mongodb::bson::DateTime::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::util::{map_input_dto, RecipeStatus};
+    use crate::ingredients::parse_ingredient;
+    use crate::models::recipe_model::{Recipe, RecipeDTO};
+
+    use super::{compute_nutrition, FOODS_CSV, FoodIndex, grams};
+    use super::food_csv::parse_foods;
+
+    fn index() -> FoodIndex {
+        FoodIndex::new(parse_foods(FOODS_CSV).unwrap())
+    }
+
+    fn recipe(ingredients: &[&str], recipe_yield: Option<&str>) -> Recipe {
+        map_input_dto(RecipeDTO {
+            id: None,
+            title: "Test".to_string(),
+            description: String::new(),
+            steps: Vec::new(),
+            photo_url: String::new(),
+            ingredients: ingredients.iter().map(|line| line.to_string()).collect(),
+            email: "cook@example.com".to_string(),
+            tags: Vec::new(),
+            recipe_yield: recipe_yield.map(str::to_string),
+            prep_time_minutes: None,
+            cook_time_minutes: None,
+            total_time_minutes: None,
+        }, None, RecipeStatus::Created)
+    }
+
+    fn food_of(index: &FoodIndex, name: &str) -> Option<String> {
+        index.find(name).map(|food| food.fdc_id.clone())
+    }
+
+    #[test]
+    fn find_prefers_the_most_specific_alias() {
+        let index = index();
+
+        assert_eq!(food_of(&index, "brown sugar"), Some("19334".to_string()));
+        assert_eq!(food_of(&index, "sugar"), Some("19335".to_string()));
+        assert_eq!(food_of(&index, "Peanut butter"), Some("16098".to_string()));
+        assert_eq!(food_of(&index, "kall mjölk"), Some("01077".to_string()));
+        assert_eq!(food_of(&index, "vetemjöl"), Some("20081".to_string()));
+        assert_eq!(food_of(&index, "Eggs"), Some("01123".to_string()));
+    }
+
+    #[test]
+    fn find_matches_whole_words_only() {
+        let index = index();
+
+        assert_eq!(food_of(&index, "buttermilk"), None);
+        assert_eq!(food_of(&index, "kärnmjölk"), None);
+        assert_eq!(food_of(&index, "licorice"), None);
+        assert_eq!(food_of(&index, "eggplant"), None);
+    }
+
+    #[test]
+    fn grams_by_mass_density_and_piece() {
+        let index = index();
+        let weigh = |line: &str| {
+            let ingredient = parse_ingredient(line);
+            grams(&ingredient, index.find(&ingredient.name).unwrap())
+        };
+
+        assert_eq!(weigh("0.5 kg vetemjöl"), Ok(500.0));
+        assert!((weigh("1 dl vetemjöl").unwrap() - 53.0).abs() < 1e-9);
+        assert_eq!(weigh("2 ägg"), Ok(100.0));
+        assert_eq!(weigh("2 cloves garlic"), Ok(10.0));
+        assert!((weigh("1 pinch salt").unwrap() - 0.36).abs() < 1e-9);
+        assert_eq!(weigh("salt"), Err("No amount".to_string()));
+        assert_eq!(weigh("1 pkg butter"), Err("No weight known for pkg of Butter, without salt".to_string()));
+    }
+
+    #[test]
+    fn compute_nutrition_totals_and_servings() {
+        let nutrition = compute_nutrition(&recipe(&["100 g vetemjöl", "2 eggs", "", "1 handful of magic", "salt"], Some("4 servings")), &index());
+
+        assert_eq!(nutrition.servings, 4.0);
+        assert!(nutrition.servings_from_yield);
+        assert_eq!(nutrition.ingredients.len(), 4);
+        assert_eq!(nutrition.ingredients.iter().map(|ingredient| ingredient.matched).collect::<Vec<bool>>(), vec![true, true, false, false]);
+        assert_eq!(nutrition.ingredients[2].reason.as_deref(), Some("Not in the food table"));
+        assert_eq!(nutrition.ingredients[3].reason.as_deref(), Some("No amount"));
+        assert_eq!(nutrition.ingredients[3].fdc_id.as_deref(), Some("02047"));
+
+        // 364 kcal per 100 g flour plus 143 per 100 g egg for 100 g of eggs
+        assert_eq!(nutrition.total.energy_kcal, 507.0);
+        assert_eq!(nutrition.per_serving.energy_kcal, 126.8);
+    }
+
+    #[test]
+    fn compute_nutrition_without_yield_is_one_serving() {
+        let nutrition = compute_nutrition(&recipe(&["1 l mjölk"], Some("one loaf")), &index());
+
+        assert_eq!(nutrition.servings, 1.0);
+        assert!(!nutrition.servings_from_yield);
+        assert_eq!(nutrition.ingredients[0].grams, Some(1030.0));
+        assert_eq!(nutrition.per_serving, nutrition.total);
+    }
+}
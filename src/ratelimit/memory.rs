@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::ratelimit::Taken;
+
+// Buckets that are full again are the same as no bucket, they are dropped this often so the map doesn't grow with every client
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,
+}
+
+/// Buckets of the rate_limit.backend "memory", each instance counts for itself
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore { buckets: Mutex::new(HashMap::new()), last_sweep: Mutex::new(Instant::now()) }
+    }
+}
+
+impl MemoryStore {
+    pub fn take(&self, key: &str, capacity: f64, per_second: f64) -> Taken {
+        self.take_at(key, capacity, per_second, Instant::now())
+    }
+
+    fn take_at(&self, key: &str, capacity: f64, per_second: f64, now: Instant) -> Taken {
+        self.sweep(now);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now, full_at: now });
+
+        let refilled = bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second;
+        let tokens = refilled.min(capacity);
+        let allowed = tokens >= 1.0;
+
+        bucket.tokens = if allowed { tokens - 1.0 } else { tokens };
+        bucket.updated = now;
+        bucket.full_at = now + Duration::from_secs_f64((capacity - bucket.tokens) / per_second);
+
+        Taken { allowed, tokens: bucket.tokens }
+    }
+
+    fn sweep(&self, now: Instant) {
+        let mut last_sweep = self.last_sweep.lock().unwrap();
+        if now.duration_since(*last_sweep) < SWEEP_INTERVAL {
+            return;
+        }
+        *last_sweep = now;
+
+        self.buckets.lock().unwrap().retain(|_, bucket| bucket.full_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_allows_a_burst_then_refuses() {
+        let store = MemoryStore::default();
+        let now = Instant::now();
+
+        let tokens: Vec<(bool, f64)> = (0..4).map(|_| store.take_at("writes:ip:1", 3.0, 1.0, now)).map(|taken| (taken.allowed, taken.tokens)).collect();
+        assert_eq!(tokens, vec![(true, 2.0), (true, 1.0), (true, 0.0), (false, 0.0)]);
+
+        // Other keys have buckets of their own
+        assert!(store.take_at("writes:ip:2", 3.0, 1.0, now).allowed);
+    }
+
+    #[test]
+    fn take_refills_up_to_the_capacity() {
+        let store = MemoryStore::default();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            store.take_at("reads:uid:a", 3.0, 2.0, now);
+        }
+        assert!(!store.take_at("reads:uid:a", 3.0, 2.0, now).allowed);
+
+        // Half a second at 2 per second is one token, which the request takes
+        let taken = store.take_at("reads:uid:a", 3.0, 2.0, now + Duration::from_millis(500));
+        assert!(taken.allowed);
+        assert_eq!(taken.tokens, 0.0);
+
+        let taken = store.take_at("reads:uid:a", 3.0, 2.0, now + Duration::from_secs(30));
+        assert_eq!(taken.tokens, 2.0);
+    }
+
+    #[test]
+    fn sweep_drops_the_buckets_that_are_full_again() {
+        let store = MemoryStore::default();
+        let now = Instant::now();
+
+        store.take_at("reads:ip:1", 10.0, 1.0, now); // Full again after a second
+        for _ in 0..10 {
+            store.take_at("uploads:ip:1", 10.0, 0.1, now); // After 100 seconds
+        }
+
+        // Not before the sweep interval
+        store.take_at("writes:ip:1", 10.0, 1.0, now + Duration::from_secs(30));
+        assert_eq!(store.buckets.lock().unwrap().len(), 3);
+
+        store.take_at("writes:ip:2", 10.0, 1.0, now + SWEEP_INTERVAL + Duration::from_secs(1));
+        let mut keys: Vec<String> = store.buckets.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["uploads:ip:1", "writes:ip:2"]);
+    }
+}
@@ -0,0 +1,347 @@
+pub mod memory;
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix_web::{HttpMessage, HttpResponse};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::web::Data;
+use futures::future::LocalBoxFuture;
+
+use crate::api::util::Response;
+use crate::api::version::unversioned;
+use crate::auth::VerifiedUser;
+use crate::metrics::METRICS;
+use crate::ratelimit::memory::MemoryStore;
+use crate::repository::mongo_repo::MongoRepo;
+use crate::settings::{BucketSettings, RateLimitBackend, RateLimitSettings};
+
+/*
+    Token bucket rate limiting per client and route group. A client is the Firebase uid of a request
+    with a valid token (see auth/keys.rs), otherwise the IP address. Each group has its own bucket of
+    rate_limit.<group>.burst requests that refills at rate_limit.<group>.per_minute, so a client can
+    use up its uploads without losing its reads.
+    Limited responses carry the RateLimit-* headers of the IETF draft, 429 responses Retry-After too.
+    When the mongo backend fails requests are let through, MongoDB being down shouldn't turn into 429s.
+ */
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteGroup {
+    Reads,
+    Writes,
+    Imports, // Parsing pages and JSON-LD sent by the client
+    Uploads, // Whole archives of other recipe managers
+}
+
+impl RouteGroup {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteGroup::Reads => "reads",
+            RouteGroup::Writes => "writes",
+            RouteGroup::Imports => "imports",
+            RouteGroup::Uploads => "uploads",
+        }
+    }
+
+    /// None for what isn't limited: CORS preflights, health checks and metrics.
+    /// `route` is the pattern, requests that match no route count as reads or writes by method
+    pub fn of(method: &Method, route: Option<&str>) -> Option<Self> {
+        match (method, route.map(unversioned)) {
+            (&Method::OPTIONS, _) => None,
+            (_, Some(route)) if route == "/health" || route.starts_with("/health/") || route == "/metrics" => None,
+            (&Method::POST, Some("/recipes/import")) => Some(RouteGroup::Imports),
+            (&Method::POST, Some("/imports")) => Some(RouteGroup::Uploads),
+            (&Method::GET | &Method::HEAD, _) => Some(RouteGroup::Reads),
+            _ => Some(RouteGroup::Writes),
+        }
+    }
+
+    fn bucket(self, settings: &RateLimitSettings) -> BucketSettings {
+        match self {
+            RouteGroup::Reads => settings.reads,
+            RouteGroup::Writes => settings.writes,
+            RouteGroup::Imports => settings.imports,
+            RouteGroup::Uploads => settings.uploads,
+        }
+    }
+}
+
+/// What was left in a bucket after a request
+pub struct Taken {
+    pub allowed: bool,
+    pub tokens: f64,
+}
+
+enum Store {
+    Memory(MemoryStore),
+    Mongo(Data<MongoRepo>),
+}
+
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    store: Store,
+    failing: AtomicBool, // So a failing backend is logged once, not for every request
+}
+
+impl RateLimiter {
+    /// Creates the TTL index of the mongo backend, the limiter works without it but buckets would pile up
+    pub async fn new(settings: &RateLimitSettings, db: Data<MongoRepo>) -> Self {
+        let store = match settings.backend {
+            RateLimitBackend::Memory => Store::Memory(MemoryStore::default()),
+            RateLimitBackend::Mongo => {
+                if let Err(err) = db.ensure_rate_limit_indexes().await {
+                    log::warn!("Failed to create rate limit indexes: {}", err);
+                }
+                Store::Mongo(db)
+            }
+        };
+
+        RateLimiter { settings: settings.clone(), store, failing: AtomicBool::new(false) }
+    }
+
+    /// None when the backend failed
+    async fn take(&self, group: RouteGroup, client: &str) -> Option<(Taken, BucketSettings)> {
+        let bucket = group.bucket(&self.settings);
+        let (capacity, per_second) = (bucket.burst as f64, bucket.per_minute as f64 / 60.0);
+        let key = format!("{}:{}", group.name(), client);
+
+        let taken = match &self.store {
+            Store::Memory(store) => store.take(&key, capacity, per_second),
+            Store::Mongo(db) => match db.take_rate_limit_token(&key, capacity, per_second).await {
+                Ok(bucket) => {
+                    if self.failing.swap(false, Ordering::Relaxed) {
+                        log::info!("Rate limiting with MongoDB again");
+                    }
+                    Taken { allowed: bucket.allowed, tokens: bucket.tokens }
+                }
+                Err(err) => {
+                    if !self.failing.swap(true, Ordering::Relaxed) {
+                        log::warn!("Rate limiting is off, MongoDB failed: {}", err);
+                    }
+                    return None;
+                }
+            },
+        };
+
+        Some((taken, bucket))
+    }
+}
+
+// "uid:<uid>" or "ip:<address>", X-Forwarded-For and Forwarded are set by clients as they like unless a proxy replaces them
+fn client_key(req: &ServiceRequest, trust_forwarded: bool) -> String {
+    if let Some(VerifiedUser(user)) = req.extensions().get::<VerifiedUser>() {
+        return format!("uid:{}", user.user_id);
+    }
+
+    let ip = match trust_forwarded {
+        true => req.connection_info().realip_remote_addr().map(str::to_string),
+        false => req.peer_addr().map(|address| address.ip().to_string()),
+    };
+    format!("ip:{}", ip.unwrap_or_default())
+}
+
+fn insert_headers(headers: &mut HeaderMap, taken: &Taken, bucket: BucketSettings) {
+    let per_second = bucket.per_minute as f64 / 60.0;
+    let reset_secs = ((bucket.burst as f64 - taken.tokens) / per_second).ceil() as u64;
+    let numbers = [
+        (RATELIMIT_LIMIT, bucket.burst as u64),
+        (RATELIMIT_REMAINING, taken.tokens.floor() as u64),
+        (RATELIMIT_RESET, reset_secs),
+    ];
+
+    for (name, value) in numbers {
+        headers.insert(name, HeaderValue::from(value));
+    }
+    if let Ok(policy) = HeaderValue::from_str(&format!("{};w=60;burst={}", bucket.per_minute, bucket.burst)) {
+        headers.insert(RATELIMIT_POLICY, policy);
+    }
+}
+
+fn too_many_requests(taken: &Taken, bucket: BucketSettings) -> HttpResponse {
+    let per_second = bucket.per_minute as f64 / 60.0;
+    let retry_after = ((1.0 - taken.tokens).max(0.0) / per_second).ceil().max(1.0) as u64; // Until there is a token again
+
+    let mut response = HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, retry_after))
+        .json(Response { message: format!("Too many requests, try again in {}s", retry_after) });
+    insert_headers(response.headers_mut(), taken, bucket);
+    response
+}
+
+/// Middleware, App::wrap(RateLimit::new(..)). Has to be inside the one attaching the auth keys, which verifies the token
+pub struct RateLimit {
+    limiter: Data<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Data<RateLimiter>) -> Self {
+        RateLimit { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), limiter: self.limiter.clone() }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Data<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let limited = match RouteGroup::of(req.method(), req.match_pattern().as_deref()).filter(|_| limiter.settings.enabled) {
+                Some(group) => limiter.take(group, &client_key(&req, limiter.settings.trust_forwarded)).await.map(|(taken, bucket)| (group, taken, bucket)),
+                None => None,
+            };
+            let Some((group, taken, bucket)) = limited else {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            };
+
+            if !taken.allowed {
+                METRICS.rate_limited(group.name());
+                return Ok(req.into_response(too_many_requests(&taken, bucket)).map_into_right_body());
+            }
+
+            let mut response = service.call(req).await?;
+            insert_headers(response.headers_mut(), &taken, bucket);
+            Ok(response.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App};
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn route_group_of_requests() {
+        assert_eq!(RouteGroup::of(&Method::OPTIONS, Some("/recipes")), None);
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/health")), None);
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/health/ready")), None);
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/metrics")), None);
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/healthy")), Some(RouteGroup::Reads));
+
+        assert_eq!(RouteGroup::of(&Method::POST, Some("/recipes/import")), Some(RouteGroup::Imports));
+        assert_eq!(RouteGroup::of(&Method::POST, Some("/api/v2/recipes/import")), Some(RouteGroup::Imports));
+        assert_eq!(RouteGroup::of(&Method::POST, Some("/imports")), Some(RouteGroup::Uploads));
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/imports/{id}")), Some(RouteGroup::Reads));
+
+        assert_eq!(RouteGroup::of(&Method::GET, Some("/api/v1/recipes/{id}")), Some(RouteGroup::Reads));
+        assert_eq!(RouteGroup::of(&Method::HEAD, None), Some(RouteGroup::Reads));
+        assert_eq!(RouteGroup::of(&Method::PATCH, Some("/recipes/{id}")), Some(RouteGroup::Writes));
+        assert_eq!(RouteGroup::of(&Method::DELETE, None), Some(RouteGroup::Writes));
+    }
+
+    fn limiter() -> Data<RateLimiter> {
+        let settings = RateLimitSettings {
+            reads: BucketSettings { per_minute: 60, burst: 5 },
+            writes: BucketSettings { per_minute: 6, burst: 2 },
+            ..RateLimitSettings::default()
+        };
+        Data::new(RateLimiter { settings, store: Store::Memory(MemoryStore::default()), failing: AtomicBool::new(false) })
+    }
+
+    fn header(response: &ServiceResponse<impl MessageBody>, name: &str) -> Option<String> {
+        response.headers().get(name).map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn limited_responses_carry_the_ratelimit_headers() {
+        let app = init_service(
+            App::new()
+                .wrap(RateLimit::new(limiter()))
+                .route("/recipes", web::get().to(HttpResponse::Ok))
+                .route("/recipes", web::post().to(HttpResponse::Created))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let post = || TestRequest::post().uri("/recipes").peer_addr("10.0.0.1:1234".parse().unwrap()).to_request();
+
+        let response = call_service(&app, post()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(header(&response, "ratelimit-limit").as_deref(), Some("2"));
+        assert_eq!(header(&response, "ratelimit-remaining").as_deref(), Some("1"));
+        assert_eq!(header(&response, "ratelimit-reset").as_deref(), Some("10")); // A token every 10 seconds
+        assert_eq!(header(&response, "ratelimit-policy").as_deref(), Some("6;w=60;burst=2"));
+        assert_eq!(header(&response, "retry-after"), None);
+
+        let response = call_service(&app, post()).await;
+        assert_eq!(header(&response, "ratelimit-remaining").as_deref(), Some("0"));
+        assert_eq!(header(&response, "ratelimit-reset").as_deref(), Some("20"));
+
+        let response = call_service(&app, post()).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&response, "retry-after").as_deref(), Some("10"));
+        assert_eq!(header(&response, "ratelimit-remaining").as_deref(), Some("0"));
+        let body: serde_json::Value = read_body_json(response).await;
+        assert_eq!(body["message"], "Too many requests, try again in 10s");
+
+        // Reads have a bucket of their own, and so do other clients
+        let response = call_service(&app, TestRequest::get().uri("/recipes").peer_addr("10.0.0.1:1234".parse().unwrap()).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "ratelimit-remaining").as_deref(), Some("4"));
+        let response = call_service(&app, TestRequest::post().uri("/recipes").peer_addr("10.0.0.2:1234".parse().unwrap()).to_request()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = call_service(&app, TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(header(&response, "ratelimit-limit"), None);
+    }
+
+    #[actix_web::test]
+    async fn client_key_prefers_the_verified_user() {
+        let req = TestRequest::get()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_srv_request();
+        assert_eq!(client_key(&req, false), "ip:10.0.0.1");
+        assert_eq!(client_key(&req, true), "ip:203.0.113.7");
+
+        let user = serde_json::from_value(serde_json::json!({
+            "iss": "", "aud": "", "sub": "uid-1", "iat": 0, "exp": 0, "auth_time": 0, "user_id": "uid-1",
+            "firebase": { "sign_in_provider": "password", "identities": {} },
+        }))
+        .unwrap();
+        req.extensions_mut().insert(VerifiedUser(user));
+        assert_eq!(client_key(&req, true), "uid:uid-1");
+    }
+}
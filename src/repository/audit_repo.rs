@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::options::{FindOptions, IndexOptions};
+
+use crate::metrics::observe_mongo;
+use crate::models::audit_model::AuditEntry;
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+// An index with the same keys exists with other options, here a TTL index with another expireAfterSeconds
+const INDEX_OPTIONS_CONFLICT: i32 = 85;
+
+impl MongoRepo {
+    /// expireAfterSeconds of the TTL index, retention.audit_days is at most MAX_RETENTION_DAYS so it fits
+    pub fn audit_ttl_secs(retention_days: u64) -> u64 {
+        retention_days.saturating_mul(24 * 60 * 60).min(i32::MAX as u64)
+    }
+
+    /// collMod changing the expireAfterSeconds of the TTL index on `timestamp`, create_indexes won't change an existing index
+    pub fn audit_ttl_update(collection: &str, ttl_secs: u64) -> Document {
+        doc! {
+            "collMod": collection,
+            "index": {"keyPattern": {"timestamp": 1}, "expireAfterSeconds": ttl_secs as i64},
+        }
+    }
+
+    /// Creates the query indexes for the audit log plus a TTL index on `timestamp`,
+    /// Mongo removes entries by itself once they are older than `retention_days`.
+    /// When the index exists from an earlier retention.audit_days it is changed to the current one
+    pub async fn ensure_audit_indexes(&self, retention_days: u64) -> Result<(), Error> {
+        observe_mongo("ensure_audit_indexes", async {
+            let col: Collection<AuditEntry> = MongoRepo::collection_switch(self, CollectionName::AuditLog).await;
+            let ttl_secs = MongoRepo::audit_ttl_secs(retention_days);
+
+            let target_index = IndexModel::builder().keys(doc! {"target_id": 1, "timestamp": -1}).build();
+            let actor_index = IndexModel::builder().keys(doc! {"actor_uid": 1, "timestamp": -1}).build();
+            col.create_indexes(vec![target_index, actor_index], None).await?;
+
+            let ttl_index = IndexModel::builder()
+                .keys(doc! {"timestamp": 1})
+                .options(IndexOptions::builder()
+                    .expire_after(Some(Duration::from_secs(ttl_secs)))
+                    .build())
+                .build();
+
+            match col.create_index(ttl_index, None).await {
+                Ok(_) => Ok(()),
+                Err(err) if matches!(&*err.kind, ErrorKind::Command(error) if error.code == INDEX_OPTIONS_CONFLICT) => {
+                    let update = MongoRepo::audit_ttl_update(self.collection_name(CollectionName::AuditLog), ttl_secs);
+                    self.run_command(update).await?;
+                    log::info!("Audit entries are now kept for {} days", retention_days);
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            }
+        }).await
+    }
+
+    pub async fn insert_audit_entry(&self, entry: AuditEntry) -> Result<(), Error> {
+        observe_mongo("insert_audit_entry", async {
+            let col = MongoRepo::collection_switch::<AuditEntry>(self, CollectionName::AuditLog).await;
+
+            col.insert_one(entry, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Newest first, `page` starts at 1
+    pub fn audit_page_options(page: u32, per_page: u32) -> FindOptions {
+        let skip = (page.max(1) as u64 - 1).saturating_mul(per_page as u64);
+
+        FindOptions::builder()
+            .sort(doc! {"timestamp": -1})
+            .skip(Some(skip))
+            .limit(Some(per_page as i64))
+            .build()
+    }
+
+    /// filter is built by the caller, newest entries first
+    pub async fn find_audit_entries(&self, filter: Document, page: u32, per_page: u32) -> Result<Vec<AuditEntry>, Error> {
+        observe_mongo("find_audit_entries", async {
+            let col = MongoRepo::collection_switch::<AuditEntry>(self, CollectionName::AuditLog).await;
+
+            let mut cursors = col
+                .find(filter, MongoRepo::audit_page_options(page, per_page))
+                .await?;
+
+            let mut entries: Vec<AuditEntry> = Vec::new();
+
+            while let Some(entry) = cursors
+                .try_next()
+                .await?
+            {
+                entries.push(entry)
+            }
+
+            Ok(entries)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::repository::mongo_repo::MongoRepo;
+
+    #[test]
+    fn ttl_follows_the_retention() {
+        assert_eq!(MongoRepo::audit_ttl_secs(365), 31_536_000);
+        assert_eq!(MongoRepo::audit_ttl_secs(u64::MAX), i32::MAX as u64);
+
+        assert_eq!(MongoRepo::audit_ttl_update("AuditLog", 86_400), doc! {
+            "collMod": "AuditLog",
+            "index": {"keyPattern": {"timestamp": 1}, "expireAfterSeconds": 86_400_i64},
+        });
+    }
+
+    #[test]
+    fn pages_skip_the_ones_before() {
+        let first = MongoRepo::audit_page_options(1, 50);
+        assert_eq!((first.skip, first.limit, first.sort), (Some(0), Some(50), Some(doc! {"timestamp": -1})));
+
+        let third = MongoRepo::audit_page_options(3, 20);
+        assert_eq!((third.skip, third.limit), (Some(40), Some(20)));
+        assert_eq!(MongoRepo::audit_page_options(0, 20).skip, Some(0));
+        assert_eq!(MongoRepo::audit_page_options(u32::MAX, 100).skip, Some((u32::MAX as u64 - 1) * 100));
+    }
+}
@@ -0,0 +1,192 @@
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+
+use crate::ingredients::allergens::{dietary, TABLE_VERSION};
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::dietary_model::{Allergen, DietaryLabel, DietaryOverride};
+use crate::models::recipe_model::Recipe;
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    pub async fn ensure_dietary_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_dietary_indexes", async {
+            let col: Collection<Recipe> = MongoRepo::collection_switch(self, CollectionName::Recipes).await;
+
+            col.create_index(IndexModel::builder().keys(doc! {"dietary.labels": 1}).build(), None).await?;
+            col.create_index(IndexModel::builder().keys(doc! {"dietary.table_version": 1}).build(), None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Recipes having all `labels` and none of `allergens`, merged into the filters of the list queries.
+    /// Recipes that were never classified or have unknown ingredients are not free from anything
+    pub fn dietary_filter(labels: &[DietaryLabel], allergens: &[Allergen]) -> Document {
+        let mut filter = Document::new();
+
+        if !labels.is_empty() {
+            filter.insert("dietary.labels", doc! {"$all": labels.iter().map(DietaryLabel::as_str).collect::<Vec<&str>>()});
+        }
+        if !allergens.is_empty() {
+            filter.insert("dietary", doc! {"$exists": true});
+            filter.insert("dietary.complete", true);
+            filter.insert("dietary.allergens", doc! {"$nin": allergens.iter().map(Allergen::as_str).collect::<Vec<&str>>()});
+        }
+
+        filter
+    }
+
+    // Pipeline stage setting `dietary` from the derived values (expressions) and the override stored on the recipe,
+    // the same as ingredients/allergens.rs dietary(). Earlier stages are visible, so a new override counts
+    fn dietary_stage(derived_labels: Bson, derived_allergens: Bson, unknown: Bson, table_version: Bson) -> Document {
+        let known = doc! {"$eq": [{"$size": unknown.clone()}, 0]};
+
+        doc! {"$set": {"dietary": {
+            "labels": {"$ifNull": ["$dietary_override.labels", {"$cond": [known.clone(), derived_labels.clone(), []]}]},
+            "allergens": {"$ifNull": ["$dietary_override.allergens", derived_allergens.clone()]},
+            "derived_labels": derived_labels,
+            "derived_allergens": derived_allergens,
+            "unknown_ingredients": unknown,
+            "complete": {"$or": [known, {"$isArray": "$dietary_override.allergens"}]},
+            "table_version": table_version,
+        }}}
+    }
+
+    /// Stage of an update pipeline that changes the ingredients, the labels are written by the same update
+    pub fn classify_stage(ingredients: &[String]) -> Document {
+        let derived = dietary(ingredients, None);
+        let literal = |value: Bson| Bson::Document(doc! {"$literal": value});
+
+        MongoRepo::dietary_stage(
+            literal(to_bson(&derived.derived_labels).unwrap_or_default()),
+            literal(to_bson(&derived.derived_allergens).unwrap_or_default()),
+            literal(to_bson(&derived.unknown_ingredients).unwrap_or_default()),
+            Bson::Int64(TABLE_VERSION as i64),
+        )
+    }
+
+    // Stage of an update pipeline that changes the override, the derived values stay. A recipe that was never classified
+    // gets table_version 0 and is classified by jobs/dietary_backfill.rs
+    fn reclassify_stage() -> Document {
+        MongoRepo::dietary_stage(
+            Bson::Document(doc! {"$ifNull": ["$dietary.derived_labels", []]}),
+            Bson::Document(doc! {"$ifNull": ["$dietary.derived_allergens", []]}),
+            Bson::Document(doc! {"$ifNull": ["$dietary.unknown_ingredients", []]}),
+            Bson::Document(doc! {"$ifNull": ["$dietary.table_version", 0]}),
+        )
+    }
+
+    /// Classifies a recipe stored before dietary labels existed or with an older table (jobs/dietary_backfill.rs).
+    /// Skipped when the recipe has been updated in the meantime, that update classified it
+    pub async fn classify_recipe(&self, recipe: &Recipe) -> Result<(), Error> {
+        observe_mongo("classify_recipe", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let Some(id) = recipe.id else {
+                return Ok(());
+            };
+
+            let classified = dietary(&recipe.ingredients, recipe.dietary_override.as_ref());
+            let mut filter = MongoRepo::version_filter(id, Some(recipe.version));
+            filter.remove("deleted_at"); // The trash too, restoring a recipe doesn't classify it
+
+            col.update_one(filter, doc! {"$set": {"dietary": to_bson(&classified)?}}, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Sets or (with None) removes the owner's override, it is a change of the recipe so `version` is bumped
+    pub async fn set_dietary_override(&self, id: &str, owner_override: Option<DietaryOverride>, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("set_dietary_override", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let mut set = Document::new();
+            let mut unset = Vec::new();
+            match owner_override {
+                Some(owner_override) => { set.insert("dietary_override", to_bson(&owner_override).ok()?); }
+                None => unset.push("dietary_override".to_string()),
+            }
+
+            let mut pipeline = MongoRepo::update_pipeline(set, unset, None);
+            pipeline.push(MongoRepo::reclassify_stage());
+
+            col.find_one_and_update(
+                filter,
+                pipeline,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
+    }
+
+    /// Recipes never classified or classified with an older version of the mapping table, the trash included
+    pub async fn get_recipes_to_classify(&self) -> Result<Vec<Recipe>, Error> {
+        observe_mongo("get_recipes_to_classify", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let mut cursors = col
+                .find(doc! {"dietary.table_version": {"$ne": TABLE_VERSION}}, None)
+                .await?;
+
+            let mut recipes: Vec<Recipe> = Vec::new();
+
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                recipes.push(recipe)
+            }
+
+            Ok(recipes)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Bson};
+
+    use crate::models::dietary_model::{Allergen, DietaryLabel};
+    use crate::repository::mongo_repo::MongoRepo;
+
+    #[test]
+    fn free_from_needs_a_complete_classification() {
+        let filter = MongoRepo::dietary_filter(&[DietaryLabel::Vegan], &[Allergen::Sesame]);
+
+        assert_eq!(filter, doc! {
+            "dietary.labels": {"$all": ["vegan"]},
+            "dietary": {"$exists": true},
+            "dietary.complete": true,
+            "dietary.allergens": {"$nin": ["sesame"]},
+        });
+        assert!(MongoRepo::dietary_filter(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn update_pipeline_classifies_in_the_same_update() {
+        let ingredients = vec!["$5 of peanuts".to_string()];
+        let pipeline = MongoRepo::update_pipeline(doc! {"ingredients": &ingredients}, vec!["photo_url".to_string()], Some(&ingredients));
+
+        assert_eq!(pipeline.len(), 3);
+        let set = pipeline[0].get_document("$set").unwrap();
+        assert_eq!(set.get_document("ingredients").unwrap(), &doc! {"$literal": ["$5 of peanuts"]});
+        assert!(set.contains_key("updated"));
+        assert_eq!(pipeline[1], doc! {"$unset": ["photo_url"]});
+
+        let dietary = pipeline[2].get_document("$set").unwrap().get_document("dietary").unwrap();
+        assert_eq!(dietary.get_document("derived_allergens").unwrap(), &doc! {"$literal": ["peanuts"]});
+        assert!(dietary.get_document("labels").unwrap().contains_key("$ifNull"));
+
+        let pipeline = MongoRepo::update_pipeline(doc! {"title": "Pancakes"}, Vec::new(), None);
+        assert_eq!(pipeline.len(), 1);
+        assert_eq!(pipeline[0].get_document("$set").unwrap().get("title"), Some(&Bson::Document(doc! {"$literal": "Pancakes"})));
+    }
+}
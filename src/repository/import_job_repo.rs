@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, to_bson};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::IndexOptions;
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::import_model::{ImportJob, ImportJobStatus};
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+// Finished or not, nobody polls an import job after a week
+const IMPORT_JOB_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+impl MongoRepo {
+    pub async fn ensure_import_job_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_import_job_indexes", async {
+            let col: Collection<ImportJob> = MongoRepo::collection_switch(self, CollectionName::ImportJobs).await;
+
+            let ttl_index = IndexModel::builder()
+                .keys(doc! {"created": 1})
+                .options(IndexOptions::builder().expire_after(Some(IMPORT_JOB_TTL)).build())
+                .build();
+
+            col.create_index(ttl_index, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// The job id is set by the caller, so it can be returned before the job has run
+    pub async fn insert_import_job(&self, job: &ImportJob) -> Result<(), Error> {
+        observe_mongo("insert_import_job", async {
+            let col = MongoRepo::collection_switch::<ImportJob>(self, CollectionName::ImportJobs).await;
+
+            col.insert_one(job, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Replaces the stored job with the current progress
+    pub async fn save_import_job(&self, job: &ImportJob) -> Result<(), Error> {
+        observe_mongo("save_import_job", async {
+            let col = MongoRepo::collection_switch::<ImportJob>(self, CollectionName::ImportJobs).await;
+
+            col.replace_one(doc! {"_id": job.id}, job, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Queued and running jobs when the server starts, the archive was only in the memory of the previous process
+    pub async fn fail_interrupted_import_jobs(&self) -> Result<u64, Error> {
+        observe_mongo("fail_interrupted_import_jobs", async {
+            let col = MongoRepo::collection_switch::<ImportJob>(self, CollectionName::ImportJobs).await;
+            let unfinished = [to_bson(&ImportJobStatus::Queued)?, to_bson(&ImportJobStatus::Running)?];
+
+            let result = col.update_many(
+                doc! {"status": {"$in": unfinished.to_vec()}},
+                doc! {"$set": {
+                    "status": to_bson(&ImportJobStatus::Failed)?,
+                    "error": "Interrupted by a restart of the server, start the import again",
+                    "updated": mongodb::bson::DateTime::now(),
+                }},
+                None,
+            ).await?;
+
+            Ok(result.modified_count)
+        }).await
+    }
+
+    /// Only the user that started the import can see it
+    pub async fn get_import_job(&self, id: &str, owner_uid: &str) -> Option<ImportJob> {
+        observe_mongo("get_import_job", async {
+            let col = MongoRepo::collection_switch::<ImportJob>(self, CollectionName::ImportJobs).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            col.find_one(doc! {"_id": obj_id, "owner_uid": owner_uid}, None).await.ok_recorded()?
+        }).await
+    }
+}
@@ -0,0 +1,308 @@
+use chrono::{Days, NaiveDate};
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::meal_plan_model::{MealPlan, MealPlanEntry, MealPlanEntryDTO, MealSlot, ResolvedMealPlanEntry};
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    pub async fn ensure_meal_plan_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_meal_plan_indexes", async {
+            let plans: Collection<MealPlan> = MongoRepo::collection_switch(self, CollectionName::MealPlans).await;
+            let entries: Collection<MealPlanEntry> = MongoRepo::collection_switch(self, CollectionName::MealPlanEntries).await;
+
+            let token_index = IndexModel::builder()
+                .keys(doc! {"feed_token": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+
+            plans.create_indexes(vec![token_index, IndexModel::builder().keys(doc! {"owner_uid": 1}).build()], None).await?;
+            entries.create_indexes(vec![
+                IndexModel::builder().keys(doc! {"owner_uid": 1, "date": 1}).build(),
+                IndexModel::builder().keys(doc! {"plan_id": 1, "date": 1}).build(),
+            ], None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn insert_meal_plan(&self, plan: &MealPlan) -> Result<(), Error> {
+        observe_mongo("insert_meal_plan", async {
+            let col = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+
+            col.insert_one(plan, None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_meal_plans_by_owner(&self, owner_uid: &str) -> Result<Vec<MealPlan>, Error> {
+        observe_mongo("get_meal_plans_by_owner", async {
+            let col = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+
+            let mut cursors = col
+                .find(doc! {"owner_uid": owner_uid}, FindOptions::builder().sort(doc! {"created": 1}).build())
+                .await?;
+
+            let mut plans: Vec<MealPlan> = Vec::new();
+
+            while let Some(plan) = cursors
+                .try_next()
+                .await?
+            {
+                plans.push(plan)
+            }
+
+            Ok(plans)
+        }).await
+    }
+
+    pub async fn get_meal_plan(&self, id: &str, owner_uid: &str) -> Option<MealPlan> {
+        observe_mongo("get_meal_plan", async {
+            let col = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            col.find_one(doc! {"_id": obj_id, "owner_uid": owner_uid}, None).await.ok_recorded()?
+        }).await
+    }
+
+    /// For the .ics feed, the token replaces the owner check
+    pub async fn get_meal_plan_by_feed_token(&self, id: &str, feed_token: &str) -> Option<MealPlan> {
+        observe_mongo("get_meal_plan_by_feed_token", async {
+            let col = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            col.find_one(doc! {"_id": obj_id, "feed_token": feed_token}, None).await.ok_recorded()?
+        }).await
+    }
+
+    pub async fn rename_meal_plan(&self, id: &str, owner_uid: &str, name: &str) -> Option<MealPlan> {
+        observe_mongo("rename_meal_plan", async {
+            let col = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+            col.find_one_and_update(
+                doc! {"_id": obj_id, "owner_uid": owner_uid},
+                doc! {"$set": {"name": name, "updated": mongodb::bson::DateTime::now()}},
+                options)
+                .await
+                .ok_recorded()?
+        }).await
+    }
+
+    /// Deletes the plan with all of its entries, false if the user has no such plan
+    pub async fn delete_meal_plan(&self, id: &str, owner_uid: &str) -> Result<bool, Error> {
+        observe_mongo("delete_meal_plan", async {
+            let plans = MongoRepo::collection_switch::<MealPlan>(self, CollectionName::MealPlans).await;
+            let entries = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+            let Ok(obj_id) = ObjectId::parse_str(id) else { return Ok(false) };
+
+            let result = plans.delete_one(doc! {"_id": obj_id, "owner_uid": owner_uid}, None).await?;
+
+            if result.deleted_count == 0 {
+                return Ok(false);
+            }
+
+            entries.delete_many(doc! {"plan_id": obj_id}, None).await?;
+            Ok(true)
+        }).await
+    }
+
+    pub async fn insert_meal_plan_entry(&self, entry: &MealPlanEntry) -> Result<(), Error> {
+        observe_mongo("insert_meal_plan_entry", async {
+            let col = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+
+            col.insert_one(entry, None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn update_meal_plan_entry(&self, plan_id: ObjectId, entry_id: &str, owner_uid: &str, entry: &MealPlanEntryDTO, recipe_id: ObjectId) -> Option<MealPlanEntry> {
+        observe_mongo("update_meal_plan_entry", async {
+            let col = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+            let obj_id = ObjectId::parse_str(entry_id).ok()?;
+
+            let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+            col.find_one_and_update(
+                doc! {"_id": obj_id, "plan_id": plan_id, "owner_uid": owner_uid},
+                doc! {"$set": {
+                    "date": entry.date.to_string(),
+                    "slot": entry.slot.as_str(),
+                    "recipe_id": recipe_id,
+                    "servings": entry.servings,
+                    "note": entry.note.as_deref(),
+                    "updated": mongodb::bson::DateTime::now(),
+                }},
+                options)
+                .await
+                .ok_recorded()?
+        }).await
+    }
+
+    pub async fn delete_meal_plan_entry(&self, plan_id: ObjectId, entry_id: &str, owner_uid: &str) -> Result<bool, Error> {
+        observe_mongo("delete_meal_plan_entry", async {
+            let col = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+            let Ok(obj_id) = ObjectId::parse_str(entry_id) else { return Ok(false) };
+
+            let result = col.delete_one(doc! {"_id": obj_id, "plan_id": plan_id, "owner_uid": owner_uid}, None).await?;
+            Ok(result.deleted_count > 0)
+        }).await
+    }
+
+    /// The entries of the 7 days starting at `week_start`
+    pub fn week_filter(plan_id: ObjectId, owner_uid: &str, week_start: NaiveDate) -> Document {
+        let week_end = week_start + Days::new(7);
+        doc! {"plan_id": plan_id, "owner_uid": owner_uid, "date": {"$gte": week_start.to_string(), "$lt": week_end.to_string()}}
+    }
+
+    /// New entries on the same weekday and slot `to - from` days later
+    pub fn week_copies(entries: Vec<MealPlanEntry>, from: NaiveDate, to: NaiveDate, now: mongodb::bson::DateTime) -> Vec<MealPlanEntry> {
+        let offset = to.signed_duration_since(from);
+
+        entries
+            .into_iter()
+            .map(|entry| MealPlanEntry {
+                id: Some(ObjectId::new()),
+                date: entry.date + offset,
+                created: now,
+                updated: now,
+                ..entry
+            })
+            .collect()
+    }
+
+    /// Copies the 7 days starting at `from` to the 7 days starting at `to` (same weekday and slot).
+    /// None when the target week already has entries, copying twice would plan every meal twice
+    pub async fn copy_meal_plan_week(&self, plan_id: ObjectId, owner_uid: &str, from: NaiveDate, to: NaiveDate) -> Result<Option<Vec<MealPlanEntry>>, Error> {
+        observe_mongo("copy_meal_plan_week", async {
+            let col = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+
+            if col.count_documents(MongoRepo::week_filter(plan_id, owner_uid, to), None).await? > 0 {
+                return Ok(None);
+            }
+
+            let mut cursors = col
+                .find(MongoRepo::week_filter(plan_id, owner_uid, from), None)
+                .await?;
+
+            let mut entries: Vec<MealPlanEntry> = Vec::new();
+
+            while let Some(entry) = cursors
+                .try_next()
+                .await?
+            {
+                entries.push(entry)
+            }
+
+            let copies = MongoRepo::week_copies(entries, from, to, mongodb::bson::DateTime::now());
+
+            if !copies.is_empty() {
+                col.insert_many(&copies, None).await?;
+            }
+
+            Ok(Some(copies))
+        }).await
+    }
+
+    /// Entries matching `filter` joined with a summary of their recipe, sorted by day and slot
+    pub async fn resolve_meal_plan_entries(&self, filter: Document) -> Result<Vec<ResolvedMealPlanEntry>, Error> {
+        observe_mongo("resolve_meal_plan_entries", async {
+            let col = MongoRepo::collection_switch::<MealPlanEntry>(self, CollectionName::MealPlanEntries).await;
+            let slot_order: Vec<&str> = MealSlot::ALL.iter().map(MealSlot::as_str).collect();
+
+            let pipeline = vec![
+                doc! {"$match": filter},
+                doc! {"$lookup": {
+                    "from": self.collection_name(CollectionName::Recipes),
+                    "let": {"recipe_id": "$recipe_id"},
+                    "pipeline": [
+                        {"$match": {"$expr": {"$eq": ["$_id", "$$recipe_id"]}, "deleted_at": null}},
+                        {"$project": {"title": 1, "photo_url": 1, "recipe_yield": 1, "total_time_minutes": 1, "tags": 1}},
+                    ],
+                    "as": "recipe",
+                }},
+                doc! {"$unwind": {"path": "$recipe", "preserveNullAndEmptyArrays": true}},
+                doc! {"$addFields": {"slot_order": {"$indexOfArray": [slot_order, "$slot"]}}},
+                doc! {"$sort": {"date": 1, "slot_order": 1}},
+                doc! {"$project": {"slot_order": 0, "owner_uid": 0, "created": 0}},
+            ];
+
+            let mut cursors = col.aggregate(pipeline, None).await?;
+            let mut entries: Vec<ResolvedMealPlanEntry> = Vec::new();
+
+            while let Some(document) = cursors
+                .try_next()
+                .await?
+            {
+                entries.push(mongodb::bson::from_document(document)?)
+            }
+
+            Ok(entries)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use mongodb::bson::doc;
+    use mongodb::bson::oid::ObjectId;
+
+    use crate::models::meal_plan_model::{MealPlanEntry, MealSlot};
+    use crate::repository::mongo_repo::MongoRepo;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn week_is_seven_days_from_the_start() {
+        let plan_id = ObjectId::new();
+
+        assert_eq!(MongoRepo::week_filter(plan_id, "uid", date("2024-02-26")), doc! {
+            "plan_id": plan_id,
+            "owner_uid": "uid",
+            "date": {"$gte": "2024-02-26", "$lt": "2024-03-04"},
+        });
+    }
+
+    #[test]
+    fn copies_keep_weekday_and_slot() {
+        let created = mongodb::bson::DateTime::from_millis(0);
+        let now = mongodb::bson::DateTime::from_millis(1_000);
+        let entry = |day: &str, slot: MealSlot| MealPlanEntry {
+            id: Some(ObjectId::new()),
+            plan_id: ObjectId::new(),
+            owner_uid: "uid".to_string(),
+            date: date(day),
+            slot,
+            recipe_id: ObjectId::new(),
+            servings: Some(4),
+            note: None,
+            created,
+            updated: created,
+        };
+        let entries = vec![entry("2024-02-26", MealSlot::Dinner), entry("2024-02-29", MealSlot::Breakfast), entry("2024-03-03", MealSlot::Lunch)];
+
+        // Across the end of a leap year February, and back in time
+        let copies = MongoRepo::week_copies(entries.clone(), date("2024-02-26"), date("2024-03-04"), now);
+        let dates: Vec<(String, MealSlot)> = copies.iter().map(|copy| (copy.date.to_string(), copy.slot)).collect();
+        assert_eq!(dates, vec![
+            ("2024-03-04".to_string(), MealSlot::Dinner),
+            ("2024-03-07".to_string(), MealSlot::Breakfast),
+            ("2024-03-10".to_string(), MealSlot::Lunch),
+        ]);
+        assert!(copies.iter().zip(&entries).all(|(copy, entry)| copy.id != entry.id && copy.recipe_id == entry.recipe_id && copy.servings == Some(4)));
+        assert!(copies.iter().all(|copy| copy.created == now && copy.updated == now));
+
+        let earlier = MongoRepo::week_copies(entries, date("2024-02-26"), date("2024-02-12"), now);
+        assert_eq!(earlier[0].date, date("2024-02-12"));
+        assert_eq!(earlier[2].date, date("2024-02-18"));
+    }
+}
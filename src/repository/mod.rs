@@ -1 +1,12 @@
 pub mod mongo_repo;
+pub mod audit_repo;
+pub mod revision_repo;
+pub mod trash_repo;
+pub mod import_job_repo;
+pub mod meal_plan_repo;
+pub mod shopping_list_repo;
+pub mod pantry_repo;
+pub mod nutrition_repo;
+pub mod dietary_repo;
+pub mod substitution_repo;
+pub mod rate_limit_repo;
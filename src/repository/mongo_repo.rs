@@ -1,227 +1,520 @@
-extern crate dotenv;
-
-use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::TryStreamExt;
 use mongodb::{Client, Collection, Database};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::bson::oid::ObjectId;
-use mongodb::error::Error;
-use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use mongodb::options::{ClientOptions, FindOneAndUpdateOptions, FindOptions, ReturnDocument};
 
+use crate::ingredients::allergens::dietary;
+use crate::metrics::{METRICS, observe_mongo, OkRecorded};
+use crate::metrics::pool::PoolEvents;
 use crate::models::recipe_model::Recipe;
+use crate::settings::MongoSettings;
+use crate::telemetry::mongo::CommandEvents;
 
 // https://dev.to/hackmamba/create-a-graphql-powered-project-management-endpoint-in-rust-and-mongodb-actix-web-version-3j1
 // Impl multiple Collections for MongoDB
 
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+const DUPLICATE_KEY: i32 = 11000;
+
+/// E11000, a unique index rejected the write
+pub fn is_duplicate_key(err: &Error) -> bool {
+    match &*err.kind {
+        ErrorKind::Write(WriteFailure::WriteError(error)) => error.code == DUPLICATE_KEY,
+        ErrorKind::Command(error) => error.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
 pub struct MongoRepo {
+    client: Client,
     db: Database,
+    recipes_collection: String, // mongo.recipes_collection, the other collections have fixed names
 }
 
 pub enum CollectionName {
     Recipes,
+    AuditLog,
+    RecipeRevisions,
+    ImportJobs,
+    MealPlans,
+    MealPlanEntries,
+    ShoppingLists,
+    Pantry,
+    Foods,
+    SubstitutionRules,
+    RateLimits,
+}
+
+impl CollectionName {
+    /// Default collection name in the database, use MongoRepo::collection_name for $lookup in aggregations
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollectionName::Recipes => "Recipes",
+            CollectionName::AuditLog => "AuditLog",
+            CollectionName::RecipeRevisions => "RecipeRevisions",
+            CollectionName::ImportJobs => "ImportJobs",
+            CollectionName::MealPlans => "MealPlans",
+            CollectionName::MealPlanEntries => "MealPlanEntries",
+            CollectionName::ShoppingLists => "ShoppingLists",
+            CollectionName::Pantry => "Pantry",
+            CollectionName::Foods => "Foods",
+            CollectionName::SubstitutionRules => "SubstitutionRules",
+            CollectionName::RateLimits => "RateLimits",
+        }
+    }
 }
 
 impl MongoRepo {
-    pub async fn init() -> Self {
-        let uri = env::var("MONGO_URI").expect("MONGO_URI environment variable not set");
-        let client = Client::with_uri_str(uri).await.expect("Failed to connect to MongoDB with provided URI");
-        let db = client.database("alsomeb");
-        MongoRepo { db }
+    /// Connects with retries (see connect_with_retry) and creates the indexes, Err when MongoDB stays unreachable
+    pub async fn init(settings: &MongoSettings, audit_retention_days: u64) -> Result<Self, Error> {
+        let client = MongoRepo::connect_with_retry(settings).await?;
+        let db = client.database(&settings.database);
+        let repo = MongoRepo { client, db, recipes_collection: settings.recipes_collection.clone() };
+
+        // Audit entries are kept for retention.audit_days before the TTL index removes them
+        if let Err(err) = repo.ensure_audit_indexes(audit_retention_days).await {
+            log::warn!("Failed to create audit log indexes: {}", err);
+        }
+
+        if let Err(err) = repo.ensure_revision_indexes().await {
+            log::warn!("Failed to create recipe revision indexes: {}", err);
+        }
+
+        if let Err(err) = repo.ensure_import_job_indexes().await {
+            log::warn!("Failed to create import job indexes: {}", err);
+        }
+
+        // Import jobs run in the process that accepted the archive, whatever was unfinished died with the last one
+        match repo.fail_interrupted_import_jobs().await {
+            Ok(0) => {}
+            Ok(count) => log::warn!("Marked {} import jobs interrupted by a restart as failed", count),
+            Err(err) => log::warn!("Failed to mark interrupted import jobs as failed: {}", err),
+        }
+
+        if let Err(err) = repo.ensure_meal_plan_indexes().await {
+            log::warn!("Failed to create meal plan indexes: {}", err);
+        }
+
+        if let Err(err) = repo.ensure_shopping_list_indexes().await {
+            log::warn!("Failed to create shopping list indexes: {}", err);
+        }
+
+        if let Err(err) = repo.ensure_pantry_indexes().await {
+            log::warn!("Failed to create pantry indexes: {}", err);
+        }
+
+        if let Err(err) = repo.ensure_dietary_indexes().await {
+            log::warn!("Failed to create dietary label indexes: {}", err);
+        }
+
+        Ok(repo)
     }
 
-    pub async fn collection_switch<T>(data_source: &Self, col_name: CollectionName) -> Collection<T> {
-        match col_name {
-            CollectionName::Recipes => data_source.db.collection("Recipes"),
+    // Client::with_uri_str doesn't connect, so a ping is what tells us MongoDB is there
+    async fn connect(settings: &MongoSettings) -> Result<Client, Error> {
+        let mut options = ClientOptions::parse(&settings.uri).await?; // Resolves the DNS records of mongodb+srv:// URIs
+        options.cmap_event_handler = Some(Arc::new(PoolEvents)); // Pool stats for /metrics
+        options.command_event_handler = Some(Arc::new(CommandEvents::default())); // Collection and filter of the mongo spans
+        METRICS.pool.set_max_size(options.max_pool_size.unwrap_or(10)); // 10 is the driver's default
+        let client = Client::with_options(options)?;
+
+        MongoRepo::ping_client(&client).await?;
+        Ok(client)
+    }
+
+    /// mongo.connect_attempts attempts with exponential backoff, starting at mongo.connect_backoff_ms.
+    /// An invalid URI or wrong credentials fail right away, waiting won't fix those
+    async fn connect_with_retry(settings: &MongoSettings) -> Result<Client, Error> {
+        let mut backoff = Duration::from_millis(settings.connect_backoff_ms);
+        let mut attempt = 1;
+
+        loop {
+            match MongoRepo::connect(settings).await {
+                Ok(client) => {
+                    log::info!("Connected to MongoDB (attempt {})", attempt);
+                    return Ok(client);
+                }
+                Err(err) if attempt < settings.connect_attempts && !matches!(*err.kind, ErrorKind::InvalidArgument { .. } | ErrorKind::Authentication { .. }) => {
+                    log::warn!("MongoDB is not reachable (attempt {} of {}), retrying in {:?}: {}", attempt, settings.connect_attempts, backoff, err);
+                    actix_web::rt::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
         }
     }
 
+    async fn ping_client(client: &Client) -> Result<Duration, Error> {
+        let started = Instant::now();
+        let admin = client.database("admin");
+        let ping = admin.run_command(doc! {"ping": 1}, None);
 
-    pub async fn insert_recipe(&self, new_recipe: Recipe) -> Result<String, Error> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+        match actix_web::rt::time::timeout(PING_TIMEOUT, ping).await {
+            Ok(result) => result.map(|_| started.elapsed()),
+            Err(_) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("No answer to ping within {:?}", PING_TIMEOUT)).into()),
+        }
+    }
 
-        let recipe_result = col
-            .insert_one(new_recipe, None)
-            .await?;
+    /// Round trip to the server, for the readiness probe
+    pub async fn ping(&self) -> Result<Duration, Error> {
+        MongoRepo::ping_client(&self.client).await
+    }
 
-        Ok(recipe_result.inserted_id.to_string())
+    /// Closes the connections once cursors and sessions in use are dropped, or after `timeout`
+    pub async fn shutdown(&self, timeout: Duration) {
+        if actix_web::rt::time::timeout(timeout, self.client.clone().shutdown()).await.is_err() {
+            log::warn!("MongoDB operations still running after {:?}, closing the client anyway", timeout);
+            self.client.clone().shutdown_immediate().await;
+        }
+        log::info!("MongoDB client closed");
     }
 
-    pub async fn delete_recipe_by_id(&self, id: &str) -> Option<Recipe> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
-        // Convert to Object Id
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+    /// Name of the collection in this database, the recipes collection is configurable
+    pub fn collection_name(&self, col_name: CollectionName) -> &str {
+        match col_name {
+            CollectionName::Recipes => &self.recipes_collection,
+            other => other.name(),
+        }
+    }
 
-        col.find_one_and_delete(filter, None)
-            .await
-            .ok()? // ok() method to convert from Result<T, E> to Option<T>, which is a valid approach when you want to discard the error and work with an Option
+    pub async fn collection_switch<T>(data_source: &Self, col_name: CollectionName) -> Collection<T> {
+        data_source.db.collection(data_source.collection_name(col_name))
     }
 
-    pub async fn get_recipes_by_email(&self, email: &str) -> Result<Vec<Recipe>, Error> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+    /// A database command, like the collMod of ensure_audit_indexes
+    pub async fn run_command(&self, command: Document) -> Result<Document, Error> {
+        self.db.run_command(command, None).await
+    }
 
-        let mut cursors = col
-            .find(doc! {"email": email}, None)
-            .await?;
 
-        let mut recipes: Vec<Recipe> = Vec::new();
+    /// Filter on `_id` for recipes that are not in the trash (`deleted_at` missing or null)
+    pub fn active_filter(obj_id: ObjectId) -> Document {
+        doc! {"_id": obj_id, "deleted_at": null}
+    }
 
-        // This is a loop that will continue to run as long as the pattern matching succeeds.
-        // In this case, the pattern is Some(recipe), which matches the Option type returned by the try_next() method call.
+    /// Active filter plus the version the client expects when it sent If-Match.
+    /// Recipes stored before versioning have no `version` field and count as version 0
+    pub fn version_filter(obj_id: ObjectId, expected_version: Option<u32>) -> Document {
+        let mut filter = MongoRepo::active_filter(obj_id);
 
-        // Here, Some(recipe) means that if the try_next() method returns a Some variant (indicating that there is a next item in the stream),
-        // then the recipe variable inside the Some will be bound to that item.
+        match expected_version {
+            Some(0) => { filter.insert("$or", vec![doc! {"version": 0}, doc! {"version": {"$exists": false}}]); }
+            Some(version) => { filter.insert("version", version); }
+            None => {}
+        }
 
-        // try_next(): This method is called on cursors, which is an asynchronous stream of documents retrieved from a MongoDB collection.
-        // The try_next() method attempts to fetch the next item from the stream.
-        // It returns a Result<Option<Recipe>, Error>, where Ok(Some(recipe)) indicates a successfully retrieved recipe, Ok(None) indicates the end of the stream (no more items)
+        filter
+    }
 
-        // The loop body ({ recipes.push(recipe) }): For each recipe successfully matched by Some(recipe),
-        // the loop body executes. In this case, it adds the recipe to the recipes vector using the push method.
-        while let Some(recipe) = cursors
-            .try_next()
-            .await?
-        {
-            recipes.push(recipe)
+    /// Update pipeline setting `set` (taken literally, "$5" is no field path) and removing `unset` with `updated` and
+    /// `version` maintained. When the ingredients change the dietary labels are computed by the same update
+    pub fn update_pipeline(set: Document, unset: Vec<String>, ingredients: Option<&[String]>) -> Vec<Document> {
+        let mut fields: Document = set
+            .into_iter()
+            .map(|(name, value)| (name, Bson::Document(doc! {"$literal": value})))
+            .collect();
+        fields.insert("updated", mongodb::bson::DateTime::now());
+        fields.insert("version", doc! {"$add": [{"$ifNull": ["$version", 0]}, 1]});
+
+        let mut pipeline = vec![doc! {"$set": fields}];
+        if !unset.is_empty() {
+            pipeline.push(doc! {"$unset": unset});
+        }
+        if let Some(ingredients) = ingredients {
+            pipeline.push(MongoRepo::classify_stage(ingredients));
         }
 
-        Ok(recipes)
+        pipeline
     }
 
-    pub async fn update_recipe_by_id(&self, id: &str, new_recipe: Recipe) -> Option<Recipe> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+    pub async fn insert_recipe(&self, mut new_recipe: Recipe) -> Result<String, Error> {
+        observe_mongo("insert_recipe", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
 
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+            new_recipe.dietary = Some(dietary(&new_recipe.ingredients, new_recipe.dietary_override.as_ref()));
 
-        let partial_update_doc = doc! {
-        "$set": {
-            "title": new_recipe.title,
-            "description": new_recipe.description,
-            "steps": new_recipe.steps,
-            "ingredients": new_recipe.ingredients,
-            "email": new_recipe.email,
-            "updated": mongodb::bson::DateTime::now(),
-        }};
+            let recipe_result = col
+                .insert_one(new_recipe, None)
+                .await?;
 
-        col.find_one_and_update(
-            filter,
-            partial_update_doc,
-            FindOneAndUpdateOptions::builder() // OPTIONS med builder: Vi vill ha Dokumentet EFTER med nya uppdateringen, använder "FindOneAndReplaceOptions::Builder()"
-                .return_document(ReturnDocument::After)
-                .build())
-            .await
-            .ok()? // ok() method to convert from Result<T, E> to Option<T>, which is a valid approach when you want to discard the error and work with an Option
+            Ok(recipe_result.inserted_id.to_string())
+        }).await
     }
 
-    pub async fn update_recipe_img_url(&self, id: &str, img_url: &str) -> Option<Recipe> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+    /// Soft delete, the recipe is moved to the trash by setting `deleted_at` and purged later (see jobs/trash_purge.rs)
+    pub async fn delete_recipe_by_id(&self, id: &str, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("delete_recipe_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+            // Convert to Object Id
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let now = mongodb::bson::DateTime::now();
+            let trash_doc = doc! {
+            "$set": {
+                "deleted_at": now,
+                "updated": now,
+            },
+            "$inc": {"version": 1}
+            };
+
+            col.find_one_and_update(
+                filter,
+                trash_doc,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()? // Like ok(), converts from Result<T, E> to Option<T> to discard the error and work with an Option, the error still counts in the metrics
+        }).await
+    }
+
+    /// `filter` narrows the result further, like MongoRepo::dietary_filter
+    pub async fn get_recipes_by_email(&self, email: &str, mut filter: Document) -> Result<Vec<Recipe>, Error> {
+        observe_mongo("get_recipes_by_email", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            filter.insert("email", email);
+            filter.insert("deleted_at", Bson::Null);
+
+            let mut cursors = col
+                .find(filter, None)
+                .await?;
 
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+            let mut recipes: Vec<Recipe> = Vec::new();
 
-        let partial_update_doc = doc! {
-        "$set": {
-            "photo_url": img_url,
-            "updated": mongodb::bson::DateTime::now(),
-        }};
+            // This is a loop that will continue to run as long as the pattern matching succeeds.
+            // In this case, the pattern is Some(recipe), which matches the Option type returned by the try_next() method call.
 
-        col.find_one_and_update(
-            filter,
-            partial_update_doc,
-            FindOneAndUpdateOptions::builder()
-                .return_document(ReturnDocument::After)
-                .build())
-            .await
-            .ok()?
+            // Here, Some(recipe) means that if the try_next() method returns a Some variant (indicating that there is a next item in the stream),
+            // then the recipe variable inside the Some will be bound to that item.
+
+            // try_next(): This method is called on cursors, which is an asynchronous stream of documents retrieved from a MongoDB collection.
+            // The try_next() method attempts to fetch the next item from the stream.
+            // It returns a Result<Option<Recipe>, Error>, where Ok(Some(recipe)) indicates a successfully retrieved recipe, Ok(None) indicates the end of the stream (no more items)
+
+            // The loop body ({ recipes.push(recipe) }): For each recipe successfully matched by Some(recipe),
+            // the loop body executes. In this case, it adds the recipe to the recipes vector using the push method.
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                recipes.push(recipe)
+            }
+
+            Ok(recipes)
+        }).await
     }
 
-    pub async fn update_title_by_recipe_id(&self, id: &str, title: &str) -> Option<Recipe> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+    pub async fn update_recipe_by_id(&self, id: &str, new_recipe: Recipe, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("update_recipe_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let partial_update = MongoRepo::update_pipeline(doc! {
+                "title": new_recipe.title,
+                "description": new_recipe.description,
+                "steps": new_recipe.steps,
+                "ingredients": &new_recipe.ingredients,
+                "email": new_recipe.email,
+                "recipe_yield": new_recipe.recipe_yield,
+                "prep_time_minutes": new_recipe.prep_time_minutes,
+                "cook_time_minutes": new_recipe.cook_time_minutes,
+                "total_time_minutes": new_recipe.total_time_minutes,
+            }, Vec::new(), Some(&new_recipe.ingredients));
+
+            col.find_one_and_update(
+                filter,
+                partial_update,
+                FindOneAndUpdateOptions::builder() // OPTIONS med builder: Vi vill ha Dokumentet EFTER med nya uppdateringen, använder "FindOneAndReplaceOptions::Builder()"
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()? // Like ok(), converts from Result<T, E> to Option<T> to discard the error and work with an Option, the error still counts in the metrics
+        }).await
+    }
 
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+    pub async fn update_recipe_img_url(&self, id: &str, img_url: &str, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("update_recipe_img_url", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let partial_update_doc = doc! {
+            "$set": {
+                "photo_url": img_url,
+                "updated": mongodb::bson::DateTime::now(),
+            },
+            "$inc": {"version": 1}
+            };
+
+            col.find_one_and_update(
+                filter,
+                partial_update_doc,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
+    }
 
-        let partial_update_doc = doc! {
-        "$set": {
-            "title": title,
-            "updated": mongodb::bson::DateTime::now(),
-        }};
+    pub async fn update_title_by_recipe_id(&self, id: &str, title: &str, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("update_title_by_recipe_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let partial_update_doc = doc! {
+            "$set": {
+                "title": title,
+                "updated": mongodb::bson::DateTime::now(),
+            },
+            "$inc": {"version": 1}
+            };
+
+            col.find_one_and_update(
+                filter,
+                partial_update_doc,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
+    }
 
-        col.find_one_and_update(
-            filter,
-            partial_update_doc,
-            FindOneAndUpdateOptions::builder()
-                .return_document(ReturnDocument::After)
-                .build())
-            .await
-            .ok()?
+    /// Applies an update document built from a JSON (Merge) Patch ($set / $unset) as an update pipeline,
+    /// `updated`, `version` and the dietary labels are maintained here
+    pub async fn patch_recipe_by_id(&self, id: &str, update: Document, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("patch_recipe_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let set = update.get_document("$set").cloned().unwrap_or_default();
+            let unset: Vec<String> = update.get_document("$unset").map(|unset| unset.keys().cloned().collect()).unwrap_or_default();
+            let ingredients: Option<Vec<String>> = set
+                .get_array("ingredients")
+                .ok()
+                .map(|lines| lines.iter().filter_map(|line| line.as_str().map(str::to_string)).collect());
+
+            col.find_one_and_update(
+                filter,
+                MongoRepo::update_pipeline(set, unset, ingredients.as_deref()),
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
     }
 
     // Denna är förbättrad och kommer ej PANIC vid error, samt Return Option<User> istället
     pub async fn get_recipe_by_id(&self, id: &str) -> Option<Recipe> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+        observe_mongo("get_recipe_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::active_filter(obj_id);
+
+            let result = col
+                .find_one(filter, None)
+                .await;
+
+            /*
+                 In this code, ok_recorded() (ok() that counts the error in the metrics) is used to convert Result<Option<User>> to Option<Option<User>>, and then and_then is used to flatten it. Finally, the inner Option<User> is extracted
+            */
+            let user_option = result.ok_recorded().and_then(|user_result| user_result); // Some languages call this operation flatmap
+            user_option
+        }).await
+    }
 
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+    /// Recipes that are not in the trash among `ids`, in no particular order
+    pub async fn get_recipes_by_ids(&self, ids: &[ObjectId]) -> Result<Vec<Recipe>, Error> {
+        observe_mongo("get_recipes_by_ids", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
 
-        let result = col
-            .find_one(filter, None)
-            .await;
+            let mut cursors = col
+                .find(doc! {"_id": {"$in": ids}, "deleted_at": null}, None)
+                .await?;
 
-        /*
-             In this code, ok() is used to convert Result<Option<User>> to Option<Option<User>>, and then and_then is used to flatten it. Finally, the inner Option<User> is extracted
-        */
-        let user_option = result.ok().and_then(|user_result| user_result); // Some languages call this operation flatmap
-        user_option
-    }
+            let mut recipes: Vec<Recipe> = Vec::new();
 
-    pub async fn get_recipe_img_url_by_id(&self, id: &str) -> Option<String> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
-
-        let obj_id = ObjectId::parse_str(id).ok()?;
-        let filter = doc! {"_id": obj_id};
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                recipes.push(recipe)
+            }
 
-        let result = col
-            .find_one(filter, None)
-            .await;
+            Ok(recipes)
+        }).await
+    }
 
-        match result {
-            Ok(Some(recipe)) => Option::from(recipe.photo_url),
-            Ok(None) => None,
-            Err(_) => None
-        }
+    pub async fn get_recipe_img_url_by_id(&self, id: &str) -> Option<String> {
+        observe_mongo("get_recipe_img_url_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::active_filter(obj_id);
+
+            let result = col
+                .find_one(filter, None)
+                .await;
+
+            match result.ok_recorded() {
+                Some(Some(recipe)) => Option::from(recipe.photo_url),
+                Some(None) => None,
+                None => None
+            }
+        }).await
     }
 
-    pub async fn get_all_recipes_pageable(&self, page: u32, per_page: u32) -> Result<Vec<Recipe>, Error> {
-        let col = MongoRepo::collection_switch::<Recipe>(&self, CollectionName::Recipes).await;
+    pub async fn get_all_recipes_pageable(&self, page: u32, per_page: u32, mut filter: Document) -> Result<Vec<Recipe>, Error> {
+        observe_mongo("get_all_recipes_pageable", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
 
-        // Calculate the ship and limit values
-        // is used to adjust for the indexing of pages, which typically starts at 1 for human readability and usability,
-        // while the actual data skipping in a database query starts at 0.
-        let skip = (page - 1) * per_page;
-        let limit = per_page;
+            // Calculate the ship and limit values
+            // is used to adjust for the indexing of pages, which typically starts at 1 for human readability and usability,
+            // while the actual data skipping in a database query starts at 0.
+            let skip = (page - 1) * per_page;
+            let limit = per_page;
 
-        // Find Options
-        let find_options = FindOptions::builder()
-            .skip(Some(skip as u64))
-            .limit(Some(limit as i64))
-            .build();
+            // Find Options
+            let find_options = FindOptions::builder()
+                .skip(Some(skip as u64))
+                .limit(Some(limit as i64))
+                .build();
 
-        let mut cursors = col
-            .find(None, find_options) // without any options & filters to match all documents
-            .await?;
+            filter.insert("deleted_at", Bson::Null); // every recipe that isn't in the trash
 
-        let mut users: Vec<Recipe> = Vec::new();
+            let mut cursors = col
+                .find(filter, find_options)
+                .await?;
 
-        while let Some(user) = cursors
-            .try_next()
-            .await?
-        {
-            users.push(user)
-        }
+            let mut users: Vec<Recipe> = Vec::new();
+
+            while let Some(user) = cursors
+                .try_next()
+                .await?
+            {
+                users.push(user)
+            }
 
-        Ok(users)
+            Ok(users)
+        }).await
     }
 }
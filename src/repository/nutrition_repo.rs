@@ -0,0 +1,72 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneOptions, ReplaceOptions};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::nutrition_model::{Food, RecipeNutrition};
+use crate::models::recipe_model::Recipe;
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    /// Makes the Foods collection equal to `foods`, foods that were removed from the table are deleted
+    pub async fn sync_foods(&self, foods: &[Food]) -> Result<(), Error> {
+        observe_mongo("sync_foods", async {
+            let col = MongoRepo::collection_switch::<Food>(self, CollectionName::Foods).await;
+            let upsert = ReplaceOptions::builder().upsert(true).build();
+
+            for food in foods {
+                col.replace_one(doc! {"_id": &food.fdc_id}, food, upsert.clone()).await?;
+            }
+
+            let ids: Vec<&str> = foods.iter().map(|food| food.fdc_id.as_str()).collect();
+            col.delete_many(doc! {"_id": {"$nin": ids}}, None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_foods(&self) -> Result<Vec<Food>, Error> {
+        observe_mongo("get_foods", async {
+            let col = MongoRepo::collection_switch::<Food>(self, CollectionName::Foods).await;
+
+            let mut cursors = col
+                .find(doc! {}, None)
+                .await?;
+
+            let mut foods: Vec<Food> = Vec::new();
+
+            while let Some(food) = cursors
+                .try_next()
+                .await?
+            {
+                foods.push(food)
+            }
+
+            Ok(foods)
+        }).await
+    }
+
+    /// The estimate cached by save_recipe_nutrition, only this field of the recipe document is read
+    pub async fn get_recipe_nutrition(&self, id: ObjectId) -> Option<RecipeNutrition> {
+        observe_mongo("get_recipe_nutrition", async {
+            let col = MongoRepo::collection_switch::<Document>(self, CollectionName::Recipes).await;
+            let options = FindOneOptions::builder().projection(doc! {"_id": 0, "nutrition": 1}).build();
+
+            let recipe = col.find_one(doc! {"_id": id}, options).await.ok_recorded()??;
+            mongodb::bson::from_bson(recipe.get("nutrition")?.clone()).ok()
+        }).await
+    }
+
+    /// Caches the estimate on the recipe document (not a field of Recipe), unless the recipe was updated while it was computed.
+    /// Not a change of the recipe, so neither `updated` nor `version` is touched
+    pub async fn save_recipe_nutrition(&self, id: ObjectId, nutrition: &RecipeNutrition) -> Result<(), Error> {
+        observe_mongo("save_recipe_nutrition", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+            let filter = MongoRepo::version_filter(id, Some(nutrition.recipe_version));
+
+            col.update_one(filter, doc! {"$set": {"nutrition": mongodb::bson::to_bson(nutrition)?}}, None).await?;
+            Ok(())
+        }).await
+    }
+}
@@ -0,0 +1,118 @@
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::pantry_model::{PantryItem, PantryItemDTO, RecipeIngredients};
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    pub async fn ensure_pantry_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_pantry_indexes", async {
+            let col: Collection<PantryItem> = MongoRepo::collection_switch(self, CollectionName::Pantry).await;
+
+            col.create_index(IndexModel::builder().keys(doc! {"owner_uid": 1, "expires_on": 1}).build(), None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn insert_pantry_item(&self, item: &PantryItem) -> Result<(), Error> {
+        observe_mongo("insert_pantry_item", async {
+            let col = MongoRepo::collection_switch::<PantryItem>(self, CollectionName::Pantry).await;
+
+            col.insert_one(item, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Sorted by name, expiry is handled by the caller
+    pub async fn get_pantry(&self, owner_uid: &str) -> Result<Vec<PantryItem>, Error> {
+        observe_mongo("get_pantry", async {
+            let col = MongoRepo::collection_switch::<PantryItem>(self, CollectionName::Pantry).await;
+
+            let mut cursors = col
+                .find(doc! {"owner_uid": owner_uid}, FindOptions::builder().sort(doc! {"name": 1}).build())
+                .await?;
+
+            let mut items: Vec<PantryItem> = Vec::new();
+
+            while let Some(item) = cursors
+                .try_next()
+                .await?
+            {
+                items.push(item)
+            }
+
+            Ok(items)
+        }).await
+    }
+
+    pub async fn update_pantry_item(&self, id: &str, owner_uid: &str, item: &PantryItemDTO) -> Option<PantryItem> {
+        observe_mongo("update_pantry_item", async {
+            let col = MongoRepo::collection_switch::<PantryItem>(self, CollectionName::Pantry).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+            col.find_one_and_update(
+                doc! {"_id": obj_id, "owner_uid": owner_uid},
+                doc! {"$set": {
+                    "name": &item.name,
+                    "quantity": item.quantity,
+                    "unit": item.unit.as_deref(),
+                    "expires_on": item.expires_on.map(|date| date.to_string()),
+                    "updated": mongodb::bson::DateTime::now(),
+                }},
+                options)
+                .await
+                .ok_recorded()?
+        }).await
+    }
+
+    pub async fn delete_pantry_item(&self, id: &str, owner_uid: &str) -> Result<bool, Error> {
+        observe_mongo("delete_pantry_item", async {
+            let col = MongoRepo::collection_switch::<PantryItem>(self, CollectionName::Pantry).await;
+            let Ok(obj_id) = ObjectId::parse_str(id) else { return Ok(false) };
+
+            let result = col.delete_one(doc! {"_id": obj_id, "owner_uid": owner_uid}, None).await?;
+            Ok(result.deleted_count > 0)
+        }).await
+    }
+
+    /// Title and ingredients of the most recently changed recipes that aren't in the trash, for matching against a pantry.
+    /// With a `pattern` only recipes with an ingredient line matching it (case insensitive) are returned
+    pub async fn get_recipe_ingredients(&self, pattern: Option<&str>, limit: i64) -> Result<Vec<RecipeIngredients>, Error> {
+        observe_mongo("get_recipe_ingredients", async {
+            let col = MongoRepo::collection_switch::<RecipeIngredients>(self, CollectionName::Recipes).await;
+
+            let mut filter = doc! {"deleted_at": null};
+            if let Some(pattern) = pattern {
+                filter.insert("ingredients", doc! {"$regex": pattern, "$options": "i"});
+            }
+
+            let find_options = FindOptions::builder()
+                .projection(doc! {"title": 1, "photo_url": 1, "email": 1, "ingredients": 1})
+                .sort(doc! {"updated": -1})
+                .limit(limit)
+                .build();
+
+            let mut cursors = col
+                .find(filter, find_options)
+                .await?;
+
+            let mut recipes: Vec<RecipeIngredients> = Vec::new();
+
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                recipes.push(recipe)
+            }
+
+            Ok(recipes)
+        }).await
+    }
+}
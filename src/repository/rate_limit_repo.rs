@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument};
+
+use crate::metrics::observe_mongo;
+use crate::models::rate_limit_model::RateLimitBucket;
+use crate::repository::mongo_repo::{CollectionName, is_duplicate_key, MongoRepo};
+
+impl MongoRepo {
+    /// TTL index on `expires`, buckets are removed once they would be full again
+    pub async fn ensure_rate_limit_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_rate_limit_indexes", async {
+            let col: Collection<RateLimitBucket> = MongoRepo::collection_switch(self, CollectionName::RateLimits).await;
+
+            let ttl_index = IndexModel::builder()
+                .keys(doc! {"expires": 1})
+                .options(IndexOptions::builder().expire_after(Some(Duration::ZERO)).build())
+                .build();
+
+            col.create_index(ttl_index, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Refills the bucket for the time since its last update and takes a token when there is one,
+    /// in a single update so replicas can't both take the last token. The time is the server's ($$NOW),
+    /// the clocks of the replicas don't matter
+    pub async fn take_rate_limit_token(&self, key: &str, capacity: f64, per_second: f64) -> Result<RateLimitBucket, Error> {
+        observe_mongo("take_rate_limit_token", async {
+            let col = MongoRepo::collection_switch::<RateLimitBucket>(self, CollectionName::RateLimits).await;
+
+            let elapsed_secs = doc! {"$divide": [{"$subtract": ["$$NOW", {"$ifNull": ["$updated", "$$NOW"]}]}, 1000]};
+            let refilled = doc! {"$min": [capacity, {"$add": [{"$ifNull": ["$tokens", capacity]}, {"$multiply": [elapsed_secs, per_second]}]}]};
+            let has_token = doc! {"$gte": ["$tokens", 1]};
+            let tokens_left = doc! {"$cond": [has_token.clone(), {"$subtract": ["$tokens", 1]}, "$tokens"]};
+            let ms_until_full = doc! {"$multiply": [{"$divide": [{"$subtract": [capacity, tokens_left.clone()]}, per_second]}, 1000]};
+
+            let pipeline = vec![
+                doc! {"$set": {"tokens": refilled, "updated": "$$NOW"}},
+                doc! {"$set": {"allowed": has_token, "tokens": tokens_left, "expires": {"$add": ["$$NOW", {"$ceil": ms_until_full}]}}},
+            ];
+            let options = FindOneAndUpdateOptions::builder()
+                .upsert(true)
+                .return_document(ReturnDocument::After)
+                .build();
+
+            // Two requests creating the same bucket race on the upsert, the loser finds it on the second try
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                match col.find_one_and_update(doc! {"_id": key}, pipeline.clone(), options.clone()).await {
+                    Ok(Some(bucket)) => return Ok(bucket),
+                    Ok(None) => return Err(Error::custom("Upsert returned no rate limit bucket")),
+                    Err(err) if attempt < 2 && is_duplicate_key(&err) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+        }).await
+    }
+}
@@ -0,0 +1,140 @@
+use futures::TryStreamExt;
+use mongodb::IndexModel;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions, FindOptions, IndexOptions, ReturnDocument};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::recipe_model::Recipe;
+use crate::models::revision_model::RecipeRevision;
+use crate::repository::mongo_repo::{CollectionName, is_duplicate_key, MongoRepo};
+
+// Each attempt loses to an update that got its revision in first, more than a few at once is unlikely
+const REVISION_INSERT_ATTEMPTS: u32 = 5;
+
+impl MongoRepo {
+    /// Unique on (recipe_id, revision) so two concurrent updates can never get the same revision number
+    pub async fn ensure_revision_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_revision_indexes", async {
+            let col = MongoRepo::collection_switch::<RecipeRevision>(self, CollectionName::RecipeRevisions).await;
+
+            let index = IndexModel::builder()
+                .keys(doc! {"recipe_id": 1, "revision": -1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+
+            col.create_index(index, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Stores `snapshot` as the next revision of its recipe and returns the revision number.
+    /// Concurrent updates can read the same latest revision, the one losing on the unique index takes the next number
+    pub async fn insert_revision(&self, recipe_id: ObjectId, snapshot: Recipe, author_uid: &str) -> Result<u32, Error> {
+        observe_mongo("insert_revision", async {
+            let col = MongoRepo::collection_switch::<RecipeRevision>(self, CollectionName::RecipeRevisions).await;
+
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+
+                let latest = col
+                    .find_one(
+                        doc! {"recipe_id": recipe_id},
+                        FindOneOptions::builder().sort(doc! {"revision": -1}).build())
+                    .await?;
+
+                let revision = latest.map(|rev| rev.revision + 1).unwrap_or(1);
+
+                let inserted = col.insert_one(RecipeRevision {
+                    id: None,
+                    recipe_id,
+                    revision,
+                    author_uid: author_uid.to_string(),
+                    created: mongodb::bson::DateTime::now(),
+                    snapshot: snapshot.clone(),
+                }, None).await;
+
+                match inserted {
+                    Ok(_) => return Ok(revision),
+                    Err(err) if attempt < REVISION_INSERT_ATTEMPTS && is_duplicate_key(&err) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+        }).await
+    }
+
+    pub async fn get_revisions_by_recipe_id(&self, id: &str) -> Result<Vec<RecipeRevision>, Error> {
+        observe_mongo("get_revisions_by_recipe_id", async {
+            let col = MongoRepo::collection_switch::<RecipeRevision>(self, CollectionName::RecipeRevisions).await;
+
+            // An invalid id can't have any revisions
+            let Ok(obj_id) = ObjectId::parse_str(id) else {
+                return Ok(Vec::new());
+            };
+
+            let mut cursors = col
+                .find(doc! {"recipe_id": obj_id}, FindOptions::builder().sort(doc! {"revision": -1}).build())
+                .await?;
+
+            let mut revisions: Vec<RecipeRevision> = Vec::new();
+
+            while let Some(revision) = cursors
+                .try_next()
+                .await?
+            {
+                revisions.push(revision)
+            }
+
+            Ok(revisions)
+        }).await
+    }
+
+    pub async fn get_revision(&self, id: &str, revision: u32) -> Option<RecipeRevision> {
+        observe_mongo("get_revision", async {
+            let col = MongoRepo::collection_switch::<RecipeRevision>(self, CollectionName::RecipeRevisions).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = doc! {"recipe_id": obj_id, "revision": revision};
+
+            col.find_one(filter, None)
+                .await
+                .ok_recorded()
+                .and_then(|revision| revision)
+        }).await
+    }
+
+    /// Writes the content of a snapshot back onto the recipe, `_id` and `created` are kept and `version` is bumped
+    pub async fn restore_recipe_from_snapshot(&self, id: &str, snapshot: Recipe, expected_version: Option<u32>) -> Option<Recipe> {
+        observe_mongo("restore_recipe_from_snapshot", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = MongoRepo::version_filter(obj_id, expected_version);
+
+            let restore = MongoRepo::update_pipeline(doc! {
+                "title": snapshot.title,
+                "description": snapshot.description,
+                "steps": snapshot.steps,
+                "ingredients": &snapshot.ingredients,
+                "email": snapshot.email,
+                "tags": snapshot.tags,
+                "photo_url": snapshot.photo_url,
+                "recipe_yield": snapshot.recipe_yield,
+                "prep_time_minutes": snapshot.prep_time_minutes,
+                "cook_time_minutes": snapshot.cook_time_minutes,
+                "total_time_minutes": snapshot.total_time_minutes,
+            }, Vec::new(), Some(&snapshot.ingredients));
+
+            col.find_one_and_update(
+                filter,
+                restore,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
+    }
+}
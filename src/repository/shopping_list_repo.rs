@@ -0,0 +1,156 @@
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::shopping_list_model::{ShoppingItem, ShoppingList};
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    // Lists are shared with lowercased emails (see shopping_list_api.rs), the email of the token can have any case
+    fn owner_or_member(owner_uid: &str, email: Option<&str>) -> Document {
+        match email {
+            Some(email) => doc! {"$or": [{"owner_uid": owner_uid}, {"shared_with": email.to_lowercase()}]},
+            None => doc! {"owner_uid": owner_uid},
+        }
+    }
+
+    /// The owner or a household member the list is shared with (by email)
+    fn shopping_list_access(obj_id: ObjectId, owner_uid: &str, email: Option<&str>) -> Document {
+        let mut filter = doc! {"_id": obj_id};
+        filter.extend(MongoRepo::owner_or_member(owner_uid, email));
+        filter
+    }
+
+    async fn update_shopping_list(&self, filter: Document, mut update: Document) -> Option<ShoppingList> {
+        let col = MongoRepo::collection_switch::<ShoppingList>(self, CollectionName::ShoppingLists).await;
+
+        let mut set = update.get_document("$set").cloned().unwrap_or_default();
+        set.insert("updated", mongodb::bson::DateTime::now());
+        update.insert("$set", set);
+
+        col.find_one_and_update(
+            filter,
+            update,
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build())
+            .await
+            .ok_recorded()?
+    }
+
+    pub async fn ensure_shopping_list_indexes(&self) -> Result<(), Error> {
+        observe_mongo("ensure_shopping_list_indexes", async {
+            let col: Collection<ShoppingList> = MongoRepo::collection_switch(self, CollectionName::ShoppingLists).await;
+
+            col.create_indexes(vec![
+                IndexModel::builder().keys(doc! {"owner_uid": 1}).build(),
+                IndexModel::builder().keys(doc! {"shared_with": 1}).build(),
+            ], None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn insert_shopping_list(&self, list: &ShoppingList) -> Result<(), Error> {
+        observe_mongo("insert_shopping_list", async {
+            let col = MongoRepo::collection_switch::<ShoppingList>(self, CollectionName::ShoppingLists).await;
+
+            col.insert_one(list, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Own lists and lists shared with the user, most recently changed first
+    pub async fn get_shopping_lists(&self, owner_uid: &str, email: Option<&str>) -> Result<Vec<ShoppingList>, Error> {
+        observe_mongo("get_shopping_lists", async {
+            let col = MongoRepo::collection_switch::<ShoppingList>(self, CollectionName::ShoppingLists).await;
+
+            let mut cursors = col
+                .find(MongoRepo::owner_or_member(owner_uid, email), FindOptions::builder().sort(doc! {"updated": -1}).build())
+                .await?;
+
+            let mut lists: Vec<ShoppingList> = Vec::new();
+
+            while let Some(list) = cursors
+                .try_next()
+                .await?
+            {
+                lists.push(list)
+            }
+
+            Ok(lists)
+        }).await
+    }
+
+    pub async fn get_shopping_list(&self, id: &str, owner_uid: &str, email: Option<&str>) -> Option<ShoppingList> {
+        observe_mongo("get_shopping_list", async {
+            let col = MongoRepo::collection_switch::<ShoppingList>(self, CollectionName::ShoppingLists).await;
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            col.find_one(MongoRepo::shopping_list_access(obj_id, owner_uid, email), None).await.ok_recorded()?
+        }).await
+    }
+
+    pub async fn set_shopping_item_checked(&self, id: &str, owner_uid: &str, email: Option<&str>, item_id: &str, checked: bool) -> Option<ShoppingList> {
+        observe_mongo("set_shopping_item_checked", async {
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let item_id = ObjectId::parse_str(item_id).ok()?;
+
+            let mut filter = MongoRepo::shopping_list_access(obj_id, owner_uid, email);
+            filter.insert("items._id", item_id);
+
+            self.update_shopping_list(filter, doc! {"$set": {"items.$.checked": checked}}).await
+        }).await
+    }
+
+    pub async fn add_shopping_item(&self, id: &str, owner_uid: &str, email: Option<&str>, item: &ShoppingItem) -> Option<ShoppingList> {
+        observe_mongo("add_shopping_item", async {
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let item = mongodb::bson::to_document(item).ok()?;
+
+            self.update_shopping_list(MongoRepo::shopping_list_access(obj_id, owner_uid, email), doc! {"$push": {"items": item}}).await
+        }).await
+    }
+
+    pub async fn remove_shopping_item(&self, id: &str, owner_uid: &str, email: Option<&str>, item_id: &str) -> Option<ShoppingList> {
+        observe_mongo("remove_shopping_item", async {
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let item_id = ObjectId::parse_str(item_id).ok()?;
+
+            let mut filter = MongoRepo::shopping_list_access(obj_id, owner_uid, email);
+            filter.insert("items._id", item_id);
+
+            self.update_shopping_list(filter, doc! {"$pull": {"items": {"_id": item_id}}}).await
+        }).await
+    }
+
+    /// Owner only, members can't share further
+    pub async fn share_shopping_list(&self, id: &str, owner_uid: &str, email: &str) -> Option<ShoppingList> {
+        observe_mongo("share_shopping_list", async {
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            self.update_shopping_list(doc! {"_id": obj_id, "owner_uid": owner_uid}, doc! {"$addToSet": {"shared_with": email}}).await
+        }).await
+    }
+
+    pub async fn unshare_shopping_list(&self, id: &str, owner_uid: &str, email: &str) -> Option<ShoppingList> {
+        observe_mongo("unshare_shopping_list", async {
+            let obj_id = ObjectId::parse_str(id).ok()?;
+
+            self.update_shopping_list(doc! {"_id": obj_id, "owner_uid": owner_uid}, doc! {"$pull": {"shared_with": email}}).await
+        }).await
+    }
+
+    pub async fn delete_shopping_list(&self, id: &str, owner_uid: &str) -> Result<bool, Error> {
+        observe_mongo("delete_shopping_list", async {
+            let col = MongoRepo::collection_switch::<ShoppingList>(self, CollectionName::ShoppingLists).await;
+            let Ok(obj_id) = ObjectId::parse_str(id) else { return Ok(false) };
+
+            let result = col.delete_one(doc! {"_id": obj_id, "owner_uid": owner_uid}, None).await?;
+            Ok(result.deleted_count > 0)
+        }).await
+    }
+}
@@ -0,0 +1,72 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::error::Error;
+use mongodb::options::{FindOptions, UpdateOptions};
+
+use crate::metrics::observe_mongo;
+use crate::models::substitution_model::SubstitutionRule;
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+impl MongoRepo {
+    /// Adds the curated rules that aren't stored yet and removes the ones no longer shipped.
+    /// Rules are matched on ingredient and replacement so they keep their ids between restarts, admin rules are left alone
+    pub async fn sync_curated_substitutions(&self, rules: &[SubstitutionRule]) -> Result<(), Error> {
+        observe_mongo("sync_curated_substitutions", async {
+            let col = MongoRepo::collection_switch::<SubstitutionRule>(self, CollectionName::SubstitutionRules).await;
+            let upsert = UpdateOptions::builder().upsert(true).build();
+            let mut filters: Vec<Document> = Vec::new();
+
+            for rule in rules {
+                let filter = doc! {
+                    "curated": true,
+                    "ingredient": &rule.ingredient,
+                    "replacement": mongodb::bson::to_bson(&rule.replacement)?,
+                };
+
+                col.update_one(
+                    filter.clone(),
+                    doc! {
+                        "$set": {"notes": rule.notes.clone()},
+                        "$setOnInsert": {"created": rule.created},
+                    },
+                    upsert.clone(),
+                ).await?;
+                filters.push(filter);
+            }
+
+            col.delete_many(doc! {"curated": true, "$nor": filters}, None).await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn insert_substitution_rule(&self, rule: &SubstitutionRule) -> Result<(), Error> {
+        observe_mongo("insert_substitution_rule", async {
+            let col = MongoRepo::collection_switch::<SubstitutionRule>(self, CollectionName::SubstitutionRules).await;
+
+            col.insert_one(rule, None).await?;
+            Ok(())
+        }).await
+    }
+
+    /// Curated rules first, then the ones added by admins, oldest first
+    pub async fn get_substitution_rules(&self) -> Result<Vec<SubstitutionRule>, Error> {
+        observe_mongo("get_substitution_rules", async {
+            let col = MongoRepo::collection_switch::<SubstitutionRule>(self, CollectionName::SubstitutionRules).await;
+
+            let mut cursors = col
+                .find(doc! {}, FindOptions::builder().sort(doc! {"curated": -1, "_id": 1}).build())
+                .await?;
+
+            let mut rules: Vec<SubstitutionRule> = Vec::new();
+
+            while let Some(rule) = cursors
+                .try_next()
+                .await?
+            {
+                rules.push(rule)
+            }
+
+            Ok(rules)
+        }).await
+    }
+}
@@ -0,0 +1,165 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+
+use crate::metrics::{observe_mongo, OkRecorded};
+use crate::models::recipe_model::Recipe;
+use crate::repository::mongo_repo::{CollectionName, MongoRepo};
+
+// Collections with documents that belong to one recipe, by the field holding its id
+const RECIPE_REFERENCES: [(CollectionName, &str); 2] = [
+    (CollectionName::RecipeRevisions, "recipe_id"),
+    (CollectionName::MealPlanEntries, "recipe_id"),
+];
+
+impl MongoRepo {
+    /// Recipes of the user that are in the trash, most recently deleted first
+    pub async fn get_trash_by_email(&self, email: &str) -> Result<Vec<Recipe>, Error> {
+        observe_mongo("get_trash_by_email", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let mut cursors = col
+                .find(
+                    doc! {"email": email, "deleted_at": {"$ne": null}},
+                    FindOptions::builder().sort(doc! {"deleted_at": -1}).build())
+                .await?;
+
+            let mut recipes: Vec<Recipe> = Vec::new();
+
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                recipes.push(recipe)
+            }
+
+            Ok(recipes)
+        }).await
+    }
+
+    pub async fn get_trashed_recipe_by_id(&self, id: &str) -> Option<Recipe> {
+        observe_mongo("get_trashed_recipe_by_id", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            let filter = doc! {"_id": obj_id, "deleted_at": {"$ne": null}};
+
+            col.find_one(filter, None)
+                .await
+                .ok_recorded()
+                .and_then(|recipe| recipe)
+        }).await
+    }
+
+    /// Moves a recipe of `email` out of the trash, None if it isn't in the trash (or was already purged) or belongs to someone else
+    pub async fn restore_recipe_from_trash(&self, id: &str, email: &str) -> Option<Recipe> {
+        observe_mongo("restore_recipe_from_trash", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let obj_id = ObjectId::parse_str(id).ok()?;
+            // Not while it is being purged, see purge_recipe
+            let filter = doc! {"_id": obj_id, "email": email, "deleted_at": {"$ne": null}, "purging": null};
+
+            let restore_doc = doc! {
+            "$set": {
+                "updated": mongodb::bson::DateTime::now(),
+            },
+            "$unset": {"deleted_at": ""},
+            "$inc": {"version": 1}
+            };
+
+            col.find_one_and_update(
+                filter,
+                restore_doc,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build())
+                .await
+                .ok_recorded()?
+        }).await
+    }
+
+    /// Permanently removes recipes deleted before `deleted_before` together with the data that only exists
+    /// for them: revisions and meal plan entries. Photos are URLs to images hosted elsewhere and there are no
+    /// reviews or favorites in this API yet, their collections belong in RECIPE_REFERENCES when they are added.
+    /// Returns the ids of the purged recipes, audit entries are kept until their own TTL expires
+    pub async fn purge_trash(&self, deleted_before: mongodb::bson::DateTime) -> Result<Vec<ObjectId>, Error> {
+        observe_mongo("purge_trash", async {
+            let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+
+            let mut cursors = col
+                .find(MongoRepo::expired_filter(None, deleted_before), None)
+                .await?;
+
+            let mut expired: Vec<ObjectId> = Vec::new();
+
+            while let Some(recipe) = cursors
+                .try_next()
+                .await?
+            {
+                expired.extend(recipe.id)
+            }
+
+            // A recipe that fails is left for the next purge, which finds it again as long as the recipe itself is there
+            let mut ids: Vec<ObjectId> = Vec::new();
+
+            for id in expired {
+                match self.purge_recipe(id, deleted_before).await {
+                    Ok(true) => ids.push(id),
+                    Ok(false) => {}
+                    Err(err) => log::warn!("Failed to purge recipe {} from the trash, retrying with the next purge: {}", id, err),
+                }
+            }
+
+            Ok(ids)
+        }).await
+    }
+
+    /// In the trash since before `deleted_before`, `id` narrows it to one recipe
+    pub fn expired_filter(id: Option<ObjectId>, deleted_before: mongodb::bson::DateTime) -> Document {
+        let mut filter = doc! {"deleted_at": {"$lt": deleted_before}};
+        if let Some(id) = id {
+            filter.insert("_id", id);
+        }
+        filter
+    }
+
+    // The recipe is claimed first, which keeps it from being restored, then the related data goes and the recipe last.
+    // False when it was restored in the meantime
+    async fn purge_recipe(&self, id: ObjectId, deleted_before: mongodb::bson::DateTime) -> Result<bool, Error> {
+        let col = MongoRepo::collection_switch::<Recipe>(self, CollectionName::Recipes).await;
+        let filter = MongoRepo::expired_filter(Some(id), deleted_before);
+
+        let claimed = col.update_one(filter.clone(), doc! {"$set": {"purging": true}}, None).await?;
+        if claimed.matched_count == 0 {
+            return Ok(false);
+        }
+
+        for (collection, field) in RECIPE_REFERENCES {
+            let related = MongoRepo::collection_switch::<Document>(self, collection).await;
+            related.delete_many(doc! {field: id}, None).await?;
+        }
+
+        let result = col.delete_one(filter, None).await?;
+        Ok(result.deleted_count == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use mongodb::bson::oid::ObjectId;
+
+    use crate::repository::mongo_repo::MongoRepo;
+
+    #[test]
+    fn expired_filter_matches_the_trash_before_the_retention() {
+        let before = mongodb::bson::DateTime::from_millis(1_000);
+        let id = ObjectId::new();
+
+        assert_eq!(MongoRepo::expired_filter(None, before), doc! {"deleted_at": {"$lt": before}});
+        assert_eq!(MongoRepo::expired_filter(Some(id), before), doc! {"deleted_at": {"$lt": before}, "_id": id});
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use actix_web::http::header::HeaderName;
+use serde::{Deserialize, Serialize};
+
+// CORS policy per profile. The dev profile allows the local dev servers, the prod profile allows nothing
+// until its origins are configured:
+//
+// [cors.prod]
+// allowed_origins = ["https://recipes.example.com", "https://*.example.com"]
+//
+// An origin is exact, "*" for any origin, or a pattern with one "*" standing for subdomains
+// ("https://*.example.com") or a port ("http://localhost:*"). The CORS_* environment variables
+// override the profile that is running.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Dev,
+    Prod,
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "dev" => Ok(Profile::Dev),
+            "prod" => Ok(Profile::Prod),
+            _ => Err("expected dev or prod".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsProfiles {
+    pub dev: CorsSettings,
+    pub prod: CorsSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_origins: Option<Vec<String>>, // None is the default of the profile, see default_origins
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub exposed_headers: Vec<String>, // Response headers browser clients may read
+    pub supports_credentials: bool,
+    pub max_age_secs: usize, // How long browsers may cache a preflight response
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        CorsSettings {
+            allowed_origins: None,
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"].map(str::to_string).to_vec(),
+            allowed_headers: ["Authorization", "Accept", "Content-Type", "If-Match", "If-None-Match", "X-Request-Id", "traceparent"].map(str::to_string).to_vec(),
+            // So browser clients can read the recipe version for If-Match, how long to wait after a 429 and when an API version goes away
+            exposed_headers: ["ETag", "X-Request-Id", "RateLimit-Limit", "RateLimit-Remaining", "RateLimit-Reset", "RateLimit-Policy", "Retry-After", "Deprecation", "Sunset", "Link"].map(str::to_string).to_vec(),
+            supports_credentials: true,
+            max_age_secs: 3600,
+        }
+    }
+}
+
+/// Origins of a profile that has no allowed_origins configured
+pub fn default_origins(profile: Profile) -> Vec<String> {
+    match profile {
+        Profile::Dev => vec!["http://localhost:*".to_string(), "http://127.0.0.1:*".to_string()],
+        Profile::Prod => Vec::new(),
+    }
+}
+
+impl CorsProfiles {
+    pub fn get(&self, profile: Profile) -> &CorsSettings {
+        match profile {
+            Profile::Dev => &self.dev,
+            Profile::Prod => &self.prod,
+        }
+    }
+
+    pub fn get_mut(&mut self, profile: Profile) -> &mut CorsSettings {
+        match profile {
+            Profile::Dev => &mut self.dev,
+            Profile::Prod => &mut self.prod,
+        }
+    }
+}
+
+/// Whether `origin` (the Origin header) is allowed by `pattern`, an exact origin or one with a single "*".
+/// The "*" matches letters, digits, "-" and ".", so "https://*.example.com" allows "https://a.b.example.com"
+/// but not "https://example.com" or "https://evil.io/x.example.com"
+pub fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+
+    match pattern.split_once('*') {
+        None => pattern.eq_ignore_ascii_case(origin),
+        Some((prefix, suffix)) => {
+            let origin = origin.to_ascii_lowercase();
+            let (prefix, suffix) = (prefix.to_ascii_lowercase(), suffix.to_ascii_lowercase());
+
+            origin.len() > prefix.len() + suffix.len()
+                && origin.starts_with(&prefix)
+                && origin.ends_with(&suffix)
+                && origin[prefix.len()..origin.len() - suffix.len()]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        }
+    }
+}
+
+fn is_local(origin: &str) -> bool {
+    let host = origin.split_once("://").map(|(_, rest)| rest).unwrap_or(origin);
+    ["localhost", "127.0.0.1", "[::1]"].iter().any(|local| host == *local || host.starts_with(&format!("{}:", local)))
+}
+
+impl CorsSettings {
+    pub fn origins(&self) -> &[String] {
+        self.allowed_origins.as_deref().unwrap_or_default()
+    }
+
+    /// Problems that stop the server, `key` is where they are configured ("cors.prod")
+    pub fn validate(&self, key: &str) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+
+        for origin in self.origins().iter().filter(|origin| *origin != "*") {
+            let valid_scheme = origin.starts_with("http://") || origin.starts_with("https://");
+            let host = origin.split_once("://").map(|(_, host)| host).unwrap_or_default();
+
+            if !valid_scheme || host.is_empty() || host.contains('/') || origin.matches('*').count() > 1 {
+                problems.push(format!("{}.allowed_origins: \"{}\" is not an origin like https://example.com or https://*.example.com", key, origin));
+            }
+        }
+        for method in &self.allowed_methods {
+            if method.is_empty() || !method.chars().all(|c| c.is_ascii_uppercase()) {
+                problems.push(format!("{}.allowed_methods: \"{}\" is not an HTTP method", key, method));
+            }
+        }
+        for header in self.allowed_headers.iter().chain(&self.exposed_headers) {
+            if HeaderName::from_str(header).is_err() {
+                problems.push(format!("{}: \"{}\" is not a header name", key, header));
+            }
+        }
+
+        problems
+    }
+
+    /// Settings that work but are more open than a production API should be, logged at startup
+    pub fn warnings(&self, profile: Profile) -> Vec<String> {
+        let mut warnings: Vec<String> = Vec::new();
+        let origins = self.origins();
+
+        if origins.iter().any(|origin| origin == "*") {
+            warnings.push(match self.supports_credentials {
+                true => "CORS allows any origin with credentials, every website can send authenticated requests on behalf of your users".to_string(),
+                false => "CORS allows any origin".to_string(),
+            });
+        }
+        if origins.iter().any(|origin| origin.starts_with("http://*") || origin.starts_with("https://*")) && self.supports_credentials {
+            warnings.push("CORS allows credentials from every subdomain matched by a wildcard, make sure you control all of them".to_string());
+        }
+
+        if profile == Profile::Prod {
+            for origin in origins.iter().filter(|origin| *origin != "*") {
+                if is_local(origin) {
+                    warnings.push(format!("CORS allows the local origin {} in prod", origin));
+                } else if origin.starts_with("http://") {
+                    warnings.push(format!("CORS allows {} in prod, which is not HTTPS", origin));
+                }
+            }
+            if origins.is_empty() {
+                warnings.push("CORS allows no origins in prod, browsers can't call the API from a web app until cors.prod.allowed_origins is set".to_string());
+            }
+        }
+
+        warnings
+    }
+}
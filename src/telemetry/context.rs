@@ -0,0 +1,116 @@
+// W3C trace context, https://www.w3.org/TR/trace-context/
+// traceparent: 00-<trace id, 32 hex>-<parent span id, 16 hex>-<flags, 2 hex>, flag 01 is sampled
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+fn new_trace_id() -> u128 {
+    loop {
+        let id = rand::random::<u128>();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+pub fn new_span_id() -> u64 {
+    loop {
+        let id = rand::random::<u64>();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+impl TraceContext {
+    /// Start of a new trace
+    pub fn root() -> Self {
+        TraceContext { trace_id: new_trace_id(), span_id: new_span_id(), sampled: true }
+    }
+
+    /// A span in the same trace, below this one
+    pub fn child(&self) -> Self {
+        TraceContext { trace_id: self.trace_id, span_id: new_span_id(), sampled: self.sampled }
+    }
+
+    /// None for a header we don't understand, the request then starts a new trace
+    pub fn parse_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+
+        let hex = |value: &str, len: usize| value.len() == len && value.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+        // Later versions may append fields, version 00 has exactly four and ff is invalid
+        if !hex(version, 2) || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if !hex(trace_id, 32) || !hex(span_id, 16) || !hex(flags, 2) {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok().filter(|id| *id != 0)?;
+        let span_id = u64::from_str_radix(span_id, 16).ok().filter(|id| *id != 0)?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+
+        Some(TraceContext { trace_id, span_id, sampled: flags & 1 == 1 })
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    pub fn span_id_hex(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_traceparent_reads_the_w3c_header() {
+        let context = TraceContext::parse_traceparent(" 00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01 ").unwrap();
+        assert_eq!(context.trace_id_hex(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(context.span_id_hex(), "b7ad6b7169203331");
+        assert!(context.sampled);
+
+        let unsampled = TraceContext::parse_traceparent("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00").unwrap();
+        assert!(!unsampled.sampled);
+
+        // Later versions may have more fields
+        assert!(TraceContext::parse_traceparent("01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-03-extra").is_some());
+    }
+
+    #[test]
+    fn parse_traceparent_rejects_invalid_headers() {
+        for header in [
+            "",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c8031-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333z-01",
+        ] {
+            assert_eq!(TraceContext::parse_traceparent(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn child_stays_in_the_trace() {
+        let root = TraceContext::root();
+        let child = root.child();
+
+        assert_eq!(child.trace_id, root.trace_id);
+        assert_ne!(child.span_id, root.span_id);
+        assert_eq!(child.sampled, root.sampled);
+        assert_eq!(root.trace_id_hex().len(), 32);
+        assert_eq!(root.span_id_hex().len(), 16);
+    }
+}
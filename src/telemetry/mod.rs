@@ -0,0 +1,404 @@
+pub mod context;
+pub mod mongo;
+pub mod otlp;
+pub mod request;
+
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+
+use serde_json::{Map, Value};
+use tracing::{Span, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing_log::AsLog;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::{FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::settings::{LogFormat, LogSettings, OtlpSettings};
+use crate::telemetry::context::TraceContext;
+use crate::telemetry::otlp::{Exporter, SpanKind, SpanRecord};
+
+/*
+    Logging for the whole process. Our code logs with the log macros and opens tracing spans
+    (request in telemetry/request.rs, auth in auth/keys.rs, mongo in metrics/mod.rs), other crates use either.
+    tracing-log turns log records into tracing events, and the fmt layer of tracing-subscriber writes them
+    to stderr as one JSON object per line (log.format = "json") or as text, with the fields of the spans
+    the line was logged in, so every line logged while handling a request carries its request_id and trace_id.
+    log.level is an EnvFilter for the lines, spans are always recorded.
+
+    Spans also carry a W3C trace context (TraceLayer), the request span continues the trace of an incoming
+    traceparent. When otlp.endpoint is set the spans of this crate are exported when they close, see otlp.rs.
+    Fields named otel.* steer the export and are not logged: otel.name (span name), otel.kind
+    (server or client) and otel.status_code (ERROR).
+ */
+
+static EXPORTER: OnceLock<Option<Arc<Exporter>>> = OnceLock::new();
+
+// Collects the fields of a span or event, "message" is the formatted text of an event
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+/// How the fmt layer writes span fields, and the event fields of text lines: without the otel.* fields,
+/// the traceparent (see TraceLayer) and the log.* fields tracing-log adds to the events of log records
+#[derive(Debug, Clone, Copy)]
+pub struct LogFields {
+    format: LogFormat,
+}
+
+fn logged(key: &str) -> bool {
+    !key.starts_with("otel.") && !key.starts_with("log.") && key != "traceparent"
+}
+
+impl LogFields {
+    fn record<R: RecordFields>(fields: R, into: &mut Map<String, Value>) {
+        let mut all = Map::new();
+        fields.record(&mut JsonVisitor(&mut all));
+        into.extend(all.into_iter().filter(|(key, _)| logged(key)));
+    }
+
+    fn write(&self, writer: &mut Writer<'_>, fields: Map<String, Value>) -> std::fmt::Result {
+        match self.format {
+            LogFormat::Json => write!(writer, "{}", Value::Object(fields)),
+            LogFormat::Text => {
+                // The message first and without its name, like the default fields of the fmt layer
+                let message = fields.get("message").map(|message| match message {
+                    Value::String(message) => message.clone(),
+                    other => other.to_string(),
+                });
+                let pairs = fields
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != "message")
+                    .map(|(key, value)| match value {
+                        Value::String(value) => format!("{}={}", key, value),
+                        other => format!("{}={}", key, other),
+                    });
+
+                write!(writer, "{}", message.into_iter().chain(pairs).collect::<Vec<String>>().join(" "))
+            }
+        }
+    }
+}
+
+impl<'writer> FormatFields<'writer> for LogFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'writer>, fields: R) -> std::fmt::Result {
+        let mut map = Map::new();
+        Self::record(fields, &mut map);
+        self.write(&mut writer, map)
+    }
+
+    // JSON fields are kept as one object, so recorded values replace the Empty ones instead of being appended
+    fn add_fields(&self, current: &'writer mut FormattedFields<Self>, fields: &Record<'_>) -> std::fmt::Result {
+        let mut map = match (self.format, current.fields.is_empty()) {
+            (LogFormat::Json, false) => serde_json::from_str(&current.fields).map_err(|_| std::fmt::Error)?,
+            _ => Map::new(),
+        };
+        Self::record(fields, &mut map);
+        if map.is_empty() {
+            return Ok(());
+        }
+
+        match self.format {
+            LogFormat::Json => current.fields.clear(),
+            LogFormat::Text if !current.fields.is_empty() => current.fields.push(' '),
+            LogFormat::Text => {}
+        }
+        self.write(&mut current.as_writer(), map)
+    }
+}
+
+/// log.level in the EnvFilter syntax: a default level and levels per target, "actix_web=debug,rc_mongo_api::jobs=warn,info"
+pub fn log_filter(level: &str) -> Result<EnvFilter, String> {
+    EnvFilter::builder().parse(level).map_err(|err| format!("is not a valid filter: {}", err))
+}
+
+// What TraceLayer keeps in the extensions of a span
+struct SpanTrace {
+    context: TraceContext,
+    parent_span_id: Option<u64>, // Also when the parent is remote
+    started: SystemTime,
+    fields: Map<String, Value>,
+}
+
+impl SpanTrace {
+    fn into_record(self, default_name: &str) -> SpanRecord {
+        let mut attributes = self.fields;
+        let otel = |attributes: &mut Map<String, Value>, key: &str| match attributes.remove(key) {
+            Some(Value::String(value)) => Some(value),
+            _ => None,
+        };
+
+        let name = otel(&mut attributes, "otel.name").unwrap_or_else(|| default_name.to_string());
+        let kind = match otel(&mut attributes, "otel.kind").as_deref() {
+            Some("server") => SpanKind::Server,
+            Some("client") => SpanKind::Client,
+            _ => SpanKind::Internal,
+        };
+        let error = otel(&mut attributes, "otel.status_code").as_deref() == Some("ERROR");
+
+        SpanRecord {
+            name,
+            kind,
+            context: self.context,
+            parent_span_id: self.parent_span_id,
+            start: self.started,
+            end: SystemTime::now(),
+            attributes,
+            error,
+        }
+    }
+}
+
+/// Gives every span a trace context, the one of its parent span or of its traceparent field, and
+/// hands the spans of this crate to the exporter when they close
+pub struct TraceLayer {
+    exporter: Option<Arc<Exporter>>,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for TraceLayer {
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut fields = Map::new();
+        attributes.record(&mut JsonVisitor(&mut fields));
+        let remote = match fields.remove("traceparent") {
+            Some(Value::String(header)) => TraceContext::parse_traceparent(&header),
+            _ => None,
+        };
+        let parent = span.parent().and_then(|parent| parent.extensions().get::<SpanTrace>().map(|trace| trace.context));
+
+        let (context, parent_span_id) = match (parent, remote) {
+            (Some(parent), _) => (parent.child(), Some(parent.span_id)),
+            (None, Some(remote)) => (remote.child(), Some(remote.span_id)),
+            (None, None) => (TraceContext::root(), None),
+        };
+        span.extensions_mut().insert(SpanTrace { context, parent_span_id, started: SystemTime::now(), fields });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(trace) = span.extensions_mut().get_mut::<SpanTrace>() {
+                values.record(&mut JsonVisitor(&mut trace.fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let (Some(exporter), Some(span)) = (&self.exporter, ctx.span(&id)) else {
+            return;
+        };
+        if !span.metadata().target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+
+        let trace = span.extensions_mut().remove::<SpanTrace>();
+        if let Some(trace) = trace.filter(|trace| trace.context.sampled) {
+            exporter.push(trace.into_record(span.name()));
+        }
+    }
+}
+
+/// The registry with the fmt layer writing to `writer` and the TraceLayer
+fn subscriber<W>(log: &LogSettings, exporter: Option<Arc<Exporter>>, writer: W) -> Result<impl Subscriber + Send + Sync, String>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let fields = LogFields { format: log.format };
+    let lines = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+    let lines = match log.format {
+        LogFormat::Json => lines.json().flatten_event(true).with_current_span(false).with_span_list(true).fmt_fields(fields).boxed(),
+        LogFormat::Text => lines.fmt_fields(fields).boxed(),
+    };
+
+    Ok(Registry::default()
+        .with(lines.with_filter(log_filter(&log.level)?))
+        .with(TraceLayer { exporter }.with_filter(filter_fn(|metadata| metadata.is_span()))))
+}
+
+/// Trace context of a span, None for a disabled span
+pub fn context_of(span: &Span) -> Option<TraceContext> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(id)?;
+        let context = span.extensions().get::<SpanTrace>().map(|trace| trace.context);
+        context
+    })
+    .flatten()
+}
+
+/// Trace context of the span the current thread is in
+pub fn current_context() -> Option<TraceContext> {
+    context_of(&Span::current())
+}
+
+/// None when otlp.endpoint isn't set
+pub fn exporter() -> Option<Arc<Exporter>> {
+    EXPORTER.get().cloned().flatten()
+}
+
+/// Installs the subscriber and the logger, once at startup
+pub fn init(log: &LogSettings, otlp: &OtlpSettings) -> Result<(), String> {
+    let exporter = Exporter::new(otlp)?.map(Arc::new);
+    let max_level = log_filter(&log.level)?.max_level_hint();
+
+    EXPORTER.set(exporter.clone()).map_err(|_| "Logging is already initialized".to_string())?;
+    tracing::subscriber::set_global_default(subscriber(log, exporter, std::io::stderr)?).map_err(|err| err.to_string())?;
+    // Records of the log macros below the most verbose level of the filter aren't even made
+    tracing_log::LogTracer::builder()
+        .with_max_level(max_level.map(|level| level.as_log()).unwrap_or(log::LevelFilter::Trace))
+        .init()
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(str::to_string).collect()
+        }
+    }
+
+    fn logged_with(level: &str, format: LogFormat, log: impl FnOnce()) -> Vec<String> {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let settings = LogSettings { level: level.to_string(), format };
+
+        tracing::subscriber::with_default(subscriber(&settings, None, move || writer.clone()).unwrap(), log);
+        capture.lines()
+    }
+
+    #[test]
+    fn log_filter_takes_levels_per_target() {
+        assert!(log_filter("info").is_ok());
+        assert!(log_filter("rc_mongo_api::jobs=warn,actix_web=debug,info").is_ok());
+        assert!(log_filter("rc_mongo_api=loud").is_err());
+
+        let lines = logged_with("rc_mongo_api::api=debug,warn", LogFormat::Json, || {
+            tracing::debug!(target: "rc_mongo_api::api::recipe_api", "kept");
+            tracing::info!(target: "rc_mongo_api::jobs", "dropped");
+            tracing::warn!(target: "actix_web", "kept too");
+        });
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"kept\""));
+        assert!(lines[1].contains("\"kept too\""));
+
+        // Only the listed targets without a default level
+        let lines = logged_with("actix_web=info", LogFormat::Json, || {
+            tracing::error!(target: "rc_mongo_api", "dropped");
+            tracing::info!(target: "actix_web::server", "kept");
+        });
+        assert_eq!(lines.len(), 1);
+    }
+
+    #[test]
+    fn json_lines_carry_the_span_fields_but_not_the_export_ones() {
+        let lines = logged_with("info", LogFormat::Json, || {
+            let span = tracing::info_span!(
+                "request",
+                request_id = "abc",
+                status = tracing::field::Empty,
+                traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+                otel.kind = "server",
+            );
+            let _entered = span.enter();
+            span.record("status", 404);
+            tracing::info!(latency_ms = 3, "Request done");
+        });
+
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "Request done");
+        assert_eq!(line["latency_ms"], 3);
+        assert_eq!(line["spans"], serde_json::json!([{ "name": "request", "request_id": "abc", "status": 404 }]));
+    }
+
+    #[test]
+    fn text_lines_put_the_message_first() {
+        let lines = logged_with("info", LogFormat::Text, || {
+            let _entered = tracing::info_span!("request", request_id = "abc", otel.kind = "server").entered();
+            tracing::info!(status = 200, "Request done");
+        });
+
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("request{request_id=abc}"), "{}", lines[0]);
+        assert!(lines[0].ends_with("Request done status=200"), "{}", lines[0]);
+        assert!(!lines[0].contains("otel"));
+    }
+
+    #[test]
+    fn spans_continue_the_trace_of_their_parent_or_traceparent() {
+        let settings = LogSettings { level: "off".to_string(), format: LogFormat::Json };
+
+        tracing::subscriber::with_default(subscriber(&settings, None, std::io::sink).unwrap(), || {
+            let remote = tracing::info_span!("request", traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01");
+            let request = context_of(&remote).unwrap();
+            assert_eq!(request.trace_id_hex(), "0af7651916cd43dd8448eb211c80319c");
+            assert_ne!(request.span_id_hex(), "b7ad6b7169203331");
+
+            // Debug spans are traced although their lines aren't logged
+            let child = remote.in_scope(|| {
+                let _mongo = tracing::debug_span!("mongo").entered();
+                current_context().unwrap()
+            });
+            assert_eq!(child.trace_id, request.trace_id);
+            assert_ne!(child.span_id, request.span_id);
+
+            let root = context_of(&tracing::info_span!("request", traceparent = "not a traceparent")).unwrap();
+            assert_ne!(root.trace_id, request.trace_id);
+            assert!(current_context().is_none());
+        });
+    }
+}
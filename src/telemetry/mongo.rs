@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use mongodb::bson::{Bson, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::event::command::{CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent};
+use serde_json::{Map, Value};
+
+use crate::telemetry::context::TraceContext;
+use crate::telemetry::otlp::{SpanKind, SpanRecord};
+
+/*
+    Command monitoring of the MongoDB driver. The driver sends commands from the task of the MongoRepo
+    method, so the current span is the mongo span of observe_mongo (metrics/mod.rs), which gets the
+    collection and the shape of the filter: field names and operators, every value replaced by "?".
+    Commands slower than otlp.slow_command_ms are exported as client spans below it.
+ */
+
+struct StartedCommand {
+    parent: TraceContext,
+    name: String,
+    collection: String,
+    filter: Option<String>,
+    db: String,
+    server: String,
+    start: SystemTime,
+}
+
+#[derive(Default)]
+pub struct CommandEvents {
+    started: Mutex<HashMap<i32, StartedCommand>>, // By request id, the one of the succeeded or failed event
+}
+
+// {"author": "x", "ratings": {"$gte": 4}} -> {"author": "?", "ratings": {"$gte": "?"}}
+fn shape(value: &Bson) -> Value {
+    match value {
+        Bson::Document(document) => Value::Object(document.iter().map(|(key, value)| (key.clone(), shape(value))).collect()),
+        // $and and $or hold filters, other arrays are values
+        Bson::Array(items) if items.iter().all(|item| matches!(item, Bson::Document(_))) && !items.is_empty() => {
+            Value::Array(items.iter().map(shape).collect())
+        }
+        _ => Value::from("?"),
+    }
+}
+
+fn filter_of(command_name: &str, command: &Document) -> Option<Bson> {
+    let first_statement = |key: &str, filter: &str| -> Option<Bson> {
+        command.get_array(key).ok()?.first()?.as_document()?.get(filter).cloned()
+    };
+
+    match command_name {
+        "find" => command.get("filter").cloned(),
+        "count" | "distinct" | "findAndModify" => command.get("query").cloned(),
+        "update" => first_statement("updates", "q"),
+        "delete" => first_statement("deletes", "q"),
+        "aggregate" => {
+            let matches: Vec<Bson> = command
+                .get_array("pipeline")
+                .ok()?
+                .iter()
+                .filter_map(|stage| stage.as_document()?.get("$match").cloned())
+                .collect();
+            match matches.len() {
+                0 => None,
+                1 => matches.into_iter().next(),
+                _ => Some(Bson::Array(matches)),
+            }
+        }
+        _ => None,
+    }
+}
+
+// Server messages can quote values (a duplicate key for one), the code name is enough to find the cause
+fn error_type(error: &Error) -> String {
+    match &*error.kind {
+        ErrorKind::Command(error) => error.code_name.clone(),
+        ErrorKind::Write(_) | ErrorKind::BulkWrite(_) => "WriteError".to_string(),
+        ErrorKind::Io(_) => "IoError".to_string(),
+        _ => "Error".to_string(),
+    }
+}
+
+impl CommandEvents {
+    fn finished(&self, request_id: i32, duration: Duration, error: Option<String>) {
+        let Some(command) = self.started.lock().unwrap().remove(&request_id) else {
+            return;
+        };
+        let Some(exporter) = super::exporter().filter(|exporter| duration >= exporter.slow_command) else {
+            return;
+        };
+
+        let mut attributes = Map::new();
+        attributes.insert("db.system".to_string(), Value::from("mongodb"));
+        attributes.insert("db.name".to_string(), Value::from(command.db));
+        attributes.insert("db.operation".to_string(), Value::from(command.name.clone()));
+        attributes.insert("db.mongodb.collection".to_string(), Value::from(command.collection.clone()));
+        attributes.insert("server.address".to_string(), Value::from(command.server));
+        if let Some(filter) = command.filter {
+            attributes.insert("db.statement".to_string(), Value::from(filter));
+        }
+        if let Some(error) = &error {
+            attributes.insert("error.type".to_string(), Value::from(error.as_str()));
+        }
+
+        exporter.push(SpanRecord {
+            name: format!("{} {}", command.name, command.collection),
+            kind: SpanKind::Client,
+            context: command.parent.child(),
+            parent_span_id: Some(command.parent.span_id),
+            start: command.start,
+            end: command.start + duration,
+            attributes,
+            error: error.is_some(),
+        });
+    }
+}
+
+impl CommandEventHandler for CommandEvents {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        // getMore has the cursor id where other commands have the collection
+        let collection = event
+            .command
+            .get_str(&event.command_name)
+            .or_else(|_| event.command.get_str("collection"))
+            .unwrap_or_default()
+            .to_string();
+        let filter = filter_of(&event.command_name, &event.command).map(|filter| shape(&filter).to_string());
+
+        let span = tracing::Span::current();
+        if !collection.is_empty() {
+            span.record("collection", collection.as_str());
+        }
+        if let Some(filter) = &filter {
+            span.record("filter", filter.as_str());
+        }
+
+        // Only commands of sampled traces can become spans
+        let Some(parent) = super::current_context().filter(|context| context.sampled && super::exporter().is_some()) else {
+            return;
+        };
+        self.started.lock().unwrap().insert(event.request_id, StartedCommand {
+            parent,
+            name: event.command_name,
+            collection,
+            filter,
+            db: event.db,
+            server: event.connection.address.to_string(),
+            start: SystemTime::now(),
+        });
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        self.finished(event.request_id, event.duration, None);
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        self.finished(event.request_id, event.duration, Some(error_type(&event.failure)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn shape_replaces_values() {
+        let filter = doc! {
+            "author": "someone@example.com",
+            "ratings": { "$gte": 4 },
+            "tags": { "$in": ["vegan", "quick"] },
+            "$or": [{ "deleted_at": null }, { "deleted_at": { "$exists": false } }],
+        };
+
+        assert_eq!(shape(&Bson::Document(filter)), json!({
+            "author": "?",
+            "ratings": { "$gte": "?" },
+            "tags": { "$in": "?" },
+            "$or": [{ "deleted_at": "?" }, { "deleted_at": { "$exists": "?" } }],
+        }));
+        assert_eq!(shape(&Bson::Array(Vec::new())), json!("?"));
+    }
+
+    #[test]
+    fn filter_of_finds_the_filter_of_each_command() {
+        let filter = doc! { "email": "someone@example.com" };
+
+        assert_eq!(filter_of("find", &doc! { "find": "recipes", "filter": filter.clone() }), Some(Bson::Document(filter.clone())));
+        assert_eq!(filter_of("count", &doc! { "count": "recipes", "query": filter.clone() }), Some(Bson::Document(filter.clone())));
+        assert_eq!(filter_of("findAndModify", &doc! { "findAndModify": "recipes", "query": filter.clone() }), Some(Bson::Document(filter.clone())));
+        assert_eq!(
+            filter_of("update", &doc! { "update": "recipes", "updates": [{ "q": filter.clone(), "u": { "$set": { "title": "x" } } }] }),
+            Some(Bson::Document(filter.clone())),
+        );
+        assert_eq!(filter_of("delete", &doc! { "delete": "recipes", "deletes": [{ "q": filter.clone(), "limit": 1 }] }), Some(Bson::Document(filter.clone())));
+        assert_eq!(filter_of("insert", &doc! { "insert": "recipes", "documents": [{ "title": "x" }] }), None);
+        assert_eq!(filter_of("update", &doc! { "update": "recipes", "updates": [] }), None);
+    }
+
+    #[test]
+    fn filter_of_aggregate_takes_the_match_stages() {
+        let first = doc! { "email": "someone@example.com" };
+        let second = doc! { "rating": { "$gte": 4 } };
+
+        let single = doc! { "aggregate": "recipes", "pipeline": [{ "$match": first.clone() }, { "$limit": 10 }] };
+        assert_eq!(filter_of("aggregate", &single), Some(Bson::Document(first.clone())));
+
+        let several = doc! { "aggregate": "recipes", "pipeline": [{ "$match": first.clone() }, { "$sort": { "title": 1 } }, { "$match": second.clone() }] };
+        assert_eq!(filter_of("aggregate", &several), Some(Bson::Array(vec![Bson::Document(first), Bson::Document(second)])));
+
+        assert_eq!(filter_of("aggregate", &doc! { "aggregate": "recipes", "pipeline": [{ "$count": "n" }] }), None);
+    }
+}
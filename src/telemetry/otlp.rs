@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::settings::OtlpSettings;
+use crate::telemetry::context::TraceContext;
+
+/*
+    Exports finished spans to an OpenTelemetry collector with OTLP/HTTP, JSON encoded
+    (POST <otlp.endpoint>/v1/traces). Spans are buffered and sent in batches every
+    otlp.export_interval_ms, when the collector is down the oldest spans are dropped instead of
+    letting the buffer grow.
+ */
+
+const MAX_BUFFERED: usize = 4096;
+const MAX_BATCH: usize = 512;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal = 1,
+    Server = 2,
+    Client = 3,
+}
+
+pub struct SpanRecord {
+    pub name: String,
+    pub kind: SpanKind,
+    pub context: TraceContext,
+    pub parent_span_id: Option<u64>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Map<String, Value>,
+    pub error: bool,
+}
+
+pub struct Exporter {
+    url: String,
+    service_name: String,
+    interval: Duration,
+    pub slow_command: Duration, // See telemetry/mongo.rs
+    client: reqwest::Client,
+    buffer: Mutex<VecDeque<SpanRecord>>,
+    dropped: AtomicU64,
+    failing: AtomicBool, // So a collector that is down is logged once, not every interval
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+// OTLP JSON AnyValue, 64 bit integers are strings
+fn any_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(value) => json!({ "boolValue": value }),
+        Value::Number(number) if number.is_i64() || number.is_u64() => json!({ "intValue": number.to_string() }),
+        Value::Number(number) => json!({ "doubleValue": number.as_f64() }),
+        Value::String(value) => json!({ "stringValue": value }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+fn attributes(attributes: &Map<String, Value>) -> Vec<Value> {
+    attributes.iter().map(|(key, value)| json!({ "key": key, "value": any_value(value) })).collect()
+}
+
+impl SpanRecord {
+    fn to_json(&self) -> Value {
+        let mut span = json!({
+            "traceId": self.context.trace_id_hex(),
+            "spanId": self.context.span_id_hex(),
+            "name": self.name,
+            "kind": self.kind as u8,
+            "startTimeUnixNano": unix_nanos(self.start),
+            "endTimeUnixNano": unix_nanos(self.end),
+            "attributes": attributes(&self.attributes),
+            "status": { "code": if self.error { 2 } else { 0 } }, // 2 is error, 0 unset
+        });
+        if let Some(parent) = self.parent_span_id {
+            span["parentSpanId"] = Value::from(format!("{:016x}", parent));
+        }
+        span
+    }
+}
+
+impl Exporter {
+    /// None when no collector is configured
+    pub fn new(settings: &OtlpSettings) -> Result<Option<Self>, String> {
+        let endpoint = settings.endpoint.trim().trim_end_matches('/');
+        if endpoint.is_empty() {
+            return Ok(None);
+        }
+
+        let client = reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build().map_err(|err| err.to_string())?;
+        let url = match endpoint.ends_with("/v1/traces") {
+            true => endpoint.to_string(),
+            false => format!("{}/v1/traces", endpoint),
+        };
+
+        Ok(Some(Exporter {
+            url,
+            service_name: settings.service_name.clone(),
+            interval: Duration::from_millis(settings.export_interval_ms),
+            slow_command: Duration::from_millis(settings.slow_command_ms),
+            client,
+            buffer: Mutex::new(VecDeque::new()),
+            dropped: AtomicU64::new(0),
+            failing: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn push(&self, span: SpanRecord) {
+        let mut buffer = self.buffer.lock().unwrap();
+
+        if buffer.len() >= MAX_BUFFERED {
+            buffer.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        buffer.push_back(span);
+    }
+
+    fn request_body(&self, spans: &[SpanRecord]) -> Value {
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": self.service_name } },
+                        { "key": "service.version", "value": { "stringValue": env!("CARGO_PKG_VERSION") } },
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans.iter().map(SpanRecord::to_json).collect::<Vec<Value>>(),
+                }],
+            }],
+        })
+    }
+
+    /// Sends everything buffered
+    pub async fn export(&self) {
+        loop {
+            let batch: Vec<SpanRecord> = {
+                let mut buffer = self.buffer.lock().unwrap();
+                let count = buffer.len().min(MAX_BATCH);
+                buffer.drain(..count).collect()
+            };
+            if batch.is_empty() {
+                break;
+            }
+
+            let result = self.client
+                .post(&self.url)
+                .json(&self.request_body(&batch))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => {
+                    if self.failing.swap(false, Ordering::Relaxed) {
+                        log::info!("Exporting traces to {} again", self.url);
+                    }
+                }
+                Err(err) => {
+                    if !self.failing.swap(true, Ordering::Relaxed) {
+                        log::warn!("Failed to export {} spans to {}: {}", batch.len(), self.url, err);
+                    }
+                    self.dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    break; // Try again next interval with what has been buffered by then
+                }
+            }
+        }
+
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::debug!("Dropped {} spans that couldn't be exported", dropped);
+        }
+    }
+}
+
+/// Background task started from main when otlp.endpoint is set
+pub async fn export_spans_periodically(exporter: Arc<Exporter>) {
+    let mut interval = actix_web::rt::time::interval(exporter.interval);
+
+    loop {
+        interval.tick().await;
+        exporter.export().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use actix_web::web::{Bytes, Data};
+
+    use super::*;
+    use crate::settings::{LogFormat, LogSettings};
+
+    // Stands in for the collector, keeps the body of every export request
+    async fn collector(status: u16) -> (String, Data<Mutex<Vec<Value>>>) {
+        let received: Data<Mutex<Vec<Value>>> = Data::new(Mutex::new(Vec::new()));
+        let bodies = received.clone();
+
+        let server = HttpServer::new(move || {
+            App::new().app_data(bodies.clone()).route("/v1/traces", web::post().to(move |bodies: Data<Mutex<Vec<Value>>>, body: Bytes| async move {
+                bodies.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap()).finish()
+            }))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let endpoint = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (endpoint, received)
+    }
+
+    fn exporter(endpoint: &str) -> Exporter {
+        let settings = OtlpSettings { endpoint: endpoint.to_string(), ..OtlpSettings::default() };
+        Exporter::new(&settings).unwrap().unwrap()
+    }
+
+    fn span(name: &str, parent_span_id: Option<u64>) -> SpanRecord {
+        SpanRecord {
+            name: name.to_string(),
+            kind: SpanKind::Server,
+            context: TraceContext { trace_id: 0x0af7651916cd43dd8448eb211c80319c, span_id: 0xb7ad6b7169203331, sampled: true },
+            parent_span_id,
+            start: UNIX_EPOCH + Duration::from_millis(1_500),
+            end: UNIX_EPOCH + Duration::from_millis(1_750),
+            attributes: Map::from_iter([
+                ("route".to_string(), Value::from("/recipes/{id}")),
+                ("status".to_string(), Value::from(500)),
+                ("cached".to_string(), Value::from(false)),
+                ("ratio".to_string(), Value::from(0.5)),
+            ]),
+            error: true,
+        }
+    }
+
+    #[test]
+    fn span_record_to_json_follows_otlp() {
+        assert_eq!(span("GET /recipes/{id}", Some(0x00f067aa0ba902b7)).to_json(), json!({
+            "traceId": "0af7651916cd43dd8448eb211c80319c",
+            "spanId": "b7ad6b7169203331",
+            "parentSpanId": "00f067aa0ba902b7",
+            "name": "GET /recipes/{id}",
+            "kind": 2,
+            "startTimeUnixNano": "1500000000",
+            "endTimeUnixNano": "1750000000",
+            "attributes": [
+                { "key": "route", "value": { "stringValue": "/recipes/{id}" } },
+                { "key": "status", "value": { "intValue": "500" } },
+                { "key": "cached", "value": { "boolValue": false } },
+                { "key": "ratio", "value": { "doubleValue": 0.5 } },
+            ],
+            "status": { "code": 2 },
+        }));
+
+        let root = span("root", None).to_json();
+        assert!(root.get("parentSpanId").is_none());
+    }
+
+    #[test]
+    fn push_drops_the_oldest_spans_when_full() {
+        let exporter = exporter("http://127.0.0.1:4318");
+
+        for index in 0..MAX_BUFFERED + 2 {
+            exporter.push(span(&index.to_string(), None));
+        }
+
+        let buffer = exporter.buffer.lock().unwrap();
+        assert_eq!(buffer.len(), MAX_BUFFERED);
+        assert_eq!(buffer.front().unwrap().name, "2");
+        assert_eq!(exporter.dropped.load(Ordering::Relaxed), 2);
+    }
+
+    #[actix_web::test]
+    async fn export_sends_the_buffered_spans_to_the_collector() {
+        let (endpoint, received) = collector(200).await;
+        let exporter = exporter(&format!("{}/", endpoint));
+        exporter.push(span("first", None));
+        exporter.push(span("second", None));
+
+        exporter.export().await;
+
+        let bodies = received.lock().unwrap();
+        assert_eq!(bodies.len(), 1);
+        let resource = &bodies[0]["resourceSpans"][0];
+        assert_eq!(resource["resource"]["attributes"][0], json!({ "key": "service.name", "value": { "stringValue": "rc-mongo-api" } }));
+        assert_eq!(resource["scopeSpans"][0]["scope"]["name"], env!("CARGO_PKG_NAME"));
+        let names: Vec<&Value> = resource["scopeSpans"][0]["spans"].as_array().unwrap().iter().map(|span| &span["name"]).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert!(exporter.buffer.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn export_counts_what_the_collector_rejects() {
+        let (endpoint, received) = collector(503).await;
+        let exporter = exporter(&endpoint);
+        exporter.push(span("lost", None));
+
+        exporter.export().await;
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert!(exporter.failing.load(Ordering::Relaxed));
+        assert!(exporter.buffer.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn spans_of_the_crate_are_exported_when_they_close() {
+        let (endpoint, received) = collector(200).await;
+        let exporter = Arc::new(exporter(&endpoint));
+        let settings = LogSettings { level: "off".to_string(), format: LogFormat::Json };
+        let subscriber = super::super::subscriber(&settings, Some(exporter.clone()), std::io::sink).unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!(
+                "request",
+                route = "/recipes",
+                status = tracing::field::Empty,
+                traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+                otel.name = "GET /recipes",
+                otel.kind = "server",
+                otel.status_code = tracing::field::Empty,
+            );
+            request.in_scope(|| {
+                let _mongo = tracing::debug_span!("mongo", operation = "get_recipes").entered();
+            });
+            request.record("status", 500);
+            request.record("otel.status_code", "ERROR");
+
+            // Not sampled by the caller, not exported
+            let _unsampled = tracing::info_span!("request", traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00");
+            // Spans of other crates aren't exported either
+            let _other = tracing::info_span!(target: "actix_web", "request");
+        });
+        exporter.export().await;
+
+        let bodies = received.lock().unwrap();
+        let spans = bodies[0]["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let (mongo, request) = (&spans[0], &spans[1]);
+        assert_eq!(mongo["name"], "mongo");
+        assert_eq!(mongo["kind"], 1);
+        assert_eq!(mongo["parentSpanId"], request["spanId"]);
+        assert_eq!(mongo["traceId"], "0af7651916cd43dd8448eb211c80319c");
+
+        assert_eq!(request["name"], "GET /recipes");
+        assert_eq!(request["kind"], 2);
+        assert_eq!(request["parentSpanId"], "b7ad6b7169203331");
+        assert_eq!(request["status"], json!({ "code": 2 }));
+        assert_eq!(request["attributes"], json!([
+            { "key": "route", "value": { "stringValue": "/recipes" } },
+            { "key": "status", "value": { "intValue": "500" } },
+        ]));
+    }
+}
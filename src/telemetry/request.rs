@@ -0,0 +1,205 @@
+use std::future::Future;
+use std::time::Instant;
+
+use actix_web::{HttpMessage, HttpResponse};
+use actix_web::body::{BodySize, BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CONTENT_TYPE, HeaderName, HeaderValue};
+use serde_json::{Map, Value};
+use tracing::Instrument;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+const MAX_ERROR_BODY_U64: u64 = 64 * 1024; // Larger and streamed error bodies are passed on untouched
+
+/// The X-Request-Id of a request, the client's or one we generated, in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+// Ids from clients end up in logs and the audit log, so only short ones without spaces or quotes are kept
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+fn new_request_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Middleware (App::wrap_fn) giving every request an id and a span its log lines are written in.
+/// The id comes back in the X-Request-Id header and in the body of error responses, and a line
+/// with the status and latency is logged when the request is done.
+/// The span continues the trace of a traceparent header and is the server span of the OTLP export
+pub fn trace_request<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let request_id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    let traceparent = req.headers().get(TRACEPARENT).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        route,
+        user_id = tracing::field::Empty, // Set by the auth span, see auth/keys.rs
+        status = tracing::field::Empty,
+        trace_id = tracing::field::Empty,
+        traceparent, // For the TraceLayer, not logged, see telemetry/mod.rs
+        otel.name = format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        otel.status_code = tracing::field::Empty,
+    );
+    if let Some(context) = super::context_of(&span) {
+        span.record("trace_id", context.trace_id_hex());
+    }
+    let path = req.path().to_string();
+    let client_ip = req.connection_info().realip_remote_addr().unwrap_or_default().to_string();
+    let started = Instant::now();
+
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    let response = span.in_scope(|| srv.call(req));
+
+    async move {
+        let latency_ms = || started.elapsed().as_millis() as u64;
+
+        let span = tracing::Span::current();
+        let record_status = |status: StatusCode| {
+            span.record("status", status.as_u16());
+            if status.is_server_error() {
+                span.record("otel.status_code", "ERROR");
+            }
+        };
+
+        match response.await {
+            Ok(response) => {
+                record_status(response.status());
+                tracing::info!(status = response.status().as_u16(), latency_ms = latency_ms(), path, client_ip, "Request done");
+                Ok(with_request_id(response, &request_id).await)
+            }
+            Err(err) => {
+                let status = err.as_response_error().status_code();
+                record_status(status);
+                tracing::info!(status = status.as_u16(), latency_ms = latency_ms(), path, client_ip, "Request failed: {}", err);
+                Err(err)
+            }
+        }
+    }
+    .instrument(span)
+}
+
+// Sets X-Request-Id and adds "request_id" to error bodies, JSON objects get the field and
+// text errors become { "message": <text>, "request_id": ... }
+async fn with_request_id<B: MessageBody + 'static>(mut response: ServiceResponse<B>, request_id: &str) -> ServiceResponse<BoxBody> {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+
+    let status = response.status();
+    let small_body = matches!(response.response().body().size(), BodySize::None | BodySize::Sized(0..=MAX_ERROR_BODY_U64));
+
+    if !(status.is_client_error() || status.is_server_error()) || !small_body {
+        return response.map_into_boxed_body();
+    }
+
+    let (req, res) = response.into_parts();
+    let (res, body) = res.into_parts();
+
+    let bytes = match to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return ServiceResponse::new(req, res.set_body(BoxBody::new(()))), // Unreadable body, nothing left to send
+    };
+
+    let mut object = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(object)) => object,
+        _ => {
+            let text = String::from_utf8_lossy(&bytes).trim().to_string();
+            let message = match text.is_empty() {
+                true => status.canonical_reason().unwrap_or_default().to_string(),
+                false => text,
+            };
+            Map::from_iter([("message".to_string(), Value::from(message))])
+        }
+    };
+    object.insert("request_id".to_string(), Value::from(request_id));
+
+    let mut res: HttpResponse<BoxBody> = res.set_body(BoxBody::new(Value::Object(object).to_string()));
+    res.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    ServiceResponse::new(req, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App, HttpResponse};
+    use serde_json::json;
+
+    use super::*;
+
+    async fn call(path: &str, request_id: Option<&str>) -> (String, StatusCode, Value, String) {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(trace_request)
+                .route("/ok", web::get().to(|| async { HttpResponse::Ok().body("fine") }))
+                .route("/text", web::get().to(|| async { HttpResponse::BadRequest().body("Bad title") }))
+                .route("/json", web::get().to(|| async { HttpResponse::Conflict().json(json!({ "message": "Version mismatch" })) }))
+                .route("/empty", web::get().to(|| async { HttpResponse::InternalServerError().finish() })),
+        )
+        .await;
+
+        let mut req = test::TestRequest::get().uri(path);
+        if let Some(request_id) = request_id {
+            req = req.insert_header((X_REQUEST_ID, request_id));
+        }
+        let response = test::call_service(&app, req.to_request()).await;
+
+        let header = response.headers().get(X_REQUEST_ID).unwrap().to_str().unwrap().to_string();
+        let status = response.status();
+        let content_type = response.headers().get(CONTENT_TYPE).map(|value| value.to_str().unwrap().to_string()).unwrap_or_default();
+        let body = test::read_body(response).await;
+        let body = serde_json::from_slice(&body).unwrap_or_else(|_| Value::from(String::from_utf8_lossy(&body).to_string()));
+        (header, status, body, content_type)
+    }
+
+    #[actix_web::test]
+    async fn request_id_is_echoed_or_generated() {
+        let (header, status, body, _) = call("/ok", Some("client-id_1.2:3")).await;
+        assert_eq!(header, "client-id_1.2:3");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "fine"); // Success bodies are left alone
+
+        let (header, _, _, _) = call("/ok", Some("has spaces")).await;
+        assert_eq!(header.len(), 32);
+        assert!(header.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[actix_web::test]
+    async fn error_bodies_get_the_request_id() {
+        let (_, status, body, content_type) = call("/text", Some("abc")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, json!({ "message": "Bad title", "request_id": "abc" }));
+        assert_eq!(content_type, "application/json");
+
+        let (_, status, body, _) = call("/json", Some("abc")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body, json!({ "message": "Version mismatch", "request_id": "abc" }));
+
+        let (_, _, body, _) = call("/empty", Some("abc")).await;
+        assert_eq!(body, json!({ "message": "Internal Server Error", "request_id": "abc" }));
+
+        let (_, status, body, _) = call("/missing", Some("abc")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({ "message": "Not Found", "request_id": "abc" }));
+    }
+}